@@ -28,13 +28,17 @@ use std::{
 use tokio::{runtime::Builder, time::sleep};
 use uuid::Uuid;
 
-use mqs_client::{ClientError, PublishableMessage, Service};
+use mqs_client::{ClientError, PublishableMessage, ReceiveFilter, Service};
 use mqs_common::{QueueConfig, UtcTime};
 
 type AnyError = Box<dyn Error + Send + Sync>;
 
 const NUM_THREADS: usize = 25;
 
+// consumers wait between MIN_POLL_BACKOFF and MAX_POLL_BACKOFF before polling an empty queue again
+const MIN_POLL_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_POLL_BACKOFF: Duration = Duration::from_secs(1);
+
 #[derive(Debug)]
 struct StringError {
     error: String,
@@ -62,7 +66,11 @@ impl Error for StringError {}
 
 fn get_service() -> Service {
     let host = env::var("MQS_SERVER").unwrap_or_else(|_| "localhost".to_string());
-    Service::new(&format!("http://{}:7843", &host))
+    let mut service = Service::new(&format!("http://{}:7843", host));
+    // compare the run time with and without Nagle's algorithm by setting MQS_NODELAY=false. Against
+    // a local server and database, neither setting was measurably faster
+    service.set_nodelay(env::var("MQS_NODELAY").map_or(true, |nodelay| nodelay != "false"));
+    service
 }
 
 fn format_duration(d: Duration) -> String {
@@ -153,7 +161,7 @@ fn main() -> Result<(), AnyError> {
         let queue_count: usize = 10;
         let queues = s.get_queues(None, None, None).await?;
         // clear data
-        for queue in queues.queues {
+        for queue in queues.items {
             let result = s.delete_queue(&queue.name, None).await?;
 
             if result.is_none() {
@@ -195,11 +203,8 @@ async fn create_queues(queue_count: usize) -> Result<(), AnyError> {
     for i in 0..queue_count {
         let result = s
             .create_queue(&format!("test-queue-{}", i), None, &QueueConfig {
-                redrive_policy:        None,
-                retention_timeout:     3600,
-                visibility_timeout:    100,
-                message_delay:         0,
-                message_deduplication: false,
+                visibility_timeout: 100,
+                ..QueueConfig::standard()
             })
             .await?;
 
@@ -217,12 +222,9 @@ async fn update_queues(queue_count: usize) -> Result<(), AnyError> {
     // update test queues
     for i in 0..queue_count {
         let result = s
-            .update_queue(&format!("test-queue-{}", i), None, &QueueConfig {
-                redrive_policy:        None,
-                retention_timeout:     3600,
-                visibility_timeout:    300,
-                message_delay:         0,
-                message_deduplication: false,
+            .update_queue(&format!("test-queue-{}", i), None, None, &QueueConfig {
+                visibility_timeout: 300,
+                ..QueueConfig::standard()
             })
             .await?;
 
@@ -316,17 +318,11 @@ async fn publish_and_consume_test_messages(queue_count: usize) -> Result<(), Any
 }
 
 async fn check_queue_empty(s: &Service, queue: &str) -> Result<(), AnyError> {
-    let info = s.describe_queue(queue, None).await?;
-    info.map_or_else(
-        || Err(StringError::from_str("Failed to describe queue").into()),
-        |description| {
-            if description.status.messages == 0 {
-                Ok(())
-            } else {
-                Err(StringError::from_str("Queue not yet empty").into())
-            }
-        },
-    )
+    if s.wait_until_empty(queue, MIN_POLL_BACKOFF, MAX_POLL_BACKOFF).await? {
+        Ok(())
+    } else {
+        Err(StringError::from_str("Queue not yet empty").into())
+    }
 }
 
 const DEFAULT_MESSAGE: [&[u8]; 3] = [
@@ -355,6 +351,12 @@ async fn publish_messages(index: usize, queue: String) -> Result<(), AnyError> {
             content_type:     DEFAULT_MESSAGE_CONTENT_TYPE[index % DEFAULT_MESSAGE_CONTENT_TYPE.len()],
             content_encoding: DEFAULT_MESSAGE_CONTENT_ENCODING[index % DEFAULT_MESSAGE_CONTENT_ENCODING.len()],
             trace_id:         DEFAULT_TRACE_ID[index % DEFAULT_TRACE_ID.len()],
+            raw_trace_id:     None,
+            group_id:         None,
+            expires_at:       None,
+            published_at:     None,
+            priority:         None,
+            attributes:       None,
             message:          message.clone(),
         });
     }
@@ -379,47 +381,84 @@ async fn consume_messages(
     workers: usize,
     publish_done: Arc<AtomicBool>,
 ) -> Result<(), AnyError> {
+    let s = get_service();
     let mut handles = Vec::with_capacity(workers);
     for _ in 0..handles.capacity() {
+        let service = s.clone();
         let queue_name = queue.clone();
         let publisher_done = publish_done.clone();
-        let handle = tokio::spawn(async move { consume_worker(index, timeout, publisher_done, queue_name).await });
+        let handle =
+            tokio::spawn(async move { consume_worker(service, index, timeout, publisher_done, queue_name).await });
         handles.push(handle);
     }
 
     let mut consumed_messages = 0;
+    let mut redelivered_messages = 0;
     let mut min_consumed = i32::MAX;
     let mut max_consumed = 0;
+    let mut consumed_per_worker = Vec::with_capacity(workers);
     for handle in handles {
-        let consumed = handle.await??;
+        let (consumed, redelivered) = handle.await??;
         consumed_messages += consumed;
+        redelivered_messages += redelivered;
         min_consumed = min_consumed.min(consumed);
         max_consumed = max_consumed.max(consumed);
+        consumed_per_worker.push(f64::from(consumed));
     }
 
     println!(
-        "Consumed {} messages (between {} and {} / worker) with {} workers in total from queue {}",
-        consumed_messages, min_consumed, max_consumed, workers, &queue
+        "Consumed {} messages (between {} and {} / worker, standard deviation {:.2}) with {} workers in total from queue {}, {} messages were redelivered",
+        consumed_messages,
+        min_consumed,
+        max_consumed,
+        standard_deviation(&consumed_per_worker),
+        workers,
+        queue,
+        redelivered_messages
     );
 
+    if redelivered_messages > 0 {
+        return Err(StringError::from_str("Some messages were received more than once").into());
+    }
+
     Ok(())
 }
 
+#[allow(clippy::cast_precision_loss)]
+fn standard_deviation(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    let variance = values.iter().map(|value| (value - mean) * (value - mean)).sum::<f64>() / count;
+
+    variance.sqrt()
+}
+
 async fn consume_worker(
+    s: Service,
     index: usize,
     timeout: Option<u16>,
     publish_done: Arc<AtomicBool>,
     queue_name: String,
-) -> Result<i32, AnyError> {
-    let s = get_service();
+) -> Result<(i32, i32), AnyError> {
     let mut consumed_messages = 0;
+    let mut redelivered_messages = 0;
+    let mut backoff = MIN_POLL_BACKOFF;
     loop {
-        let messages = s.get_messages(&queue_name, 10, timeout).await?;
+        let messages = s
+            .get_messages(&queue_name, None, 10, timeout, ReceiveFilter::default())
+            .await?;
         if messages.is_empty() {
             if publish_done.load(Ordering::Relaxed) {
                 break;
             }
-            sleep(Duration::from_millis(500)).await;
+            sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_POLL_BACKOFF);
+        } else {
+            backoff = MIN_POLL_BACKOFF;
         }
         for message in messages {
             consumed_messages += 1;
@@ -444,9 +483,13 @@ async fn consume_worker(
             if message.trace_id != DEFAULT_TRACE_ID[index % DEFAULT_TRACE_ID.len()] {
                 return Err(StringError::from_str("Message trace id does not match").into());
             }
-            if message.message_receives != 1 {
+            if message.message_receives < 1 {
                 return Err(StringError::from_str("Message was received wrong number of times").into());
             }
+            if message.message_receives > 1 {
+                // the message was received before, but not deleted in time
+                redelivered_messages += 1;
+            }
             if message.published_at <= *START || message.published_at > UtcTime::now() {
                 return Err(StringError::from_str("Message was published too early or late").into());
             }
@@ -456,7 +499,11 @@ async fn consume_worker(
             if message.content.as_slice() != DEFAULT_MESSAGE[index % DEFAULT_MESSAGE.len()] {
                 return Err(StringError::from_str("Message content does not match").into());
             }
-            let deleted = s.delete_message(None, &message.message_id).await?;
+            let receipt_handle = message
+                .receipt_handle
+                .as_ref()
+                .ok_or_else(|| StringError::from_str("Message has no receipt handle"))?;
+            let deleted = s.delete_message_by_receipt(None, receipt_handle).await?;
 
             if !deleted {
                 return Err(StringError::from_str("Failed to delete message").into());
@@ -464,5 +511,5 @@ async fn consume_worker(
         }
     }
 
-    Ok::<i32, AnyError>(consumed_messages)
+    Ok::<(i32, i32), AnyError>((consumed_messages, redelivered_messages))
 }
@@ -1,6 +1,7 @@
 use mqs_common::{QueueConfig, QueueRedrivePolicy};
 use std::{
-    env::args,
+    collections::BTreeMap,
+    env::{args, var},
     io::{stdin, Read},
     str::FromStr,
 };
@@ -15,15 +16,54 @@ pub enum ParsedArgs {
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub enum Command {
-    ListQueues(Option<usize>, Option<usize>),
+    ListQueues(Option<usize>, Option<usize>, OutputFormat),
     CreateQueue(String, QueueConfig),
     UpdateQueue(String, QueueConfig),
-    DeleteQueue(String),
+    DeleteQueue(String, DeleteQueueMode),
     DescribeQueue(String),
+    WatchQueue(String, u64),
     ReceiveMessage(String, Option<u16>),
     ReceiveMessages(String, u16, Option<u16>),
     PublishMessage(String, OwnedPublishableMessage),
     DeleteMessage(String),
+    Completions(Shell),
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum OutputFormat {
+    /// Aligned columns for humans, colored if printed to a terminal.
+    Table,
+    /// The JSON response of the server.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            _ => Err("expected table or json"),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum DeleteQueueMode {
+    /// Ask for confirmation before deleting a queue which still contains messages.
+    Confirm,
+    /// Only show what would be deleted without deleting anything.
+    DryRun,
+    /// Delete the queue without asking for confirmation.
+    Force,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -32,6 +72,8 @@ pub struct OwnedPublishableMessage {
     pub(crate) content_type:     String,
     /// Content encoding of the message.
     pub(crate) content_encoding: Option<String>,
+    /// Group of the message.
+    pub(crate) group_id:         Option<String>,
     /// Encoded body of the message.
     pub(crate) message:          Vec<u8>,
 }
@@ -57,11 +99,13 @@ pub fn parse_os_args() -> ParsedArgs {
     }
 
     let mut input = stdin();
-    parse_args(&mut input, arg_vec)
+    parse_args(&mut input, arg_vec, |name| {
+        var(name).ok().filter(|value| !value.is_empty())
+    })
 }
 
-fn parse_args<R: Read>(input: &mut R, args: Vec<String>) -> ParsedArgs {
-    match parse_top_options(args) {
+fn parse_args<R: Read, E: Fn(&str) -> Option<String>>(input: &mut R, args: Vec<String>, env: E) -> ParsedArgs {
+    match parse_top_options(args, env) {
         Err(msg) => ParsedArgs::ShowHelp(msg),
         Ok(opts) => match parse_cmd(input, opts.remaining_args) {
             Err(result) => result,
@@ -70,9 +114,15 @@ fn parse_args<R: Read>(input: &mut R, args: Vec<String>) -> ParsedArgs {
     }
 }
 
-fn parse_top_options(mut args: Vec<String>) -> Result<TopOptions, Option<String>> {
-    let mut host = "localhost".to_string();
-    let mut port = 7843;
+/// Parse the options given before the command. Options missing on the command line are read from
+/// the environment variables `MQS_HOST`, `MQS_PORT`, and `MQS_TRACE_ID` before falling back to
+/// their defaults, so an option on the command line always takes precedence over the environment.
+fn parse_top_options<E: Fn(&str) -> Option<String>>(
+    mut args: Vec<String>,
+    env: E,
+) -> Result<TopOptions, Option<String>> {
+    let mut host = None;
+    let mut port = None;
     let mut trace_id = None;
     args.reverse();
 
@@ -85,7 +135,7 @@ fn parse_top_options(mut args: Vec<String>) -> Result<TopOptions, Option<String>
                     "--host" => {
                         args.pop();
                         if let Some(new_host) = args.pop() {
-                            host = new_host;
+                            host = Some(new_host);
                         } else {
                             return Err(Some("Missing argument to --host".to_string()));
                         }
@@ -98,7 +148,7 @@ fn parse_top_options(mut args: Vec<String>) -> Result<TopOptions, Option<String>
                                     return Err(Some(format!("Failed to parse {} as port: {}", new_port, err)));
                                 },
                                 Ok(new_port) => {
-                                    port = new_port;
+                                    port = Some(new_port);
                                 },
                             };
                         } else {
@@ -133,6 +183,27 @@ fn parse_top_options(mut args: Vec<String>) -> Result<TopOptions, Option<String>
         };
     }
 
+    let host = host
+        .or_else(|| env("MQS_HOST"))
+        .unwrap_or_else(|| "localhost".to_string());
+    let port = match (port, env("MQS_PORT")) {
+        (Some(port), _) => port,
+        (None, Some(env_port)) => env_port
+            .parse()
+            .map_err(|err| Some(format!("Failed to parse {} from MQS_PORT as port: {}", env_port, err)))?,
+        (None, None) => 7843,
+    };
+    let trace_id = match (trace_id, env("MQS_TRACE_ID")) {
+        (Some(trace_id), _) => Some(trace_id),
+        (None, Some(env_trace_id)) => Some(Uuid::parse_str(&env_trace_id).map_err(|err| {
+            Some(format!(
+                "Failed to parse {} from MQS_TRACE_ID as trace id: {}",
+                env_trace_id, err
+            ))
+        })?),
+        (None, None) => None,
+    };
+
     Ok(TopOptions {
         remaining_args: args,
         host,
@@ -147,6 +218,7 @@ fn parse_cmd<R: Read>(input: &mut R, mut args: Vec<String>) -> Result<Command, P
         match s {
             "queue" => parse_queue_cmd(args),
             "message" => parse_message_cmd(input, args),
+            "completions" => parse_shell(args).map(Command::Completions),
             "help" => Err(ParsedArgs::ShowHelp(None)),
             _ => Err(ParsedArgs::ShowHelp(Some(format!("Unrecognized command {}", cmd)))),
         }
@@ -161,9 +233,10 @@ fn parse_queue_cmd(mut args: Vec<String>) -> Result<Command, ParsedArgs> {
                 .map(|(queue_name, queue_config)| Command::CreateQueue(queue_name, queue_config)),
             "update" => parse_queue_name_and_config(args, Command::UpdateQueue(String::new(), empty_queue_config()))
                 .map(|(queue_name, queue_config)| Command::UpdateQueue(queue_name, queue_config)),
-            "delete" => parse_queue_name(args, Command::DeleteQueue(String::new())).map(Command::DeleteQueue),
-            "list" => parse_limit_offset(args).map(|(offset, limit)| Command::ListQueues(offset, limit)),
+            "delete" => parse_delete_queue(args).map(|(queue_name, mode)| Command::DeleteQueue(queue_name, mode)),
+            "list" => parse_list_queues(args).map(|(offset, limit, output)| Command::ListQueues(offset, limit, output)),
             "describe" => parse_queue_name(args, Command::DescribeQueue(String::new())).map(Command::DescribeQueue),
+            "watch" => parse_watch_queue(args).map(|(queue_name, interval)| Command::WatchQueue(queue_name, interval)),
             "help" => Err(ParsedArgs::ShowHelp(None)),
             _ => Err(ParsedArgs::ShowHelp(Some(format!(
                 "Unrecognized queue subcommand {}",
@@ -173,20 +246,28 @@ fn parse_queue_cmd(mut args: Vec<String>) -> Result<Command, ParsedArgs> {
     })
 }
 
-const fn empty_queue_config() -> QueueConfig {
+pub const fn empty_queue_config() -> QueueConfig {
     QueueConfig {
         redrive_policy:        None,
         retention_timeout:     0,
         visibility_timeout:    0,
         message_delay:         0,
         message_deduplication: false,
+        allowed_content_types: Vec::new(),
+        default_content_type:  None,
+        max_in_flight:         None,
+        max_redrives:          None,
+        visibility_jitter:     None,
+        message_priorities:    false,
+        tags:                  BTreeMap::new(),
     }
 }
 
-const fn empty_owned_publishable_message() -> OwnedPublishableMessage {
+pub const fn empty_owned_publishable_message() -> OwnedPublishableMessage {
     OwnedPublishableMessage {
         content_type:     String::new(),
         content_encoding: None,
+        group_id:         None,
         message:          Vec::new(),
     }
 }
@@ -251,6 +332,13 @@ fn parse_queue_name_and_config(mut args: Vec<String>, cmd: Command) -> Result<(S
     let mut visibility_timeout = None;
     let mut message_delay = 0;
     let mut message_deduplication = false;
+    let mut allowed_content_types = Vec::new();
+    let mut default_content_type = None;
+    let mut max_in_flight = None;
+    let mut max_redrives = None;
+    let mut visibility_jitter = None;
+    let mut message_priorities = false;
+    let mut tags = BTreeMap::new();
 
     while let Some(arg) = args.pop() {
         let s: &str = &arg;
@@ -309,6 +397,67 @@ fn parse_queue_name_and_config(mut args: Vec<String>, cmd: Command) -> Result<(S
                     |val, err| format!("Failed to parse {} as message deduplication: {}", val, err),
                 )?;
             },
+            "--allowed-content-type" => {
+                allowed_content_types.push(parse_single_arg_string(
+                    &mut args,
+                    &cmd,
+                    "Missing argument to --allowed-content-type. You need to specify a content type the queue should accept.",
+                )?);
+            },
+            "--default-content-type" => {
+                default_content_type = Some(parse_single_arg_string(
+                    &mut args,
+                    &cmd,
+                    "Missing argument to --default-content-type. You need to specify the content type of messages published without one.",
+                )?);
+            },
+            "--max-in-flight" => {
+                max_in_flight = Some(parse_single_arg(
+                    &mut args,
+                    &cmd,
+                    "Missing argument to --max-in-flight. You need to specify the maximum number of messages in flight.",
+                    |val, err| format!("Failed to parse {} as maximum number of messages in flight: {}", val, err),
+                )?);
+            },
+            "--max-redrives" => {
+                max_redrives = Some(parse_single_arg(
+                    &mut args,
+                    &cmd,
+                    "Missing argument to --max-redrives. You need to specify the maximum number of redrives.",
+                    |val, err| format!("Failed to parse {} as maximum number of redrives: {}", val, err),
+                )?);
+            },
+            "--visibility-jitter" => {
+                visibility_jitter = Some(parse_single_arg(
+                    &mut args,
+                    &cmd,
+                    "Missing argument to --visibility-jitter. You need to specify the visibility jitter in percent.",
+                    |val, err| format!("Failed to parse {} as visibility jitter: {}", val, err),
+                )?);
+            },
+            "--message-priorities" => {
+                message_priorities = parse_single_arg(
+                    &mut args,
+                    &cmd,
+                    "Missing argument to --message-priorities. You need to specify whether messages should be received in the order of their priority.",
+                    |val, err| format!("Failed to parse {} as message priorities: {}", val, err),
+                )?;
+            },
+            "--tag" => {
+                let tag = parse_single_arg_string(
+                    &mut args,
+                    &cmd,
+                    "Missing argument to --tag. You need to specify a tag as KEY=VALUE.",
+                )?;
+                if let Some((key, value)) = tag.split_once('=') {
+                    tags.insert(key.to_string(), value.to_string());
+                } else {
+                    return Err(ParsedArgs::ShowCommandHelp(
+                        Some(format!("Failed to parse {} as tag, expected KEY=VALUE", tag)),
+                        Box::new(cmd),
+                    ));
+                }
+            },
             "help" | "--help" => {
                 return Err(ParsedArgs::ShowCommandHelp(None, Box::new(cmd)));
             },
@@ -330,25 +479,9 @@ fn parse_queue_name_and_config(mut args: Vec<String>, cmd: Command) -> Result<(S
         ));
     };
 
-    let redrive_policy = if let Some(max_receives) = max_receives {
-        if let Some(dead_letter_queue) = dead_letter_queue {
-            Some(QueueRedrivePolicy {
-                max_receives,
-                dead_letter_queue,
-            })
-        } else {
-            return Err(ParsedArgs::ShowCommandHelp(
-                Some("You have to specify the dead letter queue if you specify a maximum number of receives. You can use --dead-letter-queue [QUEUE] to specify it.".to_string()),
-                Box::new(cmd),
-            ));
-        }
-    } else if dead_letter_queue.is_some() {
-        return Err(ParsedArgs::ShowCommandHelp(
-            Some("You have to specify the maximum number of receives if you specify a dead letter queue. You can use --max-receives [NUMBER] to specify it.".to_string()),
-            Box::new(cmd),
-        ));
-    } else {
-        None
+    let redrive_policy = match QueueRedrivePolicy::from_parts(max_receives, dead_letter_queue) {
+        Ok(redrive_policy) => redrive_policy,
+        Err(err) => return Err(ParsedArgs::ShowCommandHelp(Some(err.to_string()), Box::new(cmd))),
     };
 
     let retention_timeout = if let Some(retention_timeout) = retention_timeout {
@@ -375,19 +508,32 @@ fn parse_queue_name_and_config(mut args: Vec<String>, cmd: Command) -> Result<(S
         ));
     };
 
-    Ok((queue_name, QueueConfig {
+    let config = QueueConfig {
         redrive_policy,
         retention_timeout,
         visibility_timeout,
         message_delay,
         message_deduplication,
-    }))
+        allowed_content_types,
+        default_content_type,
+        max_in_flight,
+        max_redrives,
+        visibility_jitter,
+        message_priorities,
+        tags,
+    };
+    if let Err(err) = config.validate() {
+        return Err(ParsedArgs::ShowCommandHelp(Some(err.to_string()), Box::new(cmd)));
+    }
+
+    Ok((queue_name, config))
 }
 
-fn parse_limit_offset(mut args: Vec<String>) -> Result<(Option<usize>, Option<usize>), ParsedArgs> {
+fn parse_list_queues(mut args: Vec<String>) -> Result<(Option<usize>, Option<usize>, OutputFormat), ParsedArgs> {
     let mut offset = None;
     let mut limit = None;
-    let cmd = Command::ListQueues(None, None);
+    let mut output = OutputFormat::Table;
+    let cmd = Command::ListQueues(None, None, OutputFormat::Table);
 
     while let Some(arg) = args.pop() {
         let s: &str = &arg;
@@ -408,6 +554,14 @@ fn parse_limit_offset(mut args: Vec<String>) -> Result<(Option<usize>, Option<us
                     |val, err| format!("Failed to parse {} as maximum number of queues to list: {}", val, err),
                 )?);
             },
+            "--output" => {
+                output = parse_single_arg(
+                    &mut args,
+                    &cmd,
+                    "Missing argument to --output. You need to specify the output format.",
+                    |val, err| format!("Failed to parse {} as output format: {}", val, err),
+                )?;
+            },
             "help" | "--help" => {
                 return Err(ParsedArgs::ShowCommandHelp(None, Box::new(cmd)));
             },
@@ -420,7 +574,7 @@ fn parse_limit_offset(mut args: Vec<String>) -> Result<(Option<usize>, Option<us
         }
     }
 
-    Ok((offset, limit))
+    Ok((offset, limit, output))
 }
 
 fn parse_queue_name(mut args: Vec<String>, cmd: Command) -> Result<String, ParsedArgs> {
@@ -460,6 +614,115 @@ fn parse_queue_name(mut args: Vec<String>, cmd: Command) -> Result<String, Parse
     Ok(queue_name)
 }
 
+fn parse_watch_queue(mut args: Vec<String>) -> Result<(String, u64), ParsedArgs> {
+    let mut queue_name = None;
+    let mut interval = 2;
+    let cmd = Command::WatchQueue(String::new(), 0);
+
+    while let Some(arg) = args.pop() {
+        let s: &str = &arg;
+        match s {
+            "--queue-name" => {
+                queue_name = Some(parse_single_arg_string(
+                    &mut args,
+                    &cmd,
+                    "Missing argument to --queue-name. You need to specify the queue to operate on.",
+                )?);
+            },
+            "--interval" => {
+                interval = parse_single_arg(
+                    &mut args,
+                    &cmd,
+                    "Missing argument to --interval. You need to specify the number of seconds between updates.",
+                    |val, err| format!("Failed to parse {} as number of seconds between updates: {}", val, err),
+                )?;
+            },
+            "help" | "--help" => {
+                return Err(ParsedArgs::ShowCommandHelp(None, Box::new(cmd)));
+            },
+            _ => {
+                return Err(ParsedArgs::ShowCommandHelp(
+                    Some(format!("Unrecognized argument {}", arg)),
+                    Box::new(cmd),
+                ));
+            },
+        }
+    }
+
+    let queue_name = if let Some(queue_name) = queue_name {
+        queue_name
+    } else {
+        return Err(ParsedArgs::ShowCommandHelp(
+            Some("You have to specify a queue. You can use --queue-name [QUEUE] to specify one.".to_string()),
+            Box::new(cmd),
+        ));
+    };
+
+    if interval == 0 {
+        return Err(ParsedArgs::ShowCommandHelp(
+            Some("The interval between updates has to be at least one second.".to_string()),
+            Box::new(cmd),
+        ));
+    }
+
+    Ok((queue_name, interval))
+}
+
+fn parse_delete_queue(mut args: Vec<String>) -> Result<(String, DeleteQueueMode), ParsedArgs> {
+    let mut queue_name = None;
+    let mut dry_run = false;
+    let mut force = false;
+    let cmd = Command::DeleteQueue(String::new(), DeleteQueueMode::Confirm);
+
+    while let Some(arg) = args.pop() {
+        let s: &str = &arg;
+        match s {
+            "--queue-name" => {
+                queue_name = Some(parse_single_arg_string(
+                    &mut args,
+                    &cmd,
+                    "Missing argument to --queue-name. You need to specify the queue to operate on.",
+                )?);
+            },
+            "--dry-run" => {
+                dry_run = true;
+            },
+            "--yes" => {
+                force = true;
+            },
+            "help" | "--help" => {
+                return Err(ParsedArgs::ShowCommandHelp(None, Box::new(cmd)));
+            },
+            _ => {
+                return Err(ParsedArgs::ShowCommandHelp(
+                    Some(format!("Unrecognized argument {}", arg)),
+                    Box::new(cmd),
+                ));
+            },
+        }
+    }
+
+    let queue_name = if let Some(queue_name) = queue_name {
+        queue_name
+    } else {
+        return Err(ParsedArgs::ShowCommandHelp(
+            Some("You have to specify a queue. You can use --queue-name [QUEUE] to specify one.".to_string()),
+            Box::new(cmd),
+        ));
+    };
+
+    // a dry run never deletes anything, so it takes precedence over --yes
+    let mode = if dry_run {
+        DeleteQueueMode::DryRun
+    } else if force {
+        DeleteQueueMode::Force
+    } else {
+        DeleteQueueMode::Confirm
+    };
+
+    Ok((queue_name, mode))
+}
+
 fn parse_queue_limit_and_timeout(mut args: Vec<String>) -> Result<(String, u16, Option<u16>), ParsedArgs> {
     let mut queue_name = None;
     let mut limit = 1;
@@ -528,6 +791,7 @@ fn parse_queue_and_message<R: Read>(
     let mut queue_name = None;
     let mut content_type = None;
     let mut content_encoding = None;
+    let mut group_id = None;
     let cmd = Command::PublishMessage(String::new(), empty_owned_publishable_message());
 
     while let Some(arg) = args.pop() {
@@ -554,6 +818,13 @@ fn parse_queue_and_message<R: Read>(
                     "Missing argument to --content-encoding. You need to specify the content-encoding of the message.",
                 )?);
             },
+            "--group-id" => {
+                group_id = Some(parse_single_arg_string(
+                    &mut args,
+                    &cmd,
+                    "Missing argument to --group-id. You need to specify the group of the message.",
+                )?);
+            },
             "help" | "--help" => {
                 return Err(ParsedArgs::ShowCommandHelp(None, Box::new(cmd)));
             },
@@ -598,6 +869,7 @@ fn parse_queue_and_message<R: Read>(
     Ok((queue_name, OwnedPublishableMessage {
         content_type,
         content_encoding,
+        group_id,
         message,
     }))
 }
@@ -642,6 +914,36 @@ fn parse_message_id(mut args: Vec<String>) -> Result<String, ParsedArgs> {
     Ok(message_id)
 }
 
+fn parse_shell(mut args: Vec<String>) -> Result<Shell, ParsedArgs> {
+    let mut shell = None;
+    let cmd = Command::Completions(Shell::Bash);
+
+    while let Some(arg) = args.pop() {
+        let s: &str = &arg;
+        match s {
+            "bash" if shell.is_none() => shell = Some(Shell::Bash),
+            "zsh" if shell.is_none() => shell = Some(Shell::Zsh),
+            "fish" if shell.is_none() => shell = Some(Shell::Fish),
+            "help" | "--help" => {
+                return Err(ParsedArgs::ShowCommandHelp(None, Box::new(cmd)));
+            },
+            _ => {
+                return Err(ParsedArgs::ShowCommandHelp(
+                    Some(format!("Unrecognized argument {}", arg)),
+                    Box::new(cmd),
+                ));
+            },
+        }
+    }
+
+    shell.ok_or_else(|| {
+        ParsedArgs::ShowCommandHelp(
+            Some("You have to specify a shell. Supported shells are bash, zsh, and fish.".to_string()),
+            Box::new(cmd),
+        )
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -681,12 +983,14 @@ mod test {
     fn parse_args() {
         let create_queue = CreateQueue(String::new(), empty_queue_config());
         let update_queue = UpdateQueue(String::new(), empty_queue_config());
-        let delete_queue = DeleteQueue(String::new());
-        let list_queues = ListQueues(None, None);
+        let delete_queue = DeleteQueue(String::new(), DeleteQueueMode::Confirm);
+        let list_queues = ListQueues(None, None, OutputFormat::Table);
         let describe_queue = DescribeQueue(String::new());
+        let watch_queue = WatchQueue(String::new(), 0);
         let receive_messages = ReceiveMessages(String::new(), 0, None);
         let publish_message = PublishMessage(String::new(), empty_owned_publishable_message());
         let delete_message = DeleteMessage(String::new());
+        let completions = Completions(Shell::Bash);
 
         let test_cases = [
             no_input(vec![], ShowHelp(None)),
@@ -713,6 +1017,7 @@ mod test {
             no_input(vec!["queue", "delete", "help"], mk_show_command_help(&delete_queue)),
             no_input(vec!["queue", "list", "help"], mk_show_command_help(&list_queues)),
             no_input(vec!["queue", "describe", "help"], mk_show_command_help(&describe_queue)),
+            no_input(vec!["queue", "watch", "help"], mk_show_command_help(&watch_queue)),
             no_input(vec!["message", "receive", "help"], mk_show_command_help(&receive_messages)),
             no_input(vec!["message", "publish", "help"], mk_show_command_help(&publish_message)),
             no_input(vec!["message", "delete", "help"], mk_show_command_help(&delete_message)),
@@ -725,6 +1030,13 @@ mod test {
                 visibility_timeout: 30,
                 message_delay: 0,
                 message_deduplication: false,
+                allowed_content_types: Vec::new(),
+                default_content_type: None,
+                max_in_flight: None,
+                max_redrives: None,
+                visibility_jitter: None,
+                message_priorities: false,
+                tags: BTreeMap::new(),
             }))),
             no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--dead-letter-queue", "dead-queue", "--max-receives", "10", "--message-delay", "15", "--message-deduplication", "true"], mk_run_command(CreateQueue("test-queue".to_string(), QueueConfig {
                 redrive_policy: Some(QueueRedrivePolicy {
@@ -735,9 +1047,127 @@ mod test {
                 visibility_timeout: 30,
                 message_delay: 15,
                 message_deduplication: true,
+                allowed_content_types: Vec::new(),
+                default_content_type: None,
+                max_in_flight: None,
+                max_redrives: None,
+                visibility_jitter: None,
+                message_priorities: false,
+                tags: BTreeMap::new(),
+            }))),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--allowed-content-type", "application/json", "--allowed-content-type", "text/plain"], mk_run_command(CreateQueue("test-queue".to_string(), QueueConfig {
+                redrive_policy: None,
+                retention_timeout: 300,
+                visibility_timeout: 30,
+                message_delay: 0,
+                message_deduplication: false,
+                allowed_content_types: vec!["application/json".to_string(), "text/plain".to_string()],
+                default_content_type: None,
+                max_in_flight: None,
+                max_redrives: None,
+                visibility_jitter: None,
+                message_priorities: false,
+                tags: BTreeMap::new(),
             }))),
-            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--dead-letter-queue", "dead-queue"], mk_show_command_help_with_message("You have to specify the maximum number of receives if you specify a dead letter queue. You can use --max-receives [NUMBER] to specify it.", &create_queue)),
-            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--max-receives", "10"], mk_show_command_help_with_message("You have to specify the dead letter queue if you specify a maximum number of receives. You can use --dead-letter-queue [QUEUE] to specify it.", &create_queue)),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--tag", "team=billing", "--tag", "note=a=b"], mk_run_command(CreateQueue("test-queue".to_string(), QueueConfig {
+                redrive_policy: None,
+                retention_timeout: 300,
+                visibility_timeout: 30,
+                message_delay: 0,
+                message_deduplication: false,
+                allowed_content_types: Vec::new(),
+                default_content_type: None,
+                max_in_flight: None,
+                max_redrives: None,
+                visibility_jitter: None,
+                message_priorities: false,
+                tags: vec![("note".to_string(), "a=b".to_string()), ("team".to_string(), "billing".to_string())].into_iter().collect(),
+            }))),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--default-content-type", "application/json"], mk_run_command(CreateQueue("test-queue".to_string(), QueueConfig {
+                redrive_policy: None,
+                retention_timeout: 300,
+                visibility_timeout: 30,
+                message_delay: 0,
+                message_deduplication: false,
+                allowed_content_types: Vec::new(),
+                default_content_type: Some("application/json".to_string()),
+                max_in_flight: None,
+                max_redrives: None,
+                visibility_jitter: None,
+                message_priorities: false,
+                tags: BTreeMap::new(),
+            }))),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--max-in-flight", "50"], mk_run_command(CreateQueue("test-queue".to_string(), QueueConfig {
+                redrive_policy: None,
+                retention_timeout: 300,
+                visibility_timeout: 30,
+                message_delay: 0,
+                message_deduplication: false,
+                allowed_content_types: Vec::new(),
+                default_content_type: None,
+                max_in_flight: Some(50),
+                max_redrives: None,
+                visibility_jitter: None,
+                message_priorities: false,
+                tags: BTreeMap::new(),
+            }))),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--max-redrives", "3"], mk_run_command(CreateQueue("test-queue".to_string(), QueueConfig {
+                redrive_policy: None,
+                retention_timeout: 300,
+                visibility_timeout: 30,
+                message_delay: 0,
+                message_deduplication: false,
+                allowed_content_types: Vec::new(),
+                default_content_type: None,
+                max_in_flight: None,
+                max_redrives: Some(3),
+                visibility_jitter: None,
+                message_priorities: false,
+                tags: BTreeMap::new(),
+            }))),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--visibility-jitter", "25"], mk_run_command(CreateQueue("test-queue".to_string(), QueueConfig {
+                redrive_policy: None,
+                retention_timeout: 300,
+                visibility_timeout: 30,
+                message_delay: 0,
+                message_deduplication: false,
+                allowed_content_types: Vec::new(),
+                default_content_type: None,
+                max_in_flight: None,
+                max_redrives: None,
+                visibility_jitter: Some(25),
+                message_priorities: false,
+                tags: BTreeMap::new(),
+            }))),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--message-priorities", "true"], mk_run_command(CreateQueue("test-queue".to_string(), QueueConfig {
+                redrive_policy: None,
+                retention_timeout: 300,
+                visibility_timeout: 30,
+                message_delay: 0,
+                message_deduplication: false,
+                allowed_content_types: Vec::new(),
+                default_content_type: None,
+                max_in_flight: None,
+                max_redrives: None,
+                visibility_jitter: None,
+                message_priorities: true,
+                tags: BTreeMap::new(),
+            }))),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--message-priorities", "not a bool"], mk_show_command_help_with_message("Failed to parse not a bool as message priorities: provided string was not `true` or `false`", &create_queue)),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--visibility-jitter", "101"], mk_show_command_help_with_message("The visibility jitter has to be between 0 and 100 percent", &create_queue)),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--max-redrives", "-1"], mk_show_command_help_with_message("The maximum number of redrives must not be negative", &create_queue)),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--max-in-flight", "0"], mk_show_command_help_with_message("The maximum number of messages in flight has to be at least 1", &create_queue)),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--default-content-type"], mk_show_command_help_with_message("Missing argument to --default-content-type. You need to specify the content type of messages published without one.", &create_queue)),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--tag"], mk_show_command_help_with_message("Missing argument to --tag. You need to specify a tag as KEY=VALUE.", &create_queue)),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--tag", "team"], mk_show_command_help_with_message("Failed to parse team as tag, expected KEY=VALUE", &create_queue)),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--allowed-content-type"], mk_show_command_help_with_message("Missing argument to --allowed-content-type. You need to specify a content type the queue should accept.", &create_queue)),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--dead-letter-queue", "dead-queue"], mk_show_command_help_with_message("The maximum number of receives has to be specified together with the dead letter queue", &create_queue)),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--max-receives", "10"], mk_show_command_help_with_message("A dead letter queue has to be specified together with the maximum number of receives", &create_queue)),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--max-receives", "0", "--dead-letter-queue", "dead-queue"], mk_show_command_help_with_message("The maximum number of receives has to be at least 1", &create_queue)),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--max-receives", "10", "--dead-letter-queue", "dead queue"], mk_show_command_help_with_message("The name of the dead letter queue is not a valid queue name", &create_queue)),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "0", "--visibility-timeout", "30"], mk_show_command_help_with_message("The retention timeout has to be at least 1 second", &create_queue)),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "-1"], mk_show_command_help_with_message("The visibility timeout must not be negative", &create_queue)),
+            no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "300", "--visibility-timeout", "30", "--message-delay", "-5"], mk_show_command_help_with_message("The message delay must not be negative", &create_queue)),
             no_input(vec!["queue", "create", "--queue-name", "test-queue", "--max-receives", "not a number"], mk_show_command_help_with_message("Failed to parse not a number as maximum number of receives: invalid digit found in string", &create_queue)),
             no_input(vec!["queue", "create", "--queue-name", "test-queue", "--retention-timeout", "not a number"], mk_show_command_help_with_message("Failed to parse not a number as retention timeout: invalid digit found in string", &create_queue)),
             no_input(vec!["queue", "create", "--queue-name", "test-queue", "--visibility-timeout", "not a number"], mk_show_command_help_with_message("Failed to parse not a number as visibility timeout: invalid digit found in string", &create_queue)),
@@ -750,18 +1180,39 @@ mod test {
                 visibility_timeout: 30,
                 message_delay: 0,
                 message_deduplication: false,
+                allowed_content_types: Vec::new(),
+                default_content_type: None,
+                max_in_flight: None,
+                max_redrives: None,
+                visibility_jitter: None,
+                message_priorities: false,
+                tags: BTreeMap::new(),
             }))),
             no_input(vec!["queue", "invalid"], mk_show_help("Unrecognized queue subcommand invalid")),
-            no_input(vec!["queue", "list"], mk_run_command(ListQueues(None, None))),
-            no_input(vec!["queue", "list", "--offset", "20"], mk_run_command(ListQueues(Some(20), None))),
+            no_input(vec!["queue", "list"], mk_run_command(ListQueues(None, None, OutputFormat::Table))),
+            no_input(vec!["queue", "list", "--offset", "20"], mk_run_command(ListQueues(Some(20), None, OutputFormat::Table))),
             no_input(vec!["queue", "list", "--offset", "not a number"], mk_show_command_help_with_message("Failed to parse not a number as number of queues to skip: invalid digit found in string", &list_queues)),
-            no_input(vec!["queue", "list", "--limit", "10"], mk_run_command(ListQueues(None, Some(10)))),
+            no_input(vec!["queue", "list", "--limit", "10"], mk_run_command(ListQueues(None, Some(10), OutputFormat::Table))),
             no_input(vec!["queue", "list", "--limit", "not a number"], mk_show_command_help_with_message("Failed to parse not a number as maximum number of queues to list: invalid digit found in string", &list_queues)),
-            no_input(vec!["queue", "list", "--offset", "20", "--limit", "10"], mk_run_command(ListQueues(Some(20), Some(10)))),
+            no_input(vec!["queue", "list", "--offset", "20", "--limit", "10"], mk_run_command(ListQueues(Some(20), Some(10), OutputFormat::Table))),
+            no_input(vec!["queue", "list", "--output", "json"], mk_run_command(ListQueues(None, None, OutputFormat::Json))),
+            no_input(vec!["queue", "list", "--output", "table", "--limit", "10"], mk_run_command(ListQueues(None, Some(10), OutputFormat::Table))),
+            no_input(vec!["queue", "list", "--output"], mk_show_command_help_with_message("Missing argument to --output. You need to specify the output format.", &list_queues)),
+            no_input(vec!["queue", "list", "--output", "yaml"], mk_show_command_help_with_message("Failed to parse yaml as output format: expected table or json", &list_queues)),
             no_input(vec!["queue", "list", "--invalid"], mk_show_command_help_with_message("Unrecognized argument --invalid", &list_queues)),
+            no_input(vec!["queue", "watch"], mk_show_command_help_with_message("You have to specify a queue. You can use --queue-name [QUEUE] to specify one.", &watch_queue)),
+            no_input(vec!["queue", "watch", "--queue-name", "test-queue"], mk_run_command(WatchQueue("test-queue".to_string(), 2))),
+            no_input(vec!["queue", "watch", "--queue-name", "test-queue", "--interval", "10"], mk_run_command(WatchQueue("test-queue".to_string(), 10))),
+            no_input(vec!["queue", "watch", "--queue-name", "test-queue", "--interval"], mk_show_command_help_with_message("Missing argument to --interval. You need to specify the number of seconds between updates.", &watch_queue)),
+            no_input(vec!["queue", "watch", "--queue-name", "test-queue", "--interval", "soon"], mk_show_command_help_with_message("Failed to parse soon as number of seconds between updates: invalid digit found in string", &watch_queue)),
+            no_input(vec!["queue", "watch", "--queue-name", "test-queue", "--interval", "0"], mk_show_command_help_with_message("The interval between updates has to be at least one second.", &watch_queue)),
+            no_input(vec!["queue", "watch", "--invalid"], mk_show_command_help_with_message("Unrecognized argument --invalid", &watch_queue)),
             no_input(vec!["queue", "delete"], mk_show_command_help_with_message("You have to specify a queue. You can use --queue-name [QUEUE] to specify one.", &delete_queue)),
             no_input(vec!["queue", "delete", "--queue-name"], mk_show_command_help_with_message("Missing argument to --queue-name. You need to specify the queue to operate on.", &delete_queue)),
-            no_input(vec!["queue", "delete", "--queue-name", "delete-this"], mk_run_command(DeleteQueue("delete-this".to_string()))),
+            no_input(vec!["queue", "delete", "--queue-name", "delete-this"], mk_run_command(DeleteQueue("delete-this".to_string(), DeleteQueueMode::Confirm))),
+            no_input(vec!["queue", "delete", "--queue-name", "delete-this", "--dry-run"], mk_run_command(DeleteQueue("delete-this".to_string(), DeleteQueueMode::DryRun))),
+            no_input(vec!["queue", "delete", "--queue-name", "delete-this", "--yes"], mk_run_command(DeleteQueue("delete-this".to_string(), DeleteQueueMode::Force))),
+            no_input(vec!["queue", "delete", "--queue-name", "delete-this", "--yes", "--dry-run"], mk_run_command(DeleteQueue("delete-this".to_string(), DeleteQueueMode::DryRun))),
             no_input(vec!["queue", "delete", "--invalid"], mk_show_command_help_with_message("Unrecognized argument --invalid", &delete_queue)),
             no_input(vec!["queue", "describe"], mk_show_command_help_with_message("You have to specify a queue. You can use --queue-name [QUEUE] to specify one.", &describe_queue)),
             no_input(vec!["queue", "describe", "--queue-name"], mk_show_command_help_with_message("Missing argument to --queue-name. You need to specify the queue to operate on.", &describe_queue)),
@@ -786,12 +1237,21 @@ mod test {
             with_input(vec!["message", "publish", "--queue-name", "test-queue", "--content-type", "text/plain"], "abc", mk_run_command(PublishMessage("test-queue".to_string(), OwnedPublishableMessage {
                 content_type: "text/plain".to_string(),
                 content_encoding: None,
+                group_id: None,
                 message: "abc".as_bytes().to_vec(),
             }))),
             no_input(vec!["message", "publish", "--queue-name", "test-queue", "--content-type", "text/plain", "--content-encoding"], mk_show_command_help_with_message("Missing argument to --content-encoding. You need to specify the content-encoding of the message.", &publish_message)),
             with_input(vec!["message", "publish", "--queue-name", "test-queue", "--content-type", "text/plain", "--content-encoding", "identity"], "abc", mk_run_command(PublishMessage("test-queue".to_string(), OwnedPublishableMessage {
                 content_type: "text/plain".to_string(),
                 content_encoding: Some("identity".to_string()),
+                group_id: None,
+                message: "abc".as_bytes().to_vec(),
+            }))),
+            no_input(vec!["message", "publish", "--queue-name", "test-queue", "--content-type", "text/plain", "--group-id"], mk_show_command_help_with_message("Missing argument to --group-id. You need to specify the group of the message.", &publish_message)),
+            with_input(vec!["message", "publish", "--queue-name", "test-queue", "--content-type", "text/plain", "--group-id", "my-group"], "abc", mk_run_command(PublishMessage("test-queue".to_string(), OwnedPublishableMessage {
+                content_type: "text/plain".to_string(),
+                content_encoding: None,
+                group_id: Some("my-group".to_string()),
                 message: "abc".as_bytes().to_vec(),
             }))),
             no_input(vec!["message", "publish", "--invalid"], mk_show_command_help_with_message("Unrecognized argument --invalid", &publish_message)),
@@ -799,6 +1259,13 @@ mod test {
             no_input(vec!["message", "delete", "--message-id"], mk_show_command_help_with_message("Missing argument to --message-id. You need to specify the id of the message.", &delete_message)),
             no_input(vec!["message", "delete", "--message-id", "test-message"], mk_run_command(DeleteMessage("test-message".to_string()))),
             no_input(vec!["message", "delete", "--invalid"], mk_show_command_help_with_message("Unrecognized argument --invalid", &delete_message)),
+            no_input(vec!["completions", "help"], mk_show_command_help(&completions)),
+            no_input(vec!["completions"], mk_show_command_help_with_message("You have to specify a shell. Supported shells are bash, zsh, and fish.", &completions)),
+            no_input(vec!["completions", "tcsh"], mk_show_command_help_with_message("Unrecognized argument tcsh", &completions)),
+            no_input(vec!["completions", "bash", "zsh"], mk_show_command_help_with_message("Unrecognized argument zsh", &completions)),
+            no_input(vec!["completions", "bash"], mk_run_command(Completions(Shell::Bash))),
+            no_input(vec!["completions", "zsh"], mk_run_command(Completions(Shell::Zsh))),
+            no_input(vec!["--host", "hostname", "completions", "fish"], RunCommand("hostname".to_string(), 7843, None, Box::new(Completions(Shell::Fish)))),
         ];
 
         for test_case in test_cases {
@@ -810,7 +1277,7 @@ mod test {
                 v
             };
 
-            let parsed = super::parse_args(&mut test_case.input.as_bytes(), args.clone());
+            let parsed = super::parse_args(&mut test_case.input.as_bytes(), args.clone(), |_| None);
             assert_eq!(
                 parsed, test_case.expected,
                 "Parsing '{:?}' should yield {:?} but got {:?}",
@@ -818,4 +1285,74 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn parse_args_from_env() {
+        let env = |name: &str| match name {
+            "MQS_HOST" => Some("env-host".to_string()),
+            "MQS_PORT" => Some("1234".to_string()),
+            "MQS_TRACE_ID" => Some("4aa662d5-b5c9-4f1c-b4ce-09e7ca6c57a5".to_string()),
+            _ => None,
+        };
+        let parse = |args: &[&str], env: &dyn Fn(&str) -> Option<String>| {
+            super::parse_args(&mut "".as_bytes(), args.iter().map(ToString::to_string).collect(), env)
+        };
+        let trace_id = Uuid::parse_str("4aa662d5-b5c9-4f1c-b4ce-09e7ca6c57a5").unwrap();
+        let other_trace_id = Uuid::parse_str("0f3b5a47-3f8c-4d2e-8a5e-3f6a1c2b9d10").unwrap();
+        let list_queues = || Box::new(ListQueues(None, None, OutputFormat::Table));
+
+        assert_eq!(
+            parse(&["queue", "list"], &env),
+            RunCommand("env-host".to_string(), 1234, Some(trace_id), list_queues())
+        );
+        assert_eq!(
+            parse(
+                &[
+                    "--host",
+                    "flag-host",
+                    "--port",
+                    "4321",
+                    "--trace-id",
+                    "0f3b5a47-3f8c-4d2e-8a5e-3f6a1c2b9d10",
+                    "queue",
+                    "list"
+                ],
+                &env
+            ),
+            RunCommand("flag-host".to_string(), 4321, Some(other_trace_id), list_queues())
+        );
+        assert_eq!(
+            parse(&["--host", "flag-host", "queue", "list"], &env),
+            RunCommand("flag-host".to_string(), 1234, Some(trace_id), list_queues())
+        );
+
+        let invalid_env = |name: &str| match name {
+            "MQS_PORT" => Some("not a port".to_string()),
+            "MQS_TRACE_ID" => Some("not a uuid".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            parse(&["queue", "list"], &invalid_env),
+            mk_show_help("Failed to parse not a port from MQS_PORT as port: invalid digit found in string")
+        );
+        assert_eq!(
+            parse(&["--port", "1234", "queue", "list"], &invalid_env),
+            mk_show_help("Failed to parse not a uuid from MQS_TRACE_ID as trace id: invalid character: expected an optional prefix of `urn:uuid:` followed by [0-9a-zA-Z], found `n` at 1")
+        );
+        // options on the command line win, so invalid environment variables are never parsed
+        assert_eq!(
+            parse(
+                &[
+                    "--port",
+                    "1234",
+                    "--trace-id",
+                    "4aa662d5-b5c9-4f1c-b4ce-09e7ca6c57a5",
+                    "queue",
+                    "list"
+                ],
+                &invalid_env
+            ),
+            RunCommand("localhost".to_string(), 1234, Some(trace_id), list_queues())
+        );
+    }
 }
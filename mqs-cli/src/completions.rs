@@ -0,0 +1,359 @@
+use crate::{
+    args::{empty_owned_publishable_message, empty_queue_config, Command, DeleteQueueMode, OutputFormat, Shell},
+    help::{get_program_name, subcommand_help},
+};
+
+/// Options accepted before the command and whether they take a value.
+const TOP_OPTIONS: [(&str, bool, &str); 4] = [
+    ("--host", true, "Specify the server host"),
+    ("--port", true, "Specify the server port"),
+    ("--trace-id", true, "Set a trace id for the request send to the server"),
+    ("--help", false, "Prints help information"),
+];
+
+const SHELLS: [&str; 3] = ["bash", "zsh", "fish"];
+
+struct Subcommand {
+    group: &'static str,
+    name:  &'static str,
+    /// Flags of the subcommand, whether they take a value, and their description.
+    flags: Vec<(&'static str, bool, &'static str)>,
+}
+
+/// Collect the subcommands and their flags from the help of each command, so the completions never
+/// drift from what the parser accepts.
+fn subcommands() -> Vec<Subcommand> {
+    let commands = [
+        Command::CreateQueue(String::new(), empty_queue_config()),
+        Command::UpdateQueue(String::new(), empty_queue_config()),
+        Command::DeleteQueue(String::new(), DeleteQueueMode::Confirm),
+        Command::ListQueues(None, None, OutputFormat::Table),
+        Command::DescribeQueue(String::new()),
+        Command::WatchQueue(String::new(), 0),
+        Command::ReceiveMessages(String::new(), 0, None),
+        Command::PublishMessage(String::new(), empty_owned_publishable_message()),
+        Command::DeleteMessage(String::new()),
+    ];
+
+    commands
+        .iter()
+        .filter_map(|cmd| {
+            let (flags, subcommand, _) = subcommand_help(cmd);
+            let (group, name) = subcommand.split_once(' ')?;
+            let flags = flags
+                .into_iter()
+                .filter(|(flag, _, _)| flag.starts_with("--"))
+                .map(|(flag, description, _)| {
+                    let (flag, takes_value) = flag.split_once(' ').map_or((flag, false), |(flag, _)| (flag, true));
+                    (flag, takes_value, description)
+                })
+                .collect();
+            Some(Subcommand { group, name, flags })
+        })
+        .collect()
+}
+
+fn groups(subcommands: &[Subcommand]) -> Vec<&'static str> {
+    let mut groups: Vec<&'static str> = Vec::new();
+    for subcommand in subcommands {
+        if !groups.contains(&subcommand.group) {
+            groups.push(subcommand.group);
+        }
+    }
+
+    groups
+}
+
+fn group_subcommands(subcommands: &[Subcommand], group: &str) -> String {
+    subcommands
+        .iter()
+        .filter(|subcommand| subcommand.group == group)
+        .map(|subcommand| subcommand.name)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn flag_names(flags: &[(&'static str, bool, &'static str)]) -> String {
+    flags.iter().map(|(flag, _, _)| *flag).collect::<Vec<_>>().join(" ")
+}
+
+/// Names of all flags which take a value, separated by `|` to be used as a shell case pattern.
+fn value_flags_pattern(subcommands: &[Subcommand]) -> String {
+    let mut flags: Vec<&str> = Vec::new();
+    let all_flags = TOP_OPTIONS
+        .iter()
+        .chain(subcommands.iter().flat_map(|subcommand| subcommand.flags.iter()));
+    for (flag, takes_value, _) in all_flags {
+        if *takes_value && !flags.contains(flag) {
+            flags.push(flag);
+        }
+    }
+
+    flags.join("|")
+}
+
+fn top_value_options_pattern() -> String {
+    TOP_OPTIONS
+        .iter()
+        .filter(|(_, takes_value, _)| *takes_value)
+        .map(|(flag, _, _)| *flag)
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+fn top_level_words(subcommands: &[Subcommand]) -> String {
+    let mut words: Vec<&str> = TOP_OPTIONS.iter().map(|(flag, _, _)| *flag).collect();
+    words.extend(groups(subcommands));
+    words.push("completions");
+    words.push("help");
+
+    words.join(" ")
+}
+
+/// Shell statements finding the command and subcommand among the words before the cursor. The
+/// words are read from `$word` inside a loop the caller has to open.
+fn find_command_lines() -> Vec<String> {
+    vec![
+        "        if (( skip )); then".to_string(),
+        "            skip=0".to_string(),
+        "            continue".to_string(),
+        "        fi".to_string(),
+        "        case \"$word\" in".to_string(),
+        format!("            {}) skip=1 ;;", top_value_options_pattern()),
+        "            -*) ;;".to_string(),
+        "            *)".to_string(),
+        "                if [[ -z \"$cmd\" ]]; then".to_string(),
+        "                    cmd=\"$word\"".to_string(),
+        "                elif [[ -z \"$sub\" ]]; then".to_string(),
+        "                    sub=\"$word\"".to_string(),
+        "                fi".to_string(),
+        "                ;;".to_string(),
+        "        esac".to_string(),
+        "    done".to_string(),
+    ]
+}
+
+/// Case branches mapping the command and subcommand to the words to complete. Each branch passes
+/// the words to `complete_with`.
+fn completion_cases(subcommands: &[Subcommand], complete_with: &str) -> Vec<String> {
+    let mut lines = vec!["    case \"$cmd $sub\" in".to_string()];
+    for subcommand in subcommands {
+        lines.push(format!(
+            "        \"{} {}\") {} \"{}\" ;;",
+            subcommand.group,
+            subcommand.name,
+            complete_with,
+            flag_names(&subcommand.flags)
+        ));
+    }
+    for group in groups(subcommands) {
+        lines.push(format!(
+            "        \"{} \") {} \"{} help\" ;;",
+            group,
+            complete_with,
+            group_subcommands(subcommands, group)
+        ));
+    }
+    lines.push(format!(
+        "        \"completions \") {} \"{}\" ;;",
+        complete_with,
+        SHELLS.join(" ")
+    ));
+    lines.push(format!(
+        "        \" \") {} \"{}\" ;;",
+        complete_with,
+        top_level_words(subcommands)
+    ));
+    lines.push("    esac".to_string());
+
+    lines
+}
+
+fn function_name(program_name: &str) -> String {
+    let name: String = program_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    format!("_{}", name)
+}
+
+fn bash_script(program_name: &str, subcommands: &[Subcommand]) -> Vec<String> {
+    let function = function_name(program_name);
+    let mut lines = vec![
+        format!("{}_complete() {{", function),
+        "    COMPREPLY=($(compgen -W \"$1\" -- \"${COMP_WORDS[COMP_CWORD]}\"))".to_string(),
+        "}".to_string(),
+        String::new(),
+        format!("{}() {{", function),
+        "    local cmd=\"\" sub=\"\" skip=0 word".to_string(),
+        "    COMPREPLY=()".to_string(),
+        "    case \"${COMP_WORDS[COMP_CWORD-1]}\" in".to_string(),
+        format!("        {}) return ;;", value_flags_pattern(subcommands)),
+        "    esac".to_string(),
+        "    for word in \"${COMP_WORDS[@]:1:COMP_CWORD-1}\"; do".to_string(),
+    ];
+    lines.extend(find_command_lines());
+    lines.extend(completion_cases(subcommands, &format!("{}_complete", function)));
+    lines.push("}".to_string());
+    lines.push(String::new());
+    lines.push(format!("complete -F {} {}", function, program_name));
+
+    lines
+}
+
+fn zsh_script(program_name: &str, subcommands: &[Subcommand]) -> Vec<String> {
+    let function = function_name(program_name);
+    let mut lines = vec![
+        format!("#compdef {}", program_name),
+        String::new(),
+        format!("{}_complete() {{", function),
+        "    compadd -- ${=1}".to_string(),
+        "}".to_string(),
+        String::new(),
+        format!("{}() {{", function),
+        "    local cmd=\"\" sub=\"\" skip=0 word".to_string(),
+        "    case \"${words[CURRENT-1]}\" in".to_string(),
+        format!("        {}) return ;;", value_flags_pattern(subcommands)),
+        "    esac".to_string(),
+        "    for word in \"${(@)words[2,CURRENT-1]}\"; do".to_string(),
+    ];
+    lines.extend(find_command_lines());
+    lines.extend(completion_cases(subcommands, &format!("{}_complete", function)));
+    lines.push("}".to_string());
+    lines.push(String::new());
+    lines.push(format!("compdef {} {}", function, program_name));
+
+    lines
+}
+
+fn fish_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn fish_flag(program_name: &str, condition: &str, (flag, takes_value, description): (&str, bool, &str)) -> String {
+    format!(
+        "complete -c {} -n '{}' -l {}{} -d '{}'",
+        program_name,
+        condition,
+        flag.trim_start_matches('-'),
+        if takes_value { " -r" } else { "" },
+        fish_escape(description)
+    )
+}
+
+fn fish_script(program_name: &str, subcommands: &[Subcommand]) -> Vec<String> {
+    let groups = groups(subcommands);
+    let mut lines = vec![format!("complete -c {} -f", program_name)];
+    for option in &TOP_OPTIONS {
+        lines.push(fish_flag(program_name, "__fish_use_subcommand", *option));
+    }
+    lines.push(format!(
+        "complete -c {} -n '__fish_use_subcommand' -a '{} completions help'",
+        program_name,
+        groups.join(" ")
+    ));
+    for group in groups {
+        let names = group_subcommands(subcommands, group);
+        lines.push(format!(
+            "complete -c {} -n '__fish_seen_subcommand_from {}; and not __fish_seen_subcommand_from {}' -a '{}'",
+            program_name, group, names, names
+        ));
+    }
+    for subcommand in subcommands {
+        let condition = format!(
+            "__fish_seen_subcommand_from {}; and __fish_seen_subcommand_from {}",
+            subcommand.group, subcommand.name
+        );
+        for flag in &subcommand.flags {
+            lines.push(fish_flag(program_name, &condition, *flag));
+        }
+    }
+    lines.push(format!(
+        "complete -c {} -n '__fish_seen_subcommand_from completions; and not __fish_seen_subcommand_from {}' -a '{}'",
+        program_name,
+        SHELLS.join(" "),
+        SHELLS.join(" ")
+    ));
+
+    lines
+}
+
+/// Generate a script completing the commands and options of the command line interface in the
+/// given shell.
+pub fn completion_script(shell: Shell) -> String {
+    let program_name = get_program_name();
+    let subcommands = subcommands();
+    let mut lines = match shell {
+        Shell::Bash => bash_script(&program_name, &subcommands),
+        Shell::Zsh => zsh_script(&program_name, &subcommands),
+        Shell::Fish => fish_script(&program_name, &subcommands),
+    };
+    lines.push(String::new());
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn completion_scripts() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let script = completion_script(shell);
+            for word in [
+                "queue",
+                "message",
+                "completions",
+                "create",
+                "receive",
+                "queue-name",
+                "dry-run",
+                "trace-id",
+            ] {
+                assert!(
+                    script.contains(word),
+                    "Completion script for {:?} does not contain {}",
+                    shell,
+                    word
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn subcommand_flags() {
+        let subcommands = subcommands();
+        assert_eq!(
+            subcommands
+                .iter()
+                .map(|subcommand| format!("{} {}", subcommand.group, subcommand.name))
+                .collect::<Vec<_>>(),
+            vec![
+                "queue create",
+                "queue update",
+                "queue delete",
+                "queue list",
+                "queue describe",
+                "queue watch",
+                "message receive",
+                "message publish",
+                "message delete",
+            ]
+        );
+        let delete_queue = &subcommands[2];
+        assert_eq!(
+            delete_queue
+                .flags
+                .iter()
+                .map(|(flag, takes_value, _)| (*flag, *takes_value))
+                .collect::<Vec<_>>(),
+            vec![("--queue-name", true), ("--dry-run", false), ("--yes", false),]
+        );
+        assert_eq!(
+            value_flags_pattern(&subcommands[3..4]),
+            "--host|--port|--trace-id|--offset|--limit|--output"
+        );
+    }
+}
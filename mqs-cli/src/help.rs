@@ -1,7 +1,7 @@
 use crate::args::Command;
 use std::env::current_exe;
 
-fn get_program_name() -> String {
+pub fn get_program_name() -> String {
     current_exe()
         .ok()
         .and_then(|path_buf| {
@@ -22,20 +22,25 @@ pub fn show_help(error: Option<String>) {
     println!("    {} [OPTIONS] [SUBCOMMAND]", get_program_name());
     println!();
     println!("OPTIONS:");
-    println!("    --host <HOST>            Specify the server host (default: localhost)");
-    println!("    --port <PORT>            Specify the server port (default: 7843)");
-    println!("    --trace-id <UUID>        Set a trace id for the request send to the server");
+    println!("    --host <HOST>            Specify the server host (default: $MQS_HOST or localhost)");
+    println!("    --port <PORT>            Specify the server port (default: $MQS_PORT or 7843)");
+    println!("    --trace-id <UUID>        Set a trace id for the request send to the server (default: $MQS_TRACE_ID)");
     println!("    --help                   Prints help information");
     println!();
+    println!("Options given on the command line take precedence over the environment variables MQS_HOST,");
+    println!("MQS_PORT, and MQS_TRACE_ID, which take precedence over the defaults.");
+    println!();
     println!("SUBCOMMANDS:");
     println!("    queue create             Create a new queue");
     println!("    queue update             Update an existing queue");
     println!("    queue delete             Delete a queue");
     println!("    queue list               List queues");
     println!("    queue describe           Get information about a queue");
+    println!("    queue watch              Show the status of a queue until interrupted");
     println!("    message receive          Receive one or more messages from a queue");
     println!("    message publish          Publish a message to a queue");
     println!("    message delete           Delete a message from a queue");
+    println!("    completions <SHELL>      Print a completion script for bash, zsh, or fish");
     println!();
     println!(
         "See '{} command help' for more information on a specific command.",
@@ -91,7 +96,8 @@ pub fn show_subcommand_help(error: Option<String>, cmd: &Command) {
     }
 }
 
-fn subcommand_help(cmd: &Command) -> (Vec<(&'static str, &'static str, bool)>, &'static str, &'static str) {
+#[allow(clippy::too_many_lines)]
+pub fn subcommand_help(cmd: &Command) -> (Vec<(&'static str, &'static str, bool)>, &'static str, &'static str) {
     match cmd {
         Command::CreateQueue(_, _) => {
             #[rustfmt::skip]
@@ -103,6 +109,13 @@ fn subcommand_help(cmd: &Command) -> (Vec<(&'static str, &'static str, bool)>, &
                 ("--visibility-timeout <SECONDS>", "The amount of seconds a message is invisible after it has been received", true),
                 ("--message-delay <SECONDS>", "The amount of seconds before a message is visible for the first time", false),
                 ("--message-deduplication <true|false>", "Whether to drop duplicate messages", false),
+                ("--allowed-content-type <STRING>", "A content type accepted by the queue, can be given multiple times (default: accept all)", false),
+                ("--default-content-type <STRING>", "Content type of messages published without one (default: application/octet-stream)", false),
+                ("--max-in-flight <NUMBER>", "Maximum number of received messages which are still invisible (default: unlimited)", false),
+                ("--max-redrives <NUMBER>", "Delete messages redriven this often once they reach the maximum receives again (default: unlimited)", false),
+                ("--visibility-jitter <PERCENT>", "Randomly extend the visibility timeout of received messages by up to this percentage (default: 0)", false),
+                ("--message-priorities <true|false>", "Whether to receive messages with a higher priority first", false),
+                ("--tag <KEY=VALUE>", "A tag attached to the queue, can be given multiple times", false),
             ];
 
             #[rustfmt::skip]
@@ -118,29 +131,39 @@ fn subcommand_help(cmd: &Command) -> (Vec<(&'static str, &'static str, bool)>, &
                 ("--visibility-timeout <SECONDS>", "The amount of seconds a message is invisible after it has been received", true),
                 ("--message-delay <SECONDS>", "The amount of seconds before a message is visible for the first time", false),
                 ("--message-deduplication <true|false>", "Whether to drop duplicate messages", false),
+                ("--allowed-content-type <STRING>", "A content type accepted by the queue, can be given multiple times (default: accept all)", false),
+                ("--default-content-type <STRING>", "Content type of messages published without one (default: application/octet-stream)", false),
+                ("--max-in-flight <NUMBER>", "Maximum number of received messages which are still invisible (default: unlimited)", false),
+                ("--max-redrives <NUMBER>", "Delete messages redriven this often once they reach the maximum receives again (default: unlimited)", false),
+                ("--visibility-jitter <PERCENT>", "Randomly extend the visibility timeout of received messages by up to this percentage (default: 0)", false),
+                ("--message-priorities <true|false>", "Whether to receive messages with a higher priority first", false),
+                ("--tag <KEY=VALUE>", "A tag attached to the queue, can be given multiple times", false),
             ];
 
             #[rustfmt::skip]
             (flags, "queue update", "Edits the configuration of a queue.")
         },
-        Command::DeleteQueue(_) => {
+        Command::DeleteQueue(_, _) => {
             #[rustfmt::skip]
             let flags = vec![
                 ("--queue-name <QUEUE>", "The name of the queue to delete", true),
+                ("--dry-run", "Only show the queue and the number of messages which would be deleted", false),
+                ("--yes", "Do not ask for confirmation if the queue still contains messages, required if stdin is not a terminal", false),
             ];
 
             #[rustfmt::skip]
-            (flags, "queue delete", "Deletes a queue and all messages stored in it.")
+            (flags, "queue delete", "Deletes a queue and all messages stored in it. Asks for confirmation if the queue still contains messages and fails without asking if stdin is not a terminal.")
         },
-        Command::ListQueues(_, _) => {
+        Command::ListQueues(_, _, _) => {
             #[rustfmt::skip]
             let flags = vec![
                 ("--offset <NUMBER>", "The number of queues to skip", false),
                 ("--limit <NUMBER>", "The maximum number of queues to return", false),
+                ("--output <table|json>", "Print a table with the status of each queue or the JSON response of the server (default: table)", false),
             ];
 
             #[rustfmt::skip]
-            (flags, "queue list", "Lists existing queues.")
+            (flags, "queue list", "Lists existing queues. The table is only colored if standard output is a terminal.")
         },
         Command::DescribeQueue(_) => {
             #[rustfmt::skip]
@@ -151,6 +174,16 @@ fn subcommand_help(cmd: &Command) -> (Vec<(&'static str, &'static str, bool)>, &
             #[rustfmt::skip]
             (flags, "queue describe", "Get information about a single queue.")
         },
+        Command::WatchQueue(_, _) => {
+            #[rustfmt::skip]
+            let flags = vec![
+                ("--queue-name <QUEUE>", "The name of the queue to watch", true),
+                ("--interval <SECONDS>", "The amount of seconds between updates (default: 2)", false),
+            ];
+
+            #[rustfmt::skip]
+            (flags, "queue watch", "Repeatedly print the status of a queue until interrupted with Ctrl-C.")
+        },
         Command::ReceiveMessage(_, _) | Command::ReceiveMessages(_, _, _) => {
             #[rustfmt::skip]
             let flags = vec![
@@ -168,6 +201,7 @@ fn subcommand_help(cmd: &Command) -> (Vec<(&'static str, &'static str, bool)>, &
                 ("--queue-name <QUEUE>", "The name of the queue to send the message to", true),
                 ("--content-type <STRING>", "The content type of the message", true),
                 ("--content-encoding <STRING>", "The content encoding of the message", false),
+                ("--group-id <STRING>", "The group of the message, messages of a group are received in order", false),
             ];
 
             #[rustfmt::skip]
@@ -182,5 +216,14 @@ fn subcommand_help(cmd: &Command) -> (Vec<(&'static str, &'static str, bool)>, &
             #[rustfmt::skip]
             (flags, "message delete", "Delete a message from a queue.")
         },
+        Command::Completions(_) => {
+            #[rustfmt::skip]
+            let flags = vec![
+                ("<bash|zsh|fish>", "The shell to print the completion script for", true),
+            ];
+
+            #[rustfmt::skip]
+            (flags, "completions", "Print a script completing the commands and options of this program. Load it in your shell, e.g. with 'source <(mqs-cli completions bash)'.")
+        },
     }
 }
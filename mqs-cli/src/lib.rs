@@ -14,6 +14,7 @@
 extern crate serde_derive;
 
 mod args;
+mod completions;
 mod help;
 mod run;
 
@@ -1,8 +1,22 @@
-use crate::args::Command;
-use mqs_client::{ClientError, MessageResponse, PublishableMessage, Service};
+use crate::{
+    args::{Command, DeleteQueueMode, OutputFormat},
+    completions::completion_script,
+};
+use mqs_client::{ClientError, MessageResponse, PublishableMessage, ReceiveFilter, Service};
+use mqs_common::{Page, QueueDescriptionOutput, QueueStatus, QueuesResponse, UtcTime};
 use serde::Serialize;
+use std::{
+    env::var_os,
+    io::{stderr, stdin, stdout, IsTerminal, Write},
+    time::Duration,
+};
+use tokio::{signal::ctrl_c, time::sleep};
 use uuid::Uuid;
 
+const ANSI_BOLD: &str = "\x1b[1m";
+const ANSI_YELLOW: &str = "\x1b[33m";
+const ANSI_RESET: &str = "\x1b[0m";
+
 #[derive(Serialize, Debug)]
 struct ErrorStruct {
     err: String,
@@ -20,14 +34,21 @@ struct SuccessStruct {
 
 #[derive(Serialize, Debug)]
 struct MessageStruct {
-    pub message_id:       String,
-    pub content_type:     String,
-    pub content_encoding: Option<String>,
-    pub message_receives: i32,
-    pub published_at:     String,
-    pub visible_at:       String,
-    pub trace_id:         Option<String>,
-    pub content:          String,
+    pub message_id:        String,
+    pub content_type:      String,
+    pub content_encoding:  Option<String>,
+    pub message_receives:  i32,
+    pub published_at:      String,
+    pub visible_at:        String,
+    pub first_received_at: Option<String>,
+    pub last_received_at:  Option<String>,
+    pub trace_id:          Option<String>,
+    pub group_id:          Option<String>,
+    pub expires_at:        Option<String>,
+    pub origin_queue:      Option<String>,
+    pub redrive_reason:    Option<String>,
+    pub content_hash:      Option<String>,
+    pub content:           String,
 }
 
 fn print_json<T: ?Sized + Serialize>(json: &T) {
@@ -55,18 +76,189 @@ fn print_opt_queue_config<T: Sized + Serialize, F: FnOnce() -> String>(response:
 fn print_messages(messages: Vec<MessageResponse>) {
     for message in messages {
         print_json(&MessageStruct {
-            message_id:       message.message_id,
-            content_type:     message.content_type,
-            content_encoding: message.content_encoding,
-            message_receives: message.message_receives,
-            published_at:     message.published_at.to_rfc3339(),
-            visible_at:       message.visible_at.to_rfc3339(),
-            trace_id:         message.trace_id.map(|trace_id| trace_id.to_string()),
-            content:          base64::encode(message.content),
+            message_id:        message.message_id.to_string(),
+            content_type:      message.content_type,
+            content_encoding:  message.content_encoding,
+            message_receives:  message.message_receives,
+            published_at:      message.published_at.to_rfc3339(),
+            visible_at:        message.visible_at.to_rfc3339(),
+            first_received_at: message.first_received_at.map(|time| time.to_rfc3339()),
+            last_received_at:  message.last_received_at.map(|time| time.to_rfc3339()),
+            trace_id:          message.raw_trace_id,
+            group_id:          message.group_id,
+            expires_at:        message.expires_at.map(|time| time.to_rfc3339()),
+            origin_queue:      message.origin_queue,
+            redrive_reason:    message.redrive_reason,
+            content_hash:      message.content_hash,
+            content:           base64::encode(message.content),
         });
     }
 }
 
+struct QueueRow {
+    name:   String,
+    /// Status of the queue, `None` if it is unknown.
+    status: Option<QueueStatus>,
+}
+
+fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        3600..=86399 => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+        _ => format!("{}d {}h", seconds / 86400, seconds % 86400 / 3600),
+    }
+}
+
+fn paint(s: &str, color: &str, colors: bool) -> String {
+    if colors {
+        format!("{}{}{}", color, s, ANSI_RESET)
+    } else {
+        s.to_string()
+    }
+}
+
+/// Render queues as a table with aligned columns. The name is aligned to the left, all other columns
+/// to the right. If `colors` is set, the header is bold and queues with visible messages are
+/// highlighted.
+fn format_queue_table(rows: &[QueueRow], colors: bool) -> String {
+    let header = ["NAME", "MESSAGES", "VISIBLE", "OLDEST AGE"].map(ToString::to_string);
+    let cells: Vec<[String; 4]> = rows
+        .iter()
+        .map(|row| {
+            row.status.map_or_else(
+                || [row.name.clone(), "-".to_string(), "-".to_string(), "-".to_string()],
+                |status| {
+                    [
+                        row.name.clone(),
+                        status.messages.to_string(),
+                        status.visible_messages.to_string(),
+                        format_age(status.oldest_message_age),
+                    ]
+                },
+            )
+        })
+        .collect();
+    let mut widths = [0; 4];
+    for row in std::iter::once(&header).chain(cells.iter()) {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let pad = |row: &[String; 4]| {
+        [
+            format!("{:<width$}", row[0], width = widths[0]),
+            format!("{:>width$}", row[1], width = widths[1]),
+            format!("{:>width$}", row[2], width = widths[2]),
+            format!("{:>width$}", row[3], width = widths[3]),
+        ]
+    };
+
+    let mut table = paint(pad(&header).join("  ").trim_end(), ANSI_BOLD, colors);
+    table.push('\n');
+    for (row, status) in cells.iter().zip(rows.iter().map(|row| row.status)) {
+        let mut padded = pad(row);
+        if matches!(status, Some(status) if status.visible_messages > 0) {
+            padded[2] = paint(&padded[2], ANSI_YELLOW, colors);
+        }
+        table.push_str(padded.join("  ").trim_end());
+        table.push('\n');
+    }
+
+    table
+}
+
+fn print_queue_table(queues: Page<QueueDescriptionOutput>) {
+    let offset = queues.offset;
+    let total = queues.total;
+    let has_more = queues.next_offset().is_some();
+    let rows: Vec<QueueRow> = queues
+        .items
+        .into_iter()
+        .map(|queue| QueueRow {
+            name:   queue.name.clone(),
+            status: Some(queue.status),
+        })
+        .collect();
+
+    let colors = stdout().is_terminal() && var_os("NO_COLOR").is_none();
+    print!("{}", format_queue_table(&rows, colors));
+    if has_more || offset > 0 {
+        println!(
+            "Showing {} queues starting at offset {} of {} queues",
+            rows.len(),
+            offset,
+            total
+        );
+    }
+}
+
+fn format_status_line(time: &UtcTime, queue_name: &str, status: &QueueStatus) -> String {
+    let mut line = format!(
+        "{}  {}  messages={} visible={} oldest_age={}",
+        time.to_rfc3339(),
+        queue_name,
+        status.messages,
+        status.visible_messages,
+        format_age(status.oldest_message_age)
+    );
+    if let Some(depth) = status.dead_letter_queue_depth {
+        line.push_str(&format!(" dead_letter={}", depth));
+    }
+
+    line
+}
+
+/// Print the status of a queue every `interval` seconds until the user presses Ctrl-C. On a
+/// terminal the status line is overwritten with each update, otherwise a new line is printed.
+async fn watch_queue(s: &Service, trace_id: Option<Uuid>, queue_name: &str, interval: u64) -> Result<i32, ClientError> {
+    let interactive = stdout().is_terminal();
+    let interrupted = ctrl_c();
+    tokio::pin!(interrupted);
+
+    loop {
+        let description = tokio::select! {
+            result = &mut interrupted => {
+                result?;
+                break;
+            },
+            description = s.describe_queue(queue_name, trace_id) => description?,
+        };
+        let line = if let Some(description) = description {
+            format_status_line(&UtcTime::now(), queue_name, &description.status)
+        } else {
+            if interactive {
+                println!();
+            }
+            print_json(&ErrorStruct {
+                err: format!("queue {} does not exist", queue_name),
+            });
+
+            return Ok(2);
+        };
+        if interactive {
+            print!("\r\x1b[2K{}", line);
+            stdout().flush()?;
+        } else {
+            println!("{}", line);
+        }
+
+        tokio::select! {
+            result = &mut interrupted => {
+                result?;
+                break;
+            },
+            _ = sleep(Duration::from_secs(interval)) => {},
+        }
+    }
+
+    if interactive {
+        println!();
+    }
+
+    Ok(0)
+}
+
 pub async fn run_command(host: &str, port: u16, trace_id: Option<Uuid>, cmd: Command) -> i32 {
     match run_command_for_result(host, port, trace_id, cmd).await {
         Ok(code) => code,
@@ -89,9 +281,9 @@ async fn run_command_for_result(
     let s = Service::new(&format_host(host, port));
 
     match cmd {
-        Command::ListQueues(offset, limit) => {
-            let queues = s.get_queues(trace_id, offset, limit).await?;
-            print_json(&queues);
+        Command::ListQueues(offset, limit, output) => match output {
+            OutputFormat::Table => print_queue_table(s.get_queues_with_status(trace_id, offset, limit).await?),
+            OutputFormat::Json => print_json(&QueuesResponse::from(s.get_queues(trace_id, offset, limit).await?)),
         },
         Command::CreateQueue(queue_name, config) => {
             let response = s.create_queue(&queue_name, trace_id, &config).await?;
@@ -100,12 +292,37 @@ async fn run_command_for_result(
             }));
         },
         Command::UpdateQueue(queue_name, config) => {
-            let response = s.update_queue(&queue_name, trace_id, &config).await?;
+            let response = s.update_queue(&queue_name, trace_id, None, &config).await?;
             return Ok(print_opt_queue_config(response, || {
                 format!("queue {} does not exist", queue_name)
             }));
         },
-        Command::DeleteQueue(queue_name) => {
+        Command::DeleteQueue(queue_name, mode) => {
+            if mode != DeleteQueueMode::Force {
+                let description = match s.describe_queue_deletion(&queue_name, trace_id).await? {
+                    None => {
+                        print_json(&ErrorStruct {
+                            err: format!("queue {} does not exist", queue_name),
+                        });
+
+                        return Ok(2);
+                    },
+                    Some(description) => description,
+                };
+                if mode == DeleteQueueMode::DryRun {
+                    print_json(&description);
+
+                    return Ok(0);
+                }
+                if description.status.messages > 0 && !confirm_deletion(&queue_name, description.status.messages)? {
+                    print_json(&ErrorStruct {
+                        err: format!("deletion of queue {} aborted", queue_name),
+                    });
+
+                    return Ok(2);
+                }
+            }
+
             let response = s.delete_queue(&queue_name, trace_id).await?;
             return Ok(print_opt_queue_config(response, || {
                 format!("queue {} does not exist", queue_name)
@@ -117,12 +334,17 @@ async fn run_command_for_result(
                 format!("queue {} does not exist", queue_name)
             }));
         },
+        Command::WatchQueue(queue_name, interval) => {
+            return watch_queue(&s, trace_id, &queue_name, interval).await;
+        },
         Command::ReceiveMessage(queue_name, timeout) => {
-            let message = s.get_message(&queue_name, timeout).await?;
+            let message = s.get_message(&queue_name, trace_id, timeout).await?;
             print_messages(message.map_or_else(Vec::new, |message| vec![message]));
         },
         Command::ReceiveMessages(queue_name, limit, timeout) => {
-            let messages = s.get_messages(&queue_name, limit, timeout).await?;
+            let messages = s
+                .get_messages(&queue_name, trace_id, limit, timeout, ReceiveFilter::default())
+                .await?;
             print_messages(messages);
         },
         Command::PublishMessage(queue_name, message) => {
@@ -130,14 +352,20 @@ async fn run_command_for_result(
                 .publish_message(&queue_name, PublishableMessage {
                     content_type: &message.content_type,
                     content_encoding: message.content_encoding.as_deref(),
+                    group_id: message.group_id.as_deref(),
+                    expires_at: None,
+                    published_at: None,
+                    priority: None,
+                    attributes: None,
                     trace_id,
+                    raw_trace_id: None,
                     message: message.message,
                 })
                 .await?;
             print_json(&PublishedStruct { published });
         },
         Command::DeleteMessage(message_id) => {
-            let deleted = s.delete_message(trace_id, &message_id).await?;
+            let deleted = s.delete_message_str(trace_id, &message_id).await?;
             if !deleted {
                 print_json(&ErrorStruct {
                     err: format!("message {} did not exist", &message_id),
@@ -148,11 +376,35 @@ async fn run_command_for_result(
 
             print_json(&SuccessStruct { success: true });
         },
+        Command::Completions(shell) => {
+            print!("{}", completion_script(shell));
+        },
     }
 
     Ok(0)
 }
 
+fn confirm_deletion(queue_name: &str, messages: i64) -> Result<bool, ClientError> {
+    if !stdin().is_terminal() {
+        // nobody could answer the prompt, so don't wait for input which never arrives
+        eprintln!(
+            "Queue {} still contains {} messages, pass --yes to delete it without confirmation",
+            queue_name, messages
+        );
+
+        return Ok(false);
+    }
+    eprint!(
+        "Queue {} still contains {} messages which will be deleted. Continue? [y/N] ",
+        queue_name, messages
+    );
+    stderr().flush()?;
+    let mut answer = String::new();
+    stdin().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
 // noinspection HttpUrlsUsage
 fn format_host(host: &str, port: u16) -> String {
     if host.starts_with("http://") || host.starts_with("https://") {
@@ -161,3 +413,71 @@ fn format_host(host: &str, port: u16) -> String {
         format!("http://{}:{}", host, port)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format_ages() {
+        assert_eq!(format_age(0), "0s");
+        assert_eq!(format_age(59), "59s");
+        assert_eq!(format_age(61), "1m 1s");
+        assert_eq!(format_age(7260), "2h 1m");
+        assert_eq!(format_age(90000), "1d 1h");
+    }
+
+    #[test]
+    fn status_line() {
+        let time = UtcTime::from_timestamp(1_590_000_000);
+        let mut status = QueueStatus {
+            messages: 12,
+            visible_messages: 4,
+            oldest_message_age: 30,
+            ..QueueStatus::default()
+        };
+        assert_eq!(
+            format_status_line(&time, "orders", &status),
+            "2020-05-20T18:40:00Z  orders  messages=12 visible=4 oldest_age=30s"
+        );
+        status.dead_letter_queue_depth = Some(2);
+        assert_eq!(
+            format_status_line(&time, "orders", &status),
+            "2020-05-20T18:40:00Z  orders  messages=12 visible=4 oldest_age=30s dead_letter=2"
+        );
+    }
+
+    #[test]
+    fn queue_table() {
+        let rows = vec![
+            QueueRow {
+                name:   "orders".to_string(),
+                status: Some(QueueStatus {
+                    messages: 120,
+                    visible_messages: 3,
+                    oldest_message_age: 75,
+                    ..QueueStatus::default()
+                }),
+            },
+            QueueRow {
+                name:   "orders-dead-letter".to_string(),
+                status: Some(QueueStatus::default()),
+            },
+            QueueRow {
+                name:   "deleted".to_string(),
+                status: None,
+            },
+        ];
+        assert_eq!(
+            format_queue_table(&rows, false),
+            "NAME                MESSAGES  VISIBLE  OLDEST AGE\n\
+             orders                   120        3      1m 15s\n\
+             orders-dead-letter         0        0          0s\n\
+             deleted                    -        -           -\n"
+        );
+        let colored = format_queue_table(&rows, true);
+        assert!(colored.starts_with("\x1b[1mNAME"));
+        assert!(colored.contains("\x1b[33m      3\x1b[0m"));
+        assert!(!colored.contains("\x1b[33m      0"));
+    }
+}
@@ -0,0 +1,661 @@
+//! A synchronous client for mqs servers.
+//!
+//! `BlockingService` wraps a `Service` together with a current-thread runtime and runs every
+//! request to completion before returning. Use it from code which is not running inside an async
+//! context, e.g. scripts or simple command line tools. Do not use it from within an async runtime,
+//! blocking on a future there panics.
+//!
+//! ```
+//! use mqs_client::blocking::BlockingService;
+//!
+//! let service = BlockingService::new("http://mqs.example.com:7843").unwrap();
+//! let success = service.check_health();
+//! assert!(!success.is_ok());
+//! ```
+
+use futures_core::Stream;
+use hyper::{body::Bytes, HeaderMap, Method, StatusCode};
+use mqs_common::{
+    InFlightMessage,
+    MessageId,
+    Page,
+    PublishOutcome,
+    QueueConfig,
+    QueueConfigOutput,
+    QueueDescriptionOutput,
+    QueueWithDlqOutput,
+    ServerInfo,
+};
+use std::{error::Error, future::Future, time::Duration};
+use tokio::runtime::{Builder, Runtime};
+use uuid::Uuid;
+
+use crate::{ClientError, MessageResponse, PublishResult, PublishableMessage, ReceiveFilter, Service};
+
+/// A `BlockingService` exposes the methods of a `Service` synchronously. See the methods of
+/// `Service` for a more detailed description of each method.
+#[derive(Debug)]
+pub struct BlockingService {
+    service: Service,
+    runtime: Runtime,
+}
+
+impl BlockingService {
+    /// Create a new blocking service for the given host. The host is normalized like with
+    /// `Service::try_new`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the host is not a valid URL or the runtime to execute requests could not
+    /// be created.
+    pub fn new(host: &str) -> Result<Self, ClientError> {
+        Self::from_service(Service::try_new(host)?)
+    }
+
+    /// Create a new blocking service executing requests with the given service.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the runtime to execute requests could not be created.
+    pub fn from_service(service: Service) -> Result<Self, ClientError> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        Ok(Self { service, runtime })
+    }
+
+    /// Get the underlying async service.
+    #[must_use]
+    pub const fn service(&self) -> &Service {
+        &self.service
+    }
+
+    /// Set the maximum size of a response body. See `Service::set_max_body_size`.
+    pub fn set_max_body_size(&mut self, max_body_size: Option<usize>) -> &mut Self {
+        self.service.set_max_body_size(max_body_size);
+        self
+    }
+
+    /// Set the maximum number of messages accepted in a single response. See
+    /// `Service::set_max_messages_per_response`.
+    pub fn set_max_messages_per_response(&mut self, max_messages: Option<usize>) -> &mut Self {
+        self.service.set_max_messages_per_response(max_messages);
+        self
+    }
+
+    /// Set the maximum size of a single received message. See `Service::set_max_single_message_size`.
+    pub fn set_max_single_message_size(&mut self, max_single_message_size: Option<usize>) -> &mut Self {
+        self.service.set_max_single_message_size(max_single_message_size);
+        self
+    }
+
+    /// Compress the bodies of publish requests with gzip. See `Service::set_compress_requests`.
+    pub fn set_compress_requests(&mut self, compress_requests: bool) -> &mut Self {
+        self.service.set_compress_requests(compress_requests);
+        self
+    }
+
+    /// Receive no messages instead of an error from a queue which does not exist. See
+    /// `Service::set_treat_missing_queue_as_empty`.
+    pub fn set_treat_missing_queue_as_empty(&mut self, treat_missing_queue_as_empty: bool) -> &mut Self {
+        self.service
+            .set_treat_missing_queue_as_empty(treat_missing_queue_as_empty);
+        self
+    }
+
+    /// Set `TCP_NODELAY` on connections to the server. See `Service::set_nodelay`.
+    pub fn set_nodelay(&mut self, nodelay: bool) -> &mut Self {
+        self.service.set_nodelay(nodelay);
+        self
+    }
+
+    /// Send TCP keep-alive probes on connections to the server. See `Service::set_tcp_keepalive`.
+    pub fn set_tcp_keepalive(&mut self, tcp_keepalive: Option<Duration>) -> &mut Self {
+        self.service.set_tcp_keepalive(tcp_keepalive);
+        self
+    }
+
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// Create a new queue. See `Service::create_queue`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub fn create_queue(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        config: &QueueConfig,
+    ) -> Result<Option<QueueConfig>, ClientError> {
+        self.block_on(self.service.create_queue(queue_name, trace_id, config))
+    }
+
+    /// Create a new queue from a raw JSON configuration. See `Service::create_queue_raw`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration is not valid JSON, the request fails, or the server
+    /// returns an invalid status.
+    pub fn create_queue_raw(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        config: &str,
+    ) -> Result<Option<QueueConfig>, ClientError> {
+        self.block_on(self.service.create_queue_raw(queue_name, trace_id, config))
+    }
+
+    /// Create a new queue together with its dead letter queue. See
+    /// `Service::create_queue_with_dlq`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub fn create_queue_with_dlq(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        config: &QueueConfig,
+        dlq_config: &QueueConfig,
+    ) -> Result<Option<QueueWithDlqOutput>, ClientError> {
+        self.block_on(
+            self.service
+                .create_queue_with_dlq(queue_name, trace_id, config, dlq_config),
+        )
+    }
+
+    /// Update the configuration of a queue. See `Service::update_queue`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails, the queue was modified since the expected version, or
+    /// the server returns an invalid status.
+    pub fn update_queue(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        expected_version: Option<&str>,
+        config: &QueueConfig,
+    ) -> Result<Option<QueueConfig>, ClientError> {
+        self.block_on(
+            self.service
+                .update_queue(queue_name, trace_id, expected_version, config),
+        )
+    }
+
+    /// Change some settings of a queue while keeping the rest of its configuration. See
+    /// `Service::modify_queue`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails, the queue was modified concurrently, or the server
+    /// returns an invalid status.
+    pub fn modify_queue<F: FnOnce(&mut QueueConfig)>(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        modify: F,
+    ) -> Result<Option<QueueConfig>, ClientError> {
+        self.block_on(self.service.modify_queue(queue_name, trace_id, modify))
+    }
+
+    /// Update the configuration of a queue from a raw JSON configuration. See
+    /// `Service::update_queue_raw`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration is not valid JSON, the request fails, or the server
+    /// returns an invalid status.
+    pub fn update_queue_raw(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        config: &str,
+    ) -> Result<Option<QueueConfig>, ClientError> {
+        self.block_on(self.service.update_queue_raw(queue_name, trace_id, config))
+    }
+
+    /// Delete a queue. See `Service::delete_queue`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub fn delete_queue(&self, queue_name: &str, trace_id: Option<Uuid>) -> Result<Option<QueueConfig>, ClientError> {
+        self.block_on(self.service.delete_queue(queue_name, trace_id))
+    }
+
+    /// Rename a queue. See `Service::rename_queue`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the queue names is invalid, a queue with the new name does already
+    /// exist, the request fails, or the server returns an invalid status.
+    pub fn rename_queue(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        new_name: &str,
+    ) -> Result<Option<QueueConfig>, ClientError> {
+        self.block_on(self.service.rename_queue(queue_name, trace_id, new_name))
+    }
+
+    /// Describe what deleting a queue would delete. See `Service::describe_queue_deletion`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub fn describe_queue_deletion(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+    ) -> Result<Option<QueueDescriptionOutput>, ClientError> {
+        self.block_on(self.service.describe_queue_deletion(queue_name, trace_id))
+    }
+
+    /// List queues. See `Service::get_queues`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub fn get_queues(
+        &self,
+        trace_id: Option<Uuid>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<Page<QueueConfigOutput>, ClientError> {
+        self.block_on(self.service.get_queues(trace_id, offset, limit))
+    }
+
+    /// List queues together with their status. See `Service::get_queues_with_status`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid response.
+    pub fn get_queues_with_status(
+        &self,
+        trace_id: Option<Uuid>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<Page<QueueDescriptionOutput>, ClientError> {
+        self.block_on(self.service.get_queues_with_status(trace_id, offset, limit))
+    }
+
+    /// List queues with names starting with the given prefix. See `Service::get_queues_matching`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the prefix contains characters not allowed in queue names, the request
+    /// fails, or the server returns an invalid status.
+    pub fn get_queues_matching(
+        &self,
+        prefix: &str,
+        trace_id: Option<Uuid>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<Page<QueueConfigOutput>, ClientError> {
+        self.block_on(self.service.get_queues_matching(prefix, trace_id, offset, limit))
+    }
+
+    /// Describe a queue. See `Service::describe_queue`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub fn describe_queue(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+    ) -> Result<Option<QueueDescriptionOutput>, ClientError> {
+        self.block_on(self.service.describe_queue(queue_name, trace_id))
+    }
+
+    /// Describe a queue, optionally without its status. See `Service::describe_queue_ext`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub fn describe_queue_ext(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        include_status: bool,
+    ) -> Result<Option<QueueDescriptionOutput>, ClientError> {
+        self.block_on(self.service.describe_queue_ext(queue_name, trace_id, include_status))
+    }
+
+    /// Get the configuration of a queue. See `Service::get_queue_config`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub fn get_queue_config(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+    ) -> Result<Option<QueueConfigOutput>, ClientError> {
+        self.block_on(self.service.get_queue_config(queue_name, trace_id))
+    }
+
+    /// Describe a queue including the percentiles of the ages of its messages. See
+    /// `Service::describe_queue_with_ages`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub fn describe_queue_with_ages(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+    ) -> Result<Option<QueueDescriptionOutput>, ClientError> {
+        self.block_on(self.service.describe_queue_with_ages(queue_name, trace_id))
+    }
+
+    /// Wait until a queue contains no more messages. See `Service::wait_until_empty`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request fails, the queue does not exist, or the server returns an
+    /// invalid response.
+    pub fn wait_until_empty(
+        &self,
+        queue_name: &str,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<bool, ClientError> {
+        self.block_on(self.service.wait_until_empty(queue_name, poll_interval, timeout))
+    }
+
+    /// Receive a single message from a queue. See `Service::get_message`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status or response.
+    pub fn get_message(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        timeout: Option<u16>,
+    ) -> Result<Option<MessageResponse>, ClientError> {
+        self.block_on(self.service.get_message(queue_name, trace_id, timeout))
+    }
+
+    /// Receive up to `limit` messages from a queue, optionally only messages with the given content
+    /// type. See `Service::get_messages`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status or response.
+    pub fn get_messages(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        limit: u16,
+        timeout: Option<u16>,
+        filter: ReceiveFilter<'_>,
+    ) -> Result<Vec<MessageResponse>, ClientError> {
+        self.block_on(self.service.get_messages(queue_name, trace_id, limit, timeout, filter))
+    }
+
+    /// Receive up to `limit` messages from a queue and delete them in the same operation. See
+    /// `Service::receive_and_delete`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status or response.
+    pub fn receive_and_delete(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        limit: u16,
+        timeout: Option<u16>,
+    ) -> Result<Vec<MessageResponse>, ClientError> {
+        self.block_on(self.service.receive_and_delete(queue_name, trace_id, limit, timeout))
+    }
+
+    /// Get the metadata of the next messages in a queue without receiving them. See
+    /// `Service::peek_metadata`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status or response.
+    pub fn peek_metadata(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        limit: u16,
+        filter: ReceiveFilter<'_>,
+    ) -> Result<Vec<MessageResponse>, ClientError> {
+        self.block_on(self.service.peek_metadata(queue_name, trace_id, limit, filter))
+    }
+
+    /// Publish a single message to a queue. See `Service::publish_message`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub fn publish_message(&self, queue_name: &str, message: PublishableMessage<'_>) -> Result<bool, ClientError> {
+        self.block_on(self.service.publish_message(queue_name, message))
+    }
+
+    /// Publish a single message to a queue and tell whether it was created or dropped as a duplicate.
+    /// See `Service::publish_message_detailed`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status or message id.
+    pub fn publish_message_detailed(
+        &self,
+        queue_name: &str,
+        message: PublishableMessage<'_>,
+    ) -> Result<PublishResult, ClientError> {
+        self.block_on(self.service.publish_message_detailed(queue_name, message))
+    }
+
+    /// Publish a single message to a queue, streaming its content. See `Service::publish_stream`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the content type is not a valid header value, the request fails, or the
+    /// server returns an invalid status.
+    pub fn publish_stream<S, O, E>(&self, queue_name: &str, content_type: &str, body: S) -> Result<bool, ClientError>
+    where
+        S: Stream<Item = Result<O, E>> + Send + 'static,
+        O: Into<Bytes> + 'static,
+        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+    {
+        self.block_on(self.service.publish_stream(queue_name, content_type, body))
+    }
+
+    /// Publish multiple messages to a queue. See `Service::publish_messages`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub fn publish_messages(&self, queue_name: &str, messages: &[PublishableMessage<'_>]) -> Result<bool, ClientError> {
+        self.block_on(self.service.publish_messages(queue_name, messages))
+    }
+
+    /// Publish multiple messages to a queue and return the outcome of each message. See
+    /// `Service::publish_messages_detailed`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub fn publish_messages_detailed(
+        &self,
+        queue_name: &str,
+        messages: &[PublishableMessage<'_>],
+    ) -> Result<Vec<PublishOutcome>, ClientError> {
+        self.block_on(self.service.publish_messages_detailed(queue_name, messages))
+    }
+
+    /// Delete a message. See `Service::delete_message`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub fn delete_message(&self, trace_id: Option<Uuid>, message_id: MessageId) -> Result<bool, ClientError> {
+        self.block_on(self.service.delete_message(trace_id, message_id))
+    }
+
+    /// Delete a message given the string representation of its id. See `Service::delete_message_str`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message id is not valid, the request fails, or the server returns an
+    /// invalid status.
+    pub fn delete_message_str(&self, trace_id: Option<Uuid>, message_id: &str) -> Result<bool, ClientError> {
+        self.block_on(self.service.delete_message_str(trace_id, message_id))
+    }
+
+    /// Delete a message by the receipt handle of a receive. See `Service::delete_message_by_receipt`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the receipt handle is not valid, the request fails, or the server returns
+    /// an invalid status.
+    pub fn delete_message_by_receipt(&self, trace_id: Option<Uuid>, receipt_handle: &str) -> Result<bool, ClientError> {
+        self.block_on(self.service.delete_message_by_receipt(trace_id, receipt_handle))
+    }
+
+    /// Release a message, making it visible again. See `Service::release_message`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub fn release_message(&self, trace_id: Option<Uuid>, message_id: MessageId) -> Result<bool, ClientError> {
+        self.block_on(self.service.release_message(trace_id, message_id))
+    }
+
+    /// Move a message to another queue. See `Service::move_message`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub fn move_message(
+        &self,
+        trace_id: Option<Uuid>,
+        message_id: MessageId,
+        target_queue: &str,
+    ) -> Result<bool, ClientError> {
+        self.block_on(self.service.move_message(trace_id, message_id, target_queue))
+    }
+
+    /// Change when several messages become visible again. See `Service::change_messages_visibility`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status or response.
+    pub fn change_messages_visibility(
+        &self,
+        trace_id: Option<Uuid>,
+        message_ids: &[&str],
+        visibility_timeout: Duration,
+    ) -> Result<Vec<bool>, ClientError> {
+        self.block_on(
+            self.service
+                .change_messages_visibility(trace_id, message_ids, visibility_timeout),
+        )
+    }
+
+    /// Delete all messages in a queue with the given content hash. See
+    /// `Service::delete_messages_by_hash`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the queue name or hash is invalid, the queue does not use content based
+    /// deduplication, the request fails, or the server returns an invalid response.
+    pub fn delete_messages_by_hash(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        hash: &str,
+    ) -> Result<Option<u64>, ClientError> {
+        self.block_on(self.service.delete_messages_by_hash(queue_name, trace_id, hash))
+    }
+
+    /// List the messages of a queue which are currently in flight. See `Service::list_in_flight`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the queue name is invalid, the request fails, or the server returns an
+    /// invalid response.
+    pub fn list_in_flight(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+    ) -> Result<Option<Vec<InFlightMessage>>, ClientError> {
+        self.block_on(self.service.list_in_flight(queue_name, trace_id))
+    }
+
+    /// Move messages from a dead letter queue to another queue. See `Service::redrive_dlq`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a queue name is invalid, the request fails, or the server returns an
+    /// invalid response.
+    pub fn redrive_dlq(
+        &self,
+        dlq_name: &str,
+        trace_id: Option<Uuid>,
+        target_queue: &str,
+        max: u64,
+    ) -> Result<Option<u64>, ClientError> {
+        self.block_on(self.service.redrive_dlq(dlq_name, trace_id, target_queue, max))
+    }
+
+    /// Send an arbitrary request and return the raw response. See `Service::send_raw`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response is larger than the configured maximum
+    /// body size.
+    pub fn send_raw(
+        &self,
+        method: Method,
+        path: &str,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<(StatusCode, HeaderMap, Vec<u8>), ClientError> {
+        self.block_on(self.service.send_raw(method, path, headers, body))
+    }
+
+    /// Evaluate the health of a service. See `Service::check_health`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails, the server returns a status different from 200, or a
+    /// response different from "green" or "red".
+    pub fn check_health(&self) -> Result<bool, ClientError> {
+        self.block_on(self.service.check_health())
+    }
+
+    /// Get the version of the server and since when it is running. See `Service::server_info`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails, the server returns a status different from 200, or an
+    /// invalid response.
+    pub fn server_info(&self) -> Result<ServerInfo, ClientError> {
+        self.block_on(self.service.server_info())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn blocking_service_without_server() {
+        let mut service = BlockingService::new("http://localhost:1").unwrap();
+        service.set_max_body_size(Some(1024));
+        assert_eq!(service.service().host, "http://localhost:1");
+        assert!(service.check_health().is_err());
+        assert!(service.server_info().is_err());
+        assert!(matches!(
+            service.get_messages("my-queue", None, 0, None, ReceiveFilter::default()),
+            Ok(messages) if messages.is_empty()
+        ));
+    }
+}
@@ -0,0 +1,189 @@
+use async_trait::async_trait;
+use mqs_common::{MessageId, QueueConfig, QueueConfigOutput};
+use uuid::Uuid;
+
+use crate::{ClientError, MessageResponse, PublishResult, PublishableMessage, ReceiveFilter, Service};
+
+/// The queue and message operations of a `Service`. Write code against this trait instead of
+/// `Service` to run it with the in-memory `MockService` of the `test-util` feature in tests.
+///
+/// ```
+/// use mqs_client::{ClientError, MqsClient, ReceiveFilter};
+///
+/// // works with a `Service` as well as with a `MockService`
+/// async fn drain<C: MqsClient>(client: &C, queue_name: &str) -> Result<usize, ClientError> {
+///     let mut drained = 0;
+///     loop {
+///         let messages = client
+///             .get_messages(queue_name, None, 10, None, ReceiveFilter::default())
+///             .await?;
+///         if messages.is_empty() {
+///             return Ok(drained);
+///         }
+///         for msg in messages {
+///             if let Some(receipt_handle) = &msg.receipt_handle {
+///                 client
+///                     .delete_message_by_receipt(msg.trace_id, receipt_handle)
+///                     .await?;
+///                 drained += 1;
+///             }
+///         }
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait MqsClient: Send + Sync {
+    /// Create a new queue. See `Service::create_queue`.
+    async fn create_queue(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        config: &QueueConfig,
+    ) -> Result<Option<QueueConfig>, ClientError>;
+
+    /// Delete a queue and all its messages. See `Service::delete_queue`.
+    async fn delete_queue(&self, queue_name: &str, trace_id: Option<Uuid>) -> Result<Option<QueueConfig>, ClientError>;
+
+    /// Get the configuration of a queue. See `Service::get_queue_config`.
+    async fn get_queue_config(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+    ) -> Result<Option<QueueConfigOutput>, ClientError>;
+
+    /// Publish a single message. See `Service::publish_message`.
+    async fn publish_message(&self, queue_name: &str, message: PublishableMessage<'_>) -> Result<bool, ClientError>;
+
+    /// Publish a single message and tell whether it was deduplicated. See
+    /// `Service::publish_message_detailed`.
+    async fn publish_message_detailed(
+        &self,
+        queue_name: &str,
+        message: PublishableMessage<'_>,
+    ) -> Result<PublishResult, ClientError>;
+
+    /// Publish multiple messages. See `Service::publish_messages`.
+    async fn publish_messages(
+        &self,
+        queue_name: &str,
+        messages: &[PublishableMessage<'_>],
+    ) -> Result<bool, ClientError>;
+
+    /// Receive a single message. See `Service::get_message`.
+    async fn get_message(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        timeout: Option<u16>,
+    ) -> Result<Option<MessageResponse>, ClientError>;
+
+    /// Receive up to `limit` messages. See `Service::get_messages`.
+    async fn get_messages(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        limit: u16,
+        timeout: Option<u16>,
+        filter: ReceiveFilter<'_>,
+    ) -> Result<Vec<MessageResponse>, ClientError>;
+
+    /// Delete a message by its id. See `Service::delete_message`.
+    async fn delete_message(&self, trace_id: Option<Uuid>, message_id: MessageId) -> Result<bool, ClientError>;
+
+    /// Delete a message by the receipt handle of a receive. See `Service::delete_message_by_receipt`.
+    async fn delete_message_by_receipt(
+        &self,
+        trace_id: Option<Uuid>,
+        receipt_handle: &str,
+    ) -> Result<bool, ClientError>;
+
+    /// Make a received message visible again right away. See `Service::release_message`.
+    async fn release_message(&self, trace_id: Option<Uuid>, message_id: MessageId) -> Result<bool, ClientError>;
+
+    /// Check whether the server is healthy. See `Service::check_health`.
+    async fn check_health(&self) -> Result<bool, ClientError>;
+}
+
+#[async_trait]
+impl MqsClient for Service {
+    async fn create_queue(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        config: &QueueConfig,
+    ) -> Result<Option<QueueConfig>, ClientError> {
+        Self::create_queue(self, queue_name, trace_id, config).await
+    }
+
+    async fn delete_queue(&self, queue_name: &str, trace_id: Option<Uuid>) -> Result<Option<QueueConfig>, ClientError> {
+        Self::delete_queue(self, queue_name, trace_id).await
+    }
+
+    async fn get_queue_config(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+    ) -> Result<Option<QueueConfigOutput>, ClientError> {
+        Self::get_queue_config(self, queue_name, trace_id).await
+    }
+
+    async fn publish_message(&self, queue_name: &str, message: PublishableMessage<'_>) -> Result<bool, ClientError> {
+        Self::publish_message(self, queue_name, message).await
+    }
+
+    async fn publish_message_detailed(
+        &self,
+        queue_name: &str,
+        message: PublishableMessage<'_>,
+    ) -> Result<PublishResult, ClientError> {
+        Self::publish_message_detailed(self, queue_name, message).await
+    }
+
+    async fn publish_messages(
+        &self,
+        queue_name: &str,
+        messages: &[PublishableMessage<'_>],
+    ) -> Result<bool, ClientError> {
+        Self::publish_messages(self, queue_name, messages).await
+    }
+
+    async fn get_message(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        timeout: Option<u16>,
+    ) -> Result<Option<MessageResponse>, ClientError> {
+        Self::get_message(self, queue_name, trace_id, timeout).await
+    }
+
+    async fn get_messages(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        limit: u16,
+        timeout: Option<u16>,
+        filter: ReceiveFilter<'_>,
+    ) -> Result<Vec<MessageResponse>, ClientError> {
+        Self::get_messages(self, queue_name, trace_id, limit, timeout, filter).await
+    }
+
+    async fn delete_message(&self, trace_id: Option<Uuid>, message_id: MessageId) -> Result<bool, ClientError> {
+        Self::delete_message(self, trace_id, message_id).await
+    }
+
+    async fn delete_message_by_receipt(
+        &self,
+        trace_id: Option<Uuid>,
+        receipt_handle: &str,
+    ) -> Result<bool, ClientError> {
+        Self::delete_message_by_receipt(self, trace_id, receipt_handle).await
+    }
+
+    async fn release_message(&self, trace_id: Option<Uuid>, message_id: MessageId) -> Result<bool, ClientError> {
+        Self::release_message(self, trace_id, message_id).await
+    }
+
+    async fn check_health(&self) -> Result<bool, ClientError> {
+        Self::check_health(self).await
+    }
+}
@@ -28,43 +28,110 @@
 //! use mqs_client::Service;
 //! use tokio::runtime::Builder;
 //!
-//! let service = Service::new("https://mqs.example.com:7843");
+//! let service = Service::new("http://mqs.example.com:7843");
 //!
 //! let mut rt = Builder::new_multi_thread().enable_all().build().unwrap();
 //! let success = rt.block_on(async { service.check_health().await });
 //! assert!(!success.is_ok());
 //! ```
 
+#[macro_use]
+extern crate log;
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod client;
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock;
+
+pub use client::MqsClient;
+
+use flate2::{write::GzEncoder, Compression};
+use futures_core::Stream;
 use hyper::{
+    body::Bytes,
     client::{Client, HttpConnector},
-    header::{HeaderName, HeaderValue, CONNECTION, CONTENT_ENCODING, CONTENT_TYPE},
+    header::{
+        HeaderName,
+        HeaderValue,
+        ACCEPT,
+        CONNECTION,
+        CONTENT_ENCODING,
+        CONTENT_LENGTH,
+        CONTENT_TYPE,
+        DATE,
+        IF_MATCH,
+        RETRY_AFTER,
+        TRANSFER_ENCODING,
+    },
+    http::uri::Scheme,
     Body,
     HeaderMap,
     Method,
     Request,
     Response,
     StatusCode,
+    Uri,
 };
 use mqs_common::{
+    is_valid_queue_name,
     multipart,
     read_body,
+    ChangeVisibilityRequest,
+    ChangeVisibilityResponse,
+    ContentHashHeader,
+    DeduplicatedHeader,
+    DeleteByHashResponse,
+    ExpiresAtHeader,
+    FirstReceivedAtHeader,
+    GroupIdHeader,
+    IdempotencyKeyHeader,
+    InFlightMessage,
+    InFlightMessagesResponse,
+    InvalidMessageId,
+    LastReceivedAtHeader,
+    MessageAttributesHeader,
+    MessageContentEncodingHeader,
+    MessageId,
     MessageIdHeader,
     MessageReceivesHeader,
+    MoveMessageRequest,
+    OriginQueueHeader,
+    Page,
+    PriorityHeader,
+    PublishOutcome,
+    PublishRejection,
+    PublishRejectionResponse,
+    PublishResponse,
     PublishedAtHeader,
     QueueConfig,
+    QueueConfigOutput,
     QueueDescriptionOutput,
+    QueueWithDlqConfig,
+    QueueWithDlqOutput,
     QueuesResponse,
+    QueuesWithStatusResponse,
+    ReceiptHandleHeader,
+    RedriveReasonHeader,
+    RedriveRequest,
+    RedriveResponse,
+    RenameQueueRequest,
+    ServerInfo,
     Status::ServiceUnavailable,
     TraceIdHeader,
     UtcTime,
     VisibleAtHeader,
     DEFAULT_CONTENT_TYPE,
 };
-use serde::{de::DeserializeOwned, Serialize};
+use serde::de::{DeserializeOwned, IgnoredAny};
 use std::{
     error::Error,
-    fmt::{Display, Formatter},
+    fmt::{Debug, Display, Formatter},
+    io::Write,
+    time::{Duration, SystemTime},
 };
+use tokio::time::{sleep, Instant};
+use tokio_util::sync::{CancellationToken, DropGuard};
 use uuid::Uuid;
 
 /// If something goes wrong, we return an instance of `ClientError` to tell you what exactly failed
@@ -87,8 +154,39 @@ pub enum ClientError {
     ServiceError(u16),
     /// The response returned by the server was larger than what the client was configured to accept.
     TooLargeResponse,
-    /// The server returned an invalid health check response.
-    HealthCheckError,
+    /// The server returned an invalid health check response. Contains the (possibly truncated)
+    /// response body.
+    HealthCheckError(String),
+    /// The queue does not accept messages with the content type of the published message.
+    UnsupportedContentType,
+    /// The server rejected a publish request, e.g. because a message was too large or had an invalid
+    /// expiry time. None of the messages of the request were published.
+    PublishRejected {
+        /// Why the request was rejected.
+        reason:  PublishRejection,
+        /// Description of the problem returned by the server.
+        message: String,
+    },
+    /// The queue was modified since the version expected by an update was read.
+    VersionMismatch,
+    /// The given queue name is not valid, see `mqs_common::is_valid_queue_name`.
+    InvalidQueueName,
+    /// A queue could not be renamed because a queue with the new name does already exist.
+    QueueAlreadyExists,
+    /// The server returned more messages than the client was configured to accept in a single response.
+    TooManyMessages,
+    /// A single message returned by the server was larger than what the client was configured to
+    /// accept, even though the whole response was small enough.
+    TooLargeMessage,
+    /// A message id was not valid, see `mqs_common::MessageId`.
+    InvalidMessageId(InvalidMessageId),
+    /// The host of a service is not an http URL or contains a query. The client does not support TLS,
+    /// so https URLs are rejected as well.
+    InvalidHost,
+    /// A receipt handle contained characters which never appear in a handle issued by the server.
+    InvalidReceiptHandle,
+    /// The operation was cancelled before it completed.
+    Cancelled,
 }
 
 impl Display for ClientError {
@@ -97,7 +195,32 @@ impl Display for ClientError {
     }
 }
 
-impl Error for ClientError {}
+impl Error for ClientError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::HyperError(error) => Some(error),
+            Self::InvalidUri(error) => Some(error),
+            Self::IoError(error) => Some(error),
+            Self::ParseError(error) => Some(error),
+            Self::InvalidHeaderValue(error) => Some(error),
+            Self::MultipartParseError(error) => Some(error),
+            Self::InvalidMessageId(error) => Some(error),
+            Self::ServiceError(_)
+            | Self::TooLargeResponse
+            | Self::HealthCheckError(_)
+            | Self::UnsupportedContentType
+            | Self::PublishRejected { .. }
+            | Self::VersionMismatch
+            | Self::InvalidQueueName
+            | Self::QueueAlreadyExists
+            | Self::TooManyMessages
+            | Self::TooLargeMessage
+            | Self::InvalidHost
+            | Self::InvalidReceiptHandle
+            | Self::Cancelled => None,
+        }
+    }
+}
 
 impl From<hyper::Error> for ClientError {
     fn from(error: hyper::Error) -> Self {
@@ -135,23 +258,99 @@ impl From<multipart::InvalidMultipart> for ClientError {
     }
 }
 
+impl From<InvalidMessageId> for ClientError {
+    fn from(error: InvalidMessageId) -> Self {
+        Self::InvalidMessageId(error)
+    }
+}
+
+/// Longest time to wait before retrying a request, regardless of what the server asks for.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Get how long the server asked us to wait before retrying a request. The `Retry-After` header can
+/// either contain a number of seconds or a date. The result is capped at `MAX_RETRY_AFTER`, a date
+/// in the past results in no delay.
+fn retry_after(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    let delay = if let Ok(seconds) = value.parse() {
+        Duration::from_secs(seconds)
+    } else {
+        let date = httpdate::parse_http_date(value).ok()?;
+        date.duration_since(now).unwrap_or(Duration::ZERO)
+    };
+
+    Some(delay.min(MAX_RETRY_AFTER))
+}
+
 /// A `Service` allows you to speak to a single mqs server.
+///
+/// Cloning a service is cheap as all clones share the same connection pool, so you can pass a
+/// clone to every task instead of wrapping the service in an `Arc`.
+///
+/// Requests are retried as long as the server responds with 503 Service Unavailable. If the
+/// response carries a `Retry-After` header, the service waits as long as requested, but at most
+/// one minute, before sending the request again. Publish requests are also repeated a few times if
+/// sending them fails, e.g. because the connection was closed before the response arrived.
+#[derive(Clone)]
 pub struct Service {
-    client:        Client<HttpConnector>,
-    host:          String,
-    max_body_size: Option<usize>,
+    client:                       Client<HttpConnector>,
+    host:                         String,
+    max_body_size:                Option<usize>,
+    max_messages_per_response:    Option<usize>,
+    max_single_message_size:      Option<usize>,
+    compress_requests:            bool,
+    treat_missing_queue_as_empty: bool,
+    nodelay:                      bool,
+    tcp_keepalive:                Option<Duration>,
+}
+
+impl Debug for Service {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Service")
+            .field("host", &self.host)
+            .field("max_body_size", &self.max_body_size)
+            .field("max_messages_per_response", &self.max_messages_per_response)
+            .field("max_single_message_size", &self.max_single_message_size)
+            .field("compress_requests", &self.compress_requests)
+            .field("treat_missing_queue_as_empty", &self.treat_missing_queue_as_empty)
+            .field("nodelay", &self.nodelay)
+            .field("tcp_keepalive", &self.tcp_keepalive)
+            .finish_non_exhaustive()
+    }
 }
 
 /// A `PublishableMessage` contains all information a message can contain.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct PublishableMessage<'a> {
-    /// Content type of the message.
+    /// Content type of the message. If empty, no content type is sent and the server uses the
+    /// default content type of the queue.
     pub content_type:     &'a str,
-    /// Content encoding of the message.
+    /// Content encoding of the message. This describes the stored message and is independent of
+    /// the transport compression configured with `Service::set_compress_requests`.
     pub content_encoding: Option<&'a str>,
     /// Trace id of the message. You can use this to attach a unique identifier to a request and
     /// later recover this identifier upon message consumption.
     pub trace_id:         Option<Uuid>,
+    /// Trace id of the message in a format other than a uuid, e.g. a W3C traceparent. Sent instead
+    /// of `trace_id` if set.
+    pub raw_trace_id:     Option<&'a str>,
+    /// Group of the message. Messages of the same group are received in the order they were
+    /// published, a message is only received after all older messages of its group were deleted.
+    pub group_id:         Option<&'a str>,
+    /// Time after which the message is dropped instead of being delivered. Must not be later than
+    /// the retention timeout of the queue allows.
+    pub expires_at:       Option<UtcTime>,
+    /// Time the message was originally published at, e.g. when replaying historical events. If not
+    /// set, the server uses the current time. Must not be in the future or older than the retention
+    /// timeout of the queue allows. Received messages report this time as `published_at`, but the
+    /// queue still orders and retains the message by the time it reached the server.
+    pub published_at:     Option<UtcTime>,
+    /// Priority of the message. Queues with message priorities deliver messages with a higher
+    /// priority first, other queues ignore the priority. Messages without a priority have priority 0.
+    pub priority:         Option<i32>,
+    /// Structured attributes of the message. Must be a JSON object, the server rejects the message
+    /// otherwise.
+    pub attributes:       Option<serde_json::Value>,
     /// Encoded body of the message.
     pub message:          Vec<u8>,
 }
@@ -160,66 +359,250 @@ impl<'a> PublishableMessage<'a> {
     fn encode(self) -> (HeaderMap, Vec<u8>) {
         let mut headers = HeaderMap::new();
 
-        if let Ok(content_type) = HeaderValue::from_str(self.content_type) {
-            headers.insert(CONTENT_TYPE, content_type);
+        if !self.content_type.is_empty() {
+            if let Ok(content_type) = HeaderValue::from_str(self.content_type) {
+                headers.insert(CONTENT_TYPE, content_type);
+            }
         }
 
         if let Some(content_encoding) = self.content_encoding {
             if let Ok(content_encoding) = HeaderValue::from_str(content_encoding) {
-                headers.insert(CONTENT_ENCODING, content_encoding);
+                headers.insert(MessageContentEncodingHeader::name(), content_encoding);
             }
         }
 
-        if let Some(trace_id) = self.trace_id {
+        if let Some(raw_trace_id) = self.raw_trace_id {
+            if let Ok(trace_id) = HeaderValue::from_str(raw_trace_id) {
+                headers.insert(TraceIdHeader::name(), trace_id);
+            }
+        } else if let Some(trace_id) = self.trace_id {
             if let Ok(trace_id) = HeaderValue::from_str(&trace_id.to_string()) {
                 headers.insert(TraceIdHeader::name(), trace_id);
             }
         }
 
+        if let Some(group_id) = self.group_id {
+            if let Ok(group_id) = HeaderValue::from_str(group_id) {
+                headers.insert(GroupIdHeader::name(), group_id);
+            }
+        }
+
+        if let Some(expires_at) = self.expires_at {
+            if let Ok(expires_at) = HeaderValue::from_str(&expires_at.to_rfc3339()) {
+                headers.insert(ExpiresAtHeader::name(), expires_at);
+            }
+        }
+
+        if let Some(published_at) = self.published_at {
+            if let Ok(published_at) = HeaderValue::from_str(&published_at.to_rfc3339()) {
+                headers.insert(PublishedAtHeader::name(), published_at);
+            }
+        }
+
+        if let Some(priority) = self.priority {
+            headers.insert(PriorityHeader::name(), HeaderValue::from(priority));
+        }
+
+        if let Some(attributes) = &self.attributes {
+            if let Ok(attributes) = HeaderValue::from_str(&MessageAttributesHeader::encode(attributes)) {
+                headers.insert(MessageAttributesHeader::name(), attributes);
+            }
+        }
+
         (headers, self.message)
     }
 }
 
 /// A `MessageResponse` contains the same information as a `PublishableMessage` plus the id of the message.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct MessageResponse {
     /// Id of the message. Needed to later delete the message so it will not be received again later.
-    pub message_id:       String,
+    pub message_id:        MessageId,
+    /// Receipt handle of this receive of the message. Can be used instead of the message id to
+    /// delete the message or change its visibility until the message is received again. `None` if
+    /// the message was not received, e.g. because it was only peeked at or deleted on receive.
+    pub receipt_handle:    Option<String>,
     /// Content type of the message.
-    pub content_type:     String,
+    pub content_type:      String,
     /// Content encoding of the message.
-    pub content_encoding: Option<String>,
+    pub content_encoding:  Option<String>,
     /// Number of times this message was already received.
-    pub message_receives: i32,
+    pub message_receives:  i32,
     /// Timestamp of the message being published.
-    pub published_at:     UtcTime,
+    pub published_at:      UtcTime,
     /// Timestamp of the next time the message will be visible again.
-    pub visible_at:       UtcTime,
-    /// Trace id of the message.
-    pub trace_id:         Option<Uuid>,
+    pub visible_at:        UtcTime,
+    /// Timestamp of the first time the message was received.
+    pub first_received_at: Option<UtcTime>,
+    /// Timestamp of the most recent time the message was received.
+    pub last_received_at:  Option<UtcTime>,
+    /// Trace id of the message. `None` if the message has no trace id or it is not a uuid.
+    pub trace_id:          Option<Uuid>,
+    /// Trace id of the message as it was published, even if it is not a uuid.
+    pub raw_trace_id:      Option<String>,
+    /// Group of the message.
+    pub group_id:          Option<String>,
+    /// Timestamp after which the message expires, if the publisher set one.
+    pub expires_at:        Option<UtcTime>,
+    /// Queue the message was in before it was moved to the queue it was received from.
+    pub origin_queue:      Option<String>,
+    /// Why the message was moved to the queue it was received from, `max-receives` if it was moved
+    /// to a dead letter queue or `moved` if a client moved it.
+    pub redrive_reason:    Option<String>,
+    /// Structured attributes of the message as published in `PublishableMessage::attributes`.
+    pub attributes:        Option<serde_json::Value>,
+    /// Priority of the message as published in `PublishableMessage::priority`, 0 if none was set.
+    pub priority:          i32,
+    /// Hash of the message content computed by the server. Only set if the queue of the message
+    /// uses content based deduplication.
+    pub content_hash:      Option<String>,
+    /// All other headers of the message, for example custom attributes set by the publisher in
+    /// `x-mqs-attr-*` headers.
+    pub headers:           HeaderMap,
     /// Encoded body of the message.
-    pub content:          Vec<u8>,
+    pub content:           Vec<u8>,
+}
+
+/// Outcome of publishing a single message with `Service::publish_message_detailed`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PublishResult {
+    /// A new message was created with the given id.
+    Created(MessageId),
+    /// The queue uses content based deduplication and already contains the same message, so the
+    /// message was dropped.
+    Deduplicated,
+}
+
+/// Restricts which messages are received. Messages which do not pass the filter stay visible for
+/// other consumers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReceiveFilter<'a> {
+    /// Only receive messages with exactly this content type.
+    pub content_type: Option<&'a str>,
+    /// Only receive messages the server accepted at or after this time, e.g. when resuming from a
+    /// checkpoint. The publish time given by a publisher is not taken into account. Messages of a
+    /// group accepted earlier still have to be deleted before newer messages of the same group are
+    /// received.
+    pub since:        Option<UtcTime>,
+}
+
+/// Handle of the background task started by `Service::spawn_keep_alive`. The task stops once the
+/// handle is dropped.
+#[derive(Debug)]
+pub struct KeepAlive {
+    _guard: DropGuard,
+}
+
+/// Check that a host is an http URL without a query and remove trailing slashes from it. The client
+/// only has a plain http connector, so an https URL would fail on every request.
+fn normalize_host(host: &str) -> Result<String, ClientError> {
+    let uri: Uri = host.parse()?;
+    let scheme = uri.scheme().ok_or(ClientError::InvalidHost)?;
+    if scheme != &Scheme::HTTP {
+        return Err(ClientError::InvalidHost);
+    }
+    let authority = uri.authority().ok_or(ClientError::InvalidHost)?;
+    if uri.query().is_some() {
+        return Err(ClientError::InvalidHost);
+    }
+
+    Ok(format!(
+        "{}://{}{}",
+        scheme,
+        authority,
+        uri.path().trim_end_matches('/')
+    ))
+}
+
+/// How `receive_messages` reads messages from a queue.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ReceiveMode {
+    Receive,
+    AutoDelete,
+    HeadersOnly,
+}
+
+/// Generate a new key sent with a publish request. All retries of the request send the same key.
+fn new_idempotency_key() -> Result<HeaderValue, hyper::header::InvalidHeaderValue> {
+    HeaderValue::from_str(&Uuid::new_v4().to_string())
 }
 
 impl Service {
     const DEFAULT_MAX_BODY_SIZE: usize = 5 * 1024 * 1024;
+    const DEFAULT_MAX_MESSAGES_FACTOR: usize = 4;
+    const MAX_HEALTH_CHECK_ERROR_LENGTH: usize = 256;
+    /// Number of times a publish request is repeated with the same idempotency key if sending it
+    /// fails.
+    const MAX_PUBLISH_RETRIES: u32 = 3;
+    const TRANSPORT_RETRY_DELAY: Duration = Duration::from_millis(100);
 
-    /// Create a new instance.
+    /// Create a new instance. The host is normalized like with `try_new`. If it is not a valid URL,
+    /// a warning is logged and the host is used as given, only stripping trailing slashes.
     ///
     /// ```
     /// use mqs_client::Service;
     ///
-    /// let _service = Service::new("https://mqs.example.com:7843");
+    /// let _service = Service::new("http://mqs.example.com:7843");
     /// ```
     #[must_use]
     pub fn new(host: &str) -> Self {
+        Self::try_new(host).unwrap_or_else(|err| {
+            warn!("Using invalid host {} for mqs service: {}", host, err);
+            Self::with_host(host.trim_end_matches('/').to_string())
+        })
+    }
+
+    /// Create a new instance if the host is a valid http URL. The URL may contain a path
+    /// prefix to reach a server behind a proxy, but no query. Trailing slashes are removed, so they
+    /// don't lead to doubled slashes in the URLs of requests.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    ///
+    /// let service = Service::try_new("http://mqs.example.com:7843/").unwrap();
+    /// assert_eq!(service.host(), "http://mqs.example.com:7843");
+    /// assert!(matches!(
+    ///     Service::try_new("mqs.example.com:7843"),
+    ///     Err(ClientError::InvalidHost)
+    /// ));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the host can not be parsed as a URL, is missing a scheme or authority, uses
+    /// a scheme other than http (the client does not support TLS), or contains a query.
+    pub fn try_new(host: &str) -> Result<Self, ClientError> {
+        Ok(Self::with_host(normalize_host(host)?))
+    }
+
+    fn with_host(host: String) -> Self {
         Self {
-            client:        Client::new(),
-            host:          host.to_string(),
+            client: Self::build_client(true, None),
+            host,
             max_body_size: Some(Self::DEFAULT_MAX_BODY_SIZE),
+            max_messages_per_response: None,
+            max_single_message_size: None,
+            compress_requests: false,
+            treat_missing_queue_as_empty: false,
+            nodelay: true,
+            tcp_keepalive: None,
         }
     }
 
+    fn build_client(nodelay: bool, tcp_keepalive: Option<Duration>) -> Client<HttpConnector> {
+        let mut connector = HttpConnector::new();
+        connector.set_nodelay(nodelay);
+        connector.set_keepalive(tcp_keepalive);
+
+        Client::builder().build(connector)
+    }
+
+    /// Get the normalized host this service sends requests to.
+    #[must_use]
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
     /// Configure the maximum body size we are prepared to accept. Should the server return a bigger
     /// response, we return an error and drop the response instead of reading the whole response into
     /// memory.
@@ -227,7 +610,7 @@ impl Service {
     /// ```
     /// use mqs_client::Service;
     ///
-    /// let mut service = Service::new("https://mqs.example.com:7843");
+    /// let mut service = Service::new("http://mqs.example.com:7843");
     /// // allow at most 64 KiB
     /// service.set_max_body_size(Some(1024 * 64));
     /// // allow unlimited responses
@@ -238,6 +621,125 @@ impl Service {
         self
     }
 
+    /// Configure the maximum number of messages we are prepared to accept in a single response.
+    /// Should the server return more messages, `get_messages` returns `TooManyMessages` instead of
+    /// allocating all of them. If no maximum is configured, a response may contain up to four times
+    /// the number of messages requested.
+    ///
+    /// ```
+    /// use mqs_client::Service;
+    ///
+    /// let mut service = Service::new("http://mqs.example.com:7843");
+    /// // accept at most 100 messages per response
+    /// service.set_max_messages_per_response(Some(100));
+    /// // accept a multiple of the requested number of messages
+    /// service.set_max_messages_per_response(None);
+    /// ```
+    pub fn set_max_messages_per_response(&mut self, max_messages: Option<usize>) -> &mut Self {
+        self.max_messages_per_response = max_messages;
+        self
+    }
+
+    /// Configure the maximum size of a single message we are prepared to accept when receiving several
+    /// messages at once. Should any message of a response be bigger, `get_messages` returns
+    /// `TooLargeMessage`, even if the whole response is within the maximum body size. If no maximum
+    /// is configured, the maximum body size applies to every message as well.
+    ///
+    /// ```
+    /// use mqs_client::Service;
+    ///
+    /// let mut service = Service::new("http://mqs.example.com:7843");
+    /// service.set_max_body_size(Some(1024 * 1024));
+    /// // but no single message may be larger than 64 KiB
+    /// service.set_max_single_message_size(Some(1024 * 64));
+    /// ```
+    pub fn set_max_single_message_size(&mut self, max_single_message_size: Option<usize>) -> &mut Self {
+        self.max_single_message_size = max_single_message_size;
+        self
+    }
+
+    /// Configure whether the bodies of publish requests are compressed with gzip before they are
+    /// sent to the server. The server inflates them again before storing the messages, so this only
+    /// affects the transport and not the content encoding of the published messages.
+    ///
+    /// ```
+    /// use mqs_client::Service;
+    ///
+    /// let mut service = Service::new("http://mqs.example.com:7843");
+    /// service.set_compress_requests(true);
+    /// ```
+    pub fn set_compress_requests(&mut self, compress_requests: bool) -> &mut Self {
+        self.compress_requests = compress_requests;
+        self
+    }
+
+    /// Configure whether receiving messages from a queue which does not exist returns no messages
+    /// instead of `ServiceError(404)`. This lets a consumer drain gracefully when its queue is
+    /// deleted, for example while moving to a queue with a new name. By default, the error is
+    /// returned.
+    ///
+    /// ```
+    /// use mqs_client::Service;
+    ///
+    /// let mut service = Service::new("http://mqs.example.com:7843");
+    /// service.set_treat_missing_queue_as_empty(true);
+    /// ```
+    pub fn set_treat_missing_queue_as_empty(&mut self, treat_missing_queue_as_empty: bool) -> &mut Self {
+        self.treat_missing_queue_as_empty = treat_missing_queue_as_empty;
+        self
+    }
+
+    /// Configure whether `TCP_NODELAY` is set on connections to the server. It is set by default,
+    /// which disables Nagle's algorithm. Against a server on the same host, the benchmark in
+    /// `mqs-bench` runs equally fast with and without it (run it with `MQS_NODELAY=false` to
+    /// compare), so only change this if measurements on your network show a difference.
+    ///
+    /// Changing socket options creates a new connection pool for this service. Open connections are
+    /// not reused and clones of the service made before keep their options.
+    ///
+    /// ```
+    /// use mqs_client::Service;
+    ///
+    /// let mut service = Service::new("http://mqs.example.com:7843");
+    /// // send fewer, fuller packets instead
+    /// service.set_nodelay(false);
+    /// ```
+    pub fn set_nodelay(&mut self, nodelay: bool) -> &mut Self {
+        self.nodelay = nodelay;
+        self.client = Self::build_client(self.nodelay, self.tcp_keepalive);
+        self
+    }
+
+    /// Configure the interval of TCP keep-alive probes on connections to the server, e.g. to detect
+    /// connections dropped by a firewall while a consumer waits for new messages. By default, no
+    /// keep-alive probes are sent. Like `set_nodelay`, this creates a new connection pool.
+    ///
+    /// ```
+    /// use mqs_client::Service;
+    /// use std::time::Duration;
+    ///
+    /// let mut service = Service::new("http://mqs.example.com:7843");
+    /// service.set_tcp_keepalive(Some(Duration::from_secs(60)));
+    /// ```
+    pub fn set_tcp_keepalive(&mut self, tcp_keepalive: Option<Duration>) -> &mut Self {
+        self.tcp_keepalive = tcp_keepalive;
+        self.client = Self::build_client(self.nodelay, self.tcp_keepalive);
+        self
+    }
+
+    /// Compress the body of a publish request if configured to do so. Returns the body to send and
+    /// the value of the `Content-Encoding` header of the request, if any.
+    fn encode_request_body(&self, body: Vec<u8>) -> Result<(Vec<u8>, Option<HeaderValue>), std::io::Error> {
+        if !self.compress_requests {
+            return Ok((body, None));
+        }
+
+        let mut encoder = GzEncoder::new(Vec::with_capacity(body.len() / 2), Compression::default());
+        encoder.write_all(&body)?;
+
+        Ok((encoder.finish()?, Some(HeaderValue::from_static("gzip"))))
+    }
+
     fn new_request(
         method: Method,
         uri: &str,
@@ -282,27 +784,68 @@ impl Service {
         &self,
         builder: F,
     ) -> Result<Response<Body>, ClientError>
+    where
+        ClientError: From<E>,
+    {
+        self.request_with_retries(builder, 0).await
+    }
+
+    /// Send a request like `request`, but also repeat it up to `retries` times if sending it fails.
+    /// Only requests which can safely be repeated, like publish requests with an idempotency key, may
+    /// be retried this way, the server might have processed the failed request after all.
+    async fn request_with_retries<E: Send, F: Sync + Send + Fn() -> Result<Request<Body>, E>>(
+        &self,
+        builder: F,
+        mut retries: u32,
+    ) -> Result<Response<Body>, ClientError>
     where
         ClientError: From<E>,
     {
         loop {
-            let res = self.client.request(builder()?).await?;
+            let req = builder()?;
+            #[cfg(feature = "tracing")]
+            let span = tracing::info_span!(
+                "mqs_client_request",
+                method = %req.method(),
+                uri = %req.uri(),
+                trace_id = TraceIdHeader::get_raw(req.headers()).unwrap_or_default(),
+            );
+            let res = self.client.request(req);
+            #[cfg(feature = "tracing")]
+            let res = tracing::Instrument::instrument(res, span);
+            let res = match res.await {
+                Ok(res) => res,
+                Err(err) if retries > 0 => {
+                    retries -= 1;
+                    debug!(
+                        "Request failed, retrying after {:?}: {}",
+                        Self::TRANSPORT_RETRY_DELAY,
+                        err
+                    );
+                    sleep(Self::TRANSPORT_RETRY_DELAY).await;
+                    continue;
+                },
+                Err(err) => return Err(err.into()),
+            };
             if res.status() != StatusCode::from(ServiceUnavailable) {
                 return Ok(res);
             }
+            if let Some(delay) = retry_after(res.headers(), SystemTime::now()) {
+                debug!("Server is unavailable, retrying after {:?}", delay);
+                sleep(delay).await;
+            }
         }
     }
 
-    async fn json_request<T: Serialize + Sync>(
+    async fn json_request(
         &self,
         method: Method,
         uri: &str,
         trace_id: Option<Uuid>,
-        request: &T,
+        body: &[u8],
     ) -> Result<Response<Body>, ClientError> {
         self.request(|| {
-            let message = serde_json::to_string(request)?;
-            let mut req = Self::new_request(method.clone(), uri, trace_id, Body::from(message))?;
+            let mut req = Self::new_request(method.clone(), uri, trace_id, Body::from(body.to_vec()))?;
             req.headers_mut()
                 .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
             Ok::<_, ClientError>(req)
@@ -319,6 +862,7 @@ impl Service {
     /// // create a new queue named "new-queue". The queue will not delay new messages,
     /// // will hide messages for 30 seconds after they are received, delete messages older
     /// // than 1 hour and send messages to the queue "my-queue-dead" after 3 receives.
+    /// // The queue is tagged with the team owning it.
     /// async fn example(service: &Service) -> Result<Option<QueueConfig>, ClientError> {
     ///     service
     ///         .create_queue("new-queue", None, &QueueConfig {
@@ -330,6 +874,15 @@ impl Service {
     ///             visibility_timeout:    30,
     ///             message_delay:         0,
     ///             message_deduplication: true,
+    ///             allowed_content_types: Vec::new(),
+    ///             default_content_type:  None,
+    ///             max_in_flight:         None,
+    ///             max_redrives:          None,
+    ///             visibility_jitter:     None,
+    ///             message_priorities:    false,
+    ///             tags:                  vec![("team".to_string(), "billing".to_string())]
+    ///                 .into_iter()
+    ///                 .collect(),
     ///         })
     ///         .await
     /// }
@@ -337,30 +890,122 @@ impl Service {
     ///
     /// # Errors
     ///
-    /// Returns an error if the request fails or the server returns an invalid response.
+    /// Returns an error if the queue name is invalid, the request fails, or the server returns an
+    /// invalid response.
     pub async fn create_queue(
         &self,
         queue_name: &str,
         trace_id: Option<Uuid>,
         config: &QueueConfig,
     ) -> Result<Option<QueueConfig>, ClientError> {
+        let body = serde_json::to_vec(config)?;
+        self.create_queue_body(queue_name, trace_id, &body).await
+    }
+
+    /// Create a new queue with the given name and a configuration which is already serialized as
+    /// JSON, for example because it was read from a file. The configuration is only checked to be
+    /// valid JSON before it is send to the server.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    /// use mqs_common::QueueConfig;
+    ///
+    /// async fn example(service: &Service, config: &str) -> Result<Option<QueueConfig>, ClientError> {
+    ///     service.create_queue_raw("new-queue", None, config).await
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the queue name is invalid, the configuration is not valid JSON, the
+    /// request fails or the server returns an invalid response.
+    pub async fn create_queue_raw(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        config: &str,
+    ) -> Result<Option<QueueConfig>, ClientError> {
+        serde_json::from_str::<IgnoredAny>(config)?;
+        self.create_queue_body(queue_name, trace_id, config.as_bytes()).await
+    }
+
+    async fn create_queue_body(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        body: &[u8],
+    ) -> Result<Option<QueueConfig>, ClientError> {
+        if !is_valid_queue_name(queue_name) {
+            return Err(ClientError::InvalidQueueName);
+        }
         let uri = format!("{}/queues/{}", &self.host, queue_name);
-        let response = self.json_request(Method::PUT, &uri, trace_id, config).await?;
+        let response = self.json_request(Method::PUT, &uri, trace_id, body).await?;
+        self.parse_response_maybe(response, 201, 409).await
+    }
+
+    /// Create a new queue together with its dead letter queue. The redrive policy of `config` names
+    /// the dead letter queue, which is created with `dlq_config`. The server creates both queues in
+    /// a single transaction, so either both or none of them exist afterwards. If one of the queues
+    /// does already exist, `None` is returned.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    /// use mqs_common::{QueueConfig, QueueWithDlqOutput};
+    ///
+    /// // create a new queue named "new-queue" sending messages to "new-queue-dead" after 3
+    /// // receives. Messages in the dead letter queue are kept for a week.
+    /// async fn example(service: &Service) -> Result<Option<QueueWithDlqOutput>, ClientError> {
+    ///     let config = QueueConfig::with_dlq("new-queue-dead", 3);
+    ///     let dlq_config = QueueConfig {
+    ///         retention_timeout: 7 * 24 * 3600,
+    ///         ..QueueConfig::standard()
+    ///     };
+    ///     service
+    ///         .create_queue_with_dlq("new-queue", None, &config, &dlq_config)
+    ///         .await
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the queue name is invalid, the request fails, or the server returns an
+    /// invalid response. The server rejects configurations without a redrive policy or where the
+    /// dead letter queue redrives messages back to the queue.
+    pub async fn create_queue_with_dlq(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        config: &QueueConfig,
+        dlq_config: &QueueConfig,
+    ) -> Result<Option<QueueWithDlqOutput>, ClientError> {
+        if !is_valid_queue_name(queue_name) {
+            return Err(ClientError::InvalidQueueName);
+        }
+        let body = serde_json::to_vec(&QueueWithDlqConfig {
+            queue:             config.clone(),
+            dead_letter_queue: dlq_config.clone(),
+        })?;
+        let uri = format!("{}/queues/{}/with-dlq", self.host, queue_name);
+        let response = self.json_request(Method::PUT, &uri, trace_id, &body).await?;
         self.parse_response_maybe(response, 201, 409).await
     }
 
-    /// Update the configuration of a queue.
+    /// Update the configuration of a queue. If an expected version is given (as returned by
+    /// `describe_queue` or `get_queues`), the update is rejected with `ClientError::VersionMismatch`
+    /// if the queue was modified in the meantime. The whole configuration is replaced, including the
+    /// tags of the queue.
     ///
     /// ```
     /// use mqs_client::{ClientError, Service};
     /// use mqs_common::{QueueConfig, QueueRedrivePolicy};
+    /// use std::collections::BTreeMap;
     ///
     /// // update an existing queue named "existing-queue". The queue will not delay new messages,
     /// // will hide messages for 30 seconds after they are received, delete messages older
     /// // than 1 hour and send messages to the queue "my-queue-dead" after 3 receives.
     /// async fn example(service: &Service) -> Result<Option<QueueConfig>, ClientError> {
     ///     service
-    ///         .update_queue("existing-queue", None, &QueueConfig {
+    ///         .update_queue("existing-queue", None, None, &QueueConfig {
     ///             redrive_policy:        Some(QueueRedrivePolicy {
     ///                 dead_letter_queue: "my-queue-dead".to_string(),
     ///                 max_receives:      3,
@@ -369,6 +1014,13 @@ impl Service {
     ///             visibility_timeout:    30,
     ///             message_delay:         0,
     ///             message_deduplication: true,
+    ///             allowed_content_types: Vec::new(),
+    ///             default_content_type:  None,
+    ///             max_in_flight:         None,
+    ///             max_redrives:          None,
+    ///             visibility_jitter:     None,
+    ///             message_priorities:    false,
+    ///             tags:                  BTreeMap::new(),
     ///         })
     ///         .await
     /// }
@@ -376,15 +1028,120 @@ impl Service {
     ///
     /// # Errors
     ///
-    /// Returns an error if the request fails or the server returns an invalid response.
+    /// Returns an error if the queue name is invalid, the request fails, or the server returns an
+    /// invalid response.
     pub async fn update_queue(
         &self,
         queue_name: &str,
         trace_id: Option<Uuid>,
+        expected_version: Option<&str>,
         config: &QueueConfig,
     ) -> Result<Option<QueueConfig>, ClientError> {
+        let body = serde_json::to_vec(config)?;
+        self.update_queue_body(queue_name, trace_id, expected_version, &body)
+            .await
+    }
+
+    /// Update the configuration of a queue with a configuration which is already serialized as JSON.
+    /// The configuration is only checked to be valid JSON before it is send to the server.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    /// use mqs_common::QueueConfig;
+    ///
+    /// async fn example(service: &Service) -> Result<Option<QueueConfig>, ClientError> {
+    ///     let config = r#"{"redrive_policy":null,"retention_timeout":3600,"visibility_timeout":30,"message_delay":0,"message_deduplication":false}"#;
+    ///     service.update_queue_raw("existing-queue", None, config).await
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the queue name is invalid, the configuration is not valid JSON, the
+    /// request fails or the server returns an invalid response.
+    pub async fn update_queue_raw(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        config: &str,
+    ) -> Result<Option<QueueConfig>, ClientError> {
+        serde_json::from_str::<IgnoredAny>(config)?;
+        self.update_queue_body(queue_name, trace_id, None, config.as_bytes())
+            .await
+    }
+
+    /// Change some settings of a queue while keeping the rest of its configuration. The current
+    /// configuration of the queue is fetched, changed by `modify` and sent back to the server
+    /// together with the version it was fetched at. If the queue was modified in the meantime, the
+    /// update is rejected with `ClientError::VersionMismatch` instead of overwriting the other
+    /// change, and the caller can try again. If the queue does not exist, `None` is returned without
+    /// calling `modify`.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    /// use mqs_common::QueueConfig;
+    ///
+    /// // give consumers of "slow-queue" two minutes to process a message
+    /// async fn example(service: &Service) -> Result<Option<QueueConfig>, ClientError> {
+    ///     service
+    ///         .modify_queue("slow-queue", None, |config| config.visibility_timeout = 120)
+    ///         .await
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the queue name is invalid, the queue was modified concurrently, the
+    /// request fails, or the server returns an invalid response.
+    pub async fn modify_queue<F: FnOnce(&mut QueueConfig)>(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        modify: F,
+    ) -> Result<Option<QueueConfig>, ClientError> {
+        if !is_valid_queue_name(queue_name) {
+            return Err(ClientError::InvalidQueueName);
+        }
+        let mut output = match self.get_queue_config(queue_name, trace_id).await? {
+            None => return Ok(None),
+            Some(output) => output,
+        };
+        let version = output.version.take();
+        let mut config = output.into_config();
+        modify(&mut config);
+        self.update_queue(queue_name, trace_id, version.as_deref(), &config)
+            .await
+    }
+
+    async fn update_queue_body(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        expected_version: Option<&str>,
+        body: &[u8],
+    ) -> Result<Option<QueueConfig>, ClientError> {
+        if !is_valid_queue_name(queue_name) {
+            return Err(ClientError::InvalidQueueName);
+        }
         let uri = format!("{}/queues/{}", &self.host, queue_name);
-        let response = self.json_request(Method::POST, &uri, trace_id, config).await?;
+        let if_match = expected_version
+            .map(|version| HeaderValue::from_str(&format!("\"{}\"", version)))
+            .transpose()?;
+        let response = self
+            .request(|| {
+                let mut req = Self::new_request(Method::POST, &uri, trace_id, Body::from(body.to_vec()))?;
+                req.headers_mut()
+                    .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+                if let Some(if_match) = &if_match {
+                    req.headers_mut().insert(IF_MATCH, if_match.clone());
+                }
+                Ok::<_, ClientError>(req)
+            })
+            .await?;
+        if response.status().as_u16() == 409 {
+            return Err(ClientError::VersionMismatch);
+        }
+
         self.parse_response_maybe(response, 200, 404).await
     }
 
@@ -415,43 +1172,228 @@ impl Service {
         self.parse_response_maybe(response, 200, 404).await
     }
 
-    /// Retrieve a list of all queues.
+    /// Rename a queue. Its messages move to the new name and queues using it as their dead letter
+    /// queue are changed to use the new name. If the queue did exist, its new configuration is
+    /// returned, otherwise `None` is returned.
     ///
     /// ```
     /// use mqs_client::{ClientError, Service};
-    /// use uuid::Uuid;
-    ///
-    /// // Delete all queues found on a server (including all messages).
-    /// async fn delete_all_queues(service: &Service) -> Result<(), ClientError> {
-    ///     // a new trace_id for this operation, we will group all operations under this id
-    ///     // to easier see what belongs together in logs
-    ///     let trace_id = Uuid::new_v4();
+    /// use mqs_common::QueueConfig;
     ///
-    ///     loop {
-    ///         let queues = service.get_queues(Some(trace_id), None, Some(10)).await?;
-    ///         for queue in queues.queues {
-    ///             service.delete_queue(&queue.name, Some(trace_id)).await?;
-    ///         }
+    /// async fn example(service: &Service) -> Result<Option<QueueConfig>, ClientError> {
+    ///     match service.rename_queue("orders", None, "orders-v1").await {
+    ///         Err(ClientError::QueueAlreadyExists) => Ok(None),
+    ///         result => result,
     ///     }
-    ///
-    ///     Ok(())
     /// }
     /// ```
     ///
     /// # Errors
     ///
-    /// Returns an error if the request fails or the server returns an invalid response.
-    pub async fn get_queues(
+    /// Returns an error if one of the queue names is invalid, a queue with the new name does already
+    /// exist, the request fails, or the server returns an invalid response.
+    pub async fn rename_queue(
         &self,
+        queue_name: &str,
         trace_id: Option<Uuid>,
-        offset: Option<usize>,
-        limit: Option<usize>,
-    ) -> Result<QueuesResponse, ClientError> {
-        let uri = match (offset, limit) {
-            (Some(offset), Some(limit)) => format!("{}/queues?offset={}&limit={}", &self.host, offset, limit),
-            (Some(offset), None) => format!("{}/queues?offset={}", &self.host, offset),
-            (None, Some(limit)) => format!("{}/queues?limit={}", &self.host, limit),
-            (None, None) => format!("{}/queues", &self.host),
+        new_name: &str,
+    ) -> Result<Option<QueueConfig>, ClientError> {
+        if !is_valid_queue_name(queue_name) || !is_valid_queue_name(new_name) {
+            return Err(ClientError::InvalidQueueName);
+        }
+        let uri = format!("{}/queues/{}/rename", self.host, queue_name);
+        let body = serde_json::to_vec(&RenameQueueRequest {
+            name: new_name.to_string(),
+        })?;
+        let response = self.json_request(Method::POST, &uri, trace_id, &body).await?;
+        if response.status().as_u16() == 409 {
+            return Err(ClientError::QueueAlreadyExists);
+        }
+
+        self.parse_response_maybe(response, 200, 404).await
+    }
+
+    /// Describe what deleting a queue would delete without deleting anything. If the queue exists,
+    /// its configuration and the number of messages currently stored in it are returned, otherwise
+    /// `None` is returned.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    ///
+    /// // only delete a queue if it does not contain any messages
+    /// async fn delete_if_empty(service: &Service, queue_name: &str) -> Result<bool, ClientError> {
+    ///     match service.describe_queue_deletion(queue_name, None).await? {
+    ///         Some(description) if description.status.messages == 0 => {
+    ///             Ok(service.delete_queue(queue_name, None).await?.is_some())
+    ///         },
+    ///         _ => Ok(false),
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid response.
+    pub async fn describe_queue_deletion(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+    ) -> Result<Option<QueueDescriptionOutput>, ClientError> {
+        let uri = format!("{}/queues/{}/deletion", self.host, queue_name);
+        let response = self
+            .request(|| Self::new_request(Method::GET, &uri, trace_id, Body::default()))
+            .await?;
+        self.parse_response_maybe(response, 200, 404).await
+    }
+
+    /// Retrieve a page of the list of all queues. Use `Page::next_offset` to get the offset of the
+    /// following page.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    /// use uuid::Uuid;
+    ///
+    /// // Delete all queues found on a server (including all messages).
+    /// async fn delete_all_queues(service: &Service) -> Result<(), ClientError> {
+    ///     // a new trace_id for this operation, we will group all operations under this id
+    ///     // to easier see what belongs together in logs
+    ///     let trace_id = Uuid::new_v4();
+    ///
+    ///     // collect all names first, deleting queues while listing them would shift the pages
+    ///     let mut names = Vec::new();
+    ///     let mut offset = Some(0);
+    ///     while let Some(next) = offset {
+    ///         let page = service
+    ///             .get_queues(Some(trace_id), Some(next), Some(10))
+    ///             .await?;
+    ///         offset = page.next_offset();
+    ///         names.extend(page.items.iter().map(|queue| queue.name.clone()));
+    ///     }
+    ///     for name in names {
+    ///         service.delete_queue(&name, Some(trace_id)).await?;
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid response.
+    pub async fn get_queues(
+        &self,
+        trace_id: Option<Uuid>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<Page<QueueConfigOutput>, ClientError> {
+        let response: QueuesResponse = self.list_queues(None, false, trace_id, offset, limit).await?;
+
+        Ok(response.into_page(offset.unwrap_or(0), limit))
+    }
+
+    /// Retrieve a list of all queues together with their status. The server computes the status of
+    /// all queues on the page at once, which is much cheaper than calling `describe_queue` for every
+    /// listed queue.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    ///
+    /// // Get the names of all queues with visible messages on the first page.
+    /// async fn busy_queues(service: &Service) -> Result<Vec<String>, ClientError> {
+    ///     let queues = service
+    ///         .get_queues_with_status(None, None, Some(100))
+    ///         .await?;
+    ///
+    ///     Ok(queues
+    ///         .items
+    ///         .iter()
+    ///         .filter(|queue| queue.status.visible_messages > 0)
+    ///         .map(|queue| queue.name.clone())
+    ///         .collect())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid response.
+    pub async fn get_queues_with_status(
+        &self,
+        trace_id: Option<Uuid>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<Page<QueueDescriptionOutput>, ClientError> {
+        let response: QueuesWithStatusResponse = self.list_queues(None, true, trace_id, offset, limit).await?;
+
+        Ok(response.into_page(offset.unwrap_or(0), limit))
+    }
+
+    /// Retrieve a list of all queues with names starting with the given prefix. The queues are
+    /// filtered by the server, so only matching queues are transferred and `total` counts only the
+    /// matching queues.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    ///
+    /// // Get the names of all queues of the orders service, one per tenant.
+    /// async fn order_queues(service: &Service) -> Result<Vec<String>, ClientError> {
+    ///     let queues = service
+    ///         .get_queues_matching("orders-tenant-", None, None, None)
+    ///         .await?;
+    ///
+    ///     Ok(queues
+    ///         .items
+    ///         .iter()
+    ///         .map(|queue| queue.name.clone())
+    ///         .collect())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the prefix contains characters not allowed in queue names, the request
+    /// fails, or the server returns an invalid response.
+    pub async fn get_queues_matching(
+        &self,
+        prefix: &str,
+        trace_id: Option<Uuid>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<Page<QueueConfigOutput>, ClientError> {
+        // a prefix of a valid queue name is a valid queue name itself, so we don't need to encode it
+        if !prefix.is_empty() && !is_valid_queue_name(prefix) {
+            return Err(ClientError::InvalidQueueName);
+        }
+
+        let response: QueuesResponse = self.list_queues(Some(prefix), false, trace_id, offset, limit).await?;
+
+        Ok(response.into_page(offset.unwrap_or(0), limit))
+    }
+
+    async fn list_queues<T: DeserializeOwned>(
+        &self,
+        prefix: Option<&str>,
+        status: bool,
+        trace_id: Option<Uuid>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<T, ClientError> {
+        let mut query = Vec::new();
+        if let Some(prefix) = prefix {
+            query.push(format!("prefix={}", prefix));
+        }
+        if status {
+            query.push("status=true".to_string());
+        }
+        if let Some(offset) = offset {
+            query.push(format!("offset={}", offset));
+        }
+        if let Some(limit) = limit {
+            query.push(format!("limit={}", limit));
+        }
+        let uri = if query.is_empty() {
+            format!("{}/queues", self.host)
+        } else {
+            format!("{}/queues?{}", self.host, query.join("&"))
         };
         let mut response = self
             .request(|| Self::new_request(Method::GET, &uri, trace_id, Body::default()))
@@ -459,8 +1401,7 @@ impl Service {
         match response.status().as_u16() {
             200 => {
                 if let Some(body) = read_body(response.body_mut(), self.max_body_size).await? {
-                    let value = serde_json::from_slice(body.as_slice())?;
-                    Ok(value)
+                    Ok(serde_json::from_slice(body.as_slice())?)
                 } else {
                     Err(ClientError::TooLargeResponse)
                 }
@@ -502,6 +1443,169 @@ impl Service {
         self.parse_response_maybe(response, 200, 404).await
     }
 
+    /// Get information about a single queue, optionally without its status. Computing the status
+    /// of a queue requires counting its messages, which can be expensive for large queues. If
+    /// `include_status` is false, only the configuration is returned and the status is all zero.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    ///
+    /// async fn visibility_timeout(
+    ///     service: &Service,
+    ///     queue_name: &str,
+    /// ) -> Result<Option<i64>, ClientError> {
+    ///     let description = service.describe_queue_ext(queue_name, None, false).await?;
+    ///
+    ///     Ok(description.map(|description| description.visibility_timeout))
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid response.
+    pub async fn describe_queue_ext(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        include_status: bool,
+    ) -> Result<Option<QueueDescriptionOutput>, ClientError> {
+        let uri = format!("{}/queues/{}?status={}", self.host, queue_name, include_status);
+        let response = self
+            .request(|| Self::new_request(Method::GET, &uri, trace_id, Body::default()))
+            .await?;
+        self.parse_response_maybe(response, 200, 404).await
+    }
+
+    /// Get the configuration of a single queue. Unlike `describe_queue`, the server doesn't count the
+    /// messages of the queue, so use this if only the configuration is needed, for example to copy
+    /// it to another environment.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    ///
+    /// async fn same_visibility_timeout(
+    ///     source: &Service,
+    ///     target: &Service,
+    ///     queue_name: &str,
+    /// ) -> Result<bool, ClientError> {
+    ///     let source_config = source.get_queue_config(queue_name, None).await?;
+    ///     let target_config = target.get_queue_config(queue_name, None).await?;
+    ///
+    ///     Ok(match (source_config, target_config) {
+    ///         (Some(source_config), Some(target_config)) => {
+    ///             source_config.visibility_timeout == target_config.visibility_timeout
+    ///         },
+    ///         _ => false,
+    ///     })
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid response.
+    pub async fn get_queue_config(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+    ) -> Result<Option<QueueConfigOutput>, ClientError> {
+        let uri = format!("{}/queues/{}?status=false", self.host, queue_name);
+        let response = self
+            .request(|| Self::new_request(Method::GET, &uri, trace_id, Body::default()))
+            .await?;
+        self.parse_response_maybe(response, 200, 404).await
+    }
+
+    /// Get information about a single queue including the percentiles of the ages of its messages
+    /// in `status.age_percentiles`. The percentiles are computed from a sample of the messages in
+    /// the queue, so this is more expensive than `describe_queue`.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    ///
+    /// async fn median_age(service: &Service, queue_name: &str) -> Result<Option<u64>, ClientError> {
+    ///     let description = service.describe_queue_with_ages(queue_name, None).await?;
+    ///
+    ///     Ok(description
+    ///         .and_then(|description| description.status.age_percentiles.map(|ages| ages.p50)))
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid response.
+    pub async fn describe_queue_with_ages(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+    ) -> Result<Option<QueueDescriptionOutput>, ClientError> {
+        let uri = format!("{}/queues/{}?age_percentiles=true", self.host, queue_name);
+        let response = self
+            .request(|| Self::new_request(Method::GET, &uri, trace_id, Body::default()))
+            .await?;
+        self.parse_response_maybe(response, 200, 404).await
+    }
+
+    /// Wait until a queue contains no more messages. The queue is described every `poll_interval`
+    /// until its status reports no messages or `timeout` expires. A poll still running when the
+    /// timeout expires is cancelled.
+    ///
+    /// Returns `true` if the queue became empty and `false` if the timeout expired first.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    /// use std::time::Duration;
+    ///
+    /// async fn drain(service: &Service, queue_name: &str) -> Result<(), ClientError> {
+    ///     if !service
+    ///         .wait_until_empty(
+    ///             queue_name,
+    ///             Duration::from_millis(500),
+    ///             Duration::from_secs(60),
+    ///         )
+    ///         .await?
+    ///     {
+    ///         println!("Queue {} still contains messages", queue_name);
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request fails, the queue does not exist, or the server returns an
+    /// invalid response.
+    pub async fn wait_until_empty(
+        &self,
+        queue_name: &str,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<bool, ClientError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let description = match tokio::time::timeout(
+                deadline.saturating_duration_since(Instant::now()),
+                self.describe_queue(queue_name, None),
+            )
+            .await
+            {
+                Ok(description) => description?,
+                Err(_) => return Ok(false),
+            };
+            match description {
+                None => return Err(ClientError::ServiceError(404)),
+                Some(description) if description.status.messages == 0 => return Ok(true),
+                Some(_) => {},
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(false);
+            }
+            sleep(poll_interval.min(remaining)).await;
+        }
+    }
+
     /// Receive a single message from a queue.
     ///
     /// ```
@@ -512,13 +1616,11 @@ impl Service {
     ///     queue_name: &str,
     ///     callback: F,
     /// ) -> Result<bool, ClientError> {
-    ///     match service.get_message(queue_name, None).await? {
+    ///     match service.get_message(queue_name, None, None).await? {
     ///         None => Ok(false),
     ///         Some(msg) => {
     ///             callback(msg.content_type, msg.content_encoding, msg.content);
-    ///             service
-    ///                 .delete_message(msg.trace_id, &msg.message_id)
-    ///                 .await?;
+    ///             service.delete_message(msg.trace_id, msg.message_id).await?;
     ///             Ok(true)
     ///         },
     ///     }
@@ -531,9 +1633,12 @@ impl Service {
     pub async fn get_message(
         &self,
         queue_name: &str,
+        trace_id: Option<Uuid>,
         timeout: Option<u16>,
     ) -> Result<Option<MessageResponse>, ClientError> {
-        let mut messages = self.get_messages(queue_name, 1, timeout).await?;
+        let mut messages = self
+            .get_messages(queue_name, trace_id, 1, timeout, ReceiveFilter::default())
+            .await?;
         Ok(messages.pop())
     }
 
@@ -541,7 +1646,7 @@ impl Service {
         headers: &HeaderMap,
         get_body: F,
     ) -> Result<MessageResponse, ClientError> {
-        let message_id = MessageIdHeader::get(headers);
+        let message_id = MessageIdHeader::get(headers).parse::<MessageId>()?;
         let content_type = headers
             .get(CONTENT_TYPE)
             .map_or_else(|| DEFAULT_CONTENT_TYPE, |h| h.to_str().unwrap_or(DEFAULT_CONTENT_TYPE))
@@ -552,27 +1657,105 @@ impl Service {
         let message_receives = MessageReceivesHeader::get(headers);
         let published_at = PublishedAtHeader::get(headers);
         let visible_at = VisibleAtHeader::get(headers);
+        let first_received_at = FirstReceivedAtHeader::get(headers);
+        let last_received_at = LastReceivedAtHeader::get(headers);
         let trace_id = TraceIdHeader::get(headers);
+        let raw_trace_id = TraceIdHeader::get_raw(headers).map(ToString::to_string);
+        let group_id = GroupIdHeader::get(headers).map(ToString::to_string);
+        let expires_at = ExpiresAtHeader::get(headers);
+        let origin_queue = OriginQueueHeader::get(headers).map(ToString::to_string);
+        let redrive_reason = RedriveReasonHeader::get(headers).map(ToString::to_string);
+        let content_hash = ContentHashHeader::get(headers).map(ToString::to_string);
+        let attributes = MessageAttributesHeader::get(headers).and_then(Result::ok);
+        let priority = PriorityHeader::get(headers).and_then(Result::ok).unwrap_or(0);
+        let receipt_handle = ReceiptHandleHeader::get(headers).map(ToString::to_string);
+        let standard_headers = [
+            CONTENT_TYPE,
+            CONTENT_ENCODING,
+            CONTENT_LENGTH,
+            CONNECTION,
+            DATE,
+            TRANSFER_ENCODING,
+            MessageIdHeader::name(),
+            MessageReceivesHeader::name(),
+            PublishedAtHeader::name(),
+            VisibleAtHeader::name(),
+            FirstReceivedAtHeader::name(),
+            LastReceivedAtHeader::name(),
+            TraceIdHeader::name(),
+            GroupIdHeader::name(),
+            ExpiresAtHeader::name(),
+            OriginQueueHeader::name(),
+            RedriveReasonHeader::name(),
+            ContentHashHeader::name(),
+            MessageAttributesHeader::name(),
+            ReceiptHandleHeader::name(),
+            PriorityHeader::name(),
+        ];
+        let mut other_headers = headers.clone();
+        for name in &standard_headers {
+            other_headers.remove(name);
+        }
         let content = get_body()?;
         Ok(MessageResponse {
             message_id,
+            receipt_handle,
             content_type,
             content_encoding,
             message_receives,
             published_at,
             visible_at,
+            first_received_at,
+            last_received_at,
             trace_id,
+            raw_trace_id,
+            group_id,
+            expires_at,
+            origin_queue,
+            redrive_reason,
+            attributes,
+            priority,
+            content_hash,
+            headers: other_headers,
             content,
         })
     }
 
-    /// Receive one or more messages from a queue.
+    fn parse_multipart_messages(
+        &self,
+        boundary: &str,
+        body: &[u8],
+        limit: u16,
+    ) -> Result<Vec<MessageResponse>, ClientError> {
+        let chunks = multipart::parse(boundary.as_bytes(), body)?;
+        let max_messages = self
+            .max_messages_per_response
+            .unwrap_or_else(|| usize::from(limit) * Self::DEFAULT_MAX_MESSAGES_FACTOR);
+        if chunks.len() > max_messages {
+            return Err(ClientError::TooManyMessages);
+        }
+        if let Some(max_size) = self.max_single_message_size.or(self.max_body_size) {
+            if chunks.iter().any(|(_, message)| message.len() > max_size) {
+                return Err(ClientError::TooLargeMessage);
+            }
+        }
+        let mut messages = Vec::with_capacity(chunks.len());
+        for (headers, message) in chunks {
+            messages.push(Self::parse_message(&headers, || Ok(message.to_vec()))?);
+        }
+        Ok(messages)
+    }
+
+    /// Receive one or more messages from a queue. Fewer messages than requested, or none at all, are
+    /// returned if the queue limits the number of messages in flight and the limit is reached. If the
+    /// queue does not exist, `ServiceError(404)` is returned unless the service was configured with
+    /// `set_treat_missing_queue_as_empty`.
     ///
     /// For example, to retrieve up to 20 messages, waiting up to 10 seconds,
     /// the following function could be used:
     ///
     /// ```
-    /// use mqs_client::{ClientError, Service};
+    /// use mqs_client::{ClientError, ReceiveFilter, Service};
     ///
     /// async fn consume_multiple<F: Fn(String, Option<String>, Vec<u8>)>(
     ///     service: &Service,
@@ -580,11 +1763,12 @@ impl Service {
     ///     callback: F,
     /// ) -> Result<usize, ClientError> {
     ///     let mut count = 0;
-    ///     for msg in service.get_messages(queue_name, 20, Some(10)).await? {
+    ///     for msg in service
+    ///         .get_messages(queue_name, None, 20, Some(10), ReceiveFilter::default())
+    ///         .await?
+    ///     {
     ///         callback(msg.content_type, msg.content_encoding, msg.content);
-    ///         service
-    ///             .delete_message(msg.trace_id, &msg.message_id)
-    ///             .await?;
+    ///         service.delete_message(msg.trace_id, msg.message_id).await?;
     ///         count += 1;
     ///     }
     ///
@@ -592,24 +1776,256 @@ impl Service {
     /// }
     /// ```
     ///
+    /// If a content type is given, only messages with exactly this content type are returned. Other
+    /// messages stay visible for other consumers:
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, MessageResponse, ReceiveFilter, Service};
+    ///
+    /// async fn receive_json(
+    ///     service: &Service,
+    ///     queue_name: &str,
+    /// ) -> Result<Vec<MessageResponse>, ClientError> {
+    ///     let filter = ReceiveFilter {
+    ///         content_type: Some("application/json"),
+    ///         ..ReceiveFilter::default()
+    ///     };
+    ///     service
+    ///         .get_messages(queue_name, None, 10, None, filter)
+    ///         .await
+    /// }
+    /// ```
+    ///
+    /// Similarly, a consumer resuming from a checkpoint can skip messages the server accepted before
+    /// it. Keep in mind that older messages of a group still block newer messages of the same group:
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, MessageResponse, ReceiveFilter, Service};
+    /// use mqs_common::UtcTime;
+    ///
+    /// async fn receive_since(
+    ///     service: &Service,
+    ///     queue_name: &str,
+    ///     checkpoint: UtcTime,
+    /// ) -> Result<Vec<MessageResponse>, ClientError> {
+    ///     let filter = ReceiveFilter {
+    ///         since: Some(checkpoint),
+    ///         ..ReceiveFilter::default()
+    ///     };
+    ///     service
+    ///         .get_messages(queue_name, None, 10, None, filter)
+    ///         .await
+    /// }
+    /// ```
+    ///
+    /// A trace id can be given to correlate the receive with other requests. Each received message
+    /// still carries the trace id it was published with:
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, MessageResponse, ReceiveFilter, Service};
+    /// use uuid::Uuid;
+    ///
+    /// async fn receive_traced(
+    ///     service: &Service,
+    ///     queue_name: &str,
+    ///     trace_id: Uuid,
+    /// ) -> Result<Vec<MessageResponse>, ClientError> {
+    ///     service
+    ///         .get_messages(
+    ///             queue_name,
+    ///             Some(trace_id),
+    ///             10,
+    ///             None,
+    ///             ReceiveFilter::default(),
+    ///         )
+    ///         .await
+    /// }
+    /// ```
+    ///
     /// # Errors
     ///
-    /// Returns an error if the request fails or the server returns an invalid status.
+    /// Returns an error if the content type is not a valid header value, the request fails, the
+    /// server returns an invalid status, or the server returns more messages or larger messages than
+    /// configured with `set_max_messages_per_response` and `set_max_single_message_size`.
     pub async fn get_messages(
         &self,
         queue_name: &str,
+        trace_id: Option<Uuid>,
         limit: u16,
         timeout: Option<u16>,
+        filter: ReceiveFilter<'_>,
     ) -> Result<Vec<MessageResponse>, ClientError> {
-        if limit == 0 {
-            return Ok(Vec::new());
-        }
+        self.receive_messages(queue_name, trace_id, limit, timeout, filter, ReceiveMode::Receive)
+            .await
+    }
 
-        let uri = format!("{}/messages/{}", &self.host, queue_name);
-        let mut response = self
-            .request(|| {
-                let mut req = Self::new_request(Method::GET, &uri, None, Body::default())?;
-                if let Ok(value) = HeaderValue::from_str(&format!("{}", limit)) {
+    /// Receive one or more messages from a queue like `get_messages`, but stop waiting as soon as the
+    /// given token is cancelled. This allows a consumer waiting for messages with a long timeout to
+    /// shut down promptly instead of waiting for the server to respond:
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, ReceiveFilter, Service};
+    /// use tokio_util::sync::CancellationToken;
+    ///
+    /// async fn consume(
+    ///     service: &Service,
+    ///     queue_name: &str,
+    ///     shutdown: CancellationToken,
+    /// ) -> Result<(), ClientError> {
+    ///     loop {
+    ///         let messages = match service
+    ///             .get_messages_cancellable(
+    ///                 queue_name,
+    ///                 None,
+    ///                 10,
+    ///                 Some(20),
+    ///                 ReceiveFilter::default(),
+    ///                 &shutdown,
+    ///             )
+    ///             .await
+    ///         {
+    ///             Err(ClientError::Cancelled) => return Ok(()),
+    ///             result => result?,
+    ///         };
+    ///         for msg in messages {
+    ///             service.delete_message(msg.trace_id, msg.message_id).await?;
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// The token covers the whole operation, including requests retried because the server was
+    /// unavailable. Once it is cancelled, the request in flight is aborted and not retried. If the
+    /// server already selected messages for the aborted request, they are not returned to the
+    /// consumer and become visible again after the visibility timeout of the queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Cancelled` if the token was cancelled before the server responded. Otherwise, returns
+    /// the same errors as `get_messages`.
+    pub async fn get_messages_cancellable(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        limit: u16,
+        timeout: Option<u16>,
+        filter: ReceiveFilter<'_>,
+        cancellation_token: &CancellationToken,
+    ) -> Result<Vec<MessageResponse>, ClientError> {
+        tokio::select! {
+            biased;
+            _ = cancellation_token.cancelled() => Err(ClientError::Cancelled),
+            result = self.get_messages(queue_name, trace_id, limit, timeout, filter) => result,
+        }
+    }
+
+    /// Receive one or more messages from a queue and delete them in the same operation.
+    ///
+    /// Messages received with `get_messages` stay in the queue until they are deleted and become
+    /// visible again if the consumer fails to do so before the visibility timeout expires. Thus,
+    /// every message is processed at least once. Messages received with `receive_and_delete` are
+    /// deleted by the server before they are returned. If the consumer crashes or the response is
+    /// lost, the messages are gone, so every message is processed at most once:
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    ///
+    /// async fn consume_at_most_once<F: Fn(String, Option<String>, Vec<u8>)>(
+    ///     service: &Service,
+    ///     queue_name: &str,
+    ///     callback: F,
+    /// ) -> Result<usize, ClientError> {
+    ///     let messages = service
+    ///         .receive_and_delete(queue_name, None, 20, Some(10))
+    ///         .await?;
+    ///     let count = messages.len();
+    ///     for msg in messages {
+    ///         // no need to delete the message, it is already gone
+    ///         callback(msg.content_type, msg.content_encoding, msg.content);
+    ///     }
+    ///
+    ///     Ok(count)
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails, the server returns an invalid status, or the server
+    /// returns more messages or larger messages than configured with `set_max_messages_per_response`
+    /// and `set_max_single_message_size`.
+    pub async fn receive_and_delete(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        limit: u16,
+        timeout: Option<u16>,
+    ) -> Result<Vec<MessageResponse>, ClientError> {
+        self.receive_messages(
+            queue_name,
+            trace_id,
+            limit,
+            timeout,
+            ReceiveFilter::default(),
+            ReceiveMode::AutoDelete,
+        )
+        .await
+    }
+
+    /// Get the metadata of the messages which would be received next from a queue without receiving
+    /// them. The messages don't become invisible, their receive count is not increased and their
+    /// content is not transferred, so `content` is empty for all returned messages:
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, ReceiveFilter, Service};
+    ///
+    /// async fn pending_content_types(
+    ///     service: &Service,
+    ///     queue_name: &str,
+    /// ) -> Result<Vec<String>, ClientError> {
+    ///     let messages = service
+    ///         .peek_metadata(queue_name, None, 10, ReceiveFilter::default())
+    ///         .await?;
+    ///
+    ///     Ok(messages.into_iter().map(|msg| msg.content_type).collect())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails, the server returns an invalid status, or the server
+    /// returns more messages than configured with `set_max_messages_per_response`.
+    pub async fn peek_metadata(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        limit: u16,
+        filter: ReceiveFilter<'_>,
+    ) -> Result<Vec<MessageResponse>, ClientError> {
+        self.receive_messages(queue_name, trace_id, limit, None, filter, ReceiveMode::HeadersOnly)
+            .await
+    }
+
+    async fn receive_messages(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        limit: u16,
+        timeout: Option<u16>,
+        filter: ReceiveFilter<'_>,
+        mode: ReceiveMode,
+    ) -> Result<Vec<MessageResponse>, ClientError> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        let uri = format!("{}/messages/{}", &self.host, queue_name);
+        let mut response = self
+            .request(|| {
+                let mut req = Self::new_request(Method::GET, &uri, trace_id, Body::default())?;
+                // always receive a multipart document, even for a single message
+                req.headers_mut()
+                    .insert(ACCEPT, HeaderValue::from_static("multipart/mixed"));
+                if let Ok(value) = HeaderValue::from_str(&format!("{}", limit)) {
                     req.headers_mut()
                         .insert(HeaderName::from_static("x-mqs-max-messages"), value);
                 }
@@ -619,6 +2035,33 @@ impl Service {
                             .insert(HeaderName::from_static("x-mqs-max-wait-time"), value);
                     }
                 }
+                if let Some(content_type) = filter.content_type {
+                    req.headers_mut().insert(
+                        HeaderName::from_static("x-mqs-filter-content-type"),
+                        HeaderValue::from_str(content_type)?,
+                    );
+                }
+                if let Some(since) = filter.since {
+                    req.headers_mut().insert(
+                        HeaderName::from_static("x-mqs-since"),
+                        HeaderValue::from_str(&since.to_rfc3339())?,
+                    );
+                }
+                match mode {
+                    ReceiveMode::Receive => {},
+                    ReceiveMode::AutoDelete => {
+                        req.headers_mut().insert(
+                            HeaderName::from_static("x-mqs-auto-delete"),
+                            HeaderValue::from_static("true"),
+                        );
+                    },
+                    ReceiveMode::HeadersOnly => {
+                        req.headers_mut().insert(
+                            HeaderName::from_static("x-mqs-headers-only"),
+                            HeaderValue::from_static("true"),
+                        );
+                    },
+                }
                 Ok::<_, ClientError>(req)
             })
             .await?;
@@ -629,28 +2072,51 @@ impl Service {
                     .get(CONTENT_TYPE)
                     .map_or_else(|| DEFAULT_CONTENT_TYPE, |h| h.to_str().unwrap_or(DEFAULT_CONTENT_TYPE))
                     .to_string();
+                let boundary = multipart::is_multipart(&content_type).ok_or(ClientError::MultipartParseError(
+                    multipart::InvalidMultipart::ContentType,
+                ))?;
                 if let Some(body) = read_body(response.body_mut(), self.max_body_size).await? {
-                    if let Some(boundary) = multipart::is_multipart(&content_type) {
-                        let chunks = multipart::parse(boundary.as_bytes(), body.as_slice())?;
-                        let mut messages = Vec::with_capacity(chunks.len());
-                        for (headers, message) in chunks {
-                            messages.push(Self::parse_message(&headers, || Ok(message.to_vec()))?);
-                        }
-                        Ok(messages)
-                    } else {
-                        let message = Self::parse_message(response.headers(), || Ok(body))?;
-                        Ok(vec![message])
-                    }
+                    self.parse_multipart_messages(&boundary, &body, limit)
                 } else {
                     Err(ClientError::TooLargeResponse)
                 }
             },
             204 => Ok(Vec::new()),
+            404 if self.treat_missing_queue_as_empty => Ok(Vec::new()),
             status => Err(ClientError::ServiceError(status)),
         }
     }
 
-    /// Publish a single message to a queue.
+    /// Publish a single message to a queue. If the queue restricts the content types it accepts and
+    /// the content type of the message is not one of them, `UnsupportedContentType` is returned. If
+    /// the server rejects the message for another reason, like an expiry time later than the retention
+    /// timeout of the queue, `PublishRejected` tells why:
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, PublishableMessage, Service};
+    /// use mqs_common::PublishRejection;
+    ///
+    /// async fn publish_or_drop(
+    ///     service: &Service,
+    ///     message: PublishableMessage<'_>,
+    /// ) -> Result<(), ClientError> {
+    ///     match service.publish_message("my-queue", message).await {
+    ///         Ok(_) => Ok(()),
+    ///         Err(ClientError::PublishRejected {
+    ///             reason: PublishRejection::TooLarge,
+    ///             ..
+    ///         }) => {
+    ///             // the message will never fit, retrying won't help
+    ///             Ok(())
+    ///         },
+    ///         Err(err) => Err(err),
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// Each call sends a new idempotency key with the request. If the request is retried because the
+    /// server was unavailable or sending it failed, the retry sends the same key, so the server doesn't
+    /// publish the message again if it already handled an earlier attempt.
     ///
     /// ```
     /// use mqs_client::{ClientError, PublishableMessage, Service};
@@ -658,6 +2124,12 @@ impl Service {
     /// async fn example(service: &Service) -> Result<bool, ClientError> {
     ///     let message = PublishableMessage {
     ///         trace_id:         None,
+    ///         raw_trace_id:     None,
+    ///         group_id:         None,
+    ///         expires_at:       None,
+    ///         published_at:     None,
+    ///         priority:         None,
+    ///         attributes:       None,
     ///         content_encoding: None,
     ///         content_type:     "application/json; encoding=utf-8",
     ///         message:          b"{}".to_vec(),
@@ -667,6 +2139,9 @@ impl Service {
     /// }
     /// ```
     ///
+    /// Returns `false` if the message was dropped as a duplicate, use `publish_message_detailed` to
+    /// also get the id of a new message.
+    ///
     /// # Errors
     ///
     /// Returns an error if the request fails or the server returns an invalid status.
@@ -675,126 +2150,749 @@ impl Service {
         queue_name: &str,
         message: PublishableMessage<'_>,
     ) -> Result<bool, ClientError> {
+        let response = self.send_publish_request(queue_name, message).await?;
+
+        Ok(response.status().as_u16() == 201)
+    }
+
+    /// Publish a single message to a queue like `publish_message`, but tell whether the message was
+    /// created or dropped as a duplicate of a message already in the queue.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, PublishResult, PublishableMessage, Service};
+    ///
+    /// async fn example(
+    ///     service: &Service,
+    ///     message: PublishableMessage<'_>,
+    /// ) -> Result<(), ClientError> {
+    ///     match service
+    ///         .publish_message_detailed("my-queue", message)
+    ///         .await?
+    ///     {
+    ///         PublishResult::Created(message_id) => println!("Published message {}", message_id),
+    ///         PublishResult::Deduplicated => println!("Skipped duplicate message"),
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status or message id.
+    pub async fn publish_message_detailed(
+        &self,
+        queue_name: &str,
+        message: PublishableMessage<'_>,
+    ) -> Result<PublishResult, ClientError> {
+        let response = self.send_publish_request(queue_name, message).await?;
+        if DeduplicatedHeader::get(response.headers()) {
+            return Ok(PublishResult::Deduplicated);
+        }
+
+        match response.status().as_u16() {
+            201 => Ok(PublishResult::Created(
+                MessageIdHeader::get(response.headers()).parse::<MessageId>()?,
+            )),
+            status => Err(ClientError::ServiceError(status)),
+        }
+    }
+
+    /// Publish a single message and return the response of the server if it answered with 200 or
+    /// 201, i.e., the message was either created or dropped as a duplicate.
+    async fn send_publish_request(
+        &self,
+        queue_name: &str,
+        message: PublishableMessage<'_>,
+    ) -> Result<Response<Body>, ClientError> {
         let uri = format!("{}/messages/{}", &self.host, queue_name);
+        let idempotency_key = new_idempotency_key()?;
+        let (headers, body) = message.encode();
+        let (body, transport_encoding) = self.encode_request_body(body)?;
         let response = self
-            .request(|| {
-                let (headers, body) = message.clone().encode();
-                let mut req = Self::new_request(Method::POST, &uri, None, Body::from(body))?;
-                for (key, value) in headers {
-                    // we never get the same header twice from PublishableMessage::encode, so we
-                    // can just ignore that case
-                    if let Some(key) = key {
-                        req.headers_mut().insert(key, value);
+            .request_with_retries(
+                || {
+                    let mut req = Self::new_request(Method::POST, &uri, None, Body::from(body.clone()))?;
+                    req.headers_mut()
+                        .insert(IdempotencyKeyHeader::name(), idempotency_key.clone());
+                    for (key, value) in headers.clone() {
+                        // we never get the same header twice from PublishableMessage::encode, so we
+                        // can just ignore that case
+                        if let Some(key) = key {
+                            req.headers_mut().insert(key, value);
+                        }
                     }
-                }
-                Ok::<_, ClientError>(req)
-            })
+                    if let Some(transport_encoding) = &transport_encoding {
+                        req.headers_mut().insert(CONTENT_ENCODING, transport_encoding.clone());
+                    }
+                    Ok::<_, ClientError>(req)
+                },
+                Self::MAX_PUBLISH_RETRIES,
+            )
             .await?;
+        match response.status().as_u16() {
+            200 | 201 => Ok(response),
+            _ => Err(self.publish_error(response).await),
+        }
+    }
+
+    /// Publish a single message to a queue, streaming its content to the server instead of holding it
+    /// in memory. The content is sent as a chunked request, so its size does not need to be known in
+    /// advance. The server rejects messages larger than its maximum message size with status 413.
+    ///
+    /// As the content can only be read once, the request is not retried if the server is currently
+    /// unavailable. `ServiceError(503)` is returned instead.
+    ///
+    /// ```
+    /// use hyper::{Body, Request};
+    /// use mqs_client::{ClientError, Service};
+    ///
+    /// async fn forward(service: &Service, req: Request<Body>) -> Result<bool, ClientError> {
+    ///     service
+    ///         .publish_stream("my-queue", "application/octet-stream", req.into_body())
+    ///         .await
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the content type is not a valid header value, the request fails, or the
+    /// server returns an invalid status.
+    pub async fn publish_stream<S, O, E>(
+        &self,
+        queue_name: &str,
+        content_type: &str,
+        body: S,
+    ) -> Result<bool, ClientError>
+    where
+        S: Stream<Item = Result<O, E>> + Send + 'static,
+        O: Into<Bytes> + 'static,
+        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+    {
+        let uri = format!("{}/messages/{}", self.host, queue_name);
+        let mut req = Self::new_request(Method::POST, &uri, None, Body::wrap_stream(body))?;
+        req.headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_str(content_type)?);
+        let response = self.client.request(req).await?;
+        match response.status().as_u16() {
+            200 => Ok(false),
+            201 => Ok(true),
+            _ => Err(self.publish_error(response).await),
+        }
+    }
+
+    /// Publish a set of messages to a queue. If any of the messages has a content type not accepted
+    /// by the queue, none of the messages are published and `UnsupportedContentType` is returned.
+    /// Retries are deduplicated by the server like for `publish_message`.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, PublishableMessage, Service};
+    /// use uuid::Uuid;
+    ///
+    /// async fn example(service: &Service) -> Result<bool, ClientError> {
+    ///     let trace_id = Uuid::new_v4();
+    ///     let mut messages = Vec::with_capacity(10);
+    ///     for i in 0..messages.capacity() {
+    ///         messages.push(PublishableMessage {
+    ///             trace_id:         Some(trace_id),
+    ///             raw_trace_id:     None,
+    ///             group_id:         Some("my-group"),
+    ///             expires_at:       None,
+    ///             published_at:     None,
+    ///             priority:         None,
+    ///             attributes:       None,
+    ///             content_type:     "text/plain",
+    ///             content_encoding: None,
+    ///             message:          format!("Message {}", i).into_bytes(),
+    ///         });
+    ///     }
+    ///
+    ///     service.publish_messages("my-queue", &messages).await
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub async fn publish_messages(
+        &self,
+        queue_name: &str,
+        messages: &[PublishableMessage<'_>],
+    ) -> Result<bool, ClientError> {
+        let response = self.send_messages(queue_name, messages).await?;
         match response.status().as_u16() {
             200 => Ok(false),
             201 => Ok(true),
+            _ => Err(self.publish_error(response).await),
+        }
+    }
+
+    /// Publish a set of messages to a queue like `publish_messages`, but return the outcome of each
+    /// message in the order of `messages`. A message which was dropped as a duplicate has no id.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, PublishableMessage, Service};
+    ///
+    /// async fn example(
+    ///     service: &Service,
+    ///     messages: &[PublishableMessage<'_>],
+    /// ) -> Result<(), ClientError> {
+    ///     let outcomes = service
+    ///         .publish_messages_detailed("my-queue", messages)
+    ///         .await?;
+    ///     for outcome in outcomes {
+    ///         match outcome.message_id {
+    ///             Some(message_id) => println!("Published message {}", message_id),
+    ///             None => println!("Skipped duplicate message"),
+    ///         }
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub async fn publish_messages_detailed(
+        &self,
+        queue_name: &str,
+        messages: &[PublishableMessage<'_>],
+    ) -> Result<Vec<PublishOutcome>, ClientError> {
+        let mut response = self.send_messages(queue_name, messages).await?;
+        match response.status().as_u16() {
+            200 | 201 => {
+                if let Some(body) = read_body(response.body_mut(), self.max_body_size).await? {
+                    let value: PublishResponse = serde_json::from_slice(body.as_slice())?;
+                    Ok(value.messages)
+                } else {
+                    Err(ClientError::TooLargeResponse)
+                }
+            },
+            _ => Err(self.publish_error(response).await),
+        }
+    }
+
+    /// Convert the response to a rejected publish request into an error. The server returns why it
+    /// rejected the request in the body of the response.
+    async fn publish_error(&self, mut response: Response<Body>) -> ClientError {
+        let status = response.status().as_u16();
+        let rejection = match read_body(response.body_mut(), self.max_body_size).await {
+            Ok(Some(body)) => serde_json::from_slice::<PublishRejectionResponse>(&body).ok(),
+            _ => None,
+        };
+        match (status, rejection) {
+            (415, _) => ClientError::UnsupportedContentType,
+            (
+                _,
+                Some(PublishRejectionResponse {
+                    error,
+                    reason: Some(reason),
+                }),
+            ) => ClientError::PublishRejected { reason, message: error },
+            // bodies exceeding the maximum message size are rejected before they reach the queue
+            (413, rejection) => ClientError::PublishRejected {
+                reason:  PublishRejection::TooLarge,
+                message: rejection.map_or_else(|| "Payload too large".to_string(), |rejection| rejection.error),
+            },
+            (status, _) => ClientError::ServiceError(status),
+        }
+    }
+
+    async fn send_messages(
+        &self,
+        queue_name: &str,
+        messages: &[PublishableMessage<'_>],
+    ) -> Result<Response<Body>, ClientError> {
+        let uri = format!("{}/messages/{}", self.host, queue_name);
+        let idempotency_key = new_idempotency_key()?;
+        let document = multipart::encode(messages.iter().map(|msg| msg.clone().encode()));
+        let content_type = document.content_type_header();
+        let (body, transport_encoding) = self.encode_request_body(document.into_body())?;
+        self.request_with_retries(
+            || {
+                let mut req = Self::new_request(Method::POST, &uri, None, Body::from(body.clone()))?;
+                req.headers_mut().insert(CONTENT_TYPE, content_type.clone());
+                req.headers_mut()
+                    .insert(IdempotencyKeyHeader::name(), idempotency_key.clone());
+                if let Some(transport_encoding) = &transport_encoding {
+                    req.headers_mut().insert(CONTENT_ENCODING, transport_encoding.clone());
+                }
+                Ok::<_, ClientError>(req)
+            },
+            Self::MAX_PUBLISH_RETRIES,
+        )
+        .await
+    }
+
+    /// Delete a single message. The message is identified by its id and not by the queue in which it
+    /// currently resides (a redrive policy will move a message as soon as it hits the maximum receive
+    /// count. It will then be invisible in the new queue until the visibility timeout expires. Thus
+    /// it is possible for messages to be in different queues directly after they have been received
+    /// from some queue).
+    ///
+    /// If the server runs with `ALLOW_MESSAGE_IDS=false`, it rejects message ids and the request
+    /// fails with 400 Bad Request. Use `delete_message_by_receipt` in that case.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, ReceiveFilter, Service};
+    ///
+    /// async fn consume_all<F: Fn(String, Option<String>, Vec<u8>)>(
+    ///     service: &Service,
+    ///     queue_name: &str,
+    ///     callback: F,
+    /// ) -> Result<(), ClientError> {
+    ///     loop {
+    ///         let messages = service
+    ///             .get_messages(queue_name, None, 10, Some(20), ReceiveFilter::default())
+    ///             .await?;
+    ///         for msg in messages {
+    ///             callback(msg.content_type, msg.content_encoding, msg.content);
+    ///             service.delete_message(msg.trace_id, msg.message_id).await?;
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub async fn delete_message(&self, trace_id: Option<Uuid>, message_id: MessageId) -> Result<bool, ClientError> {
+        let uri = format!("{}/messages/{}", &self.host, message_id);
+        let response = self
+            .request(|| Self::new_request(Method::DELETE, &uri, trace_id, Body::default()))
+            .await?;
+        match response.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            status => Err(ClientError::ServiceError(status)),
+        }
+    }
+
+    /// Delete a single message given the string representation of its id, for example an id read
+    /// from the command line. See `delete_message`.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    ///
+    /// async fn delete_by_id(service: &Service, message_id: &str) -> Result<bool, ClientError> {
+    ///     service.delete_message_str(None, message_id).await
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message id is not valid, the request fails, or the server returns an
+    /// invalid status.
+    pub async fn delete_message_str(&self, trace_id: Option<Uuid>, message_id: &str) -> Result<bool, ClientError> {
+        self.delete_message(trace_id, message_id.parse()?).await
+    }
+
+    /// Delete a message by the receipt handle of a receive instead of its id. Returns false if the
+    /// message does not exist anymore or was received again since the handle was issued, e.g.
+    /// because processing it took longer than the visibility timeout of its queue.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, ReceiveFilter, Service};
+    ///
+    /// async fn consume(service: &Service, queue_name: &str) -> Result<(), ClientError> {
+    ///     let messages = service
+    ///         .get_messages(queue_name, None, 10, Some(20), ReceiveFilter::default())
+    ///         .await?;
+    ///     for msg in messages {
+    ///         println!("Processing message {}", msg.message_id);
+    ///         if let Some(receipt_handle) = &msg.receipt_handle {
+    ///             if !service
+    ///                 .delete_message_by_receipt(msg.trace_id, receipt_handle)
+    ///                 .await?
+    ///             {
+    ///                 println!("Message {} was received again", msg.message_id);
+    ///             }
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the receipt handle is not valid, the request fails, or the server returns
+    /// an invalid status.
+    pub async fn delete_message_by_receipt(
+        &self,
+        trace_id: Option<Uuid>,
+        receipt_handle: &str,
+    ) -> Result<bool, ClientError> {
+        // receipt handles are url safe, anything else can't be a handle issued by the server
+        if receipt_handle.is_empty()
+            || !receipt_handle
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        {
+            return Err(ClientError::InvalidReceiptHandle);
+        }
+        let uri = format!("{}/messages/{}", self.host, receipt_handle);
+        let response = self
+            .request(|| Self::new_request(Method::DELETE, &uri, trace_id, Body::default()))
+            .await?;
+        match response.status().as_u16() {
+            200 => Ok(true),
+            404 | 409 => Ok(false),
             status => Err(ClientError::ServiceError(status)),
         }
     }
 
-    /// Publish a set of messages to a queue.
+    /// Release a message received earlier, making it immediately visible again instead of waiting
+    /// for the visibility timeout of the queue to expire. Use this if you failed to process a message
+    /// and want it to be retried as soon as possible. Returns false if the message does not exist or
+    /// is not in flight. Fails with 400 Bad Request if the server runs with `ALLOW_MESSAGE_IDS=false`.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    ///
+    /// async fn consume_one<F: Fn(Vec<u8>) -> bool>(
+    ///     service: &Service,
+    ///     queue_name: &str,
+    ///     callback: F,
+    /// ) -> Result<(), ClientError> {
+    ///     if let Some(msg) = service.get_message(queue_name, None, Some(20)).await? {
+    ///         if callback(msg.content) {
+    ///             service.delete_message(msg.trace_id, msg.message_id).await?;
+    ///         } else {
+    ///             service
+    ///                 .release_message(msg.trace_id, msg.message_id)
+    ///                 .await?;
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub async fn release_message(&self, trace_id: Option<Uuid>, message_id: MessageId) -> Result<bool, ClientError> {
+        let uri = format!("{}/messages/{}/release", self.host, message_id);
+        let response = self
+            .request(|| Self::new_request(Method::POST, &uri, trace_id, Body::default()))
+            .await?;
+        match response.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            status => Err(ClientError::ServiceError(status)),
+        }
+    }
+
+    /// Move a message to another queue. The message keeps its id and payload, but its receive count
+    /// is reset. Returns false if the message or the target queue does not exist. The server rejects
+    /// moving a message to the queue it is already in with `400 Bad Request`.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    ///
+    /// async fn park_message(service: &Service, queue_name: &str) -> Result<bool, ClientError> {
+    ///     if let Some(msg) = service.get_message(queue_name, None, None).await? {
+    ///         service
+    ///             .move_message(msg.trace_id, msg.message_id, "parked-messages")
+    ///             .await
+    ///     } else {
+    ///         Ok(false)
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status.
+    pub async fn move_message(
+        &self,
+        trace_id: Option<Uuid>,
+        message_id: MessageId,
+        target_queue: &str,
+    ) -> Result<bool, ClientError> {
+        let uri = format!("{}/messages/{}/move", self.host, message_id);
+        let body = serde_json::to_vec(&MoveMessageRequest {
+            queue: target_queue.to_string(),
+        })?;
+        let response = self.json_request(Method::POST, &uri, trace_id, &body).await?;
+        match response.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            status => Err(ClientError::ServiceError(status)),
+        }
+    }
+
+    /// Change when several received messages become visible again in a single request. The
+    /// messages become visible `visibility_timeout` after the server handled the request, a timeout
+    /// of zero releases them immediately. The timeout is rounded down to whole seconds and must not
+    /// exceed 12 hours.
+    ///
+    /// Messages are identified by the receipt handle of a receive, or by their id unless the server
+    /// runs with `ALLOW_MESSAGE_IDS=false`. Returns whether the visibility of each message was
+    /// changed, in the order of the given ids. Messages which do not exist anymore, are not in
+    /// flight, have an invalid id, or were received again since their receipt handle was issued are
+    /// reported as not changed. The server accepts at most 1000 messages per request.
+    ///
+    /// Use this to keep messages hidden while they take longer to process than the visibility
+    /// timeout of their queue:
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, MessageResponse, Service};
+    /// use std::time::Duration;
+    ///
+    /// async fn extend_visibility(
+    ///     service: &Service,
+    ///     messages: &[MessageResponse],
+    /// ) -> Result<bool, ClientError> {
+    ///     let ids: Vec<String> = messages
+    ///         .iter()
+    ///         .map(|msg| {
+    ///             msg.receipt_handle
+    ///                 .clone()
+    ///                 .unwrap_or_else(|| msg.message_id.to_string())
+    ///         })
+    ///         .collect();
+    ///     let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+    ///     let changed = service
+    ///         .change_messages_visibility(None, &ids, Duration::from_secs(30))
+    ///         .await?;
+    ///
+    ///     Ok(changed.into_iter().all(|changed| changed))
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an invalid status or response.
+    pub async fn change_messages_visibility(
+        &self,
+        trace_id: Option<Uuid>,
+        message_ids: &[&str],
+        visibility_timeout: Duration,
+    ) -> Result<Vec<bool>, ClientError> {
+        let uri = format!("{}/messages", self.host);
+        let body = serde_json::to_vec(&ChangeVisibilityRequest {
+            message_ids:        message_ids.iter().map(ToString::to_string).collect(),
+            visibility_timeout: visibility_timeout.as_secs(),
+        })?;
+        let mut response = self.json_request(Method::PATCH, &uri, trace_id, &body).await?;
+        match response.status().as_u16() {
+            200 => {
+                if let Some(body) = read_body(response.body_mut(), self.max_body_size).await? {
+                    let value: ChangeVisibilityResponse = serde_json::from_slice(body.as_slice())?;
+                    Ok(value.changed)
+                } else {
+                    Err(ClientError::TooLargeResponse)
+                }
+            },
+            status => Err(ClientError::ServiceError(status)),
+        }
+    }
+
+    /// Delete all messages in a queue with the given content hash, for example to purge a message
+    /// which can never be processed. The hash of a message is available as `content_hash` after
+    /// receiving it. Only queues with content based deduplication store the hash of their messages.
+    ///
+    /// Returns the number of deleted messages or `None` if the queue does not exist.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    ///
+    /// async fn purge_poison_message(service: &Service, queue_name: &str) -> Result<u64, ClientError> {
+    ///     match service.get_message(queue_name, None, None).await? {
+    ///         Some(msg) => match msg.content_hash {
+    ///             Some(hash) => Ok(service
+    ///                 .delete_messages_by_hash(queue_name, msg.trace_id, &hash)
+    ///                 .await?
+    ///                 .unwrap_or(0)),
+    ///             None => Ok(0),
+    ///         },
+    ///         None => Ok(0),
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the queue name or hash is invalid, the queue does not use content based
+    /// deduplication, the request fails, or the server returns an invalid response.
+    pub async fn delete_messages_by_hash(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        hash: &str,
+    ) -> Result<Option<u64>, ClientError> {
+        if !is_valid_queue_name(queue_name) {
+            return Err(ClientError::InvalidQueueName);
+        }
+        let uri = format!("{}/messages/{}/hash", self.host, queue_name);
+        let hash = HeaderValue::from_str(hash)?;
+        let response = self
+            .request(|| {
+                let mut req = Self::new_request(Method::DELETE, &uri, trace_id, Body::default())?;
+                req.headers_mut().insert(ContentHashHeader::name(), hash.clone());
+                Ok::<_, ClientError>(req)
+            })
+            .await?;
+        let result: Option<DeleteByHashResponse> = self.parse_response_maybe(response, 200, 404).await?;
+
+        Ok(result.map(|result| result.deleted))
+    }
+
+    /// List the messages of a queue which are currently in flight, i.e. were received and are
+    /// hidden until their visibility timeout expires. The messages are not modified. Use this to
+    /// find out which messages are being processed, e.g. when debugging duplicate deliveries.
+    ///
+    /// Returns the messages ordered by the time they become visible again or `None` if the queue
+    /// does not exist. Delayed messages which were never received are not in flight. The server
+    /// returns at most the first 1000 messages.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    ///
+    /// async fn print_in_flight(service: &Service, queue_name: &str) -> Result<(), ClientError> {
+    ///     if let Some(messages) = service.list_in_flight(queue_name, None).await? {
+    ///         for message in messages {
+    ///             println!(
+    ///                 "{} is visible at {}",
+    ///                 message.message_id, message.visible_at
+    ///             );
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the queue name is invalid, the request fails, or the server returns an
+    /// invalid response.
+    pub async fn list_in_flight(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+    ) -> Result<Option<Vec<InFlightMessage>>, ClientError> {
+        if !is_valid_queue_name(queue_name) {
+            return Err(ClientError::InvalidQueueName);
+        }
+        let uri = format!("{}/messages/{}/in-flight", self.host, queue_name);
+        let response = self
+            .request(|| Self::new_request(Method::GET, &uri, trace_id, Body::default()))
+            .await?;
+        let result: Option<InFlightMessagesResponse> = self.parse_response_maybe(response, 200, 404).await?;
+
+        Ok(result.map(|result| result.messages))
+    }
+
+    /// Move up to `max` messages from a dead letter queue to another queue, usually the queue they
+    /// were moved from, e.g. after fixing the bug which made their processing fail. The oldest
+    /// messages are moved first and their receive counts are reset. Messages in flight are not
+    /// moved.
+    ///
+    /// Returns the number of moved messages or `None` if one of the queues does not exist.
     ///
     /// ```
-    /// use mqs_client::{ClientError, PublishableMessage, Service};
-    /// use uuid::Uuid;
+    /// use mqs_client::{ClientError, Service};
     ///
-    /// async fn example(service: &Service) -> Result<bool, ClientError> {
-    ///     let trace_id = Uuid::new_v4();
-    ///     let mut messages = Vec::with_capacity(10);
-    ///     for i in 0..messages.capacity() {
-    ///         messages.push(PublishableMessage {
-    ///             trace_id:         Some(trace_id),
-    ///             content_type:     "text/plain",
-    ///             content_encoding: None,
-    ///             message:          format!("Message {}", i).into_bytes(),
-    ///         });
-    ///     }
+    /// async fn retry_failed(service: &Service) -> Result<u64, ClientError> {
+    ///     let moved = service
+    ///         .redrive_dlq("orders-dlq", None, "orders", 1000)
+    ///         .await?;
     ///
-    ///     service.publish_messages("my-queue", &messages).await
+    ///     Ok(moved.unwrap_or(0))
     /// }
     /// ```
     ///
     /// # Errors
     ///
-    /// Returns an error if the request fails or the server returns an invalid status.
-    pub async fn publish_messages(
+    /// Returns an error if a queue name is invalid, the request fails, or the server returns an
+    /// invalid response.
+    pub async fn redrive_dlq(
         &self,
-        queue_name: &str,
-        messages: &[PublishableMessage<'_>],
-    ) -> Result<bool, ClientError> {
-        let uri = format!("{}/messages/{}", &self.host, queue_name);
-        let response = self
-            .request(|| {
-                let (boundary, body) = multipart::encode(messages.iter().map(|msg| msg.clone().encode()));
-                let mut req = Self::new_request(Method::POST, &uri, None, Body::from(body))?;
-                req.headers_mut().insert(
-                    CONTENT_TYPE,
-                    HeaderValue::from_str(&format!("multipart/mixed; boundary={}", boundary))?,
-                );
-                Ok::<_, ClientError>(req)
-            })
-            .await?;
-        match response.status().as_u16() {
-            200 => Ok(false),
-            201 => Ok(true),
-            status => Err(ClientError::ServiceError(status)),
+        dlq_name: &str,
+        trace_id: Option<Uuid>,
+        target_queue: &str,
+        max: u64,
+    ) -> Result<Option<u64>, ClientError> {
+        if !is_valid_queue_name(dlq_name) || !is_valid_queue_name(target_queue) {
+            return Err(ClientError::InvalidQueueName);
         }
+        let uri = format!("{}/messages/{}/redrive", self.host, dlq_name);
+        let body = serde_json::to_vec(&RedriveRequest {
+            queue: target_queue.to_string(),
+            max,
+        })?;
+        let response = self.json_request(Method::POST, &uri, trace_id, &body).await?;
+        let result: Option<RedriveResponse> = self.parse_response_maybe(response, 200, 404).await?;
+
+        Ok(result.map(|result| result.moved))
     }
 
-    /// Delete a single message. The message is identified by its id and not by the queue in which it
-    /// currently resides (a redrive policy will move a message as soon as it hits the maximum receive
-    /// count. It will then be invisible in the new queue until the visibility timeout expires. Thus
-    /// it is possible for messages to be in different queues directly after they have been received
-    /// from some queue).
+    /// Send an arbitrary request to the server and return the raw response. The path is appended to
+    /// the host of the service and the given headers are added to the request, replacing any header
+    /// of the same name the client would send by default. Headers with multiple values are sent with
+    /// all of them. Requests are retried while the server is unavailable, just as for every other
+    /// method.
+    ///
+    /// This method bypasses any response parsing: the status code, headers, and body of the response
+    /// are returned as they were received. Use it to reach endpoints not yet covered by the typed
+    /// methods of this client.
     ///
     /// ```
+    /// use hyper::{HeaderMap, Method};
     /// use mqs_client::{ClientError, Service};
     ///
-    /// async fn consume_all<F: Fn(String, Option<String>, Vec<u8>)>(
-    ///     service: &Service,
-    ///     queue_name: &str,
-    ///     callback: F,
-    /// ) -> Result<(), ClientError> {
-    ///     loop {
-    ///         let messages = service.get_messages(queue_name, 10, Some(20)).await?;
-    ///         for msg in messages {
-    ///             callback(msg.content_type, msg.content_encoding, msg.content);
-    ///             service
-    ///                 .delete_message(msg.trace_id, &msg.message_id)
-    ///                 .await?;
-    ///         }
-    ///     }
+    /// async fn example(service: &Service) -> Result<Vec<u8>, ClientError> {
+    ///     let (_status, _headers, body) = service
+    ///         .send_raw(Method::GET, "/queues?limit=5", &HeaderMap::new(), &[])
+    ///         .await?;
+    ///     Ok(body)
     /// }
     /// ```
     ///
     /// # Errors
     ///
-    /// Returns an error if the request fails or the server returns an invalid status.
-    pub async fn delete_message(&self, trace_id: Option<Uuid>, message_id: &str) -> Result<bool, ClientError> {
-        let uri = format!("{}/messages/{}", &self.host, message_id);
-        let response = self
-            .request(|| Self::new_request(Method::DELETE, &uri, trace_id, Body::default()))
+    /// Returns an error if the request fails or the response is larger than the configured maximum
+    /// body size.
+    pub async fn send_raw(
+        &self,
+        method: Method,
+        path: &str,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<(StatusCode, HeaderMap, Vec<u8>), ClientError> {
+        let uri = if path.starts_with('/') {
+            format!("{}{}", self.host, path)
+        } else {
+            format!("{}/{}", self.host, path)
+        };
+        let mut response = self
+            .request(|| {
+                let mut req = Self::new_request(method.clone(), &uri, None, Body::from(body.to_vec()))?;
+                for name in headers.keys() {
+                    req.headers_mut().remove(name);
+                }
+                for (name, value) in headers {
+                    req.headers_mut().append(name, value.clone());
+                }
+                Ok::<_, ClientError>(req)
+            })
             .await?;
-        match response.status().as_u16() {
-            200 => Ok(true),
-            404 => Ok(false),
-            status => Err(ClientError::ServiceError(status)),
-        }
+        let body = read_body(response.body_mut(), self.max_body_size)
+            .await?
+            .ok_or(ClientError::TooLargeResponse)?;
+        Ok((response.status(), response.headers().clone(), body))
     }
 
     /// Evaluate the health of a service. Returns true if the service is healthy, false if it is not
-    /// healthy, `HealthCheckError` if the service responded with an invalid status.
+    /// healthy, `HealthCheckError` with the response body if the service responded with an invalid
+    /// body.
     ///
     /// ```
     /// use mqs_client::Service;
     /// use tokio::runtime::Builder;
     ///
-    /// let service = Service::new("https://mqs.example.com:7843");
+    /// let service = Service::new("http://mqs.example.com:7843");
     ///
     /// let mut rt = Builder::new_multi_thread().enable_all().build().unwrap();
     /// let success = rt.block_on(async { service.check_health().await });
@@ -820,10 +2918,87 @@ impl Service {
             } else if body.as_slice() == b"red" {
                 Ok(false)
             } else {
-                Err(ClientError::HealthCheckError)
+                Err(Self::health_check_error(&body))
             }
         })
     }
+
+    /// Get the version of the server and since when it is running, e.g. to verify a deployment.
+    ///
+    /// ```
+    /// use mqs_client::{ClientError, Service};
+    ///
+    /// async fn print_version(service: &Service) -> Result<(), ClientError> {
+    ///     let info = service.server_info().await?;
+    ///     println!(
+    ///         "Running mqs {} since {} ({} seconds)",
+    ///         info.version, info.started_at, info.uptime
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails, the server returns a status different from 200, or an
+    /// invalid response.
+    pub async fn server_info(&self) -> Result<ServerInfo, ClientError> {
+        let uri = format!("{}/info", self.host);
+        let response = self
+            .request(|| Self::new_request(Method::GET, &uri, None, Body::default()))
+            .await?;
+        let info: Option<ServerInfo> = self.parse_response_maybe(response, 200, 404).await?;
+
+        info.ok_or(ClientError::ServiceError(404))
+    }
+
+    /// Check the health of the service every `interval` in a background task until the returned
+    /// handle is dropped. This keeps a pooled connection to the server open during quiet periods
+    /// and removes connections the server closed, e.g. because it was restarted, from the pool
+    /// before a real request would fail on them.
+    ///
+    /// Must be called from within a tokio runtime. Failed health checks are only logged.
+    ///
+    /// ```
+    /// use mqs_client::Service;
+    /// use std::time::Duration;
+    ///
+    /// async fn consume(service: &Service) {
+    ///     let _keep_alive = service.spawn_keep_alive(Duration::from_secs(30));
+    ///     // the connection stays warm while messages are processed here
+    /// }
+    /// ```
+    #[must_use = "the background task stops once the handle is dropped"]
+    pub fn spawn_keep_alive(&self, interval: Duration) -> KeepAlive {
+        let token = CancellationToken::new();
+        let cancelled = token.clone();
+        let service = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = cancelled.cancelled() => break,
+                    _ = sleep(interval) => {},
+                }
+                tokio::select! {
+                    _ = cancelled.cancelled() => break,
+                    result = service.check_health() => {
+                        if let Err(err) = result {
+                            debug!("Keep alive health check failed: {}", err);
+                        }
+                    },
+                }
+            }
+        });
+
+        KeepAlive {
+            _guard: token.drop_guard(),
+        }
+    }
+
+    fn health_check_error(body: &[u8]) -> ClientError {
+        let body = &body[..body.len().min(Self::MAX_HEALTH_CHECK_ERROR_LENGTH)];
+        ClientError::HealthCheckError(String::from_utf8_lossy(body).into_owned())
+    }
 }
 
 #[cfg(test)]
@@ -831,12 +3006,55 @@ mod test {
     use super::*;
     use hyper::Uri;
     use mqs_common::test::make_runtime;
-    use std::io::ErrorKind;
+    use std::{
+        io::ErrorKind,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn retry_after_header() {
+        let now = httpdate::parse_http_date("Wed, 20 May 2020 18:40:00 GMT").unwrap();
+        let parse = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(RETRY_AFTER, HeaderValue::from_static(value));
+            retry_after(&headers, now)
+        };
+        assert_eq!(retry_after(&HeaderMap::new(), now), None);
+        assert_eq!(parse("0"), Some(Duration::ZERO));
+        assert_eq!(parse(" 5 "), Some(Duration::from_secs(5)));
+        assert_eq!(parse("3600"), Some(MAX_RETRY_AFTER));
+        assert_eq!(parse("Wed, 20 May 2020 18:40:30 GMT"), Some(Duration::from_secs(30)));
+        assert_eq!(parse("Wed, 20 May 2020 18:39:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse("Thu, 21 May 2020 18:40:00 GMT"), Some(MAX_RETRY_AFTER));
+        assert_eq!(parse("-1"), None);
+        assert_eq!(parse("soon"), None);
+    }
+
+    #[test]
+    fn client_error_source() {
+        let error = ClientError::from(std::io::Error::new(ErrorKind::TimedOut, "timed out"));
+        let source = error.source().unwrap().downcast_ref::<std::io::Error>().unwrap();
+        assert_eq!(source.kind(), ErrorKind::TimedOut);
+        let error = ClientError::from("not a uuid".parse::<MessageId>().unwrap_err());
+        assert!(error.source().unwrap().is::<InvalidMessageId>());
+        assert!(ClientError::ServiceError(500).source().is_none());
+        assert!(ClientError::Cancelled.source().is_none());
+    }
 
     #[test]
     fn encode_publishable_message() {
         let msg = PublishableMessage {
             trace_id:         None,
+            raw_trace_id:     None,
+            group_id:         None,
+            expires_at:       None,
+            published_at:     None,
+            priority:         None,
+            attributes:       None,
             content_encoding: None,
             content_type:     "type",
             message:          vec![1, 2, 3],
@@ -856,6 +3074,12 @@ mod test {
             trace_id:         Some(
                 Uuid::parse_str("96a372de-2db0-405b-a49e-fbcddcabefdb").expect("should be a valid uuid"),
             ),
+            raw_trace_id:     None,
+            group_id:         Some("group"),
+            expires_at:       Some(UtcTime::from_timestamp(449_884_800)),
+            published_at:     Some(UtcTime::from_timestamp(449_798_400)),
+            priority:         None,
+            attributes:       Some(serde_json::json!({"tenant": "acme"})),
             content_encoding: Some("encoding"),
             content_type:     "type",
             message:          vec![4, 5, 6],
@@ -866,16 +3090,63 @@ mod test {
                 {
                     let mut headers = HeaderMap::new();
                     headers.insert(CONTENT_TYPE, HeaderValue::from_static("type"));
-                    headers.insert(CONTENT_ENCODING, HeaderValue::from_static("encoding"));
+                    headers.insert(
+                        MessageContentEncodingHeader::name(),
+                        HeaderValue::from_static("encoding"),
+                    );
                     headers.insert(
                         TraceIdHeader::name(),
                         HeaderValue::from_static("96a372de-2db0-405b-a49e-fbcddcabefdb"),
                     );
+                    headers.insert(GroupIdHeader::name(), HeaderValue::from_static("group"));
+                    headers.insert(
+                        ExpiresAtHeader::name(),
+                        HeaderValue::from_static("1984-04-04T00:00:00Z"),
+                    );
+                    headers.insert(
+                        PublishedAtHeader::name(),
+                        HeaderValue::from_static("1984-04-03T00:00:00Z"),
+                    );
+                    headers.insert(
+                        MessageAttributesHeader::name(),
+                        HeaderValue::from_static("{\"tenant\":\"acme\"}"),
+                    );
                     headers
                 },
                 vec![4, 5, 6]
             )
         );
+        let msg = PublishableMessage {
+            trace_id:         Some(Uuid::new_v4()),
+            raw_trace_id:     Some("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"),
+            group_id:         None,
+            expires_at:       None,
+            published_at:     None,
+            priority:         None,
+            attributes:       None,
+            content_encoding: None,
+            content_type:     "type",
+            message:          vec![7],
+        };
+        let (headers, _) = msg.encode();
+        assert_eq!(
+            TraceIdHeader::get_raw(&headers),
+            Some("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01")
+        );
+        let msg = PublishableMessage {
+            trace_id:         None,
+            raw_trace_id:     None,
+            group_id:         None,
+            expires_at:       None,
+            published_at:     None,
+            priority:         None,
+            attributes:       None,
+            content_encoding: None,
+            content_type:     "",
+            message:          vec![8],
+        };
+        let (headers, _) = msg.encode();
+        assert_eq!(headers.get(CONTENT_TYPE), None);
     }
 
     #[test]
@@ -915,6 +3186,448 @@ mod test {
         let parse_error = multipart::InvalidMultipart::Chunk;
         let err = ClientError::from(parse_error);
         assert_eq!(format!("{}", err), "MultipartParseError(Chunk)");
+
+        let err = Service::health_check_error(b"yellow");
+        assert_eq!(format!("{}", err), "HealthCheckError(\"yellow\")");
+
+        let err = Service::health_check_error(&[b'x'; 1000]);
+        assert!(matches!(err, ClientError::HealthCheckError(body) if body.len() == 256));
+    }
+
+    #[test]
+    fn raw_queue_config_invalid_json() {
+        let service = Service::new("http://localhost:60000");
+        let rt = make_runtime();
+        let created = rt.block_on(async { service.create_queue_raw("my-queue", None, "{not json").await });
+        assert!(matches!(created, Err(ClientError::ParseError(_))));
+        let updated = rt.block_on(async { service.update_queue_raw("my-queue", None, "").await });
+        assert!(matches!(updated, Err(ClientError::ParseError(_))));
+    }
+
+    #[test]
+    fn invalid_queue_names() {
+        let service = Service::new("http://localhost:60000");
+        let config = QueueConfig::standard();
+        let rt = make_runtime();
+        for queue_name in &["", "my queue", "my/queue", "queue?offset=1"] {
+            let created = rt.block_on(async { service.create_queue(queue_name, None, &config).await });
+            assert!(matches!(created, Err(ClientError::InvalidQueueName)));
+            let created = rt.block_on(async { service.create_queue_raw(queue_name, None, "{}").await });
+            assert!(matches!(created, Err(ClientError::InvalidQueueName)));
+            let updated = rt.block_on(async { service.update_queue(queue_name, None, None, &config).await });
+            assert!(matches!(updated, Err(ClientError::InvalidQueueName)));
+            let updated = rt.block_on(async { service.update_queue_raw(queue_name, None, "{}").await });
+            assert!(matches!(updated, Err(ClientError::InvalidQueueName)));
+        }
+        for prefix in &["my queue", "my/queue", "queue?offset=1", "queue&limit=1"] {
+            let listed = rt.block_on(async { service.get_queues_matching(prefix, None, None, None).await });
+            assert!(matches!(listed, Err(ClientError::InvalidQueueName)));
+        }
+    }
+
+    #[test]
+    fn invalid_receipt_handles() {
+        let service = Service::new("http://localhost:60000");
+        let rt = make_runtime();
+        for receipt_handle in &["", "a/b", "handle?x=1", "a b"] {
+            let deleted = rt.block_on(async { service.delete_message_by_receipt(None, receipt_handle).await });
+            assert!(matches!(deleted, Err(ClientError::InvalidReceiptHandle)));
+        }
+        let deleted = rt.block_on(async { service.delete_message_by_receipt(None, "ZXhhbXBsZQ").await });
+        assert!(matches!(deleted, Err(ClientError::HyperError(_))));
+    }
+
+    #[test]
+    fn send_raw_without_server() {
+        let service = Service::new("http://localhost:60000");
+        let rt = make_runtime();
+        let result = rt.block_on(async { service.send_raw(Method::GET, "health", &HeaderMap::new(), &[]).await });
+        assert!(matches!(result, Err(ClientError::HyperError(_))));
+        let service = Service::new("not a host");
+        let result = rt.block_on(async { service.send_raw(Method::GET, "/health", &HeaderMap::new(), &[]).await });
+        assert!(matches!(result, Err(ClientError::InvalidUri(_))));
+    }
+
+    #[test]
+    fn send_raw_multi_valued_headers() {
+        let rt = make_runtime();
+        let request = rt.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let service = Service::new(&format!("http://{}", listener.local_addr().unwrap()));
+            let server = tokio::spawn(async move {
+                let (mut connection, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let n = connection.read(&mut buf).await.unwrap();
+                let response = b"HTTP/1.1 204 No Content\r\ncontent-length: 0\r\n\r\n";
+                connection.write_all(response).await.unwrap();
+                String::from_utf8_lossy(&buf[..n]).to_lowercase()
+            });
+            let mut headers = HeaderMap::new();
+            headers.append(ACCEPT, HeaderValue::from_static("text/plain"));
+            headers.append(ACCEPT, HeaderValue::from_static("application/json"));
+            headers.insert(CONNECTION, HeaderValue::from_static("close"));
+            let (status, _, _) = service.send_raw(Method::GET, "/", &headers, &[]).await.unwrap();
+            assert_eq!(status, StatusCode::NO_CONTENT);
+            server.await.unwrap()
+        });
+        assert!(request.contains("accept: text/plain\r\n"));
+        assert!(request.contains("accept: application/json\r\n"));
+        // the default connection header is replaced instead of being sent twice
+        assert!(request.contains("connection: close\r\n"));
+        assert!(!request.contains("keep-alive"));
+    }
+
+    fn text_message() -> PublishableMessage<'static> {
+        PublishableMessage {
+            trace_id:         None,
+            raw_trace_id:     None,
+            group_id:         None,
+            expires_at:       None,
+            published_at:     None,
+            priority:         None,
+            attributes:       None,
+            content_encoding: None,
+            content_type:     "text/plain",
+            message:          b"hello".to_vec(),
+        }
+    }
+
+    /// Start a server answering a single request with the given raw response and return a service
+    /// connected to it.
+    async fn serve_once(response: &'static [u8]) -> (Service, tokio::task::JoinHandle<()>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let service = Service::new(&format!("http://{}", listener.local_addr().unwrap()));
+        let server = tokio::spawn(async move {
+            let (mut connection, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let n = connection.read(&mut buf).await.unwrap();
+            assert!(n > 0);
+            connection.write_all(response).await.unwrap();
+        });
+        (service, server)
+    }
+
+    #[test]
+    fn publish_result() {
+        make_runtime().block_on(async {
+            // the result of publish_message only depends on the status
+            let (service, server) = serve_once(b"HTTP/1.1 201 Created\r\ncontent-length: 0\r\n\r\n").await;
+            assert!(service.publish_message("my-queue", text_message()).await.unwrap());
+            server.await.unwrap();
+            let (service, server) =
+                serve_once(b"HTTP/1.1 200 OK\r\nx-mqs-deduplicated: true\r\ncontent-length: 0\r\n\r\n").await;
+            let result = service.publish_message_detailed("my-queue", text_message()).await;
+            assert!(matches!(result, Ok(PublishResult::Deduplicated)));
+            server.await.unwrap();
+            // a message which was neither created nor marked as duplicate is an unexpected answer
+            let (service, server) = serve_once(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n").await;
+            let result = service.publish_message_detailed("my-queue", text_message()).await;
+            assert!(matches!(result, Err(ClientError::ServiceError(200))));
+            server.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn publish_retries_failed_requests() {
+        let rt = make_runtime();
+        let (result, requests) = rt.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let service = Service::new(&format!("http://{}", listener.local_addr().unwrap()));
+            let server = tokio::spawn(async move {
+                let mut requests = Vec::new();
+                // the first connection is closed before the server answers
+                for answer in &[false, true] {
+                    let (mut connection, _) = listener.accept().await.unwrap();
+                    let mut buf = [0; 1024];
+                    let n = connection.read(&mut buf).await.unwrap();
+                    requests.push(String::from_utf8_lossy(&buf[..n]).to_lowercase());
+                    if *answer {
+                        let response = b"HTTP/1.1 201 Created\r\nx-mqs-message-id: \
+                            a7d46b8e-32fe-4d35-a3af-74f9a39da2dc\r\ncontent-length: 0\r\n\r\n";
+                        connection.write_all(response).await.unwrap();
+                    }
+                }
+                requests
+            });
+            let result = service.publish_message_detailed("my-queue", text_message()).await;
+            (result, server.await.unwrap())
+        });
+        assert!(matches!(result, Ok(PublishResult::Created(_))));
+        let keys = requests
+            .iter()
+            .map(|request| {
+                request
+                    .lines()
+                    .find(|line| line.starts_with("x-mqs-idempotency-key:"))
+                    .unwrap()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        // the retry has to send the same key, the server might have published the message already
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0], keys[1]);
+    }
+
+    #[test]
+    fn wait_until_empty_without_server() {
+        let service = Service::new("http://localhost:60000");
+        let rt = make_runtime();
+        let result = rt.block_on(async {
+            service
+                .wait_until_empty("my-queue", Duration::from_millis(10), Duration::from_secs(5))
+                .await
+        });
+        assert!(matches!(result, Err(ClientError::HyperError(_))));
+    }
+
+    #[test]
+    fn idempotency_keys() {
+        let first = new_idempotency_key().unwrap();
+        let second = new_idempotency_key().unwrap();
+        assert_ne!(first, second);
+        assert!(Uuid::parse_str(first.to_str().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn publish_stream_without_server() {
+        let service = Service::new("http://localhost:60000");
+        let rt = make_runtime();
+        let result = rt.block_on(async {
+            service
+                .publish_stream("my-queue", "text/plain", Body::from("streamed"))
+                .await
+        });
+        assert!(matches!(result, Err(ClientError::HyperError(_))));
+        let result = rt.block_on(async {
+            service
+                .publish_stream("my-queue", "text/plain\0", Body::from("streamed"))
+                .await
+        });
+        assert!(matches!(result, Err(ClientError::InvalidHeaderValue(_))));
+    }
+
+    #[test]
+    fn normalize_hosts() {
+        for (host, normalized) in &[
+            ("http://localhost:7843", "http://localhost:7843"),
+            ("http://localhost:7843/", "http://localhost:7843"),
+            ("http://localhost:7843//", "http://localhost:7843"),
+            ("http://example.com/mqs/", "http://example.com/mqs"),
+        ] {
+            assert_eq!(Service::try_new(host).unwrap().host(), *normalized);
+        }
+        for host in &[
+            "localhost:7843",
+            "localhost",
+            "/queues",
+            "ftp://localhost:7843",
+            "https://mqs.example.com",
+            "http://localhost:7843/?debug=true",
+        ] {
+            assert!(
+                matches!(Service::try_new(host), Err(ClientError::InvalidHost)),
+                "{} should be invalid",
+                host
+            );
+        }
+        assert!(matches!(
+            Service::try_new("http://local host"),
+            Err(ClientError::InvalidUri(_))
+        ));
+        assert_eq!(Service::new("localhost:7843/").host(), "localhost:7843");
+    }
+
+    #[test]
+    fn get_messages_cancellable() {
+        let rt = make_runtime();
+        let result = rt.block_on(async {
+            // accept connections, but never answer, like a server waiting for messages
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let service = Service::new(&format!("http://{}", listener.local_addr().unwrap()));
+            tokio::spawn(async move {
+                let mut connections = Vec::new();
+                while let Ok((connection, _)) = listener.accept().await {
+                    connections.push(connection);
+                }
+            });
+            let token = CancellationToken::new();
+            let cancel = token.clone();
+            tokio::spawn(async move {
+                sleep(Duration::from_millis(50)).await;
+                cancel.cancel();
+            });
+            let started = Instant::now();
+            let result = service
+                .get_messages_cancellable("my-queue", None, 1, Some(20), ReceiveFilter::default(), &token)
+                .await;
+            assert!(started.elapsed() < Duration::from_secs(5));
+            result
+        });
+        assert!(matches!(result, Err(ClientError::Cancelled)));
+    }
+
+    #[test]
+    fn spawn_keep_alive() {
+        let rt = make_runtime();
+        rt.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let service = Service::new(&format!("http://{}", listener.local_addr().unwrap()));
+            let connections = Arc::new(AtomicUsize::new(0));
+            let requests = Arc::new(AtomicUsize::new(0));
+            {
+                let connections = connections.clone();
+                let requests = requests.clone();
+                tokio::spawn(async move {
+                    while let Ok((mut connection, _)) = listener.accept().await {
+                        connections.fetch_add(1, Ordering::SeqCst);
+                        let requests = requests.clone();
+                        tokio::spawn(async move {
+                            let mut buf = [0; 1024];
+                            while let Ok(n) = connection.read(&mut buf).await {
+                                if n == 0 {
+                                    break;
+                                }
+                                requests.fetch_add(1, Ordering::SeqCst);
+                                let response = b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\ngreen";
+                                if connection.write_all(response).await.is_err() {
+                                    break;
+                                }
+                            }
+                        });
+                    }
+                });
+            }
+
+            let keep_alive = service.spawn_keep_alive(Duration::from_millis(20));
+            sleep(Duration::from_millis(200)).await;
+            assert!(requests.load(Ordering::SeqCst) >= 2);
+            // all health checks reuse the same pooled connection
+            assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+            drop(keep_alive);
+            sleep(Duration::from_millis(50)).await;
+            let stopped_at = requests.load(Ordering::SeqCst);
+            sleep(Duration::from_millis(100)).await;
+            assert_eq!(requests.load(Ordering::SeqCst), stopped_at);
+        });
+    }
+
+    #[test]
+    fn max_messages_per_response() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        headers.insert(
+            MessageIdHeader::name(),
+            HeaderValue::from_static("96a372de-2db0-405b-a49e-fbcddcabefdb"),
+        );
+        let document = multipart::encode((0..5).map(|_| (headers.clone(), b"message".to_vec())));
+        let boundary = format!("--{}", document.boundary());
+        let body = document.into_body();
+        let mut service = Service::new("http://localhost:60000");
+        assert_eq!(service.parse_multipart_messages(&boundary, &body, 2).unwrap().len(), 5);
+        assert!(matches!(
+            service.parse_multipart_messages(&boundary, &body, 1),
+            Err(ClientError::TooManyMessages)
+        ));
+        service.set_max_messages_per_response(Some(4));
+        assert!(matches!(
+            service.parse_multipart_messages(&boundary, &body, 10),
+            Err(ClientError::TooManyMessages)
+        ));
+        service.set_max_messages_per_response(Some(5));
+        assert_eq!(service.parse_multipart_messages(&boundary, &body, 1).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn max_single_message_size() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        headers.insert(
+            MessageIdHeader::name(),
+            HeaderValue::from_static("96a372de-2db0-405b-a49e-fbcddcabefdb"),
+        );
+        let document = multipart::encode(
+            vec![
+                (headers.clone(), b"small".to_vec()),
+                (headers.clone(), vec![b'x'; 100]),
+                (headers, b"small".to_vec()),
+            ]
+            .into_iter(),
+        );
+        let boundary = format!("--{}", document.boundary());
+        let body = document.into_body();
+        let mut service = Service::new("http://localhost:60000");
+        assert_eq!(service.parse_multipart_messages(&boundary, &body, 3).unwrap().len(), 3);
+
+        // without a limit of its own, a message may be as large as the whole body
+        service.set_max_body_size(Some(99));
+        assert!(matches!(
+            service.parse_multipart_messages(&boundary, &body, 3),
+            Err(ClientError::TooLargeMessage)
+        ));
+        service.set_max_body_size(None);
+        service.set_max_single_message_size(Some(99));
+        assert!(matches!(
+            service.parse_multipart_messages(&boundary, &body, 3),
+            Err(ClientError::TooLargeMessage)
+        ));
+        service.set_max_single_message_size(Some(100));
+        assert_eq!(service.parse_multipart_messages(&boundary, &body, 3).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn parse_message_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("3"));
+        headers.insert(
+            MessageIdHeader::name(),
+            HeaderValue::from_static("96a372de-2db0-405b-a49e-fbcddcabefdb"),
+        );
+        headers.insert(GroupIdHeader::name(), HeaderValue::from_static("group"));
+        headers.insert(
+            ExpiresAtHeader::name(),
+            HeaderValue::from_static("1984-04-04T00:00:00Z"),
+        );
+        headers.insert(ContentHashHeader::name(), HeaderValue::from_static("hash"));
+        headers.insert(TraceIdHeader::name(), HeaderValue::from_static("my-trace"));
+        headers.insert(
+            HeaderName::from_static("x-mqs-attr-tenant"),
+            HeaderValue::from_static("acme"),
+        );
+        let message = Service::parse_message(&headers, || Ok(vec![1, 2, 3])).unwrap();
+        assert_eq!(message.message_id.to_string(), "96a372de-2db0-405b-a49e-fbcddcabefdb");
+        assert_eq!(message.content_type, "text/plain");
+        assert_eq!(message.group_id, Some("group".to_string()));
+        assert_eq!(message.expires_at, Some(UtcTime::from_timestamp(449_884_800)));
+        assert_eq!(message.content_hash, Some("hash".to_string()));
+        assert_eq!(message.trace_id, None);
+        assert_eq!(message.raw_trace_id, Some("my-trace".to_string()));
+        assert_eq!(message.headers.len(), 1);
+        assert_eq!(
+            message.headers.get("x-mqs-attr-tenant"),
+            Some(&HeaderValue::from_static("acme"))
+        );
+        assert_eq!(message.content, vec![1, 2, 3]);
+
+        headers.insert(MessageIdHeader::name(), HeaderValue::from_static("my-queue"));
+        assert!(matches!(
+            Service::parse_message(&headers, || Ok(vec![1, 2, 3])),
+            Err(ClientError::InvalidMessageId(_))
+        ));
+    }
+
+    #[test]
+    fn clone_and_debug_service() {
+        let mut service = Service::new("http://localhost:7843");
+        service.set_max_body_size(Some(1024));
+        let copy = service.clone();
+        assert_eq!(copy.host, service.host);
+        assert_eq!(copy.max_body_size, Some(1024));
+        assert_eq!(
+            format!("{:?}", copy),
+            "Service { host: \"http://localhost:7843\", max_body_size: Some(1024), max_messages_per_response: None, max_single_message_size: None, compress_requests: false, treat_missing_queue_as_empty: false, nodelay: true, tcp_keepalive: None, .. }"
+        );
     }
 
     #[test]
@@ -925,4 +3638,35 @@ mod test {
         service.set_max_body_size(Some(64 * 1024));
         assert_eq!(service.max_body_size, Some(64 * 1024));
     }
+
+    #[test]
+    fn set_socket_options() {
+        let mut service = Service::new("http://localhost:7843");
+        assert!(service.nodelay);
+        assert_eq!(service.tcp_keepalive, None);
+        service
+            .set_nodelay(false)
+            .set_tcp_keepalive(Some(Duration::from_secs(30)));
+        assert!(!service.nodelay);
+        assert_eq!(service.tcp_keepalive, Some(Duration::from_secs(30)));
+        // the new client still sends requests, there is just no server listening
+        assert!(make_runtime().block_on(service.check_health()).is_err());
+    }
+
+    #[test]
+    fn encode_request_body() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let mut service = Service::new("http://localhost:7843");
+        let (body, encoding) = service.encode_request_body(b"message".to_vec()).unwrap();
+        assert_eq!(body, b"message");
+        assert_eq!(encoding, None);
+        service.set_compress_requests(true);
+        let (body, encoding) = service.encode_request_body(b"message".to_vec()).unwrap();
+        assert_eq!(encoding, Some(HeaderValue::from_static("gzip")));
+        let mut decompressed = Vec::new();
+        GzDecoder::new(body.as_slice()).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, b"message");
+    }
 }
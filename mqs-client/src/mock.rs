@@ -0,0 +1,845 @@
+//! An in-memory replacement for an mqs server to test code using the client.
+//!
+//! `MockService` implements `MqsClient` like `Service` does, but keeps all queues and messages in
+//! memory instead of sending requests to a server. Received messages are hidden for the visibility
+//! timeout of their queue, new messages for its message delay, and queues with message
+//! deduplication drop messages with the same content as a message already in the queue. Use a
+//! `FixedClock` to control the time the service sees:
+//!
+//! ```
+//! use mqs_client::{mock::MockService, MqsClient, PublishableMessage};
+//! use mqs_common::{FixedClock, QueueConfig, UtcTime};
+//! use std::{sync::Arc, time::Duration};
+//!
+//! let clock = Arc::new(FixedClock::new(UtcTime::from_timestamp(1_590_000_000)));
+//! let service = MockService::new()
+//!     .with_clock(clock.clone())
+//!     .with_queue("my-queue", QueueConfig::standard());
+//! service
+//!     .seed_message("my-queue", PublishableMessage {
+//!         content_type:     "text/plain",
+//!         content_encoding: None,
+//!         trace_id:         None,
+//!         raw_trace_id:     None,
+//!         group_id:         None,
+//!         expires_at:       None,
+//!         published_at:     None,
+//!         priority:         None,
+//!         attributes:       None,
+//!         message:          b"hello".to_vec(),
+//!     })
+//!     .unwrap();
+//!
+//! let runtime = tokio::runtime::Builder::new_current_thread()
+//!     .build()
+//!     .unwrap();
+//! runtime.block_on(async {
+//!     let message = service
+//!         .get_message("my-queue", None, None)
+//!         .await
+//!         .unwrap()
+//!         .unwrap();
+//!     assert_eq!(message.content, b"hello");
+//!     // the message is hidden until the visibility timeout of the queue passed
+//!     assert!(service
+//!         .get_message("my-queue", None, None)
+//!         .await
+//!         .unwrap()
+//!         .is_none());
+//!     clock.advance(Duration::from_secs(30));
+//!     assert!(service
+//!         .get_message("my-queue", None, None)
+//!         .await
+//!         .unwrap()
+//!         .is_some());
+//! });
+//! assert_eq!(service.messages("my-queue").len(), 1);
+//! ```
+//!
+//! The redrive policy of a queue moves messages received `max_receives` times to its dead letter
+//! queue, if that queue exists in the service, and `max_redrives` deletes them instead once they were
+//! moved that often, even if the dead letter queue does not exist. The receive reaching
+//! `max_receives` still returns the message in both cases.
+//! `max_in_flight` limits how many messages of a queue can be received and invisible at the same
+//! time. Receipt handles only identify a receive within the same `MockService`.
+//!
+//! Not simulated are message groups, the visibility jitter of a queue, and receives waiting for new
+//! messages: the timeout of a receive is ignored.
+
+use async_trait::async_trait;
+use hyper::HeaderMap;
+use mqs_common::{
+    is_valid_queue_name,
+    Clock,
+    MessageId,
+    PublishRejection,
+    QueueConfig,
+    QueueConfigOutput,
+    SystemClock,
+    UtcTime,
+    DEFAULT_CONTENT_TYPE,
+};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    convert::TryFrom,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+use uuid::Uuid;
+
+use crate::{ClientError, MessageResponse, MqsClient, PublishResult, PublishableMessage, ReceiveFilter};
+
+struct MockQueue {
+    config:   QueueConfig,
+    /// Messages of the queue in the order they were published in.
+    messages: Vec<MockMessage>,
+}
+
+struct MockMessage {
+    id:                Uuid,
+    content_type:      String,
+    content_encoding:  Option<String>,
+    trace_id:          Option<String>,
+    group_id:          Option<String>,
+    expires_at:        Option<UtcTime>,
+    published_at:      UtcTime,
+    /// Time the message reached the mock, used for the retention of its queue.
+    created_at:        UtcTime,
+    priority:          i32,
+    attributes:        Option<serde_json::Value>,
+    content:           Vec<u8>,
+    receives:          i32,
+    /// Number of times the message was received in any queue. Unlike `receives`, it is not reset
+    /// when the message is moved to another queue, so it identifies each receive.
+    total_receives:    u64,
+    visible_at:        UtcTime,
+    first_received_at: Option<UtcTime>,
+    last_received_at:  Option<UtcTime>,
+    redrive_count:     i32,
+    origin_queue:      Option<String>,
+}
+
+impl MockMessage {
+    fn to_response(&self) -> MessageResponse {
+        MessageResponse {
+            message_id:        MessageId::new(self.id),
+            receipt_handle:    None,
+            content_type:      self.content_type.clone(),
+            content_encoding:  self.content_encoding.clone(),
+            message_receives:  self.receives,
+            published_at:      self.published_at,
+            visible_at:        self.visible_at,
+            first_received_at: self.first_received_at,
+            last_received_at:  self.last_received_at,
+            trace_id:          self
+                .trace_id
+                .as_deref()
+                .and_then(|trace_id| Uuid::parse_str(trace_id).ok()),
+            raw_trace_id:      self.trace_id.clone(),
+            group_id:          self.group_id.clone(),
+            expires_at:        self.expires_at,
+            origin_queue:      self.origin_queue.clone(),
+            redrive_reason:    self.origin_queue.as_ref().map(|_| "max-receives".to_string()),
+            attributes:        self.attributes.clone(),
+            priority:          self.priority,
+            content_hash:      None,
+            headers:           HeaderMap::new(),
+            content:           self.content.clone(),
+        }
+    }
+
+    /// Identifies the latest receive of the message, so the handle becomes stale once the message
+    /// is received again.
+    fn receipt_handle(&self) -> String {
+        format!("{}-{}", self.id.simple(), self.total_receives)
+    }
+
+    fn is_expired(&self, config: &QueueConfig, now: UtcTime) -> bool {
+        self.created_at.add(seconds(config.retention_timeout)) < now
+            || matches!(self.expires_at, Some(expires_at) if expires_at < now)
+    }
+}
+
+/// Build the error a server returns for a rejected message.
+fn rejected(reason: PublishRejection, message: &str) -> ClientError {
+    ClientError::PublishRejected {
+        reason,
+        message: message.to_string(),
+    }
+}
+
+/// Convert a number of seconds from a queue configuration into a duration.
+fn seconds(seconds: i64) -> Duration {
+    Duration::from_secs(u64::try_from(seconds).unwrap_or(0))
+}
+
+/// A `MockService` behaves like a `Service` connected to a server with an empty database, but
+/// keeps all queues and messages in memory. Requests for queues which don't exist fail with
+/// `ClientError::ServiceError(404)` like they would with a server.
+pub struct MockService {
+    clock:  Arc<dyn Clock>,
+    queues: Mutex<HashMap<String, MockQueue>>,
+}
+
+impl Default for MockService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockService {
+    /// Create a new service without any queues which uses the time of the system.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            clock:  Arc::new(SystemClock),
+            queues: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Read the current time from the given clock instead of the system.
+    #[must_use]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Create a queue with the given configuration. An existing queue with the same name is
+    /// replaced together with its messages.
+    #[must_use]
+    pub fn with_queue(self, queue_name: &str, config: QueueConfig) -> Self {
+        self.lock().insert(queue_name.to_string(), MockQueue {
+            config,
+            messages: Vec::new(),
+        });
+        self
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, MockQueue>> {
+        // every operation leaves the queues in a consistent state, so we can ignore a poisoned lock
+        self.queues.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Publish a message without an async runtime, e.g. to fill a queue before the code under test
+    /// runs. Behaves like `Service::publish_message_detailed`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the queue does not exist or rejects the message like a server would, e.g.
+    /// because it does not accept the content type of the message.
+    pub fn seed_message(
+        &self,
+        queue_name: &str,
+        message: PublishableMessage<'_>,
+    ) -> Result<PublishResult, ClientError> {
+        let now = self.clock.now();
+        let mut queues = self.lock();
+        let queue = queues.get_mut(queue_name).ok_or(ClientError::ServiceError(404))?;
+        let content_type = if message.content_type.is_empty() {
+            queue
+                .config
+                .default_content_type
+                .as_deref()
+                .unwrap_or(DEFAULT_CONTENT_TYPE)
+        } else {
+            message.content_type
+        };
+        if !queue.config.allowed_content_types.is_empty()
+            && !queue
+                .config
+                .allowed_content_types
+                .iter()
+                .any(|allowed| allowed == content_type)
+        {
+            return Err(ClientError::UnsupportedContentType);
+        }
+        let retention = seconds(queue.config.retention_timeout);
+        if matches!(message.expires_at, Some(expires_at) if expires_at > now.add(retention)) {
+            return Err(rejected(
+                PublishRejection::ExpiryExceedsRetention,
+                "Message expiry time exceeds the retention timeout of the queue",
+            ));
+        }
+        let published_at = message.published_at.unwrap_or(now);
+        if published_at > now {
+            return Err(rejected(
+                PublishRejection::PublishTimeInFuture,
+                "Message publish time is in the future",
+            ));
+        }
+        if published_at < now.sub(retention) {
+            return Err(rejected(
+                PublishRejection::PublishTimeExceedsRetention,
+                "Message publish time exceeds the retention timeout of the queue",
+            ));
+        }
+        if matches!(&message.attributes, Some(attributes) if !attributes.is_object()) {
+            return Err(rejected(
+                PublishRejection::InvalidAttributes,
+                "Message attributes need to be a JSON object",
+            ));
+        }
+        if queue.config.message_deduplication
+            && queue
+                .messages
+                .iter()
+                .any(|existing| existing.content == message.message)
+        {
+            return Ok(PublishResult::Deduplicated);
+        }
+
+        let id = Uuid::new_v4();
+        queue.messages.push(MockMessage {
+            id,
+            content_type: content_type.to_string(),
+            content_encoding: message.content_encoding.map(ToString::to_string),
+            trace_id: message
+                .raw_trace_id
+                .map(ToString::to_string)
+                .or_else(|| message.trace_id.map(|trace_id| trace_id.to_string())),
+            group_id: message.group_id.map(ToString::to_string),
+            expires_at: message.expires_at,
+            published_at,
+            created_at: now,
+            priority: message.priority.unwrap_or(0),
+            attributes: message.attributes,
+            content: message.message,
+            receives: 0,
+            total_receives: 0,
+            visible_at: now.add(seconds(queue.config.message_delay)),
+            first_received_at: None,
+            last_received_at: None,
+            redrive_count: 0,
+            origin_queue: None,
+        });
+
+        Ok(PublishResult::Created(MessageId::new(id)))
+    }
+
+    /// Get all messages currently stored in a queue in the order they were published in, including
+    /// messages which are not visible at the moment. Returns an empty list if the queue does not
+    /// exist.
+    #[must_use]
+    pub fn messages(&self, queue_name: &str) -> Vec<MessageResponse> {
+        self.lock().get(queue_name).map_or_else(Vec::new, |queue| {
+            queue.messages.iter().map(MockMessage::to_response).collect()
+        })
+    }
+}
+
+#[async_trait]
+impl MqsClient for MockService {
+    /// Create a new queue. See `Service::create_queue`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the queue name or the configuration is invalid.
+    async fn create_queue(
+        &self,
+        queue_name: &str,
+        _trace_id: Option<Uuid>,
+        config: &QueueConfig,
+    ) -> Result<Option<QueueConfig>, ClientError> {
+        if !is_valid_queue_name(queue_name) {
+            return Err(ClientError::InvalidQueueName);
+        }
+        if config.validate().is_err() {
+            return Err(ClientError::ServiceError(400));
+        }
+        let mut queues = self.lock();
+        if queues.contains_key(queue_name) {
+            return Ok(None);
+        }
+        queues.insert(queue_name.to_string(), MockQueue {
+            config:   config.clone(),
+            messages: Vec::new(),
+        });
+
+        Ok(Some(config.clone()))
+    }
+
+    /// Delete a queue and all its messages. See `Service::delete_queue`.
+    ///
+    /// # Errors
+    ///
+    /// Never returns an error, the result only mirrors `Service::delete_queue`.
+    async fn delete_queue(
+        &self,
+        queue_name: &str,
+        _trace_id: Option<Uuid>,
+    ) -> Result<Option<QueueConfig>, ClientError> {
+        Ok(self.lock().remove(queue_name).map(|queue| queue.config))
+    }
+
+    /// Get the configuration of a queue. See `Service::get_queue_config`. The version of the
+    /// returned configuration is always `None`.
+    ///
+    /// # Errors
+    ///
+    /// Never returns an error, the result only mirrors `Service::get_queue_config`.
+    async fn get_queue_config(
+        &self,
+        queue_name: &str,
+        _trace_id: Option<Uuid>,
+    ) -> Result<Option<QueueConfigOutput>, ClientError> {
+        Ok(self.lock().get(queue_name).map(|queue| {
+            let config = queue.config.clone();
+            QueueConfigOutput {
+                name:                  queue_name.to_string(),
+                redrive_policy:        config.redrive_policy,
+                retention_timeout:     config.retention_timeout,
+                visibility_timeout:    config.visibility_timeout,
+                message_delay:         config.message_delay,
+                message_deduplication: config.message_deduplication,
+                allowed_content_types: config.allowed_content_types,
+                default_content_type:  config.default_content_type,
+                max_in_flight:         config.max_in_flight,
+                max_redrives:          config.max_redrives,
+                visibility_jitter:     config.visibility_jitter,
+                message_priorities:    config.message_priorities,
+                tags:                  config.tags,
+                version:               None,
+            }
+        }))
+    }
+
+    /// Publish a single message. See `Service::publish_message`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as `seed_message`.
+    async fn publish_message(&self, queue_name: &str, message: PublishableMessage<'_>) -> Result<bool, ClientError> {
+        let result = self.seed_message(queue_name, message)?;
+
+        Ok(matches!(result, PublishResult::Created(_)))
+    }
+
+    /// Publish a single message and tell whether it was deduplicated. See
+    /// `Service::publish_message_detailed`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as `seed_message`.
+    async fn publish_message_detailed(
+        &self,
+        queue_name: &str,
+        message: PublishableMessage<'_>,
+    ) -> Result<PublishResult, ClientError> {
+        self.seed_message(queue_name, message)
+    }
+
+    /// Publish multiple messages. See `Service::publish_messages`. Unlike with a server, the
+    /// messages before a rejected message stay published.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as `seed_message`.
+    async fn publish_messages(
+        &self,
+        queue_name: &str,
+        messages: &[PublishableMessage<'_>],
+    ) -> Result<bool, ClientError> {
+        for message in messages {
+            self.seed_message(queue_name, message.clone())?;
+        }
+
+        Ok(true)
+    }
+
+    /// Receive a single message. See `Service::get_message`. The service never waits for new
+    /// messages, the timeout is ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the queue does not exist.
+    async fn get_message(
+        &self,
+        queue_name: &str,
+        trace_id: Option<Uuid>,
+        timeout: Option<u16>,
+    ) -> Result<Option<MessageResponse>, ClientError> {
+        let messages = self
+            .get_messages(queue_name, trace_id, 1, timeout, ReceiveFilter::default())
+            .await?;
+
+        Ok(messages.into_iter().next())
+    }
+
+    /// Receive up to `limit` messages. See `Service::get_messages`. The service never waits for new
+    /// messages, the timeout is ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the queue does not exist.
+    async fn get_messages(
+        &self,
+        queue_name: &str,
+        _trace_id: Option<Uuid>,
+        limit: u16,
+        _timeout: Option<u16>,
+        filter: ReceiveFilter<'_>,
+    ) -> Result<Vec<MessageResponse>, ClientError> {
+        let now = self.clock.now();
+        let mut queues = self.lock();
+        let queue = queues.get_mut(queue_name).ok_or(ClientError::ServiceError(404))?;
+        let config = queue.config.clone();
+        queue.messages.retain(|message| !message.is_expired(&config, now));
+        let mut limit = usize::from(limit);
+        if let Some(max_in_flight) = config.max_in_flight {
+            // messages hidden by the message delay of the queue were never received
+            let in_flight = queue
+                .messages
+                .iter()
+                .filter(|message| message.receives > 0 && message.visible_at > now)
+                .count();
+            limit = limit.min(usize::try_from(max_in_flight).unwrap_or(0).saturating_sub(in_flight));
+        }
+
+        let mut candidates: Vec<&mut MockMessage> = queue
+            .messages
+            .iter_mut()
+            .filter(|message| {
+                message.visible_at <= now
+                    && !matches!(filter.content_type, Some(content_type) if message.content_type != content_type)
+                    && !matches!(filter.since, Some(since) if message.created_at < since)
+            })
+            .collect();
+        if config.message_priorities {
+            candidates.sort_by_key(|message| Reverse(message.priority));
+        } else {
+            candidates.sort_by_key(|message| message.visible_at);
+        }
+
+        let visible_at = now.add(seconds(config.visibility_timeout));
+        let received: Vec<MessageResponse> = candidates
+            .into_iter()
+            .take(limit)
+            .map(|message| {
+                message.receives += 1;
+                message.total_receives += 1;
+                message.visible_at = visible_at;
+                message.first_received_at = message.first_received_at.or(Some(now));
+                message.last_received_at = Some(now);
+                MessageResponse {
+                    receipt_handle: Some(message.receipt_handle()),
+                    ..message.to_response()
+                }
+            })
+            .collect();
+
+        let policy = match &config.redrive_policy {
+            Some(policy) => policy,
+            None => return Ok(received),
+        };
+        let has_dead_letter_queue = queues.contains_key(&policy.dead_letter_queue);
+        let queue = queues.get_mut(queue_name).ok_or(ClientError::ServiceError(404))?;
+        let (exceeded, mut remaining): (Vec<MockMessage>, Vec<MockMessage>) =
+            queue.messages.drain(..).partition(|message| {
+                message.receives >= policy.max_receives
+                    && received
+                        .iter()
+                        .any(|response| *response.message_id.as_uuid() == message.id)
+            });
+        let mut moved = Vec::with_capacity(exceeded.len());
+        for mut message in exceeded {
+            // a message which was moved too often already is returned one last time, but then dropped
+            // instead of being moved again
+            if matches!(config.max_redrives, Some(max_redrives) if message.redrive_count >= max_redrives) {
+                continue;
+            }
+            if has_dead_letter_queue {
+                message.receives = 0;
+                message.redrive_count += 1;
+                message.origin_queue = Some(queue_name.to_string());
+                moved.push(message);
+            } else {
+                remaining.push(message);
+            }
+        }
+        queue.messages = remaining;
+        if let Some(dead_letter_queue) = queues.get_mut(&policy.dead_letter_queue) {
+            dead_letter_queue.messages.extend(moved);
+        }
+
+        Ok(received)
+    }
+
+    /// Delete a message. See `Service::delete_message`.
+    ///
+    /// # Errors
+    ///
+    /// Never returns an error, the result only mirrors `Service::delete_message`.
+    async fn delete_message(&self, _trace_id: Option<Uuid>, message_id: MessageId) -> Result<bool, ClientError> {
+        let id = *message_id.as_uuid();
+        for queue in self.lock().values_mut() {
+            if let Some(index) = queue.messages.iter().position(|message| message.id == id) {
+                queue.messages.remove(index);
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Delete a message by the receipt handle of a receive. See
+    /// `Service::delete_message_by_receipt`. Handles issued by another `MockService` are rejected
+    /// like a server would reject a handle signed with another key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the receipt handle is not valid.
+    async fn delete_message_by_receipt(
+        &self,
+        _trace_id: Option<Uuid>,
+        receipt_handle: &str,
+    ) -> Result<bool, ClientError> {
+        if receipt_handle.is_empty()
+            || !receipt_handle
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        {
+            return Err(ClientError::InvalidReceiptHandle);
+        }
+        let (id, total_receives) = receipt_handle
+            .split_once('-')
+            .and_then(|(id, receives)| Some((Uuid::parse_str(id).ok()?, receives.parse::<u64>().ok()?)))
+            .ok_or(ClientError::ServiceError(400))?;
+        for queue in self.lock().values_mut() {
+            if let Some(index) = queue.messages.iter().position(|message| message.id == id) {
+                // the message was received again since the handle was issued
+                if queue.messages[index].total_receives != total_receives {
+                    return Ok(false);
+                }
+                queue.messages.remove(index);
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Make a received message visible again right away. See `Service::release_message`.
+    ///
+    /// # Errors
+    ///
+    /// Never returns an error, the result only mirrors `Service::release_message`.
+    async fn release_message(&self, _trace_id: Option<Uuid>, message_id: MessageId) -> Result<bool, ClientError> {
+        let id = *message_id.as_uuid();
+        let now = self.clock.now();
+        for queue in self.lock().values_mut() {
+            if let Some(message) = queue.messages.iter_mut().find(|message| message.id == id) {
+                if message.receives == 0 || message.visible_at <= now {
+                    return Ok(false);
+                }
+                message.visible_at = now;
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Check the health of the service, which is always healthy. See `Service::check_health`.
+    ///
+    /// # Errors
+    ///
+    /// Never returns an error, the result only mirrors `Service::check_health`.
+    async fn check_health(&self) -> Result<bool, ClientError> {
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mqs_common::{test::make_runtime, FixedClock, QueueRedrivePolicy};
+
+    fn message(content: &[u8]) -> PublishableMessage<'static> {
+        PublishableMessage {
+            content_type:     "",
+            content_encoding: None,
+            trace_id:         None,
+            raw_trace_id:     None,
+            group_id:         None,
+            expires_at:       None,
+            published_at:     None,
+            priority:         None,
+            attributes:       None,
+            message:          content.to_vec(),
+        }
+    }
+
+    #[test]
+    fn mock_queues() {
+        make_runtime().block_on(async {
+            let service = MockService::new();
+            let config = QueueConfig::standard();
+            assert_eq!(
+                service.create_queue("my-queue", None, &config).await.unwrap(),
+                Some(config.clone())
+            );
+            assert_eq!(service.create_queue("my-queue", None, &config).await.unwrap(), None);
+            assert!(matches!(
+                service.create_queue("not a queue", None, &config).await,
+                Err(ClientError::InvalidQueueName)
+            ));
+            let output = service.get_queue_config("my-queue", None).await.unwrap().unwrap();
+            assert_eq!(output.name, "my-queue");
+            assert_eq!(output.into_config(), config);
+            assert_eq!(service.delete_queue("my-queue", None).await.unwrap(), Some(config));
+            assert!(service.get_queue_config("my-queue", None).await.unwrap().is_none());
+            assert!(matches!(
+                service.publish_message("my-queue", message(b"lost")).await,
+                Err(ClientError::ServiceError(404))
+            ));
+            assert!(matches!(
+                service.get_message("my-queue", None, None).await,
+                Err(ClientError::ServiceError(404))
+            ));
+        });
+    }
+
+    #[test]
+    fn mock_messages() {
+        let clock = Arc::new(FixedClock::new(UtcTime::from_timestamp(1_590_000_000)));
+        let service = MockService::new()
+            .with_clock(clock.clone())
+            .with_queue("dedup-queue", QueueConfig {
+                message_deduplication: true,
+                allowed_content_types: vec!["text/plain".to_string()],
+                default_content_type: Some("text/plain".to_string()),
+                ..QueueConfig::standard()
+            })
+            .with_queue("priority-queue", QueueConfig {
+                message_delay: 5,
+                message_priorities: true,
+                ..QueueConfig::standard()
+            });
+
+        assert!(matches!(
+            service.seed_message("dedup-queue", message(b"first")),
+            Ok(PublishResult::Created(_))
+        ));
+        assert!(matches!(
+            service.seed_message("dedup-queue", message(b"first")),
+            Ok(PublishResult::Deduplicated)
+        ));
+        assert!(matches!(
+            service.seed_message("dedup-queue", PublishableMessage {
+                content_type: "application/json",
+                ..message(b"{}")
+            }),
+            Err(ClientError::UnsupportedContentType)
+        ));
+        let messages = service.messages("dedup-queue");
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content_type, "text/plain");
+
+        for (content, priority) in &[(&b"low"[..], None), (b"high", Some(3)), (b"higher", Some(7))] {
+            service
+                .seed_message("priority-queue", PublishableMessage {
+                    priority: *priority,
+                    ..message(content)
+                })
+                .unwrap();
+        }
+        make_runtime().block_on(async {
+            // messages are hidden for the message delay of the queue
+            assert!(service
+                .get_message("priority-queue", None, None)
+                .await
+                .unwrap()
+                .is_none());
+            clock.advance(Duration::from_secs(5));
+            let received = service
+                .get_messages("priority-queue", None, 2, None, ReceiveFilter::default())
+                .await
+                .unwrap();
+            let contents: Vec<&[u8]> = received.iter().map(|message| message.content.as_slice()).collect();
+            assert_eq!(contents, vec![&b"higher"[..], b"high"]);
+            assert_eq!(received[0].message_receives, 1);
+
+            assert!(service.release_message(None, received[0].message_id).await.unwrap());
+            assert!(service.delete_message(None, received[1].message_id).await.unwrap());
+            assert!(!service.delete_message(None, received[1].message_id).await.unwrap());
+            let received = service
+                .get_messages("priority-queue", None, 10, None, ReceiveFilter::default())
+                .await
+                .unwrap();
+            let contents: Vec<&[u8]> = received.iter().map(|message| message.content.as_slice()).collect();
+            assert_eq!(contents, vec![&b"higher"[..], b"low"]);
+            assert_eq!(received[0].message_receives, 2);
+            assert!(service
+                .get_message("priority-queue", None, None)
+                .await
+                .unwrap()
+                .is_none());
+        });
+    }
+
+    #[test]
+    fn mock_receipt_handles() {
+        let service = MockService::new()
+            .with_queue("receipt-source", QueueConfig {
+                redrive_policy: Some(QueueRedrivePolicy {
+                    max_receives:      1,
+                    dead_letter_queue: "receipt-dlq".to_string(),
+                }),
+                visibility_timeout: 0,
+                ..QueueConfig::standard()
+            })
+            .with_queue("receipt-dlq", QueueConfig::standard());
+        service.seed_message("receipt-source", message(b"moved")).unwrap();
+        make_runtime().block_on(async {
+            let receive = |queue_name: &'static str| {
+                let service = &service;
+                async move {
+                    let message = service.get_message(queue_name, None, None).await.unwrap().unwrap();
+                    message.receipt_handle.unwrap()
+                }
+            };
+            // the first receive moves the message, which resets its receives in the new queue
+            let first_receipt = receive("receipt-source").await;
+            let second_receipt = receive("receipt-dlq").await;
+            assert_ne!(first_receipt, second_receipt);
+            assert!(!service.delete_message_by_receipt(None, &first_receipt).await.unwrap());
+            assert!(service.delete_message_by_receipt(None, &second_receipt).await.unwrap());
+        });
+    }
+
+    #[test]
+    fn mock_max_redrives_without_dead_letter_queue() {
+        let config = |max_redrives| QueueConfig {
+            redrive_policy: Some(QueueRedrivePolicy {
+                max_receives:      1,
+                dead_letter_queue: "missing-dlq".to_string(),
+            }),
+            visibility_timeout: 0,
+            max_redrives,
+            ..QueueConfig::standard()
+        };
+        let service = MockService::new()
+            .with_queue("dropping-queue", config(Some(0)))
+            .with_queue("keeping-queue", config(None));
+        for queue_name in &["dropping-queue", "keeping-queue"] {
+            service.seed_message(queue_name, message(b"poison")).unwrap();
+        }
+        make_runtime().block_on(async {
+            for queue_name in &["dropping-queue", "keeping-queue"] {
+                assert!(service.get_message(queue_name, None, None).await.unwrap().is_some());
+            }
+            // the dead letter queue does not exist, but the message was redriven too often already
+            assert!(service
+                .get_message("dropping-queue", None, None)
+                .await
+                .unwrap()
+                .is_none());
+            assert!(service
+                .get_message("keeping-queue", None, None)
+                .await
+                .unwrap()
+                .is_some());
+        });
+    }
+}
@@ -0,0 +1,78 @@
+use crate::UtcTime;
+use std::{
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+/// A `Clock` provides the current time. Code which depends on the current time should ask a clock
+/// instead of calling `UtcTime::now` directly, so tests can control the time it sees.
+pub trait Clock: Send + Sync {
+    /// Get the current time.
+    fn now(&self) -> UtcTime;
+}
+
+/// A `SystemClock` returns the current time of the system.
+///
+/// ```
+/// use mqs_common::{Clock, SystemClock, UtcTime};
+///
+/// let before = UtcTime::now();
+/// let now = SystemClock.now();
+/// assert!(before <= now);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> UtcTime {
+        UtcTime::now()
+    }
+}
+
+/// A `FixedClock` always returns the same time until it is explicitly set or advanced.
+///
+/// ```
+/// use mqs_common::{Clock, FixedClock, UtcTime};
+/// use std::time::Duration;
+///
+/// let clock = FixedClock::new(UtcTime::from_timestamp(1000));
+/// assert_eq!(clock.now(), UtcTime::from_timestamp(1000));
+/// clock.advance(Duration::from_secs(30));
+/// assert_eq!(clock.now(), UtcTime::from_timestamp(1030));
+/// clock.set(UtcTime::from_timestamp(0));
+/// assert_eq!(clock.now(), UtcTime::from_timestamp(0));
+/// ```
+#[derive(Debug)]
+pub struct FixedClock {
+    time: Mutex<UtcTime>,
+}
+
+impl FixedClock {
+    /// Create a new clock returning the given time.
+    #[must_use]
+    pub const fn new(time: UtcTime) -> Self {
+        Self { time: Mutex::new(time) }
+    }
+
+    /// Set the time returned by the clock.
+    pub fn set(&self, time: UtcTime) {
+        *self.lock() = time;
+    }
+
+    /// Move the time returned by the clock forward by the given duration.
+    pub fn advance(&self, duration: Duration) {
+        let mut time = self.lock();
+        *time = time.add(duration);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, UtcTime> {
+        // the guarded time is always valid, so we can ignore a poisoned lock
+        self.time.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> UtcTime {
+        *self.lock()
+    }
+}
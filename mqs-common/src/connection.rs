@@ -6,6 +6,7 @@ use r2d2::{
     Error,
     HandleError,
     HandleEvent,
+    ManageConnection,
     PooledConnection,
 };
 use std::{
@@ -13,6 +14,7 @@ use std::{
     fmt::{Display, Formatter},
     time::Duration,
 };
+use tokio::task::spawn_blocking;
 
 /// Type alias for our database connection pool type.
 pub type Pool = r2d2::Pool<ConnectionManager<PgConnection>>;
@@ -78,6 +80,42 @@ pub fn init_pool_maybe() -> Result<(Pool, u16), InitPoolError> {
     Ok((pool, max_size))
 }
 
+/// Number of times `get_connection` tries to get a working connection from a pool.
+const MAX_CONNECTION_ATTEMPTS: usize = 3;
+
+/// Get a working connection from a pool.
+///
+/// The pool validates connections before handing them out and replaces broken ones. If no working
+/// connection can be acquired in time (for example because the database is currently restarting),
+/// the request is retried a few times before `None` is returned. Callers should then answer with
+/// 503 Service Unavailable, so clients retry later.
+///
+/// Waiting for a connection blocks, so it happens on the blocking thread pool of the runtime
+/// instead of stalling the worker thread polling the calling future.
+pub async fn get_connection(pool: &Pool) -> Option<DBConn> {
+    let pool = pool.clone();
+    let result = spawn_blocking(move || {
+        for attempt in 1..=MAX_CONNECTION_ATTEMPTS {
+            match pool.get() {
+                Ok(conn) => return Some(conn),
+                Err(err) => warn!(
+                    "Failed to get a database connection (attempt {} of {}): {}",
+                    attempt, MAX_CONNECTION_ATTEMPTS, err
+                ),
+            }
+        }
+
+        None
+    })
+    .await;
+
+    result.unwrap_or_else(|err| {
+        error!("Failed to wait for a database connection: {}", err);
+
+        None
+    })
+}
+
 fn database_url() -> Result<String, InitPoolError> {
     env::var("DATABASE_URL").map_err(|_| InitPoolError::MissingVariable("DATABASE_URL"))
 }
@@ -157,6 +195,12 @@ impl<E: Display> HandleError<E> for ConnectionHandler {
 pub trait Source<R>: Send {
     /// Get a resource from a `Source`.
     fn get(&self) -> Option<R>;
+
+    /// Report how many resources the `Source` currently manages. Returns `None` if the `Source`
+    /// does not track its resources.
+    fn state(&self) -> Option<SourceState> {
+        None
+    }
 }
 
 impl<'a, R, T: Source<R>> Source<R> for &'a T
@@ -166,6 +210,59 @@ where
     fn get(&self) -> Option<R> {
         (*self).get()
     }
+
+    fn state(&self) -> Option<SourceState> {
+        (*self).state()
+    }
+}
+
+/// Number of resources (like database connections) managed by a `Source`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceState {
+    /// Number of resources currently held by the `Source`, both in use and idle.
+    pub connections:      u32,
+    /// Number of resources which are currently not in use.
+    pub idle_connections: u32,
+    /// Maximum number of resources the `Source` will hold.
+    pub max_connections:  u32,
+}
+
+impl SourceState {
+    /// Check whether all resources are in use and no new ones can be created, so anyone trying to
+    /// get a resource from the `Source` has to wait.
+    ///
+    /// ```
+    /// use mqs_common::connection::SourceState;
+    ///
+    /// let state = SourceState {
+    ///     connections:      10,
+    ///     idle_connections: 0,
+    ///     max_connections:  10,
+    /// };
+    /// assert!(state.is_exhausted());
+    /// let state = SourceState {
+    ///     connections:      5,
+    ///     idle_connections: 0,
+    ///     max_connections:  10,
+    /// };
+    /// assert!(!state.is_exhausted());
+    /// ```
+    #[must_use]
+    pub const fn is_exhausted(&self) -> bool {
+        self.idle_connections == 0 && self.connections >= self.max_connections
+    }
+}
+
+impl<M: ManageConnection> From<&r2d2::Pool<M>> for SourceState {
+    fn from(pool: &r2d2::Pool<M>) -> Self {
+        let state = pool.state();
+
+        Self {
+            connections:      state.connections,
+            idle_connections: state.idle_connections,
+            max_connections:  pool.max_size(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -178,6 +275,15 @@ mod test {
         assert_eq!("url://database", &super::database_url().unwrap());
     }
 
+    #[test]
+    async fn get_connection_unavailable() {
+        let manager = ConnectionManager::<PgConnection>::new("postgres://localhost:1/mqs");
+        let pool = Pool::builder()
+            .connection_timeout(Duration::from_millis(10))
+            .build_unchecked(manager);
+        assert!(get_connection(&pool).await.is_none());
+    }
+
     #[test]
     async fn pool() {
         env::set_var("MAX_POOL_SIZE", "50");
@@ -22,15 +22,26 @@ extern crate serde_derive;
 extern crate tokio;
 
 use hyper::{body::HttpBody, header::HeaderName, Body, HeaderMap};
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    error::Error,
+    fmt::{Display, Formatter},
+    num::ParseIntError,
+};
 use uuid::Uuid;
 
+mod clock;
 /// Utilities to connect to the database.
 #[cfg(feature = "server")]
 pub mod connection;
 /// Logging utils for mqs applications.
 pub mod logger;
+mod message_id;
 /// Encoding and decoding of multipart/mixed messages.
 pub mod multipart;
+mod page;
+mod random;
 /// Request routing and handling.
 pub mod router;
 /// Run a server with the given handler method.
@@ -39,9 +50,14 @@ pub mod server;
 mod status;
 mod time;
 
-pub use crate::time::*;
+pub use crate::{clock::*, message_id::*, page::*, random::*, time::*};
 pub use status::*;
 
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_false(value: &bool) -> bool {
+    !*value
+}
+
 /// Content type used if the client does not specify one.
 pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
 
@@ -135,6 +151,49 @@ impl MessageReceivesHeader {
     }
 }
 
+/// Header containing the priority of a message. Messages with a higher priority are received first
+/// from queues with message priorities.
+#[derive(Clone, Copy)]
+pub struct PriorityHeader {}
+
+impl PriorityHeader {
+    /// Get the name of the header containing the message priority.
+    ///
+    /// ```
+    /// use hyper::header::HeaderName;
+    /// use mqs_common::PriorityHeader;
+    ///
+    /// assert_eq!(
+    ///     HeaderName::from_static("x-mqs-priority"),
+    ///     PriorityHeader::name()
+    /// );
+    /// ```
+    #[must_use]
+    pub const fn name() -> HeaderName {
+        HeaderName::from_static("x-mqs-priority")
+    }
+
+    /// Parse the priority of a message. Returns `None` in case the header is missing or does not
+    /// contain a valid string and an error if the header does not contain a valid number.
+    ///
+    /// ```
+    /// use http::HeaderValue;
+    /// use hyper::HeaderMap;
+    /// use mqs_common::PriorityHeader;
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert!(PriorityHeader::get(&headers).is_none());
+    /// headers.insert(PriorityHeader::name(), HeaderValue::from_static("-5"));
+    /// assert_eq!(PriorityHeader::get(&headers), Some(Ok(-5)));
+    /// headers.insert(PriorityHeader::name(), HeaderValue::from_static("urgent"));
+    /// assert!(PriorityHeader::get(&headers).unwrap().is_err());
+    /// ```
+    #[must_use]
+    pub fn get(headers: &HeaderMap) -> Option<Result<i32, ParseIntError>> {
+        get_header(headers, Self::name()).map(str::parse)
+    }
+}
+
 /// Header containing the time the message was published at.
 #[derive(Clone, Copy)]
 pub struct PublishedAtHeader {}
@@ -275,131 +334,1239 @@ impl VisibleAtHeader {
     }
 }
 
-/// Header containing the trace id.
+/// Header containing the time after which a message expires and is no longer delivered.
 #[derive(Clone, Copy)]
-pub struct TraceIdHeader {}
+pub struct ExpiresAtHeader {}
 
-impl TraceIdHeader {
-    /// Get the name of the header containing the trace id.
+impl ExpiresAtHeader {
+    /// Get the name of the header containing the time a message expires at.
     ///
     /// ```
     /// use hyper::header::HeaderName;
-    /// use mqs_common::TraceIdHeader;
+    /// use mqs_common::ExpiresAtHeader;
     ///
-    /// assert_eq!(HeaderName::from_static("x-trace-id"), TraceIdHeader::name());
+    /// assert_eq!(
+    ///     HeaderName::from_static("x-mqs-expires-at"),
+    ///     ExpiresAtHeader::name()
+    /// );
     /// ```
     #[must_use]
     pub const fn name() -> HeaderName {
-        HeaderName::from_static("x-trace-id")
+        HeaderName::from_static("x-mqs-expires-at")
+    }
+
+    /// Get the time a message expires at.
+    /// Returns `None` in case the header is missing or contains an invalid value.
+    ///
+    /// ```
+    /// use http::HeaderValue;
+    /// use hyper::HeaderMap;
+    /// use mqs_common::{ExpiresAtHeader, UtcTime};
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert_eq!(ExpiresAtHeader::get(&headers), None);
+    /// headers.insert(
+    ///     ExpiresAtHeader::name(),
+    ///     HeaderValue::from_static("today is not a valid date"),
+    /// );
+    /// assert_eq!(ExpiresAtHeader::get(&headers), None);
+    /// headers.insert(
+    ///     ExpiresAtHeader::name(),
+    ///     HeaderValue::from_static("1984-04-04T00:00:00Z"),
+    /// );
+    /// let expected = UtcTime::from_timestamp(449884800);
+    /// assert_eq!(ExpiresAtHeader::get(&headers), Some(expected));
+    /// ```
+    #[must_use]
+    pub fn get(headers: &HeaderMap) -> Option<UtcTime> {
+        get_header(headers, Self::name()).and_then(|s| UtcTime::parse_from_rfc3339(s).ok())
+    }
+}
+
+/// Header containing the time the message was received for the first time.
+#[derive(Clone, Copy)]
+pub struct FirstReceivedAtHeader {}
+
+impl FirstReceivedAtHeader {
+    /// Get the name of the header containing the time the message was received for the first time.
+    ///
+    /// ```
+    /// use hyper::header::HeaderName;
+    /// use mqs_common::FirstReceivedAtHeader;
+    ///
+    /// assert_eq!(
+    ///     HeaderName::from_static("x-mqs-first-received-at"),
+    ///     FirstReceivedAtHeader::name()
+    /// );
+    /// ```
+    #[must_use]
+    pub const fn name() -> HeaderName {
+        HeaderName::from_static("x-mqs-first-received-at")
+    }
+
+    /// Get the time a message was received for the first time.
+    /// Returns `None` in case the header is missing or contains an invalid value.
+    ///
+    /// ```
+    /// use http::HeaderValue;
+    /// use hyper::HeaderMap;
+    /// use mqs_common::{FirstReceivedAtHeader, UtcTime};
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert_eq!(FirstReceivedAtHeader::get(&headers), None);
+    /// headers.insert(
+    ///     FirstReceivedAtHeader::name(),
+    ///     HeaderValue::from_static("today is not a valid date"),
+    /// );
+    /// assert_eq!(FirstReceivedAtHeader::get(&headers), None);
+    /// headers.insert(
+    ///     FirstReceivedAtHeader::name(),
+    ///     HeaderValue::from_static("1984-04-04T00:00:00Z"),
+    /// );
+    /// let expected = UtcTime::from_timestamp(449884800);
+    /// assert_eq!(FirstReceivedAtHeader::get(&headers), Some(expected));
+    /// ```
+    #[must_use]
+    pub fn get(headers: &HeaderMap) -> Option<UtcTime> {
+        get_header(headers, Self::name()).and_then(|s| UtcTime::parse_from_rfc3339(s).ok())
+    }
+}
+
+/// Header containing the time the message was received most recently.
+#[derive(Clone, Copy)]
+pub struct LastReceivedAtHeader {}
+
+impl LastReceivedAtHeader {
+    /// Get the name of the header containing the time the message was received most recently.
+    ///
+    /// ```
+    /// use hyper::header::HeaderName;
+    /// use mqs_common::LastReceivedAtHeader;
+    ///
+    /// assert_eq!(
+    ///     HeaderName::from_static("x-mqs-last-received-at"),
+    ///     LastReceivedAtHeader::name()
+    /// );
+    /// ```
+    #[must_use]
+    pub const fn name() -> HeaderName {
+        HeaderName::from_static("x-mqs-last-received-at")
+    }
+
+    /// Get the time a message was received most recently.
+    /// Returns `None` in case the header is missing or contains an invalid value.
+    ///
+    /// ```
+    /// use http::HeaderValue;
+    /// use hyper::HeaderMap;
+    /// use mqs_common::{LastReceivedAtHeader, UtcTime};
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert_eq!(LastReceivedAtHeader::get(&headers), None);
+    /// headers.insert(
+    ///     LastReceivedAtHeader::name(),
+    ///     HeaderValue::from_static("today is not a valid date"),
+    /// );
+    /// assert_eq!(LastReceivedAtHeader::get(&headers), None);
+    /// headers.insert(
+    ///     LastReceivedAtHeader::name(),
+    ///     HeaderValue::from_static("1984-04-04T00:00:00Z"),
+    /// );
+    /// let expected = UtcTime::from_timestamp(449884800);
+    /// assert_eq!(LastReceivedAtHeader::get(&headers), Some(expected));
+    /// ```
+    #[must_use]
+    pub fn get(headers: &HeaderMap) -> Option<UtcTime> {
+        get_header(headers, Self::name()).and_then(|s| UtcTime::parse_from_rfc3339(s).ok())
+    }
+}
+
+/// Header containing the receipt handle of a received message. The handle can be used instead of the
+/// message id to delete the message or change its visibility until the message is received again.
+#[derive(Clone, Copy)]
+pub struct ReceiptHandleHeader {}
+
+impl ReceiptHandleHeader {
+    /// Get the name of the header containing the receipt handle.
+    ///
+    /// ```
+    /// use hyper::header::HeaderName;
+    /// use mqs_common::ReceiptHandleHeader;
+    ///
+    /// assert_eq!(
+    ///     HeaderName::from_static("x-mqs-receipt-handle"),
+    ///     ReceiptHandleHeader::name()
+    /// );
+    /// ```
+    #[must_use]
+    pub const fn name() -> HeaderName {
+        HeaderName::from_static("x-mqs-receipt-handle")
     }
 
-    /// Get a string representation of the header containing the trace id.
+    /// Get the receipt handle of a received message.
+    /// Returns `None` in case the header is missing or contains an invalid value.
+    ///
+    /// ```
+    /// use http::HeaderValue;
+    /// use hyper::HeaderMap;
+    /// use mqs_common::ReceiptHandleHeader;
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert_eq!(ReceiptHandleHeader::get(&headers), None);
+    /// headers.insert(
+    ///     ReceiptHandleHeader::name(),
+    ///     HeaderValue::from_static("ZXhhbXBsZQ"),
+    /// );
+    /// assert_eq!(ReceiptHandleHeader::get(&headers), Some("ZXhhbXBsZQ"));
+    /// ```
+    #[must_use]
+    pub fn get(headers: &HeaderMap) -> Option<&str> {
+        get_header(headers, Self::name())
+    }
+}
+
+/// Header containing the group of a message. Messages of the same group are delivered in order.
+#[derive(Clone, Copy)]
+pub struct GroupIdHeader {}
+
+impl GroupIdHeader {
+    /// Get the name of the header containing the group id.
+    ///
+    /// ```
+    /// use hyper::header::HeaderName;
+    /// use mqs_common::GroupIdHeader;
+    ///
+    /// assert_eq!(
+    ///     HeaderName::from_static("x-mqs-group-id"),
+    ///     GroupIdHeader::name()
+    /// );
+    /// ```
+    #[must_use]
+    pub const fn name() -> HeaderName {
+        HeaderName::from_static("x-mqs-group-id")
+    }
+
+    /// Get the group id of a message.
+    /// Returns `None` in case the header is missing or contains an invalid value.
+    ///
+    /// ```
+    /// use http::HeaderValue;
+    /// use hyper::HeaderMap;
+    /// use mqs_common::GroupIdHeader;
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert_eq!(GroupIdHeader::get(&headers), None);
+    /// headers.insert(GroupIdHeader::name(), HeaderValue::from_static("my group"));
+    /// assert_eq!(GroupIdHeader::get(&headers), Some("my group"));
+    /// ```
+    #[must_use]
+    pub fn get(headers: &HeaderMap) -> Option<&str> {
+        get_header(headers, Self::name())
+    }
+}
+
+/// Header containing the queue a message was in before it was moved to its current queue.
+#[derive(Clone, Copy)]
+pub struct OriginQueueHeader {}
+
+impl OriginQueueHeader {
+    /// Get the name of the header containing the origin queue.
+    ///
+    /// ```
+    /// use hyper::header::HeaderName;
+    /// use mqs_common::OriginQueueHeader;
+    ///
+    /// assert_eq!(
+    ///     HeaderName::from_static("x-mqs-origin-queue"),
+    ///     OriginQueueHeader::name()
+    /// );
+    /// ```
+    #[must_use]
+    pub const fn name() -> HeaderName {
+        HeaderName::from_static("x-mqs-origin-queue")
+    }
+
+    /// Get the queue a message was moved from.
+    /// Returns `None` in case the header is missing or contains an invalid value.
+    ///
+    /// ```
+    /// use http::HeaderValue;
+    /// use hyper::HeaderMap;
+    /// use mqs_common::OriginQueueHeader;
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert_eq!(OriginQueueHeader::get(&headers), None);
+    /// headers.insert(
+    ///     OriginQueueHeader::name(),
+    ///     HeaderValue::from_static("my-queue"),
+    /// );
+    /// assert_eq!(OriginQueueHeader::get(&headers), Some("my-queue"));
+    /// ```
+    #[must_use]
+    pub fn get(headers: &HeaderMap) -> Option<&str> {
+        get_header(headers, Self::name())
+    }
+}
+
+/// Header containing the reason a message was moved to its current queue. Either `max-receives` if
+/// the message was moved to the dead letter queue of its queue or `moved` if a client moved it.
+#[derive(Clone, Copy)]
+pub struct RedriveReasonHeader {}
+
+impl RedriveReasonHeader {
+    /// Get the name of the header containing the redrive reason.
+    ///
+    /// ```
+    /// use hyper::header::HeaderName;
+    /// use mqs_common::RedriveReasonHeader;
+    ///
+    /// assert_eq!(
+    ///     HeaderName::from_static("x-mqs-redrive-reason"),
+    ///     RedriveReasonHeader::name()
+    /// );
+    /// ```
+    #[must_use]
+    pub const fn name() -> HeaderName {
+        HeaderName::from_static("x-mqs-redrive-reason")
+    }
+
+    /// Get the reason a message was moved to its current queue.
+    /// Returns `None` in case the header is missing or contains an invalid value.
+    ///
+    /// ```
+    /// use http::HeaderValue;
+    /// use hyper::HeaderMap;
+    /// use mqs_common::RedriveReasonHeader;
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert_eq!(RedriveReasonHeader::get(&headers), None);
+    /// headers.insert(
+    ///     RedriveReasonHeader::name(),
+    ///     HeaderValue::from_static("max-receives"),
+    /// );
+    /// assert_eq!(RedriveReasonHeader::get(&headers), Some("max-receives"));
+    /// ```
+    #[must_use]
+    pub fn get(headers: &HeaderMap) -> Option<&str> {
+        get_header(headers, Self::name())
+    }
+}
+
+/// Header containing a token generated by the client for a publish request.
+///
+/// The server remembers the result of a publish request for a short time and returns it again if
+/// the request is retried with the same token instead of publishing the messages a second time.
+#[derive(Clone, Copy)]
+pub struct IdempotencyKeyHeader {}
+
+impl IdempotencyKeyHeader {
+    /// Get the name of the header containing the idempotency key.
+    ///
+    /// ```
+    /// use hyper::header::HeaderName;
+    /// use mqs_common::IdempotencyKeyHeader;
+    ///
+    /// assert_eq!(
+    ///     HeaderName::from_static("x-mqs-idempotency-key"),
+    ///     IdempotencyKeyHeader::name()
+    /// );
+    /// ```
+    #[must_use]
+    pub const fn name() -> HeaderName {
+        HeaderName::from_static("x-mqs-idempotency-key")
+    }
+
+    /// Get the idempotency key of a publish request.
+    /// Returns `None` in case the header is missing, empty, or contains an invalid value.
+    ///
+    /// ```
+    /// use http::HeaderValue;
+    /// use hyper::HeaderMap;
+    /// use mqs_common::IdempotencyKeyHeader;
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert_eq!(IdempotencyKeyHeader::get(&headers), None);
+    /// headers.insert(IdempotencyKeyHeader::name(), HeaderValue::from_static(""));
+    /// assert_eq!(IdempotencyKeyHeader::get(&headers), None);
+    /// headers.insert(
+    ///     IdempotencyKeyHeader::name(),
+    ///     HeaderValue::from_static("0f5bb4b1-8d4e-4a52-9a41-0cd0e7ab8f3c"),
+    /// );
+    /// assert_eq!(
+    ///     IdempotencyKeyHeader::get(&headers),
+    ///     Some("0f5bb4b1-8d4e-4a52-9a41-0cd0e7ab8f3c")
+    /// );
+    /// ```
+    #[must_use]
+    pub fn get(headers: &HeaderMap) -> Option<&str> {
+        get_header(headers, Self::name()).filter(|key| !key.is_empty())
+    }
+}
+
+/// Header containing the hash of the payload of a message. Only set for messages in queues with
+/// content based deduplication.
+#[derive(Clone, Copy)]
+pub struct ContentHashHeader {}
+
+impl ContentHashHeader {
+    /// Get the name of the header containing the content hash.
+    ///
+    /// ```
+    /// use hyper::header::HeaderName;
+    /// use mqs_common::ContentHashHeader;
+    ///
+    /// assert_eq!(
+    ///     HeaderName::from_static("x-mqs-content-hash"),
+    ///     ContentHashHeader::name()
+    /// );
+    /// ```
+    #[must_use]
+    pub const fn name() -> HeaderName {
+        HeaderName::from_static("x-mqs-content-hash")
+    }
+
+    /// Get the content hash of a message.
+    /// Returns `None` in case the header is missing or contains an invalid value.
+    ///
+    /// ```
+    /// use http::HeaderValue;
+    /// use hyper::HeaderMap;
+    /// use mqs_common::ContentHashHeader;
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert_eq!(ContentHashHeader::get(&headers), None);
+    /// headers.insert(
+    ///     ContentHashHeader::name(),
+    ///     HeaderValue::from_static("n4bQgYhMfWWaL+qgxVrQFaO/TxsrC4Is0V1sFbDwCgg="),
+    /// );
+    /// assert_eq!(
+    ///     ContentHashHeader::get(&headers),
+    ///     Some("n4bQgYhMfWWaL+qgxVrQFaO/TxsrC4Is0V1sFbDwCgg=")
+    /// );
+    /// ```
+    #[must_use]
+    pub fn get(headers: &HeaderMap) -> Option<&str> {
+        get_header(headers, Self::name())
+    }
+}
+
+/// Header set on the response to a publish request if the message was dropped as a duplicate of a
+/// message already in the queue.
+#[derive(Clone, Copy)]
+pub struct DeduplicatedHeader {}
+
+impl DeduplicatedHeader {
+    /// Get the name of the header marking a deduplicated message.
+    ///
+    /// ```
+    /// use hyper::header::HeaderName;
+    /// use mqs_common::DeduplicatedHeader;
+    ///
+    /// assert_eq!(
+    ///     HeaderName::from_static("x-mqs-deduplicated"),
+    ///     DeduplicatedHeader::name()
+    /// );
+    /// ```
+    #[must_use]
+    pub const fn name() -> HeaderName {
+        HeaderName::from_static("x-mqs-deduplicated")
+    }
+
+    /// Check whether the headers mark a message as deduplicated.
+    /// Returns `false` in case the header is missing or contains anything but `true`.
+    ///
+    /// ```
+    /// use http::HeaderValue;
+    /// use hyper::HeaderMap;
+    /// use mqs_common::DeduplicatedHeader;
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert!(!DeduplicatedHeader::get(&headers));
+    /// headers.insert(DeduplicatedHeader::name(), HeaderValue::from_static("yes"));
+    /// assert!(!DeduplicatedHeader::get(&headers));
+    /// headers.insert(DeduplicatedHeader::name(), HeaderValue::from_static("true"));
+    /// assert!(DeduplicatedHeader::get(&headers));
+    /// ```
+    #[must_use]
+    pub fn get(headers: &HeaderMap) -> bool {
+        get_header(headers, Self::name()) == Some("true")
+    }
+}
+
+/// Header containing the content encoding of a message when it is published.
+///
+/// In contrast to the `Content-Encoding` header, it describes the stored message and not the body
+/// of the request, so a request can be compressed for transport while still declaring the encoding
+/// of the message itself.
+#[derive(Clone, Copy)]
+pub struct MessageContentEncodingHeader {}
+
+impl MessageContentEncodingHeader {
+    /// Get the name of the header containing the content encoding of the message.
+    ///
+    /// ```
+    /// use hyper::header::HeaderName;
+    /// use mqs_common::MessageContentEncodingHeader;
+    ///
+    /// assert_eq!(
+    ///     HeaderName::from_static("x-mqs-content-encoding"),
+    ///     MessageContentEncodingHeader::name()
+    /// );
+    /// ```
+    #[must_use]
+    pub const fn name() -> HeaderName {
+        HeaderName::from_static("x-mqs-content-encoding")
+    }
+
+    /// Get the content encoding of a message.
+    /// Returns `None` in case the header is missing or contains an invalid value.
+    ///
+    /// ```
+    /// use http::HeaderValue;
+    /// use hyper::HeaderMap;
+    /// use mqs_common::MessageContentEncodingHeader;
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert_eq!(MessageContentEncodingHeader::get(&headers), None);
+    /// headers.insert(
+    ///     MessageContentEncodingHeader::name(),
+    ///     HeaderValue::from_static("gzip"),
+    /// );
+    /// assert_eq!(MessageContentEncodingHeader::get(&headers), Some("gzip"));
+    /// ```
+    #[must_use]
+    pub fn get(headers: &HeaderMap) -> Option<&str> {
+        get_header(headers, Self::name())
+    }
+}
+
+/// Headers starting with `x-mqs-attr-` contain custom attributes of a message. They are stored with
+/// the message when it is published and returned when it is received.
+#[derive(Clone, Copy)]
+pub struct MessageAttributeHeaders {}
+
+impl MessageAttributeHeaders {
+    /// Prefix of all headers containing message attributes.
+    pub const PREFIX: &'static str = "x-mqs-attr-";
+
+    /// Check whether a header contains a message attribute.
+    ///
+    /// ```
+    /// use hyper::header::{HeaderName, CONTENT_TYPE};
+    /// use mqs_common::MessageAttributeHeaders;
+    ///
+    /// assert!(MessageAttributeHeaders::is_attribute(
+    ///     &HeaderName::from_static("x-mqs-attr-tenant")
+    /// ));
+    /// assert!(!MessageAttributeHeaders::is_attribute(&CONTENT_TYPE));
+    /// ```
+    #[must_use]
+    pub fn is_attribute(name: &HeaderName) -> bool {
+        name.as_str().starts_with(Self::PREFIX)
+    }
+
+    /// Get all message attributes from the given headers. Attributes with values which are not valid
+    /// strings are skipped.
+    ///
+    /// ```
+    /// use http::HeaderValue;
+    /// use hyper::{header::HeaderName, HeaderMap};
+    /// use mqs_common::MessageAttributeHeaders;
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// headers.insert(
+    ///     HeaderName::from_static("x-mqs-attr-tenant"),
+    ///     HeaderValue::from_static("acme"),
+    /// );
+    /// headers.insert(
+    ///     HeaderName::from_static("x-mqs-group-id"),
+    ///     HeaderValue::from_static("my group"),
+    /// );
+    /// assert_eq!(MessageAttributeHeaders::get(&headers), vec![(
+    ///     "x-mqs-attr-tenant",
+    ///     "acme"
+    /// )]);
+    /// ```
+    #[must_use]
+    pub fn get(headers: &HeaderMap) -> Vec<(&str, &str)> {
+        headers
+            .iter()
+            .filter(|(name, _)| Self::is_attribute(name))
+            .filter_map(|(name, value)| value.to_str().ok().map(|value| (name.as_str(), value)))
+            .collect()
+    }
+}
+
+/// Header containing the attributes of a message as a JSON object. An alternative to many
+/// `x-mqs-attr-` headers for publishers which need structured attributes.
+#[derive(Clone, Copy)]
+pub struct MessageAttributesHeader {}
+
+impl MessageAttributesHeader {
+    /// Get the name of the header containing the JSON attributes.
+    ///
+    /// ```
+    /// use hyper::header::HeaderName;
+    /// use mqs_common::MessageAttributesHeader;
+    ///
+    /// assert_eq!(
+    ///     HeaderName::from_static("x-mqs-attributes"),
+    ///     MessageAttributesHeader::name()
+    /// );
+    /// ```
+    #[must_use]
+    pub const fn name() -> HeaderName {
+        HeaderName::from_static("x-mqs-attributes")
+    }
+
+    /// Parse the JSON attributes of a message. Returns `None` in case the header is missing or does
+    /// not contain a valid string and an error if the header does not contain valid JSON. The
+    /// attributes are not required to be an object.
+    ///
+    /// ```
+    /// use http::HeaderValue;
+    /// use hyper::HeaderMap;
+    /// use mqs_common::MessageAttributesHeader;
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert!(MessageAttributesHeader::get(&headers).is_none());
+    /// headers.insert(MessageAttributesHeader::name(), HeaderValue::from_static("{\"tenant\":\"acme\"}"));
+    /// assert_eq!(
+    ///     MessageAttributesHeader::get(&headers).unwrap().unwrap(),
+    ///     serde_json::json!({"tenant": "acme"})
+    /// );
+    /// headers.insert(MessageAttributesHeader::name(), HeaderValue::from_static("{"));
+    /// assert!(MessageAttributesHeader::get(&headers).unwrap().is_err());
+    /// ```
+    #[must_use]
+    pub fn get(headers: &HeaderMap) -> Option<serde_json::Result<serde_json::Value>> {
+        get_header(headers, Self::name()).map(serde_json::from_str)
+    }
+
+    /// Encode attributes as the value of the header. Header values may only contain ASCII, so
+    /// other characters are escaped.
+    ///
+    /// ```
+    /// use mqs_common::MessageAttributesHeader;
+    ///
+    /// assert_eq!(
+    ///     MessageAttributesHeader::encode(&serde_json::json!({"city": "Zürich"})),
+    ///     "{\"city\":\"Z\\u00fcrich\"}"
+    /// );
+    /// ```
+    #[must_use]
+    pub fn encode(attributes: &serde_json::Value) -> String {
+        // non ASCII characters can only appear inside of strings, where they can be escaped
+        let mut encoded = String::new();
+        for c in attributes.to_string().chars() {
+            if c.is_ascii() {
+                encoded.push(c);
+            } else {
+                let mut buf = [0; 2];
+                for unit in c.encode_utf16(&mut buf) {
+                    encoded.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+        }
+
+        encoded
+    }
+}
+
+/// Header containing the trace id.
+#[derive(Clone, Copy)]
+pub struct TraceIdHeader {}
+
+impl TraceIdHeader {
+    /// Get the name of the header containing the trace id.
+    ///
+    /// ```
+    /// use hyper::header::HeaderName;
+    /// use mqs_common::TraceIdHeader;
+    ///
+    /// assert_eq!(HeaderName::from_static("x-trace-id"), TraceIdHeader::name());
+    /// ```
+    #[must_use]
+    pub const fn name() -> HeaderName {
+        HeaderName::from_static("x-trace-id")
+    }
+
+    /// Get a string representation of the header containing the trace id.
+    ///
+    /// ```
+    /// use mqs_common::TraceIdHeader;
+    ///
+    /// assert_eq!("X-TRACE-ID", &TraceIdHeader::upper());
+    /// ```
+    #[must_use]
+    pub fn upper() -> String {
+        Self::name().as_str().to_uppercase()
+    }
+
+    /// Get the trace id header value.
+    ///
+    /// ```
+    /// use hyper::{header::HeaderValue, HeaderMap};
+    /// use mqs_common::TraceIdHeader;
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert_eq!(TraceIdHeader::get(&headers), None);
+    /// headers.insert(
+    ///     TraceIdHeader::name(),
+    ///     HeaderValue::from_static("2e372a3a-9dff-4c61-8678-753bbdf4295e"),
+    /// );
+    /// assert_eq!(
+    ///     TraceIdHeader::get(&headers),
+    ///     Some("2e372a3a-9dff-4c61-8678-753bbdf4295e".parse().unwrap())
+    /// );
+    /// ```
+    #[must_use]
+    pub fn get(headers: &HeaderMap) -> Option<Uuid> {
+        get_header(headers, Self::name()).map_or_else(|| None, |s| Uuid::parse_str(s).map_or_else(|_| None, Some))
+    }
+
+    /// Get the trace id header value without parsing it. Use this to preserve trace ids of other
+    /// formats than a uuid, e.g. a W3C traceparent. Empty values are ignored.
+    ///
+    /// ```
+    /// use hyper::{header::HeaderValue, HeaderMap};
+    /// use mqs_common::TraceIdHeader;
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert_eq!(TraceIdHeader::get_raw(&headers), None);
+    /// headers.insert(
+    ///     TraceIdHeader::name(),
+    ///     HeaderValue::from_static("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"),
+    /// );
+    /// assert_eq!(
+    ///     TraceIdHeader::get_raw(&headers),
+    ///     Some("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01")
+    /// );
+    /// assert_eq!(TraceIdHeader::get(&headers), None);
+    /// ```
+    #[must_use]
+    pub fn get_raw(headers: &HeaderMap) -> Option<&str> {
+        get_header(headers, Self::name()).filter(|trace_id| !trace_id.is_empty())
+    }
+}
+
+/// Get a single header and convert it to a string.
+///
+/// ```
+/// use hyper::{
+///     header::{HeaderValue, CONTENT_TYPE},
+///     HeaderMap,
+/// };
+/// use mqs_common::get_header;
+///
+/// let mut headers = HeaderMap::new();
+/// assert_eq!(get_header(&headers, CONTENT_TYPE), None);
+/// headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+/// assert_eq!(get_header(&headers, CONTENT_TYPE), Some("text/plain"));
+/// ```
+pub fn get_header(headers: &HeaderMap, header: HeaderName) -> Option<&str> {
+    headers
+        .get(header)
+        .map_or_else(|| None, |v| v.to_str().map_or_else(|_| None, Some))
+}
+
+/// Queue configuration send to the server by the client.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct QueueConfig {
+    /// Redrive policy of the queue.
+    pub redrive_policy:        Option<QueueRedrivePolicy>,
+    /// Number of seconds until a message will no longer be returned.
+    pub retention_timeout:     i64,
+    /// Number of seconds a message will be hidden after it was received.
+    pub visibility_timeout:    i64,
+    /// Number of seconds a message will be hidden after it was published.
+    pub message_delay:         i64,
+    /// Whether duplicate messages in a queue will be dropped.
+    pub message_deduplication: bool,
+    /// Content types accepted by the queue. If empty, messages of any content type are accepted.
+    #[serde(default)]
+    pub allowed_content_types: Vec<String>,
+    /// Content type of messages published to the queue without a content type. If not set,
+    /// `application/octet-stream` is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_content_type:  Option<String>,
+    /// Maximum number of messages of the queue which may be in flight at the same time, i.e. which
+    /// were received and are still invisible. Receives return fewer messages than requested, or none
+    /// at all, once the limit is reached. If not set, the number of messages in flight is not limited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_in_flight:         Option<i64>,
+    /// Maximum number of times a message may be redriven to another queue. A message which reaches
+    /// the maximum number of receives after it was redriven this often is still delivered, but then
+    /// deleted instead of being moved to the dead letter queue again. If not set, messages can be
+    /// redriven any number of times.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_redrives:          Option<i32>,
+    /// Percentage by which the visibility timeout of received messages is randomly extended, so
+    /// messages received together don't become visible again at the same instant. If not set, the
+    /// visibility timeout is not extended.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visibility_jitter:     Option<i32>,
+    /// Whether messages are received in the order of their priority instead of the order they were
+    /// published in. Messages published without a priority have priority 0.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub message_priorities:    bool,
+    /// Arbitrary key/value tags of the queue. They are stored with the queue, but don't change its
+    /// behavior.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags:                  BTreeMap<String, String>,
+}
+
+/// Queue description returned from the server.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct QueueDescriptionOutput {
+    /// Name of the queue.
+    pub name:                  String,
+    /// Redrive policy of the queue.
+    pub redrive_policy:        Option<QueueRedrivePolicy>,
+    /// Number of seconds a message will be hidden after it was received.
+    pub retention_timeout:     i64,
+    /// Number of seconds a message will be hidden after it was received.
+    pub visibility_timeout:    i64,
+    /// Number of seconds a message will be hidden after it was published.
+    pub message_delay:         i64,
+    /// Whether duplicate messages in a queue will be dropped.
+    pub message_deduplication: bool,
+    /// Content types accepted by the queue. If empty, messages of any content type are accepted.
+    #[serde(default)]
+    pub allowed_content_types: Vec<String>,
+    /// Content type of messages published to the queue without a content type. If not set,
+    /// `application/octet-stream` is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_content_type:  Option<String>,
+    /// Maximum number of messages of the queue which may be in flight at the same time, i.e. which
+    /// were received and are still invisible. Receives return fewer messages than requested, or none
+    /// at all, once the limit is reached. If not set, the number of messages in flight is not limited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_in_flight:         Option<i64>,
+    /// Maximum number of times a message may be redriven to another queue. A message which reaches
+    /// the maximum number of receives after it was redriven this often is still delivered, but then
+    /// deleted instead of being moved to the dead letter queue again. If not set, messages can be
+    /// redriven any number of times.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_redrives:          Option<i32>,
+    /// Percentage by which the visibility timeout of received messages is randomly extended, so
+    /// messages received together don't become visible again at the same instant. If not set, the
+    /// visibility timeout is not extended.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visibility_jitter:     Option<i32>,
+    /// Whether messages are received in the order of their priority instead of the order they were
+    /// published in. Messages published without a priority have priority 0.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub message_priorities:    bool,
+    /// Arbitrary key/value tags of the queue. They are stored with the queue, but don't change its
+    /// behavior.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags:                  BTreeMap<String, String>,
+    /// Version of the queue configuration. Changes whenever the queue is updated and can be sent in
+    /// an `If-Match` header to reject updates of a queue which was modified concurrently.
+    #[serde(default)]
+    pub version:               Option<String>,
+    /// Information about messages currently in the queue. All zero if the status was not requested.
+    #[serde(default)]
+    pub status:                QueueStatus,
+}
+
+/// Redrive policy of a queue.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct QueueRedrivePolicy {
+    /// Number of receives after which a message will be moved to the dead letter queue.
+    pub max_receives:      i32,
+    /// Name of the dead letter queue.
+    pub dead_letter_queue: String,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+impl QueueConfig {
+    /// Configuration of a basic queue without a dead letter queue. Messages are kept for an hour,
+    /// hidden for 30 seconds after they were received and visible right after they were published.
+    /// Duplicate messages are not dropped and messages of any content type are accepted.
+    ///
+    /// Use struct update syntax to change single settings:
+    ///
+    /// ```
+    /// use mqs_common::QueueConfig;
+    ///
+    /// let config = QueueConfig {
+    ///     visibility_timeout: 300,
+    ///     ..QueueConfig::standard()
+    /// };
+    /// assert_eq!(config.retention_timeout, 3600);
+    /// assert_eq!(config.visibility_timeout, 300);
+    /// assert_eq!(config.validate(), Ok(()));
+    /// assert_eq!(QueueConfig::standard(), QueueConfig::default());
+    /// ```
+    #[must_use]
+    pub const fn standard() -> Self {
+        Self {
+            redrive_policy:        None,
+            retention_timeout:     3600,
+            visibility_timeout:    30,
+            message_delay:         0,
+            message_deduplication: false,
+            allowed_content_types: Vec::new(),
+            default_content_type:  None,
+            max_in_flight:         None,
+            max_redrives:          None,
+            visibility_jitter:     None,
+            message_priorities:    false,
+            tags:                  BTreeMap::new(),
+        }
+    }
+
+    /// Configuration of a basic queue (see [`QueueConfig::standard`]) which moves messages to the
+    /// dead letter queue `dead_letter_queue` after they were received `max_receives` times.
+    ///
+    /// ```
+    /// use mqs_common::{QueueConfig, QueueRedrivePolicy};
+    ///
+    /// let config = QueueConfig::with_dlq("my-queue-dead", 3);
+    /// assert_eq!(
+    ///     config.redrive_policy,
+    ///     Some(QueueRedrivePolicy {
+    ///         max_receives:      3,
+    ///         dead_letter_queue: "my-queue-dead".to_string(),
+    ///     })
+    /// );
+    /// assert_eq!(config.validate(), Ok(()));
+    /// ```
+    #[must_use]
+    pub fn with_dlq(dead_letter_queue: &str, max_receives: i32) -> Self {
+        Self {
+            redrive_policy: Some(QueueRedrivePolicy {
+                max_receives,
+                dead_letter_queue: dead_letter_queue.to_string(),
+            }),
+            ..Self::standard()
+        }
+    }
+
+    /// Check whether the configuration describes a valid queue.
+    ///
+    /// ```
+    /// use mqs_common::{QueueConfig, QueueConfigError, QueueRedrivePolicy};
+    /// use std::collections::BTreeMap;
+    ///
+    /// let mut config = QueueConfig {
+    ///     redrive_policy:        Some(QueueRedrivePolicy {
+    ///         max_receives:      3,
+    ///         dead_letter_queue: "my-queue-dead".to_string(),
+    ///     }),
+    ///     retention_timeout:     3600,
+    ///     visibility_timeout:    30,
+    ///     message_delay:         0,
+    ///     message_deduplication: false,
+    ///     allowed_content_types: Vec::new(),
+    ///     default_content_type:  None,
+    ///     max_in_flight:         None,
+    ///     max_redrives:          None,
+    ///     visibility_jitter:     None,
+    ///     message_priorities:    false,
+    ///     tags:                  BTreeMap::new(),
+    /// };
+    /// assert_eq!(config.validate(), Ok(()));
+    /// config.retention_timeout = 0;
+    /// assert_eq!(
+    ///     config.validate(),
+    ///     Err(QueueConfigError::InvalidRetentionTimeout)
+    /// );
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the first problem found with the configuration.
+    pub fn validate(&self) -> Result<(), QueueConfigError> {
+        if let Some(redrive_policy) = &self.redrive_policy {
+            if redrive_policy.max_receives < 1 {
+                return Err(QueueConfigError::InvalidMaxReceives);
+            }
+            if !is_valid_queue_name(&redrive_policy.dead_letter_queue) {
+                return Err(QueueConfigError::InvalidDeadLetterQueue);
+            }
+        }
+        if self.retention_timeout < 1 {
+            return Err(QueueConfigError::InvalidRetentionTimeout);
+        }
+        if self.visibility_timeout < 0 {
+            return Err(QueueConfigError::InvalidVisibilityTimeout);
+        }
+        if self.message_delay < 0 {
+            return Err(QueueConfigError::InvalidMessageDelay);
+        }
+        if matches!(self.max_in_flight, Some(max_in_flight) if max_in_flight < 1) {
+            return Err(QueueConfigError::InvalidMaxInFlight);
+        }
+        if matches!(self.max_redrives, Some(max_redrives) if max_redrives < 0) {
+            return Err(QueueConfigError::InvalidMaxRedrives);
+        }
+        if matches!(self.visibility_jitter, Some(visibility_jitter) if !(0..=100).contains(&visibility_jitter)) {
+            return Err(QueueConfigError::InvalidVisibilityJitter);
+        }
+
+        Ok(())
+    }
+}
+
+impl QueueRedrivePolicy {
+    /// Assemble a redrive policy from its optional parts. Either both or none of the parts have to
+    /// be given.
     ///
     /// ```
-    /// use mqs_common::TraceIdHeader;
+    /// use mqs_common::{QueueConfigError, QueueRedrivePolicy};
     ///
-    /// assert_eq!("X-TRACE-ID", &TraceIdHeader::upper());
+    /// assert_eq!(QueueRedrivePolicy::from_parts(None, None), Ok(None));
+    /// assert_eq!(
+    ///     QueueRedrivePolicy::from_parts(Some(3), None),
+    ///     Err(QueueConfigError::MissingDeadLetterQueue)
+    /// );
+    /// assert_eq!(
+    ///     QueueRedrivePolicy::from_parts(None, Some("my-queue-dead".to_string())),
+    ///     Err(QueueConfigError::MissingMaxReceives)
+    /// );
     /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if only one of the parts is given.
+    pub fn from_parts(
+        max_receives: Option<i32>,
+        dead_letter_queue: Option<String>,
+    ) -> Result<Option<Self>, QueueConfigError> {
+        match (max_receives, dead_letter_queue) {
+            (Some(max_receives), Some(dead_letter_queue)) => Ok(Some(Self {
+                max_receives,
+                dead_letter_queue,
+            })),
+            (Some(_), None) => Err(QueueConfigError::MissingDeadLetterQueue),
+            (None, Some(_)) => Err(QueueConfigError::MissingMaxReceives),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+/// Configuration of a queue and its dead letter queue, which are created together.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct QueueWithDlqConfig {
+    /// Configuration of the queue. Its redrive policy names the dead letter queue.
+    pub queue:             QueueConfig,
+    /// Configuration of the dead letter queue.
+    pub dead_letter_queue: QueueConfig,
+}
+
+impl QueueWithDlqConfig {
+    /// Get the name of the dead letter queue from the redrive policy of the queue, if it has one.
     #[must_use]
-    pub fn upper() -> String {
-        Self::name().as_str().to_uppercase()
+    pub fn dead_letter_queue_name(&self) -> Option<&str> {
+        self.queue
+            .redrive_policy
+            .as_ref()
+            .map(|policy| policy.dead_letter_queue.as_str())
     }
 
-    /// Get the trace id header value.
+    /// Check whether the configurations describe a valid queue named `queue_name` and its dead
+    /// letter queue. The queue needs a redrive policy naming the dead letter queue and the dead
+    /// letter queue must not redrive messages back to the queue or to itself.
     ///
     /// ```
-    /// use hyper::{header::HeaderValue, HeaderMap};
-    /// use mqs_common::TraceIdHeader;
+    /// use mqs_common::{QueueConfig, QueueConfigError, QueueRedrivePolicy, QueueWithDlqConfig};
     ///
-    /// let mut headers = HeaderMap::new();
-    /// assert_eq!(TraceIdHeader::get(&headers), None);
-    /// headers.insert(
-    ///     TraceIdHeader::name(),
-    ///     HeaderValue::from_static("2e372a3a-9dff-4c61-8678-753bbdf4295e"),
+    /// let config = |dead_letter_queue: Option<&str>| QueueConfig {
+    ///     redrive_policy:        dead_letter_queue.map(|dead_letter_queue| QueueRedrivePolicy {
+    ///         max_receives:      3,
+    ///         dead_letter_queue: dead_letter_queue.to_string(),
+    ///     }),
+    ///     retention_timeout:     3600,
+    ///     visibility_timeout:    30,
+    ///     message_delay:         0,
+    ///     message_deduplication: false,
+    ///     allowed_content_types: Vec::new(),
+    ///     default_content_type:  None,
+    ///     max_in_flight:         None,
+    ///     max_redrives:          None,
+    ///     visibility_jitter:     None,
+    ///     message_priorities:    false,
+    ///     tags:                  Default::default(),
+    /// };
+    /// let with_dlq = |queue, dead_letter_queue| QueueWithDlqConfig {
+    ///     queue:             config(queue),
+    ///     dead_letter_queue: config(dead_letter_queue),
+    /// };
+    ///
+    /// assert_eq!(
+    ///     with_dlq(Some("my-queue-dead"), None).validate("my-queue"),
+    ///     Ok(())
     /// );
     /// assert_eq!(
-    ///     TraceIdHeader::get(&headers),
-    ///     Some("2e372a3a-9dff-4c61-8678-753bbdf4295e".parse().unwrap())
+    ///     with_dlq(None, None).validate("my-queue"),
+    ///     Err(QueueConfigError::MissingRedrivePolicy)
+    /// );
+    /// assert_eq!(
+    ///     with_dlq(Some("my-queue"), None).validate("my-queue"),
+    ///     Err(QueueConfigError::RedriveCycle)
+    /// );
+    /// assert_eq!(
+    ///     with_dlq(Some("my-queue-dead"), Some("my-queue")).validate("my-queue"),
+    ///     Err(QueueConfigError::RedriveCycle)
     /// );
     /// ```
-    #[must_use]
-    pub fn get(headers: &HeaderMap) -> Option<Uuid> {
-        get_header(headers, Self::name()).map_or_else(|| None, |s| Uuid::parse_str(s).map_or_else(|_| None, Some))
-    }
-}
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the configurations is invalid, the queue has no redrive policy,
+    /// or the redrive policies form a cycle.
+    pub fn validate(&self, queue_name: &str) -> Result<(), QueueConfigError> {
+        self.queue.validate()?;
+        self.dead_letter_queue.validate()?;
+        let dead_letter_queue = self
+            .dead_letter_queue_name()
+            .ok_or(QueueConfigError::MissingRedrivePolicy)?;
+        if dead_letter_queue == queue_name {
+            return Err(QueueConfigError::RedriveCycle);
+        }
+        if let Some(policy) = &self.dead_letter_queue.redrive_policy {
+            if policy.dead_letter_queue == queue_name || policy.dead_letter_queue == dead_letter_queue {
+                return Err(QueueConfigError::RedriveCycle);
+            }
+        }
 
-/// Get a single header and convert it to a string.
-///
-/// ```
-/// use hyper::{
-///     header::{HeaderValue, CONTENT_TYPE},
-///     HeaderMap,
-/// };
-/// use mqs_common::get_header;
-///
-/// let mut headers = HeaderMap::new();
-/// assert_eq!(get_header(&headers, CONTENT_TYPE), None);
-/// headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
-/// assert_eq!(get_header(&headers, CONTENT_TYPE), Some("text/plain"));
-/// ```
-pub fn get_header(headers: &HeaderMap, header: HeaderName) -> Option<&str> {
-    headers
-        .get(header)
-        .map_or_else(|| None, |v| v.to_str().map_or_else(|_| None, Some))
+        Ok(())
+    }
 }
 
-/// Queue configuration send to the server by the client.
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
-pub struct QueueConfig {
-    /// Redrive policy of the queue.
-    pub redrive_policy:        Option<QueueRedrivePolicy>,
-    /// Number of seconds until a message will no longer be returned.
-    pub retention_timeout:     i64,
-    /// Number of seconds a message will be hidden after it was received.
-    pub visibility_timeout:    i64,
-    /// Number of seconds a message will be hidden after it was published.
-    pub message_delay:         i64,
-    /// Whether duplicate messages in a queue will be dropped.
-    pub message_deduplication: bool,
+/// A `QueueConfigError` describes why a queue configuration is invalid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueConfigError {
+    /// A maximum number of receives was given without a dead letter queue.
+    MissingDeadLetterQueue,
+    /// A dead letter queue was given without a maximum number of receives.
+    MissingMaxReceives,
+    /// The maximum number of receives is not positive.
+    InvalidMaxReceives,
+    /// The name of the dead letter queue is not a valid queue name.
+    InvalidDeadLetterQueue,
+    /// The retention timeout is not positive.
+    InvalidRetentionTimeout,
+    /// The visibility timeout is negative.
+    InvalidVisibilityTimeout,
+    /// The message delay is negative.
+    InvalidMessageDelay,
+    /// The maximum number of messages in flight is not positive.
+    InvalidMaxInFlight,
+    /// The maximum number of redrives is negative.
+    InvalidMaxRedrives,
+    /// The visibility jitter is not a percentage between 0 and 100.
+    InvalidVisibilityJitter,
+    /// A queue created together with its dead letter queue has no redrive policy.
+    MissingRedrivePolicy,
+    /// The dead letter queue redrives messages back to the queue or to itself.
+    RedriveCycle,
 }
 
-/// Queue description returned from the server.
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub struct QueueDescriptionOutput {
-    /// Name of the queue.
-    pub name:                  String,
-    /// Redrive policy of the queue.
-    pub redrive_policy:        Option<QueueRedrivePolicy>,
-    /// Number of seconds a message will be hidden after it was received.
-    pub retention_timeout:     i64,
-    /// Number of seconds a message will be hidden after it was received.
-    pub visibility_timeout:    i64,
-    /// Number of seconds a message will be hidden after it was published.
-    pub message_delay:         i64,
-    /// Whether duplicate messages in a queue will be dropped.
-    pub message_deduplication: bool,
-    /// Information about messages currently in the queue.
-    pub status:                QueueStatus,
+impl Display for QueueConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingDeadLetterQueue => write!(
+                f,
+                "A dead letter queue has to be specified together with the maximum number of receives"
+            ),
+            Self::MissingMaxReceives => write!(
+                f,
+                "The maximum number of receives has to be specified together with the dead letter queue"
+            ),
+            Self::InvalidMaxReceives => write!(f, "The maximum number of receives has to be at least 1"),
+            Self::InvalidDeadLetterQueue => write!(f, "The name of the dead letter queue is not a valid queue name"),
+            Self::InvalidRetentionTimeout => write!(f, "The retention timeout has to be at least 1 second"),
+            Self::InvalidVisibilityTimeout => write!(f, "The visibility timeout must not be negative"),
+            Self::InvalidMessageDelay => write!(f, "The message delay must not be negative"),
+            Self::InvalidMaxInFlight => write!(f, "The maximum number of messages in flight has to be at least 1"),
+            Self::InvalidMaxRedrives => write!(f, "The maximum number of redrives must not be negative"),
+            Self::InvalidVisibilityJitter => write!(f, "The visibility jitter has to be between 0 and 100 percent"),
+            Self::MissingRedrivePolicy => write!(
+                f,
+                "A redrive policy naming the dead letter queue has to be specified to create it together with the queue"
+            ),
+            Self::RedriveCycle => write!(
+                f,
+                "The dead letter queue must not redrive messages back to the queue or to itself"
+            ),
+        }
+    }
 }
 
-/// Redrive policy of a queue.
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
-pub struct QueueRedrivePolicy {
-    /// Number of receives after which a message will be moved to the dead letter queue.
-    pub max_receives:      i32,
-    /// Name of the dead letter queue.
-    pub dead_letter_queue: String,
-}
+impl Error for QueueConfigError {}
 
 /// Description of the current status of a queue.
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
 pub struct QueueStatus {
     /// Number of messages currently in the queue.
-    pub messages:           i64,
+    pub messages:                i64,
     /// Number of messages which can currently be received in the queue.
-    pub visible_messages:   i64,
+    pub visible_messages:        i64,
     /// Age in seconds of the oldest message in the queue.
-    pub oldest_message_age: u64,
+    pub oldest_message_age:      u64,
+    /// Distribution of the ages of the messages in the queue. Only computed if requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub age_percentiles:         Option<AgePercentiles>,
+    /// Number of messages currently in the dead letter queue of the queue. Only set if the queue
+    /// has a redrive policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_letter_queue_depth: Option<i64>,
+}
+
+/// Percentiles of the ages in seconds of the messages in a queue. The percentiles are computed
+/// from a bounded sample of the messages, so they are only approximate for large queues.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
+pub struct AgePercentiles {
+    /// Median age of the messages in the queue.
+    pub p50: u64,
+    /// Age which 90 percent of the messages in the queue do not exceed.
+    pub p90: u64,
+    /// Age which 99 percent of the messages in the queue do not exceed.
+    pub p99: u64,
+}
+
+impl AgePercentiles {
+    /// Compute the percentiles of the given message ages using the nearest-rank method. All
+    /// percentiles are zero if no ages are given.
+    ///
+    /// ```
+    /// use mqs_common::AgePercentiles;
+    ///
+    /// let ages = (1..=100).rev().collect();
+    /// assert_eq!(AgePercentiles::from_ages(ages), AgePercentiles {
+    ///     p50: 50,
+    ///     p90: 90,
+    ///     p99: 99,
+    /// });
+    /// assert_eq!(
+    ///     AgePercentiles::from_ages(Vec::new()),
+    ///     AgePercentiles::default()
+    /// );
+    /// ```
+    #[must_use]
+    pub fn from_ages(mut ages: Vec<u64>) -> Self {
+        ages.sort_unstable();
+        let percentile = |p: usize| {
+            // nearest-rank: the smallest age such that p percent of the ages are at most as large
+            let rank = (p * ages.len()).div_ceil(100);
+            ages.get(rank.saturating_sub(1)).copied().unwrap_or(0)
+        };
+
+        Self {
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+        }
+    }
 }
 
 /// Queue configuration as returned by the server.
@@ -417,6 +1584,50 @@ pub struct QueueConfigOutput {
     pub message_delay:         i64,
     /// Whether duplicate messages in a queue will be dropped.
     pub message_deduplication: bool,
+    /// Content types accepted by the queue. If empty, messages of any content type are accepted.
+    #[serde(default)]
+    pub allowed_content_types: Vec<String>,
+    /// Content type of messages published to the queue without a content type. If not set,
+    /// `application/octet-stream` is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_content_type:  Option<String>,
+    /// Maximum number of messages of the queue which may be in flight at the same time, i.e. which
+    /// were received and are still invisible. Receives return fewer messages than requested, or none
+    /// at all, once the limit is reached. If not set, the number of messages in flight is not limited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_in_flight:         Option<i64>,
+    /// Maximum number of times a message may be redriven to another queue. A message which reaches
+    /// the maximum number of receives after it was redriven this often is still delivered, but then
+    /// deleted instead of being moved to the dead letter queue again. If not set, messages can be
+    /// redriven any number of times.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_redrives:          Option<i32>,
+    /// Percentage by which the visibility timeout of received messages is randomly extended, so
+    /// messages received together don't become visible again at the same instant. If not set, the
+    /// visibility timeout is not extended.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visibility_jitter:     Option<i32>,
+    /// Whether messages are received in the order of their priority instead of the order they were
+    /// published in. Messages published without a priority have priority 0.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub message_priorities:    bool,
+    /// Arbitrary key/value tags of the queue. They are stored with the queue, but don't change its
+    /// behavior.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags:                  BTreeMap<String, String>,
+    /// Version of the queue configuration. Changes whenever the queue is updated and can be sent in
+    /// an `If-Match` header to reject updates of a queue which was modified concurrently.
+    #[serde(default)]
+    pub version:               Option<String>,
+}
+
+/// A queue and its dead letter queue after they were created together, as returned by the server.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub struct QueueWithDlqOutput {
+    /// Configuration of the created queue.
+    pub queue:             QueueConfigOutput,
+    /// Configuration of the created dead letter queue.
+    pub dead_letter_queue: QueueConfigOutput,
 }
 
 // Hack to get clippy to shut up about about possible constant functions for into_description.
@@ -439,6 +1650,7 @@ impl QueueConfigOutput {
     ///
     /// ```
     /// use mqs_common::{QueueConfigOutput, QueueDescriptionOutput, QueueRedrivePolicy, QueueStatus};
+    /// use std::collections::BTreeMap;
     ///
     /// let output = QueueConfigOutput {
     ///     name:                  "queue".to_string(),
@@ -450,6 +1662,14 @@ impl QueueConfigOutput {
     ///     visibility_timeout:    30,
     ///     message_delay:         0,
     ///     message_deduplication: true,
+    ///     allowed_content_types: vec!["application/json".to_string()],
+    ///     default_content_type:  None,
+    ///     max_in_flight:         None,
+    ///     max_redrives:          None,
+    ///     visibility_jitter:     None,
+    ///     message_priorities:    false,
+    ///     tags:                  BTreeMap::new(),
+    ///     version:               Some("2020-05-24T10:00:00.000000000Z".to_string()),
     /// };
     /// let description = output.into_description(10, 3, 50);
     /// assert_eq!(description, QueueDescriptionOutput {
@@ -462,10 +1682,20 @@ impl QueueConfigOutput {
     ///     visibility_timeout:    30,
     ///     message_delay:         0,
     ///     message_deduplication: true,
+    ///     allowed_content_types: vec!["application/json".to_string()],
+    ///     default_content_type:  None,
+    ///     max_in_flight:         None,
+    ///     max_redrives:          None,
+    ///     visibility_jitter:     None,
+    ///     message_priorities:    false,
+    ///     tags:                  BTreeMap::new(),
+    ///     version:               Some("2020-05-24T10:00:00.000000000Z".to_string()),
     ///     status:                QueueStatus {
-    ///         messages:           10,
-    ///         visible_messages:   3,
-    ///         oldest_message_age: 50,
+    ///         messages:                10,
+    ///         visible_messages:        3,
+    ///         oldest_message_age:      50,
+    ///         age_percentiles:         None,
+    ///         dead_letter_queue_depth: None,
     ///     },
     /// });
     /// ```
@@ -483,13 +1713,67 @@ impl QueueConfigOutput {
             visibility_timeout:    self.visibility_timeout,
             message_delay:         self.message_delay,
             message_deduplication: self.message_deduplication,
+            allowed_content_types: extract(&mut self.allowed_content_types),
+            default_content_type:  extract(&mut self.default_content_type),
+            max_in_flight:         self.max_in_flight,
+            max_redrives:          self.max_redrives,
+            visibility_jitter:     self.visibility_jitter,
+            message_priorities:    self.message_priorities,
+            tags:                  extract(&mut self.tags),
+            version:               extract(&mut self.version),
             status:                QueueStatus {
                 messages,
                 visible_messages,
                 oldest_message_age,
+                age_percentiles: None,
+                dead_letter_queue_depth: None,
             },
         }
     }
+
+    /// Convert a `QueueConfigOutput` into the `QueueConfig` which would configure the queue the
+    /// same way, e.g. to change some settings and send it back to the server. The name and the
+    /// version of the queue are dropped.
+    ///
+    /// ```
+    /// use mqs_common::{QueueConfig, QueueConfigOutput};
+    /// use std::collections::BTreeMap;
+    ///
+    /// let output = QueueConfigOutput {
+    ///     name:                  "queue".to_string(),
+    ///     redrive_policy:        None,
+    ///     retention_timeout:     3600,
+    ///     visibility_timeout:    30,
+    ///     message_delay:         0,
+    ///     message_deduplication: false,
+    ///     allowed_content_types: Vec::new(),
+    ///     default_content_type:  None,
+    ///     max_in_flight:         None,
+    ///     max_redrives:          None,
+    ///     visibility_jitter:     None,
+    ///     message_priorities:    false,
+    ///     tags:                  BTreeMap::new(),
+    ///     version:               Some("2020-05-24T10:00:00.000000000Z".to_string()),
+    /// };
+    /// assert_eq!(output.into_config(), QueueConfig::standard());
+    /// ```
+    #[must_use]
+    pub fn into_config(mut self) -> QueueConfig {
+        QueueConfig {
+            redrive_policy:        extract(&mut self.redrive_policy),
+            retention_timeout:     self.retention_timeout,
+            visibility_timeout:    self.visibility_timeout,
+            message_delay:         self.message_delay,
+            message_deduplication: self.message_deduplication,
+            allowed_content_types: extract(&mut self.allowed_content_types),
+            default_content_type:  extract(&mut self.default_content_type),
+            max_in_flight:         self.max_in_flight,
+            max_redrives:          self.max_redrives,
+            visibility_jitter:     self.visibility_jitter,
+            message_priorities:    self.message_priorities,
+            tags:                  extract(&mut self.tags),
+        }
+    }
 }
 
 /// Response for a queue list request.
@@ -501,6 +1785,335 @@ pub struct QueuesResponse {
     pub total:  i64,
 }
 
+impl QueuesResponse {
+    /// Convert the response to a page of queues. The offset and limit are the ones sent with the
+    /// request, the server doesn't return them.
+    ///
+    /// ```
+    /// use mqs_common::QueuesResponse;
+    ///
+    /// let response: QueuesResponse = serde_json::from_str(
+    ///     r#"{"queues":[
+    ///         {"name":"a","redrive_policy":null,"retention_timeout":60,"visibility_timeout":30,"message_delay":0,"message_deduplication":false}
+    ///     ],"total":3}"#,
+    /// )
+    /// .unwrap();
+    /// let page = response.into_page(1, Some(1));
+    /// assert_eq!(page.items[0].name, "a");
+    /// assert_eq!(page.next_offset(), Some(2));
+    /// ```
+    #[must_use]
+    pub fn into_page(self, offset: usize, limit: Option<usize>) -> Page<QueueConfigOutput> {
+        Page {
+            items: self.queues,
+            total: self.total,
+            offset,
+            limit,
+        }
+    }
+
+    /// Sort the queues by their name.
+    ///
+    /// ```
+    /// use mqs_common::QueuesResponse;
+    ///
+    /// let response: QueuesResponse = serde_json::from_str(
+    ///     r#"{"queues":[
+    ///         {"name":"b","redrive_policy":null,"retention_timeout":60,"visibility_timeout":30,"message_delay":0,"message_deduplication":false},
+    ///         {"name":"a","redrive_policy":null,"retention_timeout":60,"visibility_timeout":30,"message_delay":0,"message_deduplication":false}
+    ///     ],"total":2}"#,
+    /// )
+    /// .unwrap();
+    /// let names: Vec<String> = response
+    ///     .sorted_by_name()
+    ///     .queues
+    ///     .iter()
+    ///     .map(|queue| queue.name.clone())
+    ///     .collect();
+    /// assert_eq!(names, vec!["a".to_string(), "b".to_string()]);
+    /// ```
+    #[must_use]
+    pub fn sorted_by_name(mut self) -> Self {
+        self.queues.sort_by(|a, b| a.name.cmp(&b.name));
+        self
+    }
+
+    /// Sort the queues by the number of messages they contain, deepest queue first. The number of
+    /// messages is taken from the given descriptions (e.g. from `describe_queue`). Queues without
+    /// a description are sorted last, queues with the same depth are sorted by name.
+    ///
+    /// ```
+    /// use mqs_common::{QueueDescriptionOutput, QueuesResponse};
+    ///
+    /// let response: QueuesResponse = serde_json::from_str(
+    ///     r#"{"queues":[
+    ///         {"name":"a","redrive_policy":null,"retention_timeout":60,"visibility_timeout":30,"message_delay":0,"message_deduplication":false},
+    ///         {"name":"b","redrive_policy":null,"retention_timeout":60,"visibility_timeout":30,"message_delay":0,"message_deduplication":false},
+    ///         {"name":"c","redrive_policy":null,"retention_timeout":60,"visibility_timeout":30,"message_delay":0,"message_deduplication":false}
+    ///     ],"total":3}"#,
+    /// )
+    /// .unwrap();
+    /// let descriptions: Vec<QueueDescriptionOutput> = serde_json::from_str(
+    ///     r#"[
+    ///         {"name":"a","redrive_policy":null,"retention_timeout":60,"visibility_timeout":30,"message_delay":0,"message_deduplication":false,"status":{"messages":3,"visible_messages":3,"oldest_message_age":5}},
+    ///         {"name":"c","redrive_policy":null,"retention_timeout":60,"visibility_timeout":30,"message_delay":0,"message_deduplication":false,"status":{"messages":10,"visible_messages":0,"oldest_message_age":5}}
+    ///     ]"#,
+    /// )
+    /// .unwrap();
+    /// let names: Vec<String> = response
+    ///     .sorted_by_depth(&descriptions)
+    ///     .queues
+    ///     .iter()
+    ///     .map(|queue| queue.name.clone())
+    ///     .collect();
+    /// assert_eq!(names, vec![
+    ///     "c".to_string(),
+    ///     "a".to_string(),
+    ///     "b".to_string()
+    /// ]);
+    /// ```
+    #[must_use]
+    pub fn sorted_by_depth(mut self, descriptions: &[QueueDescriptionOutput]) -> Self {
+        let depth = |name: &str| {
+            descriptions
+                .iter()
+                .find(|description| description.name == name)
+                .map(|description| description.status.messages)
+        };
+        self.queues
+            .sort_by(|a, b| depth(&b.name).cmp(&depth(&a.name)).then_with(|| a.name.cmp(&b.name)));
+        self
+    }
+}
+
+impl From<Page<QueueConfigOutput>> for QueuesResponse {
+    fn from(page: Page<QueueConfigOutput>) -> Self {
+        Self {
+            queues: page.items,
+            total:  page.total,
+        }
+    }
+}
+
+/// Response for a queue list request asking for the status of each queue.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QueuesWithStatusResponse {
+    /// List of queues with their status, might be less than `total` if limit or offset was set.
+    pub queues: Vec<QueueDescriptionOutput>,
+    /// Total number of queues known to the server.
+    pub total:  i64,
+}
+
+impl QueuesWithStatusResponse {
+    /// Convert the response to a page of queues. The offset and limit are the ones sent with the
+    /// request, the server doesn't return them.
+    ///
+    /// ```
+    /// use mqs_common::QueuesWithStatusResponse;
+    ///
+    /// let response: QueuesWithStatusResponse = serde_json::from_str(
+    ///     r#"{"queues":[
+    ///         {"name":"a","redrive_policy":null,"retention_timeout":60,"visibility_timeout":30,"message_delay":0,"message_deduplication":false,"status":{"messages":3,"visible_messages":1,"oldest_message_age":5}}
+    ///     ],"total":3}"#,
+    /// )
+    /// .unwrap();
+    /// let page = response.into_page(1, Some(1));
+    /// assert_eq!(page.items[0].status.messages, 3);
+    /// assert_eq!(page.next_offset(), Some(2));
+    /// ```
+    #[must_use]
+    pub fn into_page(self, offset: usize, limit: Option<usize>) -> Page<QueueDescriptionOutput> {
+        Page {
+            items: self.queues,
+            total: self.total,
+            offset,
+            limit,
+        }
+    }
+}
+
+impl From<Page<QueueDescriptionOutput>> for QueuesWithStatusResponse {
+    fn from(page: Page<QueueDescriptionOutput>) -> Self {
+        Self {
+            queues: page.items,
+            total:  page.total,
+        }
+    }
+}
+
+/// Maximum length of a queue name in bytes.
+pub const MAX_QUEUE_NAME_LENGTH: usize = 80;
+
+/// Check whether a queue name is valid. A queue name has to consist of 1 to
+/// `MAX_QUEUE_NAME_LENGTH` ASCII letters, digits, dashes (`-`), and underscores (`_`).
+///
+/// ```
+/// use mqs_common::is_valid_queue_name;
+///
+/// assert!(is_valid_queue_name("my-queue_1"));
+/// assert!(!is_valid_queue_name("my queue"));
+/// assert!(!is_valid_queue_name("my/queue"));
+/// assert!(!is_valid_queue_name(""));
+/// ```
+#[must_use]
+pub fn is_valid_queue_name(queue_name: &str) -> bool {
+    !queue_name.is_empty()
+        && queue_name.len() <= MAX_QUEUE_NAME_LENGTH
+        && queue_name
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_')
+}
+
+/// Reason why the server rejected a publish request. If a request is rejected, none of its messages
+/// are published.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum PublishRejection {
+    /// The request body is not a valid gzip stream or multipart document.
+    InvalidBody,
+    /// The request body exceeds the maximum message size of the server after it was decompressed.
+    TooLarge,
+    /// The queue does not accept the content type of a message.
+    UnsupportedContentType,
+    /// The expiry time of a message is not a valid RFC 3339 timestamp.
+    InvalidExpiryTime,
+    /// A message would expire after the retention timeout of the queue.
+    ExpiryExceedsRetention,
+    /// The publish time of a message is not a valid RFC 3339 timestamp.
+    InvalidPublishTime,
+    /// The publish time of a message is in the future.
+    PublishTimeInFuture,
+    /// The publish time of a message is earlier than the retention timeout of the queue allows.
+    PublishTimeExceedsRetention,
+    /// The JSON attributes of a message are not a valid JSON object.
+    InvalidAttributes,
+    /// The priority of a message is not a valid number.
+    InvalidPriority,
+}
+
+/// Body of the error response to a rejected publish request.
+///
+/// ```
+/// use mqs_common::{PublishRejection, PublishRejectionResponse};
+///
+/// let body = "{\"error\":\"Message publish time is in the future\",\"reason\":\"publish_time_in_future\"}";
+/// let response: PublishRejectionResponse = serde_json::from_str(body).unwrap();
+/// assert_eq!(response.reason, Some(PublishRejection::PublishTimeInFuture));
+///
+/// // requests rejected before they reach a queue only carry a message
+/// let response: PublishRejectionResponse = serde_json::from_str("{\"error\":\"Payload too large\"}").unwrap();
+/// assert_eq!(response.reason, None);
+/// ```
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct PublishRejectionResponse {
+    /// Description of the problem.
+    pub error:  String,
+    /// Reason for the rejection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<PublishRejection>,
+}
+
+/// Result of publishing a single message of a multipart publish request.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct PublishOutcome {
+    /// Whether a new message was created. False if the message was dropped as a duplicate of a
+    /// message already in the queue.
+    pub created:    bool,
+    /// Id assigned to the new message. `None` if no message was created.
+    #[serde(default)]
+    pub message_id: Option<String>,
+}
+
+/// Result of a multipart publish request.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct PublishResponse {
+    /// Outcome for each message, in the order of the messages in the request.
+    pub messages: Vec<PublishOutcome>,
+}
+
+/// Request to move a message to another queue.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct MoveMessageRequest {
+    /// Name of the queue the message should be moved to.
+    pub queue: String,
+}
+
+/// Request to rename a queue.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct RenameQueueRequest {
+    /// New name of the queue.
+    pub name: String,
+}
+
+/// Request to move the messages of a dead letter queue back to another queue.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct RedriveRequest {
+    /// Name of the queue the messages should be moved to, usually the queue they were moved from.
+    pub queue: String,
+    /// Maximum number of messages to move.
+    pub max:   u64,
+}
+
+/// Result of a `RedriveRequest`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RedriveResponse {
+    /// Number of messages moved to the target queue.
+    pub moved: u64,
+}
+
+/// Request to change when several messages become visible again.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ChangeVisibilityRequest {
+    /// Ids or receipt handles of the messages to change.
+    pub message_ids:        Vec<String>,
+    /// Number of seconds from now until the messages become visible again. Zero makes them visible
+    /// immediately.
+    pub visibility_timeout: u64,
+}
+
+/// Result of a `ChangeVisibilityRequest`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ChangeVisibilityResponse {
+    /// Whether the visibility of each message was changed, in the order of the message ids of the
+    /// request. False if the message does not exist, is not in flight or its id is not valid.
+    pub changed: Vec<bool>,
+}
+
+/// A message which was received recently and is hidden until its visibility timeout expires.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct InFlightMessage {
+    /// Id of the message.
+    pub message_id: String,
+    /// Time at which the message becomes visible again, formatted as RFC 3339.
+    pub visible_at: String,
+}
+
+/// Messages of a queue which are currently in flight.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct InFlightMessagesResponse {
+    /// Messages in flight, ordered by the time they become visible again. At most 1000 messages are
+    /// listed.
+    pub messages: Vec<InFlightMessage>,
+}
+
+/// Result of deleting all messages with a given content hash from a queue.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DeleteByHashResponse {
+    /// Number of deleted messages.
+    pub deleted: u64,
+}
+
+/// Version and uptime of a server.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ServerInfo {
+    /// Version of the server.
+    pub version:    String,
+    /// Time at which the server was started, formatted as RFC 3339.
+    pub started_at: String,
+    /// Number of seconds since the server was started.
+    pub uptime:     u64,
+}
+
 /// Read a request or response body into a vector. If `max_size` is set, no more than this number of bytes will be read.
 /// If more bytes would need to be read, `None` is returned insted of the body.
 ///
@@ -521,6 +2134,14 @@ pub struct QueuesResponse {
 ///
 /// If reading any chunk returns an error.
 pub async fn read_body(body: &mut Body, max_size: Option<usize>) -> Result<Option<Vec<u8>>, hyper::Error> {
+    if let Some(max_length) = max_size {
+        // the lower bound is known from the content-length header, so we can reject a body which is
+        // too large without reading it
+        if matches!(u64::try_from(max_length), Ok(max_length) if body.size_hint().lower() > max_length) {
+            return Ok(None);
+        }
+    }
+
     let mut chunks = Vec::new();
     let mut total_length = 0;
 
@@ -551,11 +2172,16 @@ pub mod test {
     use hyper::Body;
     #[cfg(test)]
     use hyper::HeaderMap;
+    #[cfg(test)]
+    use std::collections::BTreeMap;
     use tokio::runtime::{Builder, Runtime};
 
     #[cfg(test)]
     use crate::{
         get_header,
+        is_valid_queue_name,
+        QueueConfig,
+        QueueConfigError,
         QueueConfigOutput,
         QueueDescriptionOutput,
         QueueRedrivePolicy,
@@ -619,6 +2245,14 @@ pub mod test {
             visibility_timeout:    30,
             message_delay:         0,
             message_deduplication: true,
+            allowed_content_types: vec!["application/json".to_string()],
+            default_content_type:  Some("application/json".to_string()),
+            max_in_flight:         None,
+            max_redrives:          None,
+            visibility_jitter:     None,
+            message_priorities:    false,
+            tags:                  BTreeMap::new(),
+            version:               Some("2020-05-24T10:00:00.000000000Z".to_string()),
         };
         let description = output.into_description(10, 3, 50);
         assert_eq!(description, QueueDescriptionOutput {
@@ -631,14 +2265,134 @@ pub mod test {
             visibility_timeout:    30,
             message_delay:         0,
             message_deduplication: true,
+            allowed_content_types: vec!["application/json".to_string()],
+            default_content_type:  Some("application/json".to_string()),
+            max_in_flight:         None,
+            max_redrives:          None,
+            visibility_jitter:     None,
+            message_priorities:    false,
+            tags:                  BTreeMap::new(),
+            version:               Some("2020-05-24T10:00:00.000000000Z".to_string()),
             status:                QueueStatus {
-                messages:           10,
-                visible_messages:   3,
-                oldest_message_age: 50,
+                messages:                10,
+                visible_messages:        3,
+                oldest_message_age:      50,
+                age_percentiles:         None,
+                dead_letter_queue_depth: None,
             },
         });
     }
 
+    #[cfg(test)]
+    type SetField = fn(&mut QueueConfig);
+
+    #[test]
+    async fn queue_config_optional_fields() {
+        let json = "{\"redrive_policy\":null,\"retention_timeout\":3600,\"visibility_timeout\":30,\"message_delay\":0,\"message_deduplication\":false}";
+        let default_config: QueueConfig = serde_json::from_str(json).unwrap();
+        // optional fields are left out while they have their default value
+        assert_eq!(
+            serde_json::to_string(&default_config).unwrap(),
+            "{\"redrive_policy\":null,\"retention_timeout\":3600,\"visibility_timeout\":30,\"message_delay\":0,\"message_deduplication\":false,\"allowed_content_types\":[]}"
+        );
+
+        let cases: Vec<(SetField, &str)> = vec![
+            (
+                |config| {
+                    config.tags.insert("team".to_string(), "billing".to_string());
+                    config.tags.insert("cost-center".to_string(), "42".to_string());
+                },
+                ",\"tags\":{\"cost-center\":\"42\",\"team\":\"billing\"}}",
+            ),
+            (
+                |config| config.default_content_type = Some("application/json".to_string()),
+                ",\"default_content_type\":\"application/json\"}",
+            ),
+            (|config| config.max_in_flight = Some(100), ",\"max_in_flight\":100}"),
+            (|config| config.max_redrives = Some(0), ",\"max_redrives\":0}"),
+            (
+                |config| config.visibility_jitter = Some(20),
+                ",\"visibility_jitter\":20}",
+            ),
+            (
+                |config| config.message_priorities = true,
+                ",\"message_priorities\":true}",
+            ),
+        ];
+        for (set_field, expected_suffix) in cases {
+            let mut config = default_config.clone();
+            set_field(&mut config);
+            let encoded = serde_json::to_string(&config).unwrap();
+            assert!(
+                encoded.ends_with(expected_suffix),
+                "{} should end with {}",
+                encoded,
+                expected_suffix
+            );
+            assert_eq!(serde_json::from_str::<QueueConfig>(&encoded).unwrap(), config);
+        }
+    }
+
+    #[test]
+    async fn queue_config_validate() {
+        let json = "{\"redrive_policy\":null,\"retention_timeout\":3600,\"visibility_timeout\":30,\"message_delay\":0,\"message_deduplication\":false}";
+        let default_config: QueueConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(default_config.validate(), Ok(()));
+
+        let cases: Vec<(SetField, Result<(), QueueConfigError>)> = vec![
+            (|config| config.max_in_flight = Some(100), Ok(())),
+            (
+                |config| config.max_in_flight = Some(0),
+                Err(QueueConfigError::InvalidMaxInFlight),
+            ),
+            (|config| config.max_redrives = Some(0), Ok(())),
+            (
+                |config| config.max_redrives = Some(-1),
+                Err(QueueConfigError::InvalidMaxRedrives),
+            ),
+            (|config| config.visibility_jitter = Some(0), Ok(())),
+            (|config| config.visibility_jitter = Some(100), Ok(())),
+            (
+                |config| config.visibility_jitter = Some(-1),
+                Err(QueueConfigError::InvalidVisibilityJitter),
+            ),
+            (
+                |config| config.visibility_jitter = Some(101),
+                Err(QueueConfigError::InvalidVisibilityJitter),
+            ),
+        ];
+        for (set_field, expected) in cases {
+            let mut config = default_config.clone();
+            set_field(&mut config);
+            assert_eq!(config.validate(), expected, "{:?}", config);
+        }
+    }
+
+    #[test]
+    async fn queue_names() {
+        for name in &[
+            "a",
+            "my-queue",
+            "my_queue",
+            "Queue-01",
+            &"q".repeat(crate::MAX_QUEUE_NAME_LENGTH),
+        ] {
+            assert!(is_valid_queue_name(name), "{} should be valid", name);
+        }
+        for name in &[
+            "",
+            "my queue",
+            "my/queue",
+            "../queue",
+            "queue?",
+            "queue.name",
+            "quëue",
+            &"q".repeat(crate::MAX_QUEUE_NAME_LENGTH + 1),
+        ] {
+            assert!(!is_valid_queue_name(name), "{} should be invalid", name);
+        }
+    }
+
     #[test]
     async fn get_headers() {
         use hyper::header::{HeaderValue, CONTENT_TYPE};
@@ -666,5 +2420,14 @@ pub mod test {
             TraceIdHeader::get(&headers),
             Some("2e372a3a-9dff-4c61-8678-753bbdf4295e".parse().unwrap())
         );
+        assert_eq!(
+            TraceIdHeader::get_raw(&headers),
+            Some("2e372a3a-9dff-4c61-8678-753bbdf4295e")
+        );
+        headers.insert(TraceIdHeader::name(), HeaderValue::from_static("my-trace"));
+        assert_eq!(TraceIdHeader::get(&headers), None);
+        assert_eq!(TraceIdHeader::get_raw(&headers), Some("my-trace"));
+        headers.insert(TraceIdHeader::name(), HeaderValue::from_static(""));
+        assert_eq!(TraceIdHeader::get_raw(&headers), None);
     }
 }
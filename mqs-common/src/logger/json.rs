@@ -2,10 +2,21 @@ use log::{Level, Log, Metadata, Record};
 use std::{
     cell::Cell,
     io::{BufWriter, Write},
-    sync::Mutex,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
 };
 
-use crate::{logger::get_trace_id, UtcTime};
+use crate::{
+    logger::{get_trace_id, AUDIT_TARGET},
+    UtcTime,
+};
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_false(value: &bool) -> bool {
+    !*value
+}
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 struct LogMessage<'a> {
@@ -18,27 +29,43 @@ struct LogMessage<'a> {
     line:        Option<u32>,
     trace_id:    Option<String>,
     message:     String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    audit:       bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    event:       Option<serde_json::Value>,
 }
 
 impl<'a> LogMessage<'a> {
     fn build(record: &Record<'a>) -> Self {
+        let message = format!("{:?}", record.args());
+        // audit entries carry their event as json, include it as an object so it can be queried
+        let audit = record.target() == AUDIT_TARGET;
+        let event = if audit {
+            serde_json::from_str(&message).ok()
+        } else {
+            None
+        };
+
         LogMessage {
-            timestamp:   UtcTime::now(),
-            level:       record.level().to_string(),
-            level_num:   record.level() as i32,
-            target:      record.target(),
+            timestamp: UtcTime::now(),
+            level: record.level().to_string(),
+            level_num: record.level() as i32,
+            target: record.target(),
             module_path: record.module_path(),
-            file:        record.file(),
-            line:        record.line(),
-            trace_id:    get_trace_id().map(|id| id.to_string()),
-            message:     format!("{:?}", record.args()),
+            file: record.file(),
+            line: record.line(),
+            trace_id: get_trace_id().map(|id| id.to_string()),
+            message,
+            audit,
+            event,
         }
     }
 }
 
 /// A json logger which writes each log message as json encoded object on a new line.
 pub struct Logger<W: Write> {
-    level:  Level,
+    // the level is stored as a number so it can be changed while other threads are logging
+    level:  AtomicUsize,
     writer: Mutex<Cell<BufWriter<W>>>,
 }
 
@@ -55,7 +82,7 @@ impl<W: Write> Logger<W> {
     /// ```
     pub fn new(level: Level, writer: W) -> Self {
         Self {
-            level,
+            level:  AtomicUsize::new(level as usize),
             writer: Mutex::new(Cell::new(BufWriter::new(writer))),
         }
     }
@@ -71,35 +98,68 @@ impl<W: Write> Logger<W> {
     /// let logger = Logger::new(Level::Info, stdout());
     /// assert_eq!(logger.level(), Level::Info);
     /// ```
-    pub const fn level(&self) -> Level {
-        self.level
+    pub fn level(&self) -> Level {
+        match self.level.load(Ordering::Relaxed) {
+            1 => Level::Error,
+            2 => Level::Warn,
+            3 => Level::Info,
+            4 => Level::Debug,
+            _ => Level::Trace,
+        }
     }
 
     /// Change the current level of this logger. This is not the level configured by the logging library,
-    /// but an additional filter for messages which are reaching this logger!
+    /// but an additional filter for messages which are reaching this logger! The level can be changed
+    /// while the logger is in use, use `set_log_level` to change both levels at once.
     ///
     /// ```
     /// use log::Level;
     /// use mqs_common::logger::json::Logger;
     /// use std::io::stdout;
     ///
-    /// let mut logger = Logger::new(Level::Info, stdout());
+    /// let logger = Logger::new(Level::Info, stdout());
     /// assert_eq!(logger.level(), Level::Info);
     /// logger.set_level(Level::Warn);
     /// assert_eq!(logger.level(), Level::Warn);
     /// ```
-    pub fn set_level(&mut self, level: Level) {
-        self.level = level;
+    pub fn set_level(&self, level: Level) {
+        self.level.store(level as usize, Ordering::Relaxed);
+    }
+
+    /// Write all buffered messages to the underlying writer and flush it. Messages are buffered
+    /// until the buffer is full or the logger is flushed, so call this before the process exits.
+    /// The logger is also flushed when it is dropped.
+    ///
+    /// ```
+    /// use log::Level;
+    /// use mqs_common::logger::json::Logger;
+    /// use std::io::stdout;
+    ///
+    /// let logger = Logger::new(Level::Info, stdout());
+    /// logger.flush();
+    /// ```
+    pub fn flush(&self) {
+        if let Ok(mut writer) = self.writer.lock() {
+            // we ignore the result of the call as we can't handle an error here
+            drop(writer.get_mut().flush());
+        }
+    }
+}
+
+impl<W: Write> Drop for Logger<W> {
+    fn drop(&mut self) {
+        Self::flush(self);
     }
 }
 
 impl<W: Write + Send> Log for Logger<W> {
     fn enabled(&self, metadata: &Metadata<'_>) -> bool {
-        metadata.level() <= self.level
+        metadata.level() <= self.level()
     }
 
     fn log(&self, record: &Record<'_>) {
-        if self.enabled(record.metadata()) {
+        // audit entries are always written, regardless of the configured level
+        if self.enabled(record.metadata()) || record.target() == AUDIT_TARGET {
             let msg = LogMessage::build(record);
             if let Ok(mut line) = serde_json::to_vec(&msg) {
                 line.push(b'\n');
@@ -112,10 +172,7 @@ impl<W: Write + Send> Log for Logger<W> {
     }
 
     fn flush(&self) {
-        if let Ok(mut writer) = self.writer.lock() {
-            // we ignore the result of the call as we can't handle an error here
-            drop(writer.get_mut().flush());
-        }
+        Self::flush(self);
     }
 }
 
@@ -123,7 +180,7 @@ impl<W: Write + Send> Log for Logger<W> {
 mod test {
     use super::*;
     use crate::UtcTime;
-    use std::io::Error;
+    use std::{io::Error, sync::Arc};
 
     struct TestWriter {
         written: Vec<u8>,
@@ -158,6 +215,8 @@ mod test {
                     line:        parsed.line,
                     trace_id:    None,
                     message:     expected_messages[i].1.to_string(),
+                    audit:       false,
+                    event:       None,
                 });
             }
         }
@@ -180,8 +239,8 @@ mod test {
         }
     }
 
-    fn log(
-        logger: &Logger<TestWriter>,
+    fn log<W: Write + Send>(
+        logger: &Logger<W>,
         level: Level,
         message: &str,
         module_path: &'static str,
@@ -260,4 +319,107 @@ mod test {
                 (Level::Error, "This has to get logged, otherwise would be bad"),
             ]);
     }
+
+    #[test]
+    async fn audit_test() {
+        let logger = Logger::new(Level::Error, TestWriter::new());
+
+        logger.log(
+            &Record::builder()
+                .args(format_args!("{}", "{\"action\":\"create\"}"))
+                .level(Level::Info)
+                .target(AUDIT_TARGET)
+                .build(),
+        );
+        log(
+            &logger,
+            Level::Info,
+            "Should not appear",
+            module_path!(),
+            file!(),
+            line!(),
+        );
+        logger.flush();
+
+        let mut writer = logger.writer.lock().unwrap();
+        let written = String::from_utf8(writer.get_mut().get_ref().written.clone()).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let parsed: LogMessage<'_> = serde_json::from_str(lines[0]).unwrap();
+        assert!(parsed.audit);
+        assert_eq!(parsed.target, AUDIT_TARGET);
+        assert_eq!(parsed.event, Some(serde_json::json!({"action": "create"})));
+    }
+
+    #[test]
+    async fn change_level_test() {
+        let logger = Logger::new(Level::Warn, TestWriter::new());
+        let start_time = UtcTime::now();
+
+        log(&logger, Level::Debug, "Not yet", module_path!(), file!(), line!());
+        logger.set_level(Level::Debug);
+        assert_eq!(logger.level(), Level::Debug);
+        log(&logger, Level::Debug, "Now we debug", module_path!(), file!(), line!());
+        logger.set_level(Level::Error);
+        assert_eq!(logger.level(), Level::Error);
+        log(&logger, Level::Warn, "Quiet again", module_path!(), file!(), line!());
+        logger.flush();
+
+        logger
+            .writer
+            .lock()
+            .unwrap()
+            .get_mut()
+            .get_ref()
+            .assert_expectations(start_time, vec![(Level::Debug, "Now we debug")]);
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl SharedWriter {
+        fn content(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    async fn flush_test() {
+        let writer = SharedWriter::default();
+        let logger = Logger::new(Level::Info, writer.clone());
+
+        log(&logger, Level::Info, "Buffered", module_path!(), file!(), line!());
+        assert_eq!(writer.content(), "");
+        logger.flush();
+        let content = writer.content();
+        let parsed: LogMessage<'_> = serde_json::from_str(content.trim_end()).unwrap();
+        assert_eq!(parsed.message, "Buffered");
+
+        log(
+            &logger,
+            Level::Info,
+            "Written on drop",
+            module_path!(),
+            file!(),
+            line!(),
+        );
+        drop(logger);
+        let content = writer.content();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let parsed: LogMessage<'_> = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(parsed.message, "Written on drop");
+    }
 }
@@ -1,10 +1,12 @@
-use log::Level;
+use log::{Level, Record};
+use serde::Serialize;
 
 use crate::logger::json::Logger;
 use std::{
     borrow::Borrow,
     env,
     io::{stdout, Stdout, Write},
+    panic::{self, Location},
 };
 
 /// A logger implementation which writes each log messages as a json encoded object.
@@ -62,8 +64,70 @@ impl<Args> FnMut<Args> for NewJsonLogger {
 
 /// Set the given json logger as the current logger and set the log level to the level specified
 /// by the json logger.
+///
+/// A registered logger is never dropped, so it can't flush itself when the process exits. The logger
+/// is flushed if the process panics, otherwise call `flush_logger` before exiting.
 pub fn configure_logger<W: Write + Send>(logger: &'static Logger<W>) {
     log::set_logger(logger)
         .map(|()| log::set_max_level(logger.level().to_level_filter()))
         .expect("logger has already been configured once");
+
+    let previous_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // write buffered messages before the panic message, they most likely explain it
+        logger.flush();
+        previous_hook(info);
+    }));
+}
+
+/// Flush the logger registered with `configure_logger`, writing all buffered messages. Call this
+/// before the process exits, otherwise the last messages might be lost.
+pub fn flush_logger() {
+    log::logger().flush();
+}
+
+/// Change the level of the given json logger as well as the maximum level of the logging library.
+///
+/// This can be called at any time. Messages which were disabled before are logged immediately after
+/// raising the level, lowering it again disables them again.
+///
+/// ```
+/// use log::{Level, LevelFilter};
+/// use mqs_common::logger::{json::Logger, set_log_level};
+/// use std::io::stdout;
+///
+/// let logger = Logger::new(Level::Info, stdout());
+/// set_log_level(&logger, Level::Debug);
+/// assert_eq!(logger.level(), Level::Debug);
+/// assert_eq!(log::max_level(), LevelFilter::Debug);
+/// ```
+pub fn set_log_level<W: Write + Send>(logger: &Logger<W>, level: Level) {
+    logger.set_level(level);
+    log::set_max_level(level.to_level_filter());
+}
+
+/// Target of audit log entries. The json logger writes entries with this target regardless of its
+/// level and marks them with `"audit": true`.
+pub const AUDIT_TARGET: &str = "audit";
+
+/// Write an audit log entry for the given event. The event is serialized as json and included as
+/// `event` in the entry written by the json logger, together with the trace id of the current request.
+///
+/// Audit entries bypass the configured log level, so they are written even if info messages are
+/// disabled.
+#[track_caller]
+pub fn audit<T: Serialize>(event: &T) {
+    let location = Location::caller();
+    match serde_json::to_string(event) {
+        Err(err) => error!("Failed to serialize audit event: {}", err),
+        Ok(json) => log::logger().log(
+            &Record::builder()
+                .args(format_args!("{}", json))
+                .level(Level::Info)
+                .target(AUDIT_TARGET)
+                .file_static(Some(location.file()))
+                .line(Some(location.line()))
+                .build(),
+        ),
+    }
 }
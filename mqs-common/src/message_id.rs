@@ -0,0 +1,105 @@
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+use uuid::Uuid;
+
+/// A `MessageId` identifies a single message, independent of the queue it currently resides in.
+///
+/// Message ids are transmitted as strings, parse them with `FromStr` to validate them before sending
+/// them to the server:
+///
+/// ```
+/// use mqs_common::MessageId;
+///
+/// let id: MessageId = "96a372de-2db0-405b-a49e-fbcddcabefdb".parse().unwrap();
+/// assert_eq!(id.to_string(), "96a372de-2db0-405b-a49e-fbcddcabefdb");
+/// assert!("my-queue".parse::<MessageId>().is_err());
+/// ```
+#[derive(Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd, Debug, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct MessageId(Uuid);
+
+impl MessageId {
+    /// Create a message id from the given uuid.
+    #[must_use]
+    pub const fn new(id: Uuid) -> Self {
+        Self(id)
+    }
+
+    /// Get the uuid of the message id.
+    #[must_use]
+    pub const fn as_uuid(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+impl From<Uuid> for MessageId {
+    fn from(id: Uuid) -> Self {
+        Self(id)
+    }
+}
+
+impl From<MessageId> for Uuid {
+    fn from(id: MessageId) -> Self {
+        id.0
+    }
+}
+
+impl Display for MessageId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// An `InvalidMessageId` is returned when parsing a `MessageId` from a string which is not a valid
+/// uuid.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct InvalidMessageId(String);
+
+impl Display for InvalidMessageId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid message id '{}'", self.0)
+    }
+}
+
+impl Error for InvalidMessageId {}
+
+impl FromStr for MessageId {
+    type Err = InvalidMessageId;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::parse_str(s)
+            .map(Self)
+            .map_err(|_| InvalidMessageId(s.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    async fn parse_message_id() {
+        let uuid = Uuid::new_v4();
+        let id: MessageId = uuid.to_string().parse().unwrap();
+        assert_eq!(id, MessageId::new(uuid));
+        assert_eq!(id.as_uuid(), &uuid);
+        assert_eq!(Uuid::from(id), uuid);
+        assert_eq!(id.to_string(), uuid.to_string());
+        assert_eq!("".parse::<MessageId>(), Err(InvalidMessageId(String::new())));
+        assert_eq!(
+            "not-an-id".parse::<MessageId>().unwrap_err().to_string(),
+            "invalid message id 'not-an-id'"
+        );
+    }
+
+    #[test]
+    async fn serialize_message_id() {
+        let id: MessageId = "96a372de-2db0-405b-a49e-fbcddcabefdb".parse().unwrap();
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, "\"96a372de-2db0-405b-a49e-fbcddcabefdb\"");
+        assert_eq!(serde_json::from_str::<MessageId>(&json).unwrap(), id);
+    }
+}
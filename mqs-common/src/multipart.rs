@@ -1,36 +1,300 @@
+use futures_core::Stream;
 use hyper::{
-    header::{HeaderName, HeaderValue, InvalidHeaderName, InvalidHeaderValue},
+    header::{HeaderName, HeaderValue, InvalidHeaderName, InvalidHeaderValue, CONTENT_LENGTH, CONTENT_TYPE},
+    Body,
     HeaderMap,
 };
 use std::{
+    convert::Infallible,
     error::Error,
     fmt::{Display, Formatter},
+    pin::Pin,
+    task::{Context, Poll},
 };
 use uuid::Uuid;
 
-/// Encode data as a multipart/mixed document, return the boundary and the body
-pub fn encode<I: Iterator<Item = (HeaderMap, Vec<u8>)>>(messages: I) -> (String, Vec<u8>) {
-    let boundary = Uuid::new_v4().to_string();
-    let full_boundary = format!("--{}", &boundary).into_bytes();
+/// A multipart/mixed document together with the boundary separating its parts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Multipart {
+    boundary: String,
+    body:     Vec<u8>,
+}
+
+impl Multipart {
+    /// The boundary separating the parts of the document (without the leading "--").
+    #[must_use]
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// The encoded document.
+    #[must_use]
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Consume the document and return the encoded body.
+    #[must_use]
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+
+    /// Build the value of the content-type header for this document. The boundary is quoted if it
+    /// contains characters which are not allowed in an unquoted parameter value.
+    ///
+    /// ```
+    /// use mqs_common::multipart::{encode, is_multipart};
+    ///
+    /// let document = encode(Vec::new().into_iter());
+    /// let content_type = document.content_type_header();
+    /// assert_eq!(
+    ///     is_multipart(content_type.to_str().unwrap()),
+    ///     Some(format!("--{}", document.boundary()))
+    /// );
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Never, the boundary of an encoded document only consists of valid header characters.
+    #[must_use]
+    pub fn content_type_header(&self) -> HeaderValue {
+        content_type_header(&self.boundary)
+    }
+}
+
+fn content_type_header(boundary: &str) -> HeaderValue {
+    let needs_quotes = boundary
+        .bytes()
+        .any(|c| !c.is_ascii_graphic() || b"()<>@,;:\\\"/[]?=".contains(&c));
+    let value = if needs_quotes {
+        format!("multipart/mixed; boundary=\"{}\"", boundary)
+    } else {
+        format!("multipart/mixed; boundary={}", boundary)
+    };
+
+    HeaderValue::from_str(&value).expect("boundary should be a valid header value")
+}
+
+/// Encode data as a multipart/mixed document.
+///
+/// The boundary is a random uuid. Should it appear in one of the messages, a new boundary is
+/// generated, so the document can always be parsed again. The whole document is kept in memory.
+/// Use `encode_streaming` for large documents.
+pub fn encode<I: Iterator<Item = (HeaderMap, Vec<u8>)>>(messages: I) -> Multipart {
+    encode_with_new_boundary(messages.collect(), || Uuid::new_v4().to_string())
+}
+
+fn encode_with_new_boundary<B: FnMut() -> String>(messages: Vec<(HeaderMap, Vec<u8>)>, next_boundary: B) -> Multipart {
+    let boundary = new_boundary(&messages, next_boundary);
+
+    encode_with_boundary(messages.into_iter(), &boundary)
+}
+
+fn new_boundary<B: FnMut() -> String>(messages: &[(HeaderMap, Vec<u8>)], mut next_boundary: B) -> String {
+    loop {
+        let boundary = next_boundary();
+        if !messages
+            .iter()
+            .any(|(_, message)| contains(message, boundary.as_bytes()))
+        {
+            break boundary;
+        }
+    }
+}
+
+/// Encode data as a multipart/mixed document using the given boundary to separate the parts.
+///
+/// The boundary must not appear in any of the messages, otherwise the document can not be parsed
+/// correctly. Prefer `encode` unless you need a specific boundary.
+///
+/// ```
+/// use hyper::HeaderMap;
+/// use mqs_common::multipart::{encode_with_boundary, parse};
+///
+/// let parts = vec![(HeaderMap::new(), b"message".to_vec())];
+/// let document = encode_with_boundary(parts.into_iter(), "my-boundary");
+/// assert_eq!(document.boundary(), "my-boundary");
+/// assert_eq!(
+///     document.body(),
+///     b"--my-boundary\r\n\r\nmessage\r\n--my-boundary--"
+/// );
+/// let parsed = parse(b"--my-boundary", document.body()).unwrap();
+/// assert_eq!(parsed[0].1, b"message");
+/// ```
+pub fn encode_with_boundary<I: Iterator<Item = (HeaderMap, Vec<u8>)>>(messages: I, boundary: &str) -> Multipart {
+    let full_boundary = format!("--{}", boundary).into_bytes();
     let mut body = Vec::with_capacity(4096);
 
     for (headers, message) in messages {
-        body.extend_from_slice(full_boundary.as_slice());
-        body.extend_from_slice(b"\r\n");
-        for (header_name, header_value) in &headers {
-            body.extend_from_slice(header_name.as_str().as_bytes());
-            body.extend_from_slice(b": ");
-            body.extend_from_slice(header_value.as_bytes());
-            body.extend_from_slice(b"\r\n");
-        }
-        body.extend_from_slice(b"\r\n");
-        body.extend_from_slice(message.as_slice());
+        encode_part(&mut body, &full_boundary, &headers, &message);
+    }
+    encode_end(&mut body, &full_boundary);
+
+    Multipart {
+        boundary: boundary.to_string(),
+        body,
+    }
+}
+
+fn contains(data: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty() || data.windows(needle.len()).any(|window| window == needle)
+}
+
+fn encode_part(body: &mut Vec<u8>, full_boundary: &[u8], headers: &HeaderMap, message: &[u8]) {
+    body.extend_from_slice(full_boundary);
+    body.extend_from_slice(b"\r\n");
+    for (header_name, header_value) in headers {
+        body.extend_from_slice(header_name.as_str().as_bytes());
+        body.extend_from_slice(b": ");
+        body.extend_from_slice(header_value.as_bytes());
         body.extend_from_slice(b"\r\n");
     }
-    body.extend_from_slice(full_boundary.as_slice());
+    body.extend_from_slice(b"\r\n");
+    body.extend_from_slice(message);
+    body.extend_from_slice(b"\r\n");
+}
+
+// length of the data encode_part adds to the body
+fn encoded_part_len(full_boundary_len: usize, headers: &HeaderMap, message: &[u8]) -> usize {
+    let headers_len: usize = headers
+        .iter()
+        .map(|(header_name, header_value)| header_name.as_str().len() + header_value.len() + 4)
+        .sum();
+
+    full_boundary_len + 2 + headers_len + 2 + message.len() + 2
+}
+
+fn encode_end(body: &mut Vec<u8>, full_boundary: &[u8]) {
+    body.extend_from_slice(full_boundary);
     body.extend_from_slice(b"--");
+}
+
+/// A multipart/mixed document which is encoded lazily, one part at a time.
+///
+/// A `MultipartStream` yields the encoded document in chunks, one for each part and a final one
+/// for the closing boundary. The parts are only taken from the underlying iterator once the
+/// previous chunk has been consumed, so at most one part is encoded at any time. The chunks are
+/// available both as an `Iterator` and as a `Stream`, which can be used directly as a `Body`.
+///
+/// ```
+/// use hyper::{header::CONTENT_TYPE, HeaderMap};
+/// use mqs_common::multipart::{encode_streaming, is_multipart, parse};
+///
+/// let parts = (0..3)
+///     .map(|i| (HeaderMap::new(), format!("message {}", i).into_bytes()))
+///     .collect();
+/// let document = encode_streaming(parts);
+/// let boundary = is_multipart(document.content_type_header().to_str().unwrap()).unwrap();
+/// let body: Vec<u8> = document.flatten().collect();
+/// let parsed = parse(boundary.as_bytes(), &body).unwrap();
+/// assert_eq!(parsed.len(), 3);
+/// assert_eq!(parsed[2].1, b"message 2");
+/// ```
+#[derive(Debug)]
+pub struct MultipartStream<I> {
+    boundary:      String,
+    full_boundary: Vec<u8>,
+    parts:         Option<I>,
+}
 
-    (boundary, body)
+impl<I> MultipartStream<I> {
+    /// The boundary separating the parts of the document (without the leading "--").
+    #[must_use]
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Build the value of the content-type header for this document. See
+    /// `Multipart::content_type_header`.
+    #[must_use]
+    pub fn content_type_header(&self) -> HeaderValue {
+        content_type_header(&self.boundary)
+    }
+}
+
+impl MultipartStream<std::vec::IntoIter<(HeaderMap, Vec<u8>)>> {
+    /// Compute the number of bytes this stream still yields without encoding the parts, e.g. to
+    /// send a content-length header. Before the first chunk is taken, this is the length of the
+    /// whole document.
+    ///
+    /// ```
+    /// use hyper::HeaderMap;
+    /// use mqs_common::multipart::encode_streaming;
+    ///
+    /// let parts = vec![(HeaderMap::new(), b"message".to_vec())];
+    /// let document = encode_streaming(parts);
+    /// let len = document.encoded_len();
+    /// assert_eq!(document.flatten().count(), len);
+    /// ```
+    #[must_use]
+    pub fn encoded_len(&self) -> usize {
+        self.parts.as_ref().map_or(0, |parts| {
+            let parts_len: usize = parts
+                .as_slice()
+                .iter()
+                .map(|(headers, message)| encoded_part_len(self.full_boundary.len(), headers, message))
+                .sum();
+            // the closing boundary is followed by "--"
+            parts_len + self.full_boundary.len() + 2
+        })
+    }
+}
+
+impl<I: Iterator<Item = (HeaderMap, Vec<u8>)> + Send + Unpin + 'static> MultipartStream<I> {
+    /// Consume the document and turn it into a body which encodes the parts while it is sent.
+    pub fn into_body(self) -> Body {
+        Body::wrap_stream(self)
+    }
+}
+
+impl<I: Iterator<Item = (HeaderMap, Vec<u8>)>> Iterator for MultipartStream<I> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let parts = self.parts.as_mut()?;
+        let mut chunk = Vec::new();
+        if let Some((headers, message)) = parts.next() {
+            encode_part(&mut chunk, &self.full_boundary, &headers, &message);
+        } else {
+            self.parts = None;
+            encode_end(&mut chunk, &self.full_boundary);
+        }
+
+        Some(chunk)
+    }
+}
+
+impl<I: Iterator<Item = (HeaderMap, Vec<u8>)> + Unpin> Stream for MultipartStream<I> {
+    type Item = Result<Vec<u8>, Infallible>;
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(self.get_mut().next().map(Ok))
+    }
+}
+
+/// Encode data as a multipart/mixed document without buffering the whole document. The parts are
+/// only encoded once the returned stream is consumed.
+///
+/// Like for `encode`, a new boundary is generated should the random boundary appear in one of the
+/// messages.
+pub fn encode_streaming(
+    messages: Vec<(HeaderMap, Vec<u8>)>,
+) -> MultipartStream<std::vec::IntoIter<(HeaderMap, Vec<u8>)>> {
+    encode_streaming_with_new_boundary(messages, || Uuid::new_v4().to_string())
+}
+
+fn encode_streaming_with_new_boundary<B: FnMut() -> String>(
+    messages: Vec<(HeaderMap, Vec<u8>)>,
+    next_boundary: B,
+) -> MultipartStream<std::vec::IntoIter<(HeaderMap, Vec<u8>)>> {
+    let boundary = new_boundary(&messages, next_boundary);
+    let full_boundary = format!("--{}", boundary).into_bytes();
+
+    MultipartStream {
+        boundary,
+        full_boundary,
+        parts: Some(messages.into_iter()),
+    }
 }
 
 /// Return the boundary from "multipart/mixed; boundary=..."
@@ -98,6 +362,10 @@ pub enum InvalidMultipart {
     HeaderName,
     /// An invalid header value was encountered in some chunk.
     HeaderValue,
+    /// A header which must be unique (content-type, content-length) was given more than once in some chunk.
+    DuplicateHeader,
+    /// The content type of a document did not describe a multipart document.
+    ContentType,
 }
 
 impl Display for InvalidMultipart {
@@ -122,9 +390,13 @@ impl From<InvalidHeaderValue> for InvalidMultipart {
 
 /// Split a message body at the boundaries and return a list of content-type/data pairs
 ///
+/// Headers given more than once in a chunk are all kept in the returned `HeaderMap` in the order
+/// they appear. The only exceptions are content-type and content-length, which have to be unique.
+///
 /// # Errors
 ///
-/// If any part of the document fails to parse (invalid chunk, header name or header value).
+/// If any part of the document fails to parse (invalid chunk, header name or header value) or a
+/// chunk contains more than one content-type or content-length header.
 pub fn parse<'a, 'b>(boundary: &'b [u8], body: &'a [u8]) -> Result<Vec<(HeaderMap, &'a [u8])>, InvalidMultipart> {
     let mut result = Vec::new();
 
@@ -164,7 +436,11 @@ pub fn parse<'a, 'b>(boundary: &'b [u8], body: &'a [u8]) -> Result<Vec<(HeaderMa
                 let mut headers = HeaderMap::new();
                 for header in split(header_text, &HeaderValueSep {}) {
                     if let Some((name, body)) = split_by(header, &b':') {
-                        headers.insert(HeaderName::from_bytes(name)?, to_header_value(body)?);
+                        let name = HeaderName::from_bytes(name)?;
+                        if (name == CONTENT_TYPE || name == CONTENT_LENGTH) && headers.contains_key(&name) {
+                            return Err(InvalidMultipart::DuplicateHeader);
+                        }
+                        headers.append(name, to_header_value(body)?);
                     }
                 }
 
@@ -363,7 +639,7 @@ const fn skip_linear_whitespace(data: &[u8]) -> usize {
 #[cfg(test)]
 mod test {
     use super::*;
-    use hyper::header::{CONTENT_ENCODING, CONTENT_TYPE};
+    use hyper::header::CONTENT_ENCODING;
 
     fn get_input() -> Vec<(HeaderMap, Vec<u8>)> {
         vec![
@@ -432,16 +708,96 @@ mod test {
 
     #[test]
     async fn encode_multipart() {
-        let (boundary, body) = encode(get_input().into_iter());
+        let document = encode(get_input().into_iter());
+        let boundary = document.boundary();
+        assert_eq!(
+            std::str::from_utf8(document.body()).unwrap(),
+            format!(
+                "--{}\r\ncontent-type: data/type\r\n\r\nThis is my first message\r\n--{}\r\ncontent-type: data/another-type\r\n\r\n\r\nAnother message\r\nWith more than one line\r\n\r\n--{}\r\ncontent-type: foo/bar\r\n\r\nLast message, don\'t forget it\r\n--{}--",
+                boundary, boundary, boundary, boundary
+            )
+        );
+    }
+
+    #[test]
+    async fn encode_multipart_boundary_in_message() {
+        let mut input = get_input();
+        input[1].1 = b"this message contains --first-boundary as text".to_vec();
+        let mut candidates = vec!["first-boundary", "second-boundary"].into_iter();
+        let document = encode_with_new_boundary(input.clone(), || candidates.next().unwrap().to_string());
+        assert_eq!(document.boundary(), "second-boundary");
+        let parsed = parse(b"--second-boundary", document.body()).unwrap();
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed[1].1, input[1].1.as_slice());
+        assert!(contains(b"abc", b"bc"));
+        assert!(!contains(b"abc", b"abcd"));
+    }
+
+    #[test]
+    async fn encode_multipart_streaming() {
+        let document = encode_streaming(get_input());
+        let boundary = document.boundary().to_string();
+        let chunks: Vec<Vec<u8>> = document.collect();
+        assert_eq!(chunks.len(), 4);
         assert_eq!(
-            std::str::from_utf8(body.as_slice()).unwrap(),
+            std::str::from_utf8(&chunks.concat()).unwrap(),
             format!(
                 "--{}\r\ncontent-type: data/type\r\n\r\nThis is my first message\r\n--{}\r\ncontent-type: data/another-type\r\n\r\n\r\nAnother message\r\nWith more than one line\r\n\r\n--{}\r\ncontent-type: foo/bar\r\n\r\nLast message, don\'t forget it\r\n--{}--",
-                &boundary, &boundary, &boundary, &boundary
+                boundary, boundary, boundary, boundary
             )
         );
     }
 
+    #[test]
+    async fn encode_multipart_streaming_boundary_in_message() {
+        let mut input = get_input();
+        input[2].1 = b"--first-boundary--".to_vec();
+        let mut candidates = vec!["first-boundary", "second-boundary"].into_iter();
+        let document = encode_streaming_with_new_boundary(input.clone(), || candidates.next().unwrap().to_string());
+        assert_eq!(document.boundary(), "second-boundary");
+        let body: Vec<u8> = document.flatten().collect();
+        let parsed = parse(b"--second-boundary", &body).unwrap();
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed[2].1, input[2].1.as_slice());
+    }
+
+    #[test]
+    async fn encoded_len() {
+        let mut document = encode_streaming(get_input());
+        let len = document.encoded_len();
+        let first = document.next().unwrap();
+        let remaining = document.encoded_len();
+        let rest: Vec<u8> = document.flatten().collect();
+        assert_eq!(remaining, rest.len());
+        assert_eq!(len, first.len() + rest.len());
+        let document = encode_streaming(Vec::new());
+        assert_eq!(document.encoded_len(), encode(Vec::new().into_iter()).body().len());
+    }
+
+    #[test]
+    async fn content_type_header() {
+        let document = Multipart {
+            boundary: "abc".to_string(),
+            body:     Vec::new(),
+        };
+        assert_eq!(
+            document.content_type_header(),
+            HeaderValue::from_static("multipart/mixed; boundary=abc")
+        );
+        let document = Multipart {
+            boundary: "my boundary:1".to_string(),
+            body:     Vec::new(),
+        };
+        assert_eq!(
+            document.content_type_header(),
+            HeaderValue::from_static("multipart/mixed; boundary=\"my boundary:1\"")
+        );
+        assert_eq!(
+            super::is_multipart(document.content_type_header().to_str().unwrap()),
+            Some("--my boundary:1".to_string())
+        );
+    }
+
     #[test]
     async fn is_multipart() {
         assert_eq!(None, super::is_multipart("text/plain"));
@@ -507,17 +863,40 @@ mod test {
         assert_eq!(parsed[1].1, b"This has no content type\r\n");
     }
 
+    #[test]
+    async fn parse_multipart_duplicate_headers() {
+        let parsed = parse(
+            b"--abc",
+            b"--abc\r\nContent-Encoding: gzip\r\nContent-Encoding: base64\r\n\r\nThis is my text\r\n--abc--",
+        );
+        assert!(parsed.is_ok());
+        let parsed = parsed.unwrap();
+        assert_eq!(parsed.len(), 1);
+        let encodings: Vec<&HeaderValue> = parsed[0].0.get_all(CONTENT_ENCODING).iter().collect();
+        assert_eq!(encodings, vec![
+            &HeaderValue::from_static("gzip"),
+            &HeaderValue::from_static("base64")
+        ]);
+
+        let parsed = parse(
+            b"--abc",
+            b"--abc\r\nContent-Type: text/plain\r\nContent-Type: application/json\r\n\r\n{}\r\n--abc--",
+        );
+        assert!(matches!(parsed, Err(InvalidMultipart::DuplicateHeader)));
+    }
+
     #[test]
     async fn gen_and_parse() {
         let input = get_input();
-        let (boundary, body) = encode(input.clone().into_iter());
+        let document = encode(input.clone().into_iter());
+        let boundary = document.boundary();
         assert!(!boundary.starts_with("--"));
-        let body_string = std::str::from_utf8(body.as_slice());
+        let body_string = std::str::from_utf8(document.body());
         assert!(body_string.is_ok());
         let body_string = body_string.unwrap();
         assert_eq!(
-            super::is_multipart(&format!("multipart/mixed; boundary={}", &boundary)),
-            Some(format!("--{}", &boundary))
+            super::is_multipart(document.content_type_header().to_str().unwrap()),
+            Some(format!("--{}", boundary))
         );
         let parsed = parse(format!("--{}", boundary).as_bytes(), body_string.as_bytes());
         assert!(parsed.is_ok());
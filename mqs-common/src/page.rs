@@ -0,0 +1,125 @@
+use std::convert::TryFrom;
+
+/// A `Page` is a part of a list of items, e.g. the queues known to a server, selected by an offset
+/// and an optional limit.
+///
+/// Use `next_offset` to request the following page:
+///
+/// ```
+/// use mqs_common::Page;
+///
+/// let all: Vec<u32> = (0..5).collect();
+/// let first = Page::from_all(all.clone(), 0, Some(2));
+/// assert_eq!(first.items, vec![0, 1]);
+/// assert_eq!(first.next_offset(), Some(2));
+/// let last = Page::from_all(all, 4, Some(2));
+/// assert_eq!(last.items, vec![4]);
+/// assert_eq!(last.next_offset(), None);
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Page<T> {
+    /// Items on this page. Contains at most `limit` items.
+    pub items:  Vec<T>,
+    /// Total number of items in the list, not only on this page.
+    pub total:  i64,
+    /// Number of items in the list before this page.
+    pub offset: usize,
+    /// Maximum number of items requested for this page. If `None`, all items after `offset` were
+    /// requested.
+    pub limit:  Option<usize>,
+}
+
+impl<T> Page<T> {
+    /// Select a page from a list containing all items.
+    #[must_use]
+    pub fn from_all(all: Vec<T>, offset: usize, limit: Option<usize>) -> Self {
+        let total = i64::try_from(all.len()).unwrap_or(i64::MAX);
+        let items = all.into_iter().skip(offset).take(limit.unwrap_or(usize::MAX)).collect();
+
+        Self {
+            items,
+            total,
+            offset,
+            limit,
+        }
+    }
+
+    /// Get the offset of the page following this page. Returns `None` if this is the last page.
+    #[must_use]
+    pub fn next_offset(&self) -> Option<usize> {
+        if self.items.is_empty() {
+            return None;
+        }
+
+        let next = self.offset + self.items.len();
+        if matches!(i64::try_from(next), Ok(next) if next < self.total) {
+            Some(next)
+        } else {
+            None
+        }
+    }
+
+    /// Convert the items on this page.
+    ///
+    /// ```
+    /// use mqs_common::Page;
+    ///
+    /// let page = Page::from_all(vec![1, 2, 3], 1, None).map(|i| i * 10);
+    /// assert_eq!(page.items, vec![20, 30]);
+    /// assert_eq!(page.total, 3);
+    /// ```
+    #[must_use]
+    pub fn map<U, F: FnMut(T) -> U>(self, f: F) -> Page<U> {
+        Page {
+            items:  self.items.into_iter().map(f).collect(),
+            total:  self.total,
+            offset: self.offset,
+            limit:  self.limit,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    async fn from_all() {
+        let all: Vec<u32> = (0..10).collect();
+        let page = Page::from_all(all.clone(), 0, None);
+        assert_eq!(page.items, all);
+        assert_eq!(page.total, 10);
+        assert_eq!(page.next_offset(), None);
+
+        let page = Page::from_all(all.clone(), 3, Some(4));
+        assert_eq!(page.items, vec![3, 4, 5, 6]);
+        assert_eq!(page.next_offset(), Some(7));
+
+        let page = Page::from_all(all.clone(), 3, Some(0));
+        assert!(page.items.is_empty());
+        assert_eq!(page.next_offset(), None);
+
+        let page = Page::from_all(all, 20, Some(5));
+        assert!(page.items.is_empty());
+        assert_eq!(page.total, 10);
+        assert_eq!(page.next_offset(), None);
+    }
+
+    #[test]
+    async fn next_offset() {
+        let page = Page {
+            items:  vec![1, 2],
+            total:  5,
+            offset: 2,
+            limit:  Some(2),
+        };
+        assert_eq!(page.next_offset(), Some(4));
+        let page = Page {
+            items:  vec![1, 2],
+            total:  4,
+            offset: 2,
+            limit:  Some(2),
+        };
+        assert_eq!(page.next_offset(), None);
+    }
+}
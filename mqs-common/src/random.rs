@@ -0,0 +1,73 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use uuid::Uuid;
+
+/// A `Random` provides random numbers. Code which depends on random numbers should ask a `Random`
+/// instead of generating them directly, so tests can control the numbers it sees.
+pub trait Random: Send + Sync {
+    /// Get a random number in the range `[0, 1)`.
+    fn next_fraction(&self) -> f64;
+}
+
+/// A `SystemRandom` returns numbers generated from the random number generator of the system.
+///
+/// ```
+/// use mqs_common::{Random, SystemRandom};
+///
+/// for _ in 0..100 {
+///     let fraction = SystemRandom.next_fraction();
+///     assert!((0.0..1.0).contains(&fraction));
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemRandom;
+
+impl Random for SystemRandom {
+    #[allow(clippy::cast_precision_loss)]
+    fn next_fraction(&self) -> f64 {
+        // the first 48 bits of a v4 uuid are random, the version is only stored after them
+        let bytes = Uuid::new_v4().into_bytes();
+        let value = bytes[..6].iter().fold(0_u64, |acc, byte| acc << 8 | u64::from(*byte));
+        value as f64 / (1_u64 << 48) as f64
+    }
+}
+
+/// A `FixedRandom` always returns the same number until it is explicitly set.
+///
+/// ```
+/// use mqs_common::{FixedRandom, Random};
+///
+/// let random = FixedRandom::new(0.5);
+/// assert_eq!(random.next_fraction(), 0.5);
+/// random.set(0.25);
+/// assert_eq!(random.next_fraction(), 0.25);
+/// ```
+#[derive(Debug)]
+pub struct FixedRandom {
+    fraction: Mutex<f64>,
+}
+
+impl FixedRandom {
+    /// Create a new random returning the given number, which should be in the range `[0, 1)`.
+    #[must_use]
+    pub const fn new(fraction: f64) -> Self {
+        Self {
+            fraction: Mutex::new(fraction),
+        }
+    }
+
+    /// Set the number returned by the random.
+    pub fn set(&self, fraction: f64) {
+        *self.lock() = fraction;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, f64> {
+        // the guarded number is always valid, so we can ignore a poisoned lock
+        self.fraction.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Random for FixedRandom {
+    fn next_fraction(&self) -> f64 {
+        *self.lock()
+    }
+}
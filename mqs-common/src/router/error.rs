@@ -0,0 +1,205 @@
+use hyper::{
+    header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER},
+    Body,
+    Response,
+};
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+};
+
+use crate::Status;
+
+/// A `HandlerError` is returned by a `TryHandler` if it can't produce a successful response. Each
+/// variant maps to a single status code, the message is sent to the client as a JSON error.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum HandlerError {
+    /// The request was invalid, e.g. a header or query parameter could not be parsed.
+    BadRequest(String),
+    /// The requested resource does not exist.
+    NotFound(String),
+    /// The request conflicts with the current state of the resource.
+    Conflict(String),
+    /// The body of the request was too large.
+    PayloadTooLarge,
+    /// The request failed because of an error on the server, e.g. a failed database query.
+    InternalServerError,
+    /// The server can't handle the request right now, but might be able to later.
+    ServiceUnavailable,
+}
+
+/// Number of seconds clients should wait before retrying a request which failed because the
+/// service was unavailable.
+const RETRY_AFTER_SECONDS: &str = "1";
+
+#[derive(Serialize)]
+struct ErrorResponse<'a> {
+    error: &'a str,
+}
+
+/// Message of an error response. Error responses carry it in their extensions, so `ErrorFormat`
+/// can replace their JSON body with the plain message.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ErrorMessage(pub String);
+
+/// Format of the body of error responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorFormat {
+    /// A JSON object with the message in its `error` field, e.g. `{"error":"Invalid queue name"}`.
+    #[default]
+    Json,
+    /// The plain message as `text/plain`, which is easier to read during development.
+    Text,
+}
+
+impl ErrorFormat {
+    /// Convert an error response to this format. Responses without an `ErrorMessage` in their
+    /// extensions are returned unchanged, as are all responses if the format is `Json`.
+    ///
+    /// ```
+    /// use mqs_common::router::{ErrorFormat, HandlerError};
+    ///
+    /// let response = ErrorFormat::Text
+    ///     .format_response(HandlerError::NotFound("No such queue".to_string()).into_response());
+    /// assert_eq!(response.status().as_u16(), 404);
+    /// assert_eq!(
+    ///     response.headers().get("Content-Type").unwrap(),
+    ///     "text/plain; charset=utf-8"
+    /// );
+    /// ```
+    #[must_use]
+    pub fn format_response(self, response: Response<Body>) -> Response<Body> {
+        if self == Self::Json {
+            return response;
+        }
+
+        let (mut parts, body) = response.into_parts();
+        if let Some(ErrorMessage(message)) = parts.extensions.get::<ErrorMessage>() {
+            let message = message.clone();
+            parts
+                .headers
+                .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain; charset=utf-8"));
+            parts.headers.insert(CONTENT_LENGTH, HeaderValue::from(message.len()));
+            Response::from_parts(parts, Body::from(message))
+        } else {
+            Response::from_parts(parts, body)
+        }
+    }
+}
+
+impl HandlerError {
+    /// Get the status code of the response for this error.
+    #[must_use]
+    pub const fn status(&self) -> Status {
+        match self {
+            Self::BadRequest(_) => Status::BadRequest,
+            Self::NotFound(_) => Status::NotFound,
+            Self::Conflict(_) => Status::Conflict,
+            Self::PayloadTooLarge => Status::PayloadTooLarge,
+            Self::InternalServerError => Status::InternalServerError,
+            Self::ServiceUnavailable => Status::ServiceUnavailable,
+        }
+    }
+
+    /// Convert the error to a response with the status of the error and a JSON body containing its
+    /// message. If the service is unavailable, the response tells the client when to retry in the
+    /// `Retry-After` header. The message is also stored as `ErrorMessage` in the extensions of the
+    /// response, so `ErrorFormat::format_response` can send it as plain text instead.
+    ///
+    /// ```
+    /// use mqs_common::router::HandlerError;
+    ///
+    /// let response = HandlerError::BadRequest("Invalid header".to_string()).into_response();
+    /// assert_eq!(response.status().as_u16(), 400);
+    /// assert_eq!(
+    ///     response.headers().get("Content-Type").unwrap(),
+    ///     "application/json"
+    /// );
+    /// ```
+    #[must_use]
+    pub fn into_response(self) -> Response<Body> {
+        let error = self.to_string();
+        // serializing a struct with a single string field can't fail
+        let body = serde_json::to_vec(&ErrorResponse { error: &error }).unwrap_or_default();
+        let mut response = Response::new(Body::from(body));
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        if self == Self::ServiceUnavailable {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from_static(RETRY_AFTER_SECONDS));
+        }
+        *response.status_mut() = self.status().into();
+        response.extensions_mut().insert(ErrorMessage(error));
+        response
+    }
+}
+
+impl Display for HandlerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadRequest(message) | Self::NotFound(message) | Self::Conflict(message) => f.write_str(message),
+            Self::PayloadTooLarge => f.write_str("Payload too large"),
+            Self::InternalServerError => f.write_str("Internal server error"),
+            Self::ServiceUnavailable => f.write_str("Service unavailable, try again later"),
+        }
+    }
+}
+
+impl Error for HandlerError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::read_body;
+
+    #[test]
+    async fn error_response() {
+        let errors = vec![
+            (
+                HandlerError::BadRequest("Invalid header".to_string()),
+                400,
+                "Invalid header",
+            ),
+            (
+                HandlerError::NotFound("No such queue".to_string()),
+                404,
+                "No such queue",
+            ),
+            (
+                HandlerError::Conflict("Queue was modified".to_string()),
+                409,
+                "Queue was modified",
+            ),
+            (HandlerError::PayloadTooLarge, 413, "Payload too large"),
+            (HandlerError::InternalServerError, 500, "Internal server error"),
+            (
+                HandlerError::ServiceUnavailable,
+                503,
+                "Service unavailable, try again later",
+            ),
+        ];
+        for (error, status, message) in errors {
+            let mut response = error.clone().into_response();
+            assert_eq!(response.status().as_u16(), status);
+            assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), "application/json");
+            assert_eq!(response.headers().contains_key(RETRY_AFTER), status == 503);
+            let body = read_body(response.body_mut(), None).await.unwrap().unwrap();
+            assert_eq!(body, format!("{{\"error\":\"{}\"}}", message).into_bytes());
+
+            let mut response = ErrorFormat::Text.format_response(error.into_response());
+            assert_eq!(response.status().as_u16(), status);
+            assert_eq!(
+                response.headers().get(CONTENT_TYPE).unwrap(),
+                "text/plain; charset=utf-8"
+            );
+            assert_eq!(response.headers().contains_key(RETRY_AFTER), status == 503);
+            let body = read_body(response.body_mut(), None).await.unwrap().unwrap();
+            assert_eq!(body, message.as_bytes());
+        }
+
+        let response = ErrorFormat::Text.format_response(Response::new(Body::from("hello")));
+        assert!(!response.headers().contains_key(CONTENT_TYPE));
+    }
+}
@@ -1,12 +1,38 @@
 use http::version::Version;
 use hyper::{
-    header::{HeaderValue, CONNECTION, CONTENT_TYPE, SERVER},
+    header::{HeaderValue, CONNECTION, SERVER},
     Body,
+    Method,
     Request,
     Response,
 };
+use percent_encoding::percent_decode_str;
+use std::borrow::Cow;
 
-use crate::{read_body, router::Router, Status};
+use crate::{
+    read_body,
+    router::{
+        metrics::{count_response, Metrics},
+        HandlerError,
+        Router,
+    },
+};
+
+/// Match method names case-insensitively by converting them to upper case, so a request with the
+/// method `get` is routed like a `GET` request.
+fn normalize_method(method: &Method) -> Method {
+    Method::from_bytes(method.as_str().to_ascii_uppercase().as_bytes()).unwrap_or_else(|_| method.clone())
+}
+
+/// Split a path into the segments used for routing. Each segment is percent-decoded and empty
+/// segments (from a leading, trailing, or repeated `/`) are dropped. Returns `None` if a segment
+/// does not decode to valid UTF-8.
+pub(crate) fn path_segments(path: &str) -> Option<Vec<Cow<'_, str>>> {
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| percent_decode_str(segment).decode_utf8().ok())
+        .collect()
+}
 
 /// Handle a single request using the given router.
 ///
@@ -14,6 +40,14 @@ use crate::{read_body, router::Router, Status};
 /// If more than `max_message_size` bytes are send by the client, an
 /// error response is returned.
 ///
+/// The method of the request is matched case-insensitively and each segment of the path is
+/// percent-decoded before routing, so `/queues/my%2Dqueue` reaches the same handler as
+/// `/queues/my-queue`. Repeated slashes are ignored.
+///
+/// The number of requests as well as the sizes of request and response bodies are recorded per
+/// route in `Metrics::global`. Error responses are sent in the format set with
+/// `Router::with_error_format`.
+///
 /// ```
 /// use async_trait::async_trait;
 /// use hyper::{Body, Method, Request, Response};
@@ -79,52 +113,59 @@ pub async fn handle<T: Send, S: Send>(
     mut req: Request<Body>,
 ) -> Response<Body> {
     let version = req.version();
+    let error_format = router.error_format();
+    *req.method_mut() = normalize_method(req.method());
     let mut response = if let Some(conn) = conn {
-        let segments = req.uri().path().split('/');
-        {
-            if let Some(handler) = router.route(req.method(), segments) {
+        let path = req.uri().path().to_string();
+        if let Some(segments) = path_segments(&path) {
+            if let Some((handler, route)) = router.route_with_pattern(req.method(), segments.iter().map(AsRef::as_ref))
+            {
+                let route_metrics = Metrics::global().route(req.method(), &route);
                 let body = if handler.needs_body() {
                     read_body(req.body_mut(), Some(max_message_size)).await
                 } else {
                     Ok(Some(Vec::new()))
                 };
-                match body {
+                let response = match body {
                     Err(err) => {
                         error!("Failed to read message body: {}", err);
 
-                        let mut response = Response::new(Body::from("{\"error\":\"Internal server error\"}"));
-                        response
-                            .headers_mut()
-                            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-                        *response.status_mut() = Status::InternalServerError.into();
-                        response
+                        route_metrics.add_request(0);
+                        HandlerError::InternalServerError.into_response()
                     },
                     Ok(None) => {
                         warn!("Body was larger than max allowed size ({})", max_message_size);
 
-                        let mut response = Response::new(Body::from("{\"error\":\"Payload too large\"}"));
-                        response
-                            .headers_mut()
-                            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-                        *response.status_mut() = Status::PayloadTooLarge.into();
-                        response
+                        // we stopped reading after the maximum size, the body was at least that large
+                        route_metrics.add_request(max_message_size);
+                        HandlerError::PayloadTooLarge.into_response()
                     },
                     Ok(Some(body)) => {
                         info!("Found handler for request {} {}", req.method(), req.uri().path());
 
+                        route_metrics.add_request(body.len());
                         handler.handle((conn, source), req, body).await
                     },
-                }
+                };
+                count_response(route_metrics, error_format.format_response(response))
             } else {
                 error!("No handler found for request {} {}", req.method(), req.uri().path());
 
-                let mut response = Response::new(Body::from("{\"error\":\"No handler found for request\"}"));
-                response
-                    .headers_mut()
-                    .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-                *response.status_mut() = Status::NotFound.into();
-                response
+                count_unmatched(
+                    req.method(),
+                    error_format.format_response(
+                        HandlerError::NotFound("No handler found for request".to_string()).into_response(),
+                    ),
+                )
             }
+        } else {
+            error!("Invalid path for request {} {}", req.method(), req.uri().path());
+
+            count_unmatched(
+                req.method(),
+                error_format
+                    .format_response(HandlerError::BadRequest("Path is not valid UTF-8".to_string()).into_response()),
+            )
         }
     } else {
         error!(
@@ -133,12 +174,7 @@ pub async fn handle<T: Send, S: Send>(
             req.uri().path()
         );
 
-        let mut response = Response::new(Body::from("{\"error\":\"Service unavailable, try again later\"}"));
-        response
-            .headers_mut()
-            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        *response.status_mut() = Status::ServiceUnavailable.into();
-        response
+        error_format.format_response(HandlerError::ServiceUnavailable.into_response())
     };
     response.headers_mut().insert(SERVER, HeaderValue::from_static("mqs"));
     if version <= Version::HTTP_11 {
@@ -149,12 +185,21 @@ pub async fn handle<T: Send, S: Send>(
     response
 }
 
+/// Record a request without a matching route. The path is not used as label, so requests for
+/// arbitrary paths can't create an unbounded number of counters. The body of such a request is
+/// never read and thus not counted.
+fn count_unmatched(method: &Method, response: Response<Body>) -> Response<Body> {
+    let route_metrics = Metrics::global().route(method, Metrics::UNMATCHED_ROUTE);
+    route_metrics.add_request(0);
+    count_response(route_metrics, response)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::{
         read_body,
-        router::{Handler, Router},
+        router::{ErrorFormat, Handler, Router, WildcardRouter},
     };
     use async_trait::async_trait;
     use hyper::{Body, Method, Request, Response};
@@ -176,6 +221,25 @@ mod test {
         }
     }
 
+    struct SegmentHandler {
+        segment: String,
+    }
+
+    #[async_trait]
+    impl Handler<(i32, ())> for SegmentHandler {
+        async fn handle(&self, _: (i32, ()), _: Request<Body>, _: Vec<u8>) -> Response<Body> {
+            Response::new(Body::from(self.segment.clone()))
+        }
+    }
+
+    impl WildcardRouter<(i32, ())> for SegmentHandler {
+        fn with_segment(&self, segment: &str) -> Router<(i32, ())> {
+            Router::new_simple(Method::GET, SegmentHandler {
+                segment: segment.to_string(),
+            })
+        }
+    }
+
     #[test]
     async fn test_handler() {
         let router = Router::new_simple(Method::GET, EchoHandler {});
@@ -207,5 +271,144 @@ mod test {
             read_body(response.body_mut(), None).await.unwrap().unwrap(),
             b"{\"error\":\"No handler found for request\"}".as_ref()
         );
+
+        let router = Router::new_simple(Method::GET, EchoHandler {}).with_error_format(ErrorFormat::Text);
+        let mut response = handle(None, (), &router, 100, Request::new(Body::default())).await;
+        assert_eq!(response.status(), 503);
+        assert_eq!(
+            read_body(response.body_mut(), None).await.unwrap().unwrap(),
+            b"Service unavailable, try again later".as_ref()
+        );
+        let mut response = handle(Some(42), (), &router, 3, Request::new(Body::from("hello".to_string()))).await;
+        assert_eq!(response.status(), 413);
+        assert_eq!(
+            read_body(response.body_mut(), None).await.unwrap().unwrap(),
+            b"Payload too large".as_ref()
+        );
+        let request = Request::builder().uri("/unknown").body(Body::default()).unwrap();
+        let mut response = handle(Some(42), (), &router, 100, request).await;
+        assert_eq!(response.status(), 404);
+        assert_eq!(
+            read_body(response.body_mut(), None).await.unwrap().unwrap(),
+            b"No handler found for request".as_ref()
+        );
+    }
+
+    #[test]
+    async fn test_handler_metrics() {
+        // the metrics are global, custom methods keep the counters apart from other tests
+        let method = Method::from_bytes(b"METERED").unwrap();
+        let router = Router::new_simple(method.clone(), EchoHandler {});
+        let request = |method: &Method, body: &'static str| {
+            let mut req = Request::new(Body::from(body));
+            *req.method_mut() = method.clone();
+            req
+        };
+        let mut response = handle(Some(42), (), &router, 100, request(&method, "metrics")).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            read_body(response.body_mut(), None).await.unwrap().unwrap(),
+            b"42 -> metrics"
+        );
+        let route_metrics = Metrics::global().route(&method, "/");
+        assert_eq!(route_metrics.requests(), 1);
+        assert_eq!(route_metrics.request_bytes(), 7);
+        assert_eq!(route_metrics.response_bytes(), 13);
+
+        let unrouted = Method::from_bytes(b"UNROUTED").unwrap();
+        let response = handle(Some(42), (), &router, 100, request(&unrouted, "ignored")).await;
+        assert_eq!(response.status(), 404);
+        let unmatched_metrics = Metrics::global().route(&unrouted, Metrics::UNMATCHED_ROUTE);
+        assert_eq!(unmatched_metrics.requests(), 1);
+        assert_eq!(unmatched_metrics.request_bytes(), 0);
+        assert_eq!(
+            unmatched_metrics.response_bytes(),
+            b"{\"error\":\"No handler found for request\"}".len() as u64
+        );
+        assert_eq!(route_metrics.requests(), 1);
+    }
+
+    #[test]
+    async fn test_handler_chunked_body() {
+        let router = Router::new_simple(Method::GET, EchoHandler {});
+        let chunked = |chunks: &'static [&'static str]| {
+            let (mut sender, body) = Body::channel();
+            tokio::spawn(async move {
+                for chunk in chunks {
+                    if sender.send_data((*chunk).into()).await.is_err() {
+                        // the handler stopped reading the body
+                        break;
+                    }
+                }
+            });
+            Request::new(body)
+        };
+
+        let mut response = handle(Some(1), (), &router, 10, chunked(&["chunk", "ed"])).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            read_body(response.body_mut(), None).await.unwrap().unwrap(),
+            b"1 -> chunked"
+        );
+        let response = handle(Some(1), (), &router, 10, chunked(&["too", " large", " to", " read"])).await;
+        assert_eq!(response.status(), 413);
+    }
+
+    #[test]
+    async fn normalize() {
+        assert_eq!(normalize_method(&Method::GET), Method::GET);
+        assert_eq!(
+            normalize_method(&Method::from_bytes(b"delete").unwrap()),
+            Method::DELETE
+        );
+        assert_eq!(
+            normalize_method(&Method::from_bytes(b"Custom").unwrap()),
+            Method::from_bytes(b"CUSTOM").unwrap()
+        );
+        assert_eq!(path_segments("/"), Some(Vec::new()));
+        assert_eq!(
+            path_segments("//queues///my-queue/"),
+            Some(vec![Cow::Borrowed("queues"), Cow::Borrowed("my-queue")])
+        );
+        assert_eq!(
+            path_segments("/queues/my%20queue%2Fname"),
+            Some(vec![Cow::Borrowed("queues"), Cow::Borrowed("my queue/name")])
+        );
+        assert_eq!(path_segments("/queues/%FF"), None);
+    }
+
+    #[test]
+    async fn test_handler_normalized_path() {
+        let router = Router::default().with_route(
+            "queues",
+            Router::default().with_wildcard(SegmentHandler { segment: String::new() }),
+        );
+        let request = |method: &[u8], path: &str| {
+            let mut req = Request::new(Body::default());
+            *req.method_mut() = Method::from_bytes(method).unwrap();
+            *req.uri_mut() = path.parse().unwrap();
+            req
+        };
+        for (method, path) in [
+            (&b"GET"[..], "/queues/foo"),
+            (b"GET", "/queues//foo"),
+            (b"GET", "//queues/foo/"),
+            (b"GET", "/queues/%66oo"),
+            (b"get", "/queues/foo"),
+        ] {
+            let mut response = handle(Some(1), (), &router, 10, request(method, path)).await;
+            assert_eq!(response.status(), 200, "{} {}", String::from_utf8_lossy(method), path);
+            assert_eq!(read_body(response.body_mut(), None).await.unwrap().unwrap(), b"foo");
+        }
+        let mut response = handle(Some(1), (), &router, 10, request(b"GET", "/queues/my%2Fqueue")).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            read_body(response.body_mut(), None).await.unwrap().unwrap(),
+            b"my/queue"
+        );
+        let response = handle(Some(1), (), &router, 10, request(b"GET", "/queues/%C3%28")).await;
+        assert_eq!(response.status(), 400);
+        let response = handle(Some(1), (), &router, 10, request(b"POST", "/queues/foo")).await;
+        assert_eq!(response.status(), 404);
     }
 }
@@ -0,0 +1,264 @@
+use async_trait::async_trait;
+use cached::once_cell::sync::Lazy;
+use futures_core::Stream;
+use hyper::{
+    body::{Bytes, HttpBody},
+    header::{HeaderValue, CONTENT_TYPE},
+    Body,
+    Method,
+    Request,
+    Response,
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+        Mutex,
+        PoisonError,
+    },
+    task::{Context, Poll},
+};
+
+use crate::router::Handler;
+
+/// Counters for the requests handled by a single route.
+#[derive(Debug, Default)]
+pub struct RouteMetrics {
+    requests:       AtomicU64,
+    request_bytes:  AtomicU64,
+    response_bytes: AtomicU64,
+}
+
+impl RouteMetrics {
+    /// Number of requests handled by the route.
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Number of bytes received in request bodies.
+    pub fn request_bytes(&self) -> u64 {
+        self.request_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Number of bytes sent in response bodies. Responses are counted before they are compressed.
+    pub fn response_bytes(&self) -> u64 {
+        self.response_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Count a request with a body of the given size.
+    pub fn add_request(&self, body_size: usize) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.request_bytes.fetch_add(body_size as u64, Ordering::Relaxed);
+    }
+
+    fn add_response_bytes(&self, size: u64) {
+        self.response_bytes.fetch_add(size, Ordering::Relaxed);
+    }
+}
+
+/// Collects the number of requests as well as bytes received and sent for each route.
+///
+/// ```
+/// use hyper::Method;
+/// use mqs_common::router::Metrics;
+///
+/// let metrics = Metrics::default();
+/// metrics.route(&Method::POST, "/messages/*").add_request(42);
+/// let route = metrics.route(&Method::POST, "/messages/*");
+/// assert_eq!(route.requests(), 1);
+/// assert_eq!(route.request_bytes(), 42);
+/// assert!(metrics
+///     .render()
+///     .contains("mqs_request_bytes_total{method=\"POST\",route=\"/messages/*\"} 42\n"));
+///
+/// let request = metrics.start_request();
+/// assert_eq!(metrics.in_flight(), 1);
+/// assert!(metrics.render().contains("mqs_requests_in_flight 1\n"));
+/// drop(request);
+/// assert_eq!(metrics.in_flight(), 0);
+/// ```
+#[derive(Debug, Default)]
+pub struct Metrics {
+    routes:    Mutex<BTreeMap<(String, String), Arc<RouteMetrics>>>,
+    in_flight: AtomicU64,
+}
+
+/// Counts a request as in flight until it is dropped.
+#[derive(Debug)]
+pub struct InFlightRequest<'a>(&'a AtomicU64);
+
+impl Drop for InFlightRequest<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Name, description, and getter of a counter exported for each route.
+type Counter = (&'static str, &'static str, fn(&RouteMetrics) -> u64);
+
+static GLOBAL_METRICS: Lazy<Metrics> = Lazy::new(Metrics::default);
+
+impl Metrics {
+    /// Route label of requests which did not match any route of the router.
+    pub const UNMATCHED_ROUTE: &'static str = "unmatched";
+
+    /// Get the metrics recorded by `handle` for all requests of this process.
+    #[must_use]
+    pub fn global() -> &'static Self {
+        &GLOBAL_METRICS
+    }
+
+    /// Get the counters of the given route, creating them if the route was not seen before.
+    pub fn route(&self, method: &Method, route: &str) -> Arc<RouteMetrics> {
+        // counters are only ever incremented, so a poisoned lock can't leave them inconsistent
+        let mut routes = self.routes.lock().unwrap_or_else(PoisonError::into_inner);
+        Arc::clone(routes.entry((method.to_string(), route.to_string())).or_default())
+    }
+
+    /// Count a request as in flight until the returned guard is dropped.
+    #[must_use]
+    pub fn start_request(&self) -> InFlightRequest<'_> {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlightRequest(&self.in_flight)
+    }
+
+    /// Number of requests which are currently being handled.
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Render all counters in the Prometheus text format.
+    #[must_use]
+    pub fn render(&self) -> String {
+        let routes = self.routes.lock().unwrap_or_else(PoisonError::into_inner);
+        let counters: [Counter; 3] = [
+            (
+                "mqs_requests_total",
+                "Number of handled requests.",
+                RouteMetrics::requests,
+            ),
+            (
+                "mqs_request_bytes_total",
+                "Number of bytes received in request bodies.",
+                RouteMetrics::request_bytes,
+            ),
+            (
+                "mqs_response_bytes_total",
+                "Number of bytes sent in response bodies before compression.",
+                RouteMetrics::response_bytes,
+            ),
+        ];
+        let mut result = String::new();
+        for (name, help, get) in &counters {
+            // writing to a string can't fail
+            let _ = writeln!(result, "# HELP {} {}", name, help);
+            let _ = writeln!(result, "# TYPE {} counter", name);
+            for ((method, route), metrics) in routes.iter() {
+                let _ = writeln!(
+                    result,
+                    "{}{{method=\"{}\",route=\"{}\"}} {}",
+                    name,
+                    method,
+                    route,
+                    get(metrics)
+                );
+            }
+        }
+        let _ = writeln!(
+            result,
+            "# HELP mqs_requests_in_flight Number of requests currently being handled."
+        );
+        let _ = writeln!(result, "# TYPE mqs_requests_in_flight gauge");
+        let _ = writeln!(result, "mqs_requests_in_flight {}", self.in_flight());
+
+        result
+    }
+}
+
+/// Count the size of the body of the given response for the given route. Bodies of unknown size are
+/// counted while they are sent to the client.
+pub(super) fn count_response(metrics: Arc<RouteMetrics>, response: Response<Body>) -> Response<Body> {
+    if let Some(size) = HttpBody::size_hint(response.body()).exact() {
+        metrics.add_response_bytes(size);
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    Response::from_parts(parts, Body::wrap_stream(CountingBody { inner: body, metrics }))
+}
+
+struct CountingBody {
+    inner:   Body,
+    metrics: Arc<RouteMetrics>,
+}
+
+impl Stream for CountingBody {
+    type Item = Result<Bytes, hyper::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let result = Pin::new(&mut self.inner).poll_next(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &result {
+            self.metrics.add_response_bytes(chunk.len() as u64);
+        }
+        result
+    }
+}
+
+/// Answers requests with the global metrics in the Prometheus text format.
+#[derive(Debug, Clone, Copy)]
+pub struct MetricsHandler;
+
+#[async_trait]
+impl<A: Send> Handler<A> for MetricsHandler {
+    async fn handle(&self, _args: A, _req: Request<Body>, _body: Vec<u8>) -> Response<Body>
+    where
+        A: 'async_trait,
+    {
+        let mut response = Response::new(Body::from(Metrics::global().render()));
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain; version=0.0.4"));
+        response
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::read_body;
+
+    #[test]
+    async fn count_response_bytes() {
+        let metrics = Metrics::default();
+        let route = metrics.route(&Method::GET, "/messages/*");
+        let mut response = count_response(Arc::clone(&route), Response::new(Body::from("hello")));
+        assert_eq!(route.response_bytes(), 5);
+        assert_eq!(read_body(response.body_mut(), None).await.unwrap().unwrap(), b"hello");
+
+        let chunks: Vec<Result<&'static str, std::io::Error>> = vec![Ok("multi"), Ok("part")];
+        let streaming = Body::wrap_stream(Iter(chunks.into_iter()));
+        let mut response = count_response(Arc::clone(&route), Response::new(streaming));
+        assert_eq!(route.response_bytes(), 5);
+        assert_eq!(
+            read_body(response.body_mut(), None).await.unwrap().unwrap(),
+            b"multipart"
+        );
+        assert_eq!(route.response_bytes(), 14);
+        assert!(metrics
+            .render()
+            .contains("mqs_response_bytes_total{method=\"GET\",route=\"/messages/*\"} 14\n"));
+    }
+
+    struct Iter<I>(I);
+
+    impl<I: Iterator + Unpin> Stream for Iter<I> {
+        type Item = I::Item;
+
+        fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            Poll::Ready(self.0.next())
+        }
+    }
+}
@@ -1,10 +1,23 @@
 use async_trait::async_trait;
-use hyper::{Body, Method, Request, Response};
+use hyper::{
+    header::{HeaderValue, ALLOW},
+    Body,
+    Method,
+    Request,
+    Response,
+};
 use std::{collections::hash_map::HashMap, sync::Arc};
 
+use crate::Status;
+
+mod error;
 mod handler;
+mod metrics;
 
+pub use error::{ErrorFormat, ErrorMessage, HandlerError};
 pub use handler::handle;
+pub(crate) use handler::path_segments;
+pub use metrics::{InFlightRequest, Metrics, MetricsHandler, RouteMetrics};
 
 /// A `Handler` represents a single route (Method + Path) a server provides.
 #[async_trait]
@@ -22,6 +35,77 @@ pub trait Handler<A>: Sync + Send {
         A: 'async_trait;
 }
 
+/// A `TryHandler` is a `Handler` which can fail.
+///
+/// Instead of building an error response itself, it returns a `HandlerError` which is converted to
+/// a response with the matching status code. This suits handlers which validate their request (like
+/// headers or query parameters) before doing any work, as they can reject it with `?`. Add it to a
+/// `Router` with `Router::with_try_handler` or wrap it in `Fallible` to use it as a `Handler`.
+///
+/// ```
+/// use async_trait::async_trait;
+/// use hyper::{Body, Request, Response};
+/// use mqs_common::router::{HandlerError, TryHandler};
+///
+/// struct EchoHandler;
+///
+/// #[async_trait]
+/// impl TryHandler<()> for EchoHandler {
+///     fn needs_body(&self) -> bool {
+///         true
+///     }
+///
+///     async fn try_handle(
+///         &self,
+///         _args: (),
+///         _req: Request<Body>,
+///         body: Vec<u8>,
+///     ) -> Result<Response<Body>, HandlerError> {
+///         if body.is_empty() {
+///             return Err(HandlerError::BadRequest("Empty body".to_string()));
+///         }
+///
+///         Ok(Response::new(Body::from(body)))
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait TryHandler<A>: Sync + Send {
+    /// A function to determine whether we need to read the body of a request to produce a response.
+    /// See `Handler::needs_body`.
+    fn needs_body(&self) -> bool {
+        false
+    }
+
+    /// Handle a single request like `Handler::handle`, but return an error if no successful response
+    /// can be produced.
+    async fn try_handle(&self, args: A, req: Request<Body>, body: Vec<u8>) -> Result<Response<Body>, HandlerError>
+    where
+        A: 'async_trait;
+}
+
+/// Wraps a `TryHandler` to use it as a `Handler`. Errors returned by the wrapped handler are
+/// converted to responses using `HandlerError::into_response`.
+#[derive(Debug, Clone, Copy)]
+pub struct Fallible<H>(pub H);
+
+#[async_trait]
+impl<A: Send, H: TryHandler<A>> Handler<A> for Fallible<H> {
+    fn needs_body(&self) -> bool {
+        self.0.needs_body()
+    }
+
+    async fn handle(&self, args: A, req: Request<Body>, body: Vec<u8>) -> Response<Body>
+    where
+        A: 'async_trait,
+    {
+        self.0
+            .try_handle(args, req, body)
+            .await
+            .unwrap_or_else(HandlerError::into_response)
+    }
+}
+
 /// A wildcard router accepts a single arbitrary string and returns a new router to continue
 /// parsing the rest of the URL.
 pub trait WildcardRouter<A>: Sync + Send {
@@ -35,6 +119,28 @@ pub struct Router<A> {
     handler:         HashMap<Method, Arc<dyn Handler<A>>>,
     wildcard_router: Option<Arc<dyn WildcardRouter<A>>>,
     sub_router:      HashMap<&'static str, Router<A>>,
+    auto_options:    bool,
+    error_format:    ErrorFormat,
+}
+
+/// Answers an OPTIONS request with the methods registered for the matched route.
+struct OptionsHandler {
+    allow: String,
+}
+
+#[async_trait]
+impl<A: Send> Handler<A> for OptionsHandler {
+    async fn handle(&self, _args: A, _req: Request<Body>, _body: Vec<u8>) -> Response<Body>
+    where
+        A: 'async_trait,
+    {
+        let mut response = Response::new(Body::default());
+        *response.status_mut() = Status::NoContent.into();
+        if let Ok(value) = HeaderValue::from_str(&self.allow) {
+            response.headers_mut().insert(ALLOW, value);
+        }
+        response
+    }
 }
 
 impl<A> Default for Router<A> {
@@ -44,28 +150,97 @@ impl<A> Default for Router<A> {
             handler:         HashMap::new(),
             wildcard_router: None,
             sub_router:      HashMap::new(),
+            auto_options:    false,
+            error_format:    ErrorFormat::Json,
         }
     }
 }
 
-impl<A> Router<A> {
+impl<A: Send> Router<A> {
     /// Route a single request with the given method and segments of the URL. The segments are
     /// expected to be the path of the URL split by the '/' characters.
     /// If no route can be found, `None` is returned.
-    pub fn route<'a, I: Iterator<Item = &'a str>>(
+    ///
+    /// If the router was created with `with_auto_options`, an OPTIONS request to a route without
+    /// an explicit OPTIONS handler is answered with the methods registered for that route.
+    pub fn route<'a, I: Iterator<Item = &'a str>>(&self, method: &Method, segments: I) -> Option<Arc<dyn Handler<A>>> {
+        self.route_with(method, segments, self.auto_options, &mut String::new())
+    }
+
+    /// Route a single request like `route` and also return the pattern of the matched route. The
+    /// pattern is the path of the route with every segment accepted by a wildcard router replaced by
+    /// `*`, so all requests handled by the same route share the same pattern.
+    ///
+    /// ```
+    /// use async_trait::async_trait;
+    /// use hyper::{Body, Method, Request, Response};
+    /// use mqs_common::router::{Handler, Router, WildcardRouter};
+    ///
+    /// struct EmptyHandler;
+    ///
+    /// #[async_trait]
+    /// impl Handler<()> for EmptyHandler {
+    ///     async fn handle(&self, _args: (), _req: Request<Body>, _body: Vec<u8>) -> Response<Body> {
+    ///         Response::new(Body::default())
+    ///     }
+    /// }
+    ///
+    /// impl WildcardRouter<()> for EmptyHandler {
+    ///     fn with_segment(&self, _segment: &str) -> Router<()> {
+    ///         Router::new_simple(Method::GET, EmptyHandler)
+    ///     }
+    /// }
+    ///
+    /// let router =
+    ///     Router::default().with_route("queues", Router::default().with_wildcard(EmptyHandler));
+    /// let (_, pattern) = router
+    ///     .route_with_pattern(&Method::GET, "/queues/my-queue".split('/'))
+    ///     .unwrap();
+    /// assert_eq!(pattern, "/queues/*");
+    /// ```
+    pub fn route_with_pattern<'a, I: Iterator<Item = &'a str>>(
+        &self,
+        method: &Method,
+        segments: I,
+    ) -> Option<(Arc<dyn Handler<A>>, String)> {
+        let mut pattern = String::new();
+        let handler = self.route_with(method, segments, self.auto_options, &mut pattern)?;
+        if pattern.is_empty() {
+            pattern.push('/');
+        }
+
+        Some((handler, pattern))
+    }
+
+    fn route_with<'a, I: Iterator<Item = &'a str>>(
         &self,
         method: &Method,
         mut segments: I,
+        auto_options: bool,
+        pattern: &mut String,
     ) -> Option<Arc<dyn Handler<A>>> {
         segments.next().map_or_else(
-            || self.handler.get(method).map(Arc::clone),
+            || {
+                self.handler.get(method).map(Arc::clone).or_else(|| {
+                    if auto_options && method == Method::OPTIONS && !self.handler.is_empty() {
+                        Some(Arc::new(OptionsHandler { allow: self.allow() }) as Arc<dyn Handler<A>>)
+                    } else {
+                        None
+                    }
+                })
+            },
             |segment| {
                 if segment.is_empty() {
-                    self.route(method, segments)
+                    self.route_with(method, segments, auto_options, pattern)
                 } else if let Some(sub) = self.sub_router.get(segment) {
-                    sub.route(method, segments)
+                    pattern.push('/');
+                    pattern.push_str(segment);
+                    sub.route_with(method, segments, auto_options, pattern)
                 } else if let Some(wildcard) = &self.wildcard_router {
-                    wildcard.with_segment(segment).route(method, segments)
+                    pattern.push_str("/*");
+                    wildcard
+                        .with_segment(segment)
+                        .route_with(method, segments, auto_options, pattern)
                 } else {
                     None
                 }
@@ -73,6 +248,41 @@ impl<A> Router<A> {
         )
     }
 
+    /// List the methods registered for the root of this router (plus OPTIONS) in a format suitable
+    /// for the Allow header.
+    fn allow(&self) -> String {
+        let mut methods: Vec<&str> = self.handler.keys().map(Method::as_str).collect();
+        methods.push(Method::OPTIONS.as_str());
+        methods.sort_unstable();
+        methods.dedup();
+        methods.join(", ")
+    }
+}
+
+impl<A> Router<A> {
+    /// Create a new router from the current router which answers OPTIONS requests for all routes
+    /// without an explicit OPTIONS handler by listing the registered methods in the Allow header.
+    /// Only has an effect on the outermost router used to route requests.
+    #[must_use]
+    pub const fn with_auto_options(mut self) -> Self {
+        self.auto_options = true;
+        self
+    }
+
+    /// Create a new router from the current router which sends the bodies of error responses in the
+    /// given format. Only has an effect on the outermost router used to route requests.
+    #[must_use]
+    pub const fn with_error_format(mut self, error_format: ErrorFormat) -> Self {
+        self.error_format = error_format;
+        self
+    }
+
+    /// Get the format of the bodies of error responses set with `with_error_format`.
+    #[must_use]
+    pub const fn error_format(&self) -> ErrorFormat {
+        self.error_format
+    }
+
     /// Create a new router with a single handler registered on the root path for the given method.
     pub fn new_simple<H: 'static + Handler<A>>(method: Method, handler: H) -> Self {
         Self::default().with_handler(method, handler)
@@ -89,6 +299,16 @@ impl<A> Router<A> {
         self
     }
 
+    /// Like `with_handler`, but for a handler which can fail. Errors returned by the handler are
+    /// converted to error responses.
+    #[must_use]
+    pub fn with_try_handler<H: 'static + TryHandler<A>>(self, method: Method, handler: H) -> Self
+    where
+        A: Send,
+    {
+        self.with_handler(method, Fallible(handler))
+    }
+
     /// Create a new router from the current router with the next segment handled by the given wildcard
     /// router. Panics if the router already has a wildcard router set.
     #[must_use]
@@ -121,7 +341,7 @@ impl<A> Router<A> {
 #[cfg(test)]
 pub(crate) mod test {
     use super::*;
-    use hyper::header::HeaderValue;
+    use crate::read_body;
 
     struct SimpleHandler;
 
@@ -320,4 +540,68 @@ pub(crate) mod test {
             );
         }
     }
+
+    #[test]
+    async fn route_auto_options() {
+        let router = Router::default().with_route(
+            "collect",
+            Router::default().with_wildcard(CollectingHandler { messages: Vec::new() }),
+        );
+        assert!(router
+            .route(&Method::OPTIONS, vec!["collect", "a"].into_iter())
+            .is_none());
+        let router = router.with_auto_options();
+        assert!(router.route(&Method::OPTIONS, vec!["collect"].into_iter()).is_none());
+        assert!(router.route(&Method::OPTIONS, vec!["other"].into_iter()).is_none());
+        let handler = router
+            .route(&Method::OPTIONS, vec!["collect", "a"].into_iter())
+            .unwrap();
+        let response = handler.handle((), Request::new(Body::default()), Vec::new()).await;
+        assert_eq!(response.status(), 204);
+        assert_eq!(
+            response.headers().get(ALLOW),
+            Some(&HeaderValue::from_static("GET, OPTIONS"))
+        );
+    }
+
+    struct TryEchoHandler;
+
+    #[async_trait]
+    impl TryHandler<()> for TryEchoHandler {
+        fn needs_body(&self) -> bool {
+            true
+        }
+
+        async fn try_handle(
+            &self,
+            _args: (),
+            _req: Request<Body>,
+            body: Vec<u8>,
+        ) -> Result<Response<Body>, HandlerError> {
+            if body.is_empty() {
+                return Err(HandlerError::BadRequest("Empty body".to_string()));
+            }
+
+            Ok(Response::new(Body::from(body)))
+        }
+    }
+
+    #[test]
+    async fn route_try_handler() {
+        let router =
+            Router::default().with_route("echo", Router::default().with_try_handler(Method::POST, TryEchoHandler));
+        let handler = router.route(&Method::POST, vec!["echo"].into_iter()).unwrap();
+        assert!(handler.needs_body());
+        let mut response = handler
+            .handle((), Request::new(Body::default()), b"hello".to_vec())
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(read_body(response.body_mut(), None).await.unwrap().unwrap(), b"hello");
+        let mut response = handler.handle((), Request::new(Body::default()), Vec::new()).await;
+        assert_eq!(response.status(), 400);
+        assert_eq!(
+            read_body(response.body_mut(), None).await.unwrap().unwrap(),
+            b"{\"error\":\"Empty body\"}".as_ref()
+        );
+    }
 }
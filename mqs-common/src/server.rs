@@ -2,24 +2,42 @@ use async_trait::async_trait;
 use cached::once_cell::sync::Lazy;
 use dotenv::dotenv;
 use hyper::{
-    server::conn::AddrStream,
+    server::{
+        accept::Accept,
+        conn::{AddrIncoming, AddrStream},
+    },
     service::{make_service_fn, service_fn},
     Body,
     Request,
     Response,
     Server,
 };
-use log::{Level, Log};
-use std::{cell::Cell, convert::Infallible, io::Stdout, net::SocketAddr, sync::Arc, time::Duration};
+use log::Level;
+use std::{
+    cell::Cell,
+    convert::Infallible,
+    future::{pending, Future},
+    io::Stdout,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
 use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
     runtime::{Builder, Runtime},
     sync::{oneshot::Sender, Mutex},
-    time::sleep,
+    time::{sleep, Instant, Sleep},
 };
 
 use crate::{
     connection::{init_pool_maybe, Pool},
-    logger::{configure_logger, create_trace_id, json::Logger, with_trace_id, NewJsonLogger},
+    logger::{audit, configure_logger, create_trace_id, json::Logger, set_log_level, with_trace_id, NewJsonLogger},
+    router::{path_segments, ErrorFormat, HandlerError, Metrics},
 };
 
 #[cfg(unix)]
@@ -51,26 +69,422 @@ fn setup_signal_handler(rt: &Runtime, tx: Sender<()>) {
     });
 }
 
+#[cfg(unix)]
+#[derive(Serialize)]
+struct LogLevelChange {
+    action: &'static str,
+    level:  &'static str,
+}
+
+#[cfg(unix)]
+type LevelChange = fn(Level) -> Level;
+
+#[cfg(unix)]
+const fn more_verbose(level: Level) -> Level {
+    match level {
+        Level::Error => Level::Warn,
+        Level::Warn => Level::Info,
+        Level::Info => Level::Debug,
+        Level::Debug | Level::Trace => Level::Trace,
+    }
+}
+
+#[cfg(unix)]
+const fn less_verbose(level: Level) -> Level {
+    match level {
+        Level::Error | Level::Warn => Level::Error,
+        Level::Info => Level::Warn,
+        Level::Debug => Level::Info,
+        Level::Trace => Level::Debug,
+    }
+}
+
+/// Raise the log level by one step on SIGUSR1 and lower it by one step on SIGUSR2. This allows
+/// enabling debug logs on a running server without restarting it.
+#[cfg(unix)]
+fn setup_log_level_handler(rt: &Runtime, logger: &'static Logger<Stdout>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let changes: [(SignalKind, LevelChange); 2] = [
+        (SignalKind::user_defined1(), more_verbose),
+        (SignalKind::user_defined2(), less_verbose),
+    ];
+    for (signal_kind, change) in changes {
+        rt.spawn(async move {
+            let mut signals = signal(signal_kind).unwrap();
+            while signals.recv().await.is_some() {
+                let level = change(logger.level());
+                set_log_level(logger, level);
+                // audit entries are written regardless of the level, so the change is always visible
+                audit(&LogLevelChange {
+                    action: "set_log_level",
+                    level:  level.as_str(),
+                });
+            }
+        });
+    }
+}
+
+#[cfg(windows)]
+fn setup_log_level_handler(_rt: &Runtime, _logger: &'static Logger<Stdout>) {
+    // there are no user defined signals on windows, the level can only be set via LOG_LEVEL
+}
+
 /// A ServerHandler handles requests of any kind, this is the main entry point for each request your
 /// server receives.
 #[async_trait]
 pub trait ServerHandler: Sync + Send {
     /// Handle a single request and produce a response.
     async fn handle(&self, req: Request<Body>) -> Response<Body>;
+
+    /// Format of the error responses sent by the server itself, e.g. if too many requests are in
+    /// flight. Defaults to `ErrorFormat::Json`.
+    fn error_format(&self) -> ErrorFormat {
+        ErrorFormat::Json
+    }
+}
+
+/// Configuration for running a server with `run`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// The port to listen on.
+    pub port:          u16,
+    /// How long to wait for requests still in progress after receiving a shutdown signal. Once this
+    /// time has passed, the server is terminated even if some requests did not complete yet.
+    pub drain_timeout: Duration,
+    /// Maximum number of requests handled at the same time. Additional requests are rejected with
+    /// `503 Service Unavailable` and a `Retry-After` header instead of waiting for a database
+    /// connection. Requests for a `MonitoringRoute` are never rejected. If `None`, requests are not
+    /// limited.
+    pub max_in_flight: Option<u64>,
+    /// How long a keep-alive connection may stay idle before it is closed. A connection is idle if
+    /// nothing is read from or written to it and no request is being handled, so a long poll
+    /// waiting for messages is not affected.
+    pub idle_timeout:  Duration,
 }
 
-/// Run a HTTP server on the given port with the given `ServerHandler`. The constructed `ServerHandler`
-/// is called for every request and has to produce a response.
+impl ServerConfig {
+    /// The drain timeout used by `ServerConfig::new`.
+    pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+    /// The idle timeout used by `ServerConfig::new`.
+    pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// Create a new configuration for the given port with the default drain and idle timeouts and
+    /// no limit on the number of requests handled at the same time.
+    ///
+    /// ```
+    /// use mqs_common::server::ServerConfig;
+    ///
+    /// let config = ServerConfig::new(7843);
+    /// assert_eq!(config.port, 7843);
+    /// assert_eq!(config.drain_timeout, ServerConfig::DEFAULT_DRAIN_TIMEOUT);
+    /// assert_eq!(config.max_in_flight, None);
+    /// assert_eq!(config.idle_timeout, ServerConfig::DEFAULT_IDLE_TIMEOUT);
+    /// ```
+    #[must_use]
+    pub const fn new(port: u16) -> Self {
+        Self {
+            port,
+            drain_timeout: Self::DEFAULT_DRAIN_TIMEOUT,
+            max_in_flight: None,
+            idle_timeout: Self::DEFAULT_IDLE_TIMEOUT,
+        }
+    }
+}
+
+/// Routes used to monitor the server. They are handled even if the server is overloaded, so
+/// monitoring keeps working, and requests for them don't count as in flight either.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonitoringRoute {
+    /// The health check at `/health`.
+    Health,
+    /// The metrics of the server at `/metrics`.
+    Metrics,
+    /// The version and uptime of the server at `/info`.
+    Info,
+}
+
+impl MonitoringRoute {
+    /// Find the monitoring route a request for the given path is routed to. The path is split into
+    /// segments just like for routing, so `//health/` is the health check as well.
+    ///
+    /// ```
+    /// use mqs_common::server::MonitoringRoute;
+    ///
+    /// assert_eq!(
+    ///     MonitoringRoute::from_path("/health"),
+    ///     Some(MonitoringRoute::Health)
+    /// );
+    /// assert_eq!(
+    ///     MonitoringRoute::from_path("//metrics/"),
+    ///     Some(MonitoringRoute::Metrics)
+    /// );
+    /// assert_eq!(
+    ///     MonitoringRoute::from_path("/info"),
+    ///     Some(MonitoringRoute::Info)
+    /// );
+    /// assert_eq!(MonitoringRoute::from_path("/queues"), None);
+    /// ```
+    #[must_use]
+    pub fn from_path(path: &str) -> Option<Self> {
+        match path_segments(path)?.as_slice() {
+            [segment] => match segment.as_ref() {
+                "health" => Some(Self::Health),
+                "metrics" => Some(Self::Metrics),
+                "info" => Some(Self::Info),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Check whether requests for the given path are exempt from `max_in_flight`.
+fn is_unlimited(path: &str) -> bool {
+    MonitoringRoute::from_path(path).is_some()
+}
+
+/// Check whether a request has to be rejected because more than `max_in_flight` requests
+/// (including this one) are in flight.
+fn is_overloaded(max_in_flight: Option<u64>, in_flight: u64) -> bool {
+    matches!(max_in_flight, Some(max) if in_flight > max)
+}
+
+/// Accepts connections which are closed once they were idle for too long.
+struct IdleIncoming {
+    incoming:     AddrIncoming,
+    idle_timeout: Duration,
+}
+
+impl Accept for IdleIncoming {
+    type Conn = IdleStream;
+    type Error = std::io::Error;
+
+    fn poll_accept(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let this = self.get_mut();
+        let idle_timeout = this.idle_timeout;
+        Pin::new(&mut this.incoming)
+            .poll_accept(cx)
+            .map(|conn| conn.map(|conn| conn.map(|stream| IdleStream::new(stream, idle_timeout))))
+    }
+}
+
+/// A connection reporting the end of its input once nothing was read from or written to it for
+/// the idle timeout while no request was handled on it. This makes hyper close the connection.
+///
+/// Hyper only reads the next request from a keep-alive connection once data arrives, so the timer
+/// is polled on every read and write to make sure the connection is woken up once it expires.
+struct IdleStream {
+    stream:          AddrStream,
+    idle_timeout:    Duration,
+    timer:           Pin<Box<Sleep>>,
+    active_requests: Arc<AtomicUsize>,
+}
+
+impl IdleStream {
+    fn new(stream: AddrStream, idle_timeout: Duration) -> Self {
+        Self {
+            stream,
+            idle_timeout,
+            timer: Box::pin(sleep(idle_timeout)),
+            active_requests: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn reset_timer(&mut self, cx: &mut Context<'_>) {
+        self.timer.as_mut().reset(Instant::now() + self.idle_timeout);
+        // register the connection to be woken up once the new deadline passes
+        let _ = self.timer.as_mut().poll(cx);
+    }
+
+    fn is_idle(&mut self, cx: &mut Context<'_>) -> bool {
+        if self.active_requests.load(Ordering::Acquire) > 0 {
+            self.reset_timer(cx);
+
+            return false;
+        }
+
+        self.timer.as_mut().poll(cx).is_ready()
+    }
+}
+
+impl AsyncRead for IdleStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        match Pin::new(&mut this.stream).poll_read(cx, buf) {
+            Poll::Pending => {
+                if this.is_idle(cx) {
+                    debug!("Closing connection from {} after being idle", this.stream.remote_addr());
+
+                    // reading nothing signals the end of the input
+                    Poll::Ready(Ok(()))
+                } else {
+                    Poll::Pending
+                }
+            },
+            Poll::Ready(result) => {
+                if buf.filled().len() > filled {
+                    this.reset_timer(cx);
+                }
+
+                Poll::Ready(result)
+            },
+        }
+    }
+}
+
+impl AsyncWrite for IdleStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.stream).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = &result {
+            if *written > 0 {
+                this.reset_timer(cx);
+            }
+        }
+
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+/// Marks a request as being handled on a connection until it is dropped.
+struct ActiveRequest(Arc<AtomicUsize>);
+
+impl ActiveRequest {
+    fn start(active_requests: &Arc<AtomicUsize>) -> Self {
+        active_requests.fetch_add(1, Ordering::AcqRel);
+
+        Self(Arc::clone(active_requests))
+    }
+}
+
+impl Drop for ActiveRequest {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Bind a HTTP server for the given `ServerHandler` to the given address.
+///
+/// Returns the address the server is bound to, which differs from the given address if the port
+/// was 0, and a future serving requests until `shutdown` completes. Requests still in progress at
+/// that time are completed before the future resolves.
+///
+/// Requests are handled just like with `run`, including the limit of `max_in_flight` requests and
+/// closing connections idle for longer than `idle_timeout`, but no logging, database pool, or
+/// signal handlers are set up. This makes it possible to run a server inside of an existing
+/// runtime, e.g. in a test. This function has to be called from within a tokio runtime.
+///
+/// # Errors
+///
+/// Returns an error if the server can not be bound to the given address.
+pub fn bind<S: ServerHandler + 'static, F: Future<Output = ()>>(
+    service: Arc<S>,
+    addr: &SocketAddr,
+    max_in_flight: Option<u64>,
+    idle_timeout: Duration,
+    shutdown: F,
+) -> hyper::Result<(SocketAddr, impl Future<Output = hyper::Result<()>>)> {
+    let make_service = make_service_fn(move |conn: &IdleStream| {
+        let remote_addr = conn.stream.remote_addr();
+        info!("New connection from {}", remote_addr);
+        let conn_service = Arc::clone(&service);
+        let active_requests = Arc::clone(&conn.active_requests);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let req_service = Arc::clone(&conn_service);
+                let active = ActiveRequest::start(&active_requests);
+                let unlimited = is_unlimited(req.uri().path());
+                let guard = if unlimited {
+                    None
+                } else {
+                    Some(Metrics::global().start_request())
+                };
+                let overloaded = !unlimited && is_overloaded(max_in_flight, Metrics::global().in_flight());
+                let id = create_trace_id(&req);
+                #[cfg(feature = "tracing")]
+                let span = tracing::info_span!(
+                    "mqs_request",
+                    trace_id = %id,
+                    method = %req.method(),
+                    path = req.uri().path(),
+                );
+                async move {
+                    if overloaded {
+                        drop(guard);
+                        debug!("Rejecting request, more than {:?} requests in flight", max_in_flight);
+                        let response = HandlerError::ServiceUnavailable.into_response();
+                        return Ok::<_, Infallible>(req_service.error_format().format_response(response));
+                    }
+
+                    let handled = with_trace_id(id, req_service.handle(req));
+                    #[cfg(feature = "tracing")]
+                    let handled = tracing::Instrument::instrument(handled, span);
+                    let response = handled.await;
+                    drop(guard);
+                    drop(active);
+                    Ok::<_, Infallible>(response)
+                }
+            }))
+        }
+    });
+
+    let incoming = AddrIncoming::bind(addr)?;
+    let addr = incoming.local_addr();
+    let server = Server::builder(IdleIncoming { incoming, idle_timeout })
+        .http1_keepalive(true)
+        .serve(make_service);
+
+    Ok((addr, server.with_graceful_shutdown(shutdown)))
+}
+
+/// Load the variables of a `.env` file in the working directory into the environment. Variables
+/// which are already set are not overwritten. `run` does this as well, call it yourself if you
+/// read your configuration from the environment before calling `run`.
+pub fn load_env() {
+    dotenv().ok();
+}
+
+/// Run a HTTP server with the given `ServerHandler`. The constructed `ServerHandler` is called for
+/// every request and has to produce a response.
 ///
 /// This function is intended to serve as your main function and therefore also sets up logging and
-/// shuts down the server after receiving a SIGTERM or SIGINT.
-pub fn run<F: FnOnce(Pool) -> S, S: ServerHandler + 'static>(mk_service: F, port: u16) {
+/// shuts down the server after receiving a SIGTERM or SIGINT. SIGUSR1 and SIGUSR2 raise and lower
+/// the log level at runtime. After such a signal no new requests are
+/// accepted and requests still in progress get up to `config.drain_timeout` to complete before the
+/// server is terminated.
+///
+/// The requests in flight are counted in the global `Metrics`. If more than `config.max_in_flight`
+/// requests are in flight, additional requests are rejected with `503 Service Unavailable` without
+/// calling the `ServerHandler`. Connections idle for longer than `config.idle_timeout` are closed.
+///
+/// Before the server accepts requests, the minimum number of database connections is established
+/// by `init_pool_maybe`. If the database can not be reached, the server does not start.
+///
+/// With the `tracing` feature, every request is additionally handled in an `mqs_request` span with
+/// its trace id, method, and path, so a `tracing` subscriber installed by your application sees the
+/// requests. Logging is not affected by this.
+pub fn run<F: FnOnce(Pool) -> S, S: ServerHandler + 'static>(mk_service: F, config: &ServerConfig) {
     static LOGGER: Lazy<Logger<Stdout>, NewJsonLogger> = Lazy::new(NewJsonLogger::new(Level::Info));
 
-    dotenv().ok();
+    load_env();
     configure_logger(&*LOGGER);
 
     let (pool, pool_size) = init_pool_maybe().expect("Failed to initialize database pool");
+    info!(
+        "Connected {} of at most {} database connections",
+        pool.state().connections,
+        pool_size
+    );
     let rt = Builder::new_multi_thread()
         .enable_all()
         .worker_threads(pool_size as usize)
@@ -87,38 +501,137 @@ pub fn run<F: FnOnce(Pool) -> S, S: ServerHandler + 'static>(mk_service: F, port
 
     let (tx, rx) = tokio::sync::oneshot::channel::<()>();
     setup_signal_handler(&rt, tx);
+    setup_log_level_handler(&rt, &LOGGER);
+
+    let drain_timeout = config.drain_timeout;
+    let max_in_flight = config.max_in_flight;
+    let idle_timeout = config.idle_timeout;
+    let port = config.port;
 
-    rt.block_on(async {
+    let drained = rt.block_on(async {
         // Setup and configure server...
         let addr = SocketAddr::from(([0, 0, 0, 0], port));
-        let make_service = make_service_fn(move |conn: &AddrStream| {
-            let remote_addr = conn.remote_addr();
-            info!("New connection from {}", remote_addr);
-            let conn_service = Arc::clone(&service);
-            async move {
-                Ok::<_, Infallible>(service_fn(move |req| {
-                    let req_service = Arc::clone(&conn_service);
-                    let id = create_trace_id(&req);
-                    async move { Ok::<_, Infallible>(with_trace_id(id, req_service.handle(req)).await) }
-                }))
-            }
-        });
-
-        let server = Server::bind(&addr).http1_keepalive(true).serve(make_service);
+        let (drain_tx, drain_rx) = tokio::sync::oneshot::channel::<()>();
+        let (addr, server) = bind(service, &addr, max_in_flight, idle_timeout, async {
+            rx.await.ok();
+            let _ = drain_tx.send(());
+        })
+        .expect("Failed to bind server");
 
         info!("Started server on {} with a pool of size {}", addr, pool_size);
 
-        let graceful = server.with_graceful_shutdown(async {
-            rx.await.ok();
-        });
+        let drain_deadline = async {
+            if drain_rx.await.is_ok() {
+                sleep(drain_timeout).await;
+            } else {
+                // the server stopped without a shutdown signal, nothing to drain
+                pending::<()>().await;
+            }
+        };
 
-        // Run the server until we are told to shutdown
-        if let Err(e) = graceful.await {
-            error!("Server terminated with error: {}", e);
-        } else {
-            info!("Completed server shutdown");
-        }
+        // Run the server until we are told to shutdown and the remaining requests completed or
+        // the drain timeout passed
+        let drained = tokio::select! {
+            result = server => {
+                if let Err(e) = result {
+                    error!("Server terminated with error: {}", e);
+                } else {
+                    info!("Completed server shutdown");
+                }
+
+                true
+            },
+            _ = drain_deadline => {
+                warn!(
+                    "Drain timeout of {:?} exceeded, terminating server with {} pending requests",
+                    drain_timeout,
+                    Metrics::global().in_flight(),
+                );
+
+                false
+            },
+        };
 
         LOGGER.flush();
+
+        drained
     });
+
+    if !drained {
+        // do not wait for the remaining requests, they had their chance to complete
+        rt.shutdown_background();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{
+        io::{ErrorKind, Read, Write},
+        net::TcpStream,
+    };
+    use tokio::task::spawn_blocking;
+
+    struct SlowHandler;
+
+    #[async_trait]
+    impl ServerHandler for SlowHandler {
+        async fn handle(&self, _req: Request<Body>) -> Response<Body> {
+            sleep(Duration::from_millis(500)).await;
+
+            Response::new(Body::from("done"))
+        }
+    }
+
+    #[test]
+    async fn overloaded() {
+        assert!(!is_overloaded(None, 1000));
+        assert!(!is_overloaded(Some(10), 10));
+        assert!(is_overloaded(Some(10), 11));
+        assert!(is_overloaded(Some(0), 1));
+        for path in &["/health", "/metrics", "/info", "//health", "/health/", "/%68ealth"] {
+            assert!(is_unlimited(path), "{} should be unlimited", path);
+        }
+        for path in &["/", "/queues", "/health/check", "/messages/health", "/%FF"] {
+            assert!(!is_unlimited(path), "{} should be limited", path);
+        }
+    }
+
+    #[test]
+    async fn idle_timeout() {
+        let (addr, server) = bind(
+            Arc::new(SlowHandler),
+            &SocketAddr::from(([127, 0, 0, 1], 0)),
+            None,
+            Duration::from_millis(200),
+            pending(),
+        )
+        .unwrap();
+        tokio::spawn(server);
+
+        let read = spawn_blocking(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            stream
+                .write_all(b"GET /messages/my-queue HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n")
+                .unwrap();
+
+            // the request takes longer than the idle timeout, but still completes
+            let mut response = Vec::new();
+            let mut buffer = [0; 1024];
+            while !response.ends_with(b"done") {
+                let read = stream.read(&mut buffer).unwrap();
+                assert_ne!(read, 0, "connection closed before the response was complete");
+                response.extend_from_slice(&buffer[..read]);
+            }
+            assert!(response.starts_with(b"HTTP/1.1 200 OK"));
+
+            // afterwards the idle connection is closed by the server
+            match stream.read(&mut buffer) {
+                Ok(read) => assert_eq!(read, 0),
+                Err(err) => assert_eq!(err.kind(), ErrorKind::ConnectionReset),
+            }
+        });
+        read.await.unwrap();
+    }
 }
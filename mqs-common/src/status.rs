@@ -4,23 +4,27 @@ use hyper::StatusCode;
 #[derive(Debug, Clone, Copy)]
 pub enum Status {
     /// HTTP 200 OK
-    Ok                  = 200,
+    Ok                   = 200,
     /// HTTP 201 Created
-    Created             = 201,
+    Created              = 201,
     /// HTTP 204 No Content
-    NoContent           = 204,
+    NoContent            = 204,
     /// HTTP 400 Bad Request
-    BadRequest          = 400,
+    BadRequest           = 400,
     /// HTTP 404 Not Found
-    NotFound            = 404,
+    NotFound             = 404,
     /// HTTP 409 Conflict
-    Conflict            = 409,
+    Conflict             = 409,
     /// HTTP 413 Payload Too Large
-    PayloadTooLarge     = 413,
+    PayloadTooLarge      = 413,
+    /// HTTP 415 Unsupported Media Type
+    UnsupportedMediaType = 415,
+    /// HTTP 429 Too Many Requests
+    TooManyRequests      = 429,
     /// HTTP 500 Internal Server Error
-    InternalServerError = 500,
+    InternalServerError  = 500,
     /// HTTP 503 Service Unavailable
-    ServiceUnavailable  = 503,
+    ServiceUnavailable   = 503,
 }
 
 impl From<&Status> for StatusCode {
@@ -33,6 +37,8 @@ impl From<&Status> for StatusCode {
             Status::NotFound => Self::NOT_FOUND,
             Status::Conflict => Self::CONFLICT,
             Status::PayloadTooLarge => Self::PAYLOAD_TOO_LARGE,
+            Status::UnsupportedMediaType => Self::UNSUPPORTED_MEDIA_TYPE,
+            Status::TooManyRequests => Self::TOO_MANY_REQUESTS,
             Status::InternalServerError => Self::INTERNAL_SERVER_ERROR,
             Status::ServiceUnavailable => Self::SERVICE_UNAVAILABLE,
         }
@@ -59,6 +65,8 @@ mod test {
             Status::NotFound,
             Status::Conflict,
             Status::PayloadTooLarge,
+            Status::UnsupportedMediaType,
+            Status::TooManyRequests,
             Status::InternalServerError,
             Status::ServiceUnavailable,
         ];
@@ -0,0 +1,2 @@
+ALTER TABLE queues
+    DROP COLUMN allowed_content_types;
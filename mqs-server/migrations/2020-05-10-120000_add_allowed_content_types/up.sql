@@ -0,0 +1,2 @@
+ALTER TABLE queues
+    ADD COLUMN allowed_content_types VARCHAR[] NOT NULL DEFAULT '{}';
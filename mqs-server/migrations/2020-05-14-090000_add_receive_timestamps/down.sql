@@ -0,0 +1,3 @@
+ALTER TABLE messages
+    DROP COLUMN first_received_at,
+    DROP COLUMN last_received_at;
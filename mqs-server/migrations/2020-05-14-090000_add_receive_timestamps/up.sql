@@ -0,0 +1,3 @@
+ALTER TABLE messages
+    ADD COLUMN first_received_at TIMESTAMP WITHOUT TIME ZONE NULL,
+    ADD COLUMN last_received_at TIMESTAMP WITHOUT TIME ZONE NULL;
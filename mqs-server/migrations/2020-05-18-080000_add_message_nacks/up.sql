@@ -0,0 +1,2 @@
+ALTER TABLE messages
+    ADD COLUMN nacks INTEGER NOT NULL DEFAULT 0;
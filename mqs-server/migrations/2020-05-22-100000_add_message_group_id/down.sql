@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS messages_queue_group_id_created_at_idx;
+
+ALTER TABLE messages
+    DROP COLUMN group_id;
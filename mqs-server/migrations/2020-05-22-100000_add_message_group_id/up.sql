@@ -0,0 +1,5 @@
+ALTER TABLE messages
+    ADD COLUMN group_id VARCHAR NULL;
+
+CREATE INDEX IF NOT EXISTS messages_queue_group_id_created_at_idx
+    ON messages (queue, group_id, created_at) WHERE group_id IS NOT NULL;
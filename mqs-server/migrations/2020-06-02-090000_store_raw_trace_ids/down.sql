@@ -0,0 +1,6 @@
+ALTER TABLE messages
+    ALTER COLUMN trace_id TYPE UUID USING (
+        CASE
+            WHEN trace_id ~* '^[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}$' THEN trace_id::UUID
+        END
+    );
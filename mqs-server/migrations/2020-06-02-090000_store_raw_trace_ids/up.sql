@@ -0,0 +1,2 @@
+ALTER TABLE messages
+    ALTER COLUMN trace_id TYPE VARCHAR USING trace_id::VARCHAR;
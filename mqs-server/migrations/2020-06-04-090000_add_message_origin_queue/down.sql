@@ -0,0 +1,4 @@
+ALTER TABLE messages
+    DROP COLUMN redrive_reason;
+ALTER TABLE messages
+    DROP COLUMN origin_queue;
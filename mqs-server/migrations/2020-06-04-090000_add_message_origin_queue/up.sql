@@ -0,0 +1,4 @@
+ALTER TABLE messages
+    ADD COLUMN origin_queue VARCHAR NULL;
+ALTER TABLE messages
+    ADD COLUMN redrive_reason VARCHAR NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE queues
+    ADD COLUMN tags JSONB NOT NULL DEFAULT '{}';
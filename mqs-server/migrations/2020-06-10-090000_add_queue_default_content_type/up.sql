@@ -0,0 +1,2 @@
+ALTER TABLE queues
+    ADD COLUMN default_content_type VARCHAR NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE messages
+    ADD COLUMN json_attributes JSONB NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE queues
+    DROP COLUMN max_in_flight;
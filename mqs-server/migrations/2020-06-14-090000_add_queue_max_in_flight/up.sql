@@ -0,0 +1,2 @@
+ALTER TABLE queues
+    ADD COLUMN max_in_flight BIGINT NULL;
@@ -0,0 +1,4 @@
+ALTER TABLE queues
+    DROP COLUMN max_redrives;
+ALTER TABLE messages
+    DROP COLUMN redrive_count;
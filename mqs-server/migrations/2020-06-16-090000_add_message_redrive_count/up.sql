@@ -0,0 +1,4 @@
+ALTER TABLE messages
+    ADD COLUMN redrive_count INT NOT NULL DEFAULT 0;
+ALTER TABLE queues
+    ADD COLUMN max_redrives INT NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE queues
+    ADD COLUMN visibility_jitter INT NULL;
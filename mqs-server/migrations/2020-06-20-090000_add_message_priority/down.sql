@@ -0,0 +1,4 @@
+ALTER TABLE queues
+    DROP COLUMN message_priorities;
+ALTER TABLE messages
+    DROP COLUMN priority;
@@ -0,0 +1,4 @@
+ALTER TABLE messages
+    ADD COLUMN priority INT NOT NULL DEFAULT 0;
+ALTER TABLE queues
+    ADD COLUMN message_priorities BOOL NOT NULL DEFAULT FALSE;
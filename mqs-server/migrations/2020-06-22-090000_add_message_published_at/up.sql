@@ -0,0 +1,6 @@
+ALTER TABLE messages
+    ADD COLUMN published_at TIMESTAMP NULL;
+UPDATE messages
+    SET published_at = created_at;
+ALTER TABLE messages
+    ALTER COLUMN published_at SET NOT NULL;
@@ -13,22 +13,35 @@
 
 //! MQS server binary.
 
+#[macro_use]
+extern crate log;
+
 use async_trait::async_trait;
 use hyper::{Body, Request, Response};
-use std::{env, env::VarError, sync::Arc};
+use std::{env, env::VarError, sync::Arc, time::Duration};
 
 use mqs_common::{
-    connection::{Pool, Source},
-    router::{handle, Router},
+    connection::{get_connection, Pool, Source, SourceState},
+    router::{handle, ErrorFormat, Router},
     server,
-    server::ServerHandler,
+    server::{MonitoringRoute, ServerConfig, ServerHandler},
+};
+use mqs_server::{
+    accepts_gzip,
+    compress_response,
+    make_router,
+    make_unavailable_router,
+    set_receipt_key,
+    PgRepository,
+    RouterConfig,
 };
-use mqs_server::{make_router, PgRepository};
 
 struct HandlerService {
-    pool:             Arc<Pool>,
-    router:           Router<(PgRepository, RepoSource)>,
-    max_message_size: usize,
+    pool:               Arc<Pool>,
+    router:             Router<(PgRepository, RepoSource)>,
+    unavailable_router: Router<((), Option<SourceState>)>,
+    max_message_size:   usize,
+    error_format:       ErrorFormat,
 }
 
 struct RepoSource {
@@ -45,14 +58,20 @@ impl Source<PgRepository> for RepoSource {
     fn get(&self) -> Option<PgRepository> {
         self.pool.try_get().map(PgRepository::new)
     }
+
+    fn state(&self) -> Option<SourceState> {
+        Some(SourceState::from(self.pool.as_ref()))
+    }
 }
 
 impl HandlerService {
-    fn new(pool: Pool, router: Router<(PgRepository, RepoSource)>, max_message_size: usize) -> Self {
+    fn new(pool: Pool, config: &RouterConfig) -> Self {
         Self {
-            pool: Arc::new(pool),
-            router,
-            max_message_size,
+            pool:               Arc::new(pool),
+            router:             make_router(config),
+            unavailable_router: make_unavailable_router(config),
+            max_message_size:   config.max_message_size,
+            error_format:       config.error_format,
         }
     }
 }
@@ -60,22 +79,55 @@ impl HandlerService {
 #[async_trait]
 impl ServerHandler for HandlerService {
     async fn handle(&self, req: Request<Body>) -> Response<Body> {
-        let repo = self.pool.get().ok().map(PgRepository::new);
-        handle(
-            repo,
-            RepoSource::new(Arc::clone(&self.pool)),
-            &self.router,
-            self.max_message_size,
-            req,
-        )
-        .await
+        let accepts_gzip = accepts_gzip(req.headers());
+        // don't block health checks, metrics and the server info if the pool is exhausted, metrics
+        // and the server info never need a connection and health checks report the missing
+        // connection instead
+        let monitoring_route = MonitoringRoute::from_path(req.uri().path());
+        let repo = match monitoring_route {
+            Some(MonitoringRoute::Metrics | MonitoringRoute::Info) => None,
+            Some(MonitoringRoute::Health) => self.pool.try_get(),
+            None => get_connection(&self.pool).await,
+        };
+        let response = if let Some(conn) = repo {
+            handle(
+                Some(PgRepository::new(conn)),
+                RepoSource::new(Arc::clone(&self.pool)),
+                &self.router,
+                self.max_message_size,
+                req,
+            )
+            .await
+        } else if monitoring_route.is_some() {
+            handle(
+                Some(()),
+                Some(SourceState::from(self.pool.as_ref())),
+                &self.unavailable_router,
+                self.max_message_size,
+                req,
+            )
+            .await
+        } else {
+            handle(
+                None,
+                RepoSource::new(Arc::clone(&self.pool)),
+                &self.router,
+                self.max_message_size,
+                req,
+            )
+            .await
+        };
+        compress_response(accepts_gzip, response)
+    }
+
+    fn error_format(&self) -> ErrorFormat {
+        self.error_format
     }
 }
 
 fn get_max_message_size() -> usize {
-    const DEFAULT_MAX_MESSAGE_SIZE: usize = 1024 * 1024;
     match env::var("MAX_MESSAGE_SIZE") {
-        Err(VarError::NotPresent) => DEFAULT_MAX_MESSAGE_SIZE,
+        Err(VarError::NotPresent) => RouterConfig::DEFAULT_MAX_MESSAGE_SIZE,
         Err(VarError::NotUnicode(_)) => {
             panic!("MAX_MESSAGE_SIZE has to be a valid unicode string (it should be a numeric string in fact)")
         },
@@ -92,9 +144,157 @@ fn get_max_message_size() -> usize {
     }
 }
 
+fn get_max_receive_batch() -> u16 {
+    match env::var("MAX_RECEIVE_BATCH") {
+        Err(VarError::NotPresent) => RouterConfig::DEFAULT_MAX_RECEIVE_BATCH,
+        Err(VarError::NotUnicode(_)) => {
+            panic!("MAX_RECEIVE_BATCH has to be a valid unicode string (it should be a numeric string in fact)")
+        },
+        Ok(s) => match s.parse::<u16>() {
+            Err(err) => panic!("Failed to parse maximum receive batch size '{}': {}", s, err),
+            Ok(0) => panic!("Maximum receive batch size must be at least 1"),
+            Ok(n) => n,
+        },
+    }
+}
+
+fn get_auto_options() -> bool {
+    match env::var("AUTO_OPTIONS") {
+        Err(VarError::NotPresent) => false,
+        Err(VarError::NotUnicode(_)) => {
+            panic!("AUTO_OPTIONS has to be a valid unicode string (it should be either 'true' or 'false' in fact)")
+        },
+        Ok(s) => match s.parse::<bool>() {
+            Err(err) => panic!("Failed to parse auto options flag '{}': {}", s, err),
+            Ok(b) => b,
+        },
+    }
+}
+
+fn get_max_queues() -> Option<u32> {
+    match env::var("MAX_QUEUES") {
+        Err(VarError::NotPresent) => None,
+        Err(VarError::NotUnicode(_)) => {
+            panic!("MAX_QUEUES has to be a valid unicode string (it should be a numeric string in fact)")
+        },
+        Ok(s) => match s.parse::<u32>() {
+            Err(err) => panic!("Failed to parse maximum number of queues '{}': {}", s, err),
+            Ok(n) => Some(n),
+        },
+    }
+}
+
+fn get_drain_timeout() -> Duration {
+    match env::var("DRAIN_TIMEOUT") {
+        Err(VarError::NotPresent) => ServerConfig::DEFAULT_DRAIN_TIMEOUT,
+        Err(VarError::NotUnicode(_)) => {
+            panic!("DRAIN_TIMEOUT has to be a valid unicode string (it should be a numeric string in fact)")
+        },
+        Ok(s) => match s.parse::<u64>() {
+            Err(err) => panic!("Failed to parse drain timeout '{}': {}", s, err),
+            Ok(n) => Duration::from_secs(n),
+        },
+    }
+}
+
+fn get_idle_timeout() -> Duration {
+    match env::var("IDLE_TIMEOUT") {
+        Err(VarError::NotPresent) => ServerConfig::DEFAULT_IDLE_TIMEOUT,
+        Err(VarError::NotUnicode(_)) => {
+            panic!("IDLE_TIMEOUT has to be a valid unicode string (it should be a numeric string in fact)")
+        },
+        Ok(s) => match s.parse::<u64>() {
+            Err(err) => panic!("Failed to parse idle timeout '{}': {}", s, err),
+            Ok(0) => panic!("Idle timeout must be at least 1 second"),
+            Ok(n) => Duration::from_secs(n),
+        },
+    }
+}
+
+fn get_max_in_flight() -> Option<u64> {
+    match env::var("MAX_IN_FLIGHT") {
+        Err(VarError::NotPresent) => None,
+        Err(VarError::NotUnicode(_)) => {
+            panic!("MAX_IN_FLIGHT has to be a valid unicode string (it should be a numeric string in fact)")
+        },
+        Ok(s) => match s.parse::<u64>() {
+            Err(err) => panic!("Failed to parse maximum number of requests in flight '{}': {}", s, err),
+            Ok(n) => Some(n),
+        },
+    }
+}
+
+fn get_error_format() -> ErrorFormat {
+    match env::var("ERROR_FORMAT") {
+        Err(VarError::NotPresent) => ErrorFormat::Json,
+        Err(VarError::NotUnicode(_)) => {
+            panic!("ERROR_FORMAT has to be a valid unicode string (it should be either 'json' or 'text' in fact)")
+        },
+        Ok(s) => match s.as_str() {
+            "json" => ErrorFormat::Json,
+            "text" => ErrorFormat::Text,
+            _ => panic!("Failed to parse error format '{}', expected 'json' or 'text'", s),
+        },
+    }
+}
+
+fn set_receipt_handle_key() {
+    match env::var("RECEIPT_HANDLE_KEY") {
+        Err(VarError::NotPresent) => warn!(
+            "RECEIPT_HANDLE_KEY is not set, receipt handles are signed with a random key and are rejected after a restart \
+             or by other instances"
+        ),
+        Err(VarError::NotUnicode(_)) => panic!("RECEIPT_HANDLE_KEY has to be a valid unicode string"),
+        Ok(s) => {
+            if s.len() < 16 {
+                panic!(
+                    "Receipt handle key must be at least 16 characters long, got {}",
+                    s.len()
+                )
+            }
+            set_receipt_key(s.as_bytes());
+        },
+    }
+}
+
+fn get_allow_message_ids() -> bool {
+    match env::var("ALLOW_MESSAGE_IDS") {
+        Err(VarError::NotPresent) => true,
+        Err(VarError::NotUnicode(_)) => {
+            panic!("ALLOW_MESSAGE_IDS has to be a valid unicode string (it should be either 'true' or 'false' in fact)")
+        },
+        Ok(s) => match s.parse::<bool>() {
+            Err(err) => panic!("Failed to parse allow message ids flag '{}': {}", s, err),
+            Ok(b) => b,
+        },
+    }
+}
+
 fn main() {
+    // the configuration below is read before the server runs, so the .env file has to be loaded
+    // first
+    server::load_env();
     server::run(
-        |pool| HandlerService::new(pool, make_router(), get_max_message_size()),
-        7843,
+        |pool| {
+            // the logger is only set up once the server runs
+            set_receipt_handle_key();
+            let config = RouterConfig {
+                max_receive_batch: get_max_receive_batch(),
+                auto_options: get_auto_options(),
+                max_queues: get_max_queues(),
+                max_message_size: get_max_message_size(),
+                allow_message_ids: get_allow_message_ids(),
+                error_format: get_error_format(),
+                ..RouterConfig::default()
+            };
+
+            HandlerService::new(pool, &config)
+        },
+        &ServerConfig {
+            port:          7843,
+            drain_timeout: get_drain_timeout(),
+            max_in_flight: get_max_in_flight(),
+            idle_timeout:  get_idle_timeout(),
+        },
     );
 }
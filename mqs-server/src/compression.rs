@@ -0,0 +1,325 @@
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use futures_core::Stream;
+use hyper::{
+    body::HttpBody,
+    header::{HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, VARY},
+    Body,
+    HeaderMap,
+    Response,
+};
+use std::{
+    error::Error,
+    io::{Read, Write},
+    mem,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Responses with a body smaller than this many bytes are never compressed.
+pub const MIN_COMPRESSION_SIZE: usize = 1024;
+
+/// Check whether the client sending a request with the given headers accepts gzip encoded responses.
+///
+/// ```
+/// use hyper::{
+///     header::{HeaderValue, ACCEPT_ENCODING},
+///     HeaderMap,
+/// };
+/// use mqs_server::accepts_gzip;
+///
+/// let mut headers = HeaderMap::new();
+/// assert!(!accepts_gzip(&headers));
+/// headers.insert(
+///     ACCEPT_ENCODING,
+///     HeaderValue::from_static("deflate, gzip;q=0.5"),
+/// );
+/// assert!(accepts_gzip(&headers));
+/// headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip;q=0"));
+/// assert!(!accepts_gzip(&headers));
+/// ```
+#[must_use]
+pub fn accepts_gzip(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|encoding| {
+            let mut parts = encoding.split(';');
+            let name = parts.next().unwrap_or_default().trim();
+            if !name.eq_ignore_ascii_case("gzip") && name != "*" {
+                return false;
+            }
+
+            // an encoding with a quality of zero is not acceptable
+            parts.all(|param| match param.trim().strip_prefix("q=") {
+                Some(q) => !matches!(q.trim().parse::<f32>(), Ok(q) if q <= 0.0),
+                None => true,
+            })
+        })
+}
+
+/// Compress the body of a response with gzip if the client accepts it.
+///
+/// Bodies known to be smaller than `MIN_COMPRESSION_SIZE` bytes and responses which already carry a
+/// content encoding (like a single message stored with an encoding) are returned unchanged. Every
+/// response we could compress gets a `Vary: accept-encoding` header, whether it was compressed or
+/// not, so caches don't serve a compressed body to clients which can't read it (or the other way
+/// around).
+///
+/// The body is compressed while it is sent, so a large (streamed) body is never held in memory as
+/// a whole. A body of unknown size is always compressed.
+pub fn compress_response(accepts_gzip: bool, mut response: Response<Body>) -> Response<Body> {
+    if response.headers().contains_key(CONTENT_ENCODING) {
+        return response;
+    }
+
+    add_vary_accept_encoding(response.headers_mut());
+    if !accepts_gzip {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let size = HttpBody::size_hint(&body).exact().or_else(|| {
+        parts
+            .headers
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+    });
+    if matches!(size, Some(size) if size < MIN_COMPRESSION_SIZE as u64) {
+        return Response::from_parts(parts, body);
+    }
+
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+
+    Response::from_parts(parts, Body::wrap_stream(GzipStream::new(body)))
+}
+
+/// Compresses the chunks of a body one at a time as they are polled.
+struct GzipStream {
+    body:    Body,
+    encoder: Option<GzEncoder<Vec<u8>>>,
+}
+
+impl GzipStream {
+    fn new(body: Body) -> Self {
+        Self {
+            body,
+            encoder: Some(GzEncoder::new(Vec::new(), Compression::default())),
+        }
+    }
+}
+
+impl Stream for GzipStream {
+    type Item = Result<Vec<u8>, Box<dyn Error + Send + Sync>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let encoder = match &mut this.encoder {
+                None => return Poll::Ready(None),
+                Some(encoder) => encoder,
+            };
+            match Pin::new(&mut this.body).poll_data(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(Err(err))) => {
+                    error!("Failed to read response body for compression: {}", err);
+
+                    return Poll::Ready(Some(Err(err.into())));
+                },
+                Poll::Ready(Some(Ok(chunk))) => {
+                    if let Err(err) = encoder.write_all(&chunk) {
+                        error!("Failed to compress response body: {}", err);
+
+                        return Poll::Ready(Some(Err(err.into())));
+                    }
+                    // the encoder keeps small chunks until it has enough data for a block
+                    let compressed = mem::take(encoder.get_mut());
+                    if !compressed.is_empty() {
+                        return Poll::Ready(Some(Ok(compressed)));
+                    }
+                },
+                Poll::Ready(None) => {
+                    let encoder = this.encoder.take().expect("encoder should still be present");
+
+                    return Poll::Ready(Some(encoder.finish().map_err(|err| {
+                        error!("Failed to compress response body: {}", err);
+
+                        err.into()
+                    })));
+                },
+            }
+        }
+    }
+}
+
+/// Add `accept-encoding` to the `Vary` header without dropping the values already present.
+fn add_vary_accept_encoding(headers: &mut HeaderMap) {
+    let present = headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|name| {
+            let name = name.trim();
+            name == "*" || name.eq_ignore_ascii_case("accept-encoding")
+        });
+    if !present {
+        headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+    }
+}
+
+/// Check whether the body of a request with the given headers is gzip encoded for transport.
+pub fn is_gzip_encoded(headers: &HeaderMap) -> bool {
+    let encoding = headers.get(CONTENT_ENCODING).and_then(|value| value.to_str().ok());
+    matches!(encoding, Some(encoding) if encoding.trim().eq_ignore_ascii_case("gzip"))
+}
+
+/// Inflate a gzip encoded request body.
+///
+/// Returns `None` if the inflated body would be larger than `max_size` bytes. The body is never
+/// inflated further than that, so a small request can not make us allocate arbitrary amounts of
+/// memory.
+pub fn decompress_gzip(body: &[u8], max_size: usize) -> std::io::Result<Option<Vec<u8>>> {
+    let mut decompressed = Vec::with_capacity(body.len().min(max_size));
+    GzDecoder::new(body)
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut decompressed)?;
+
+    Ok(if decompressed.len() > max_size {
+        None
+    } else {
+        Some(decompressed)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mqs_common::{multipart, test::read_body};
+
+    fn run_compress(accepts_gzip: bool, response: Response<Body>) -> Response<Body> {
+        compress_response(accepts_gzip, response)
+    }
+
+    #[test]
+    fn compress_large_body() {
+        let payload = "some message ".repeat(MIN_COMPRESSION_SIZE).into_bytes();
+        let mut response = run_compress(true, Response::new(Body::from(payload.clone())));
+        assert_eq!(
+            response.headers().get(CONTENT_ENCODING),
+            Some(&HeaderValue::from_static("gzip"))
+        );
+        assert_eq!(
+            response.headers().get(VARY),
+            Some(&HeaderValue::from_static("accept-encoding"))
+        );
+        let compressed = read_body(response.body_mut());
+        assert!(compressed.len() < payload.len());
+        let mut decompressed = Vec::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, payload);
+    }
+
+    #[test]
+    fn compress_streamed_body() {
+        let parts: Vec<(HeaderMap, Vec<u8>)> = (0..100)
+            .map(|i| (HeaderMap::new(), format!("message {} ", i).repeat(50).into_bytes()))
+            .collect();
+        let document = multipart::encode_streaming(parts.clone());
+        let payload = multipart::encode_with_boundary(parts.into_iter(), document.boundary());
+        let mut response = run_compress(true, Response::new(document.into_body()));
+        assert_eq!(
+            response.headers().get(CONTENT_ENCODING),
+            Some(&HeaderValue::from_static("gzip"))
+        );
+        let compressed = read_body(response.body_mut());
+        let mut decompressed = Vec::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, payload.body());
+
+        // a streamed body with a small content length is sent as it is
+        let document = multipart::encode_streaming(Vec::new());
+        let content_length = document.encoded_len();
+        let mut response = Response::new(document.into_body());
+        response
+            .headers_mut()
+            .insert(CONTENT_LENGTH, HeaderValue::from(content_length));
+        let mut response = run_compress(true, response);
+        assert_eq!(response.headers().get(CONTENT_ENCODING), None);
+        assert_eq!(read_body(response.body_mut()).len(), content_length);
+    }
+
+    #[test]
+    fn skip_compression() {
+        let payload = "some message ".repeat(MIN_COMPRESSION_SIZE).into_bytes();
+        {
+            let mut response = run_compress(false, Response::new(Body::from(payload.clone())));
+            assert_eq!(response.headers().get(CONTENT_ENCODING), None);
+            assert_eq!(
+                response.headers().get(VARY),
+                Some(&HeaderValue::from_static("accept-encoding"))
+            );
+            assert_eq!(read_body(response.body_mut()), payload);
+        }
+        {
+            let mut response = run_compress(true, Response::new(Body::from(b"small".to_vec())));
+            assert_eq!(response.headers().get(CONTENT_ENCODING), None);
+            assert_eq!(
+                response.headers().get(VARY),
+                Some(&HeaderValue::from_static("accept-encoding"))
+            );
+            assert_eq!(read_body(response.body_mut()), b"small");
+        }
+        {
+            // an existing vary header is kept and accept-encoding is not added twice
+            let mut response = Response::new(Body::from(payload.clone()));
+            response
+                .headers_mut()
+                .insert(VARY, HeaderValue::from_static("Accept-Encoding, x-trace-id"));
+            let response = run_compress(false, response);
+            let vary: Vec<&HeaderValue> = response.headers().get_all(VARY).iter().collect();
+            assert_eq!(vary, vec![&HeaderValue::from_static("Accept-Encoding, x-trace-id")]);
+        }
+        {
+            let mut response = Response::new(Body::from(payload.clone()));
+            response
+                .headers_mut()
+                .insert(CONTENT_ENCODING, HeaderValue::from_static("identity"));
+            let mut response = run_compress(true, response);
+            assert_eq!(
+                response.headers().get(CONTENT_ENCODING),
+                Some(&HeaderValue::from_static("identity"))
+            );
+            assert_eq!(response.headers().get(VARY), None);
+            assert_eq!(read_body(response.body_mut()), payload);
+        }
+    }
+
+    #[test]
+    fn gzip_request_body() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_gzip_encoded(&headers));
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("identity"));
+        assert!(!is_gzip_encoded(&headers));
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("GZIP"));
+        assert!(is_gzip_encoded(&headers));
+
+        let payload = "some message ".repeat(100).into_bytes();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&payload).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(
+            decompress_gzip(&compressed, payload.len()).unwrap(),
+            Some(payload.clone())
+        );
+        assert_eq!(decompress_gzip(&compressed, payload.len() - 1).unwrap(), None);
+        assert!(decompress_gzip(b"not gzip", payload.len()).is_err());
+    }
+}
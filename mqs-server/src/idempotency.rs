@@ -0,0 +1,166 @@
+use cached::{once_cell::sync::Lazy, stores::TimedSizedCache, Cached};
+use mqs_common::PublishOutcome;
+use std::sync::{Mutex, PoisonError};
+
+/// Number of seconds the result of a publish request is remembered for its idempotency key.
+const PUBLISH_RESULT_LIFESPAN: u64 = 300;
+
+/// Maximum number of publish results remembered at the same time. Once reached, the least recently
+/// used result is dropped, even if it did not expire yet.
+const MAX_PUBLISH_RESULTS: usize = 10_000;
+
+#[derive(Clone)]
+enum PublishState {
+    /// A request with the key is currently publishing its messages.
+    InProgress,
+    /// The request with the key published its messages with the given outcomes.
+    Done(Vec<PublishOutcome>),
+}
+
+/// Remembers the results of recent publish requests by their idempotency key, so a retried request
+/// returns the result of the original request instead of publishing its messages again. Results
+/// are only kept in the memory of this process, so a retry which reaches a different server is not
+/// deduplicated.
+pub struct PublishResults {
+    results: Mutex<TimedSizedCache<(String, String), PublishState>>,
+}
+
+/// Result of reserving an idempotency key with `PublishResults::reserve`.
+pub enum Reservation<'a> {
+    /// No request with the key was seen recently. The caller has to publish the messages and
+    /// report the outcomes with `ReservedKey::finish`.
+    Reserved(ReservedKey<'a>),
+    /// Another request with the same key is still publishing its messages.
+    InProgress,
+    /// A request with the same key already published its messages with the given outcomes.
+    Done(Vec<PublishOutcome>),
+}
+
+/// An idempotency key reserved for a single request. If it is dropped without calling `finish`
+/// (e.g. because publishing failed), the key is released again, so a retry can publish the
+/// messages.
+pub struct ReservedKey<'a> {
+    results: &'a PublishResults,
+    key:     Option<(String, String)>,
+}
+
+impl ReservedKey<'_> {
+    /// Remember the outcomes of the successful publish request holding the key.
+    pub fn finish(mut self, outcomes: Vec<PublishOutcome>) {
+        if let Some(key) = self.key.take() {
+            let mut results = self.results.lock();
+            Cached::cache_set(&mut *results, key, PublishState::Done(outcomes));
+        }
+    }
+}
+
+impl Drop for ReservedKey<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let mut results = self.results.lock();
+            Cached::cache_remove(&mut *results, &key);
+        }
+    }
+}
+
+impl PublishResults {
+    pub fn new() -> Self {
+        Self::with_capacity(MAX_PUBLISH_RESULTS)
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            results: Mutex::new(TimedSizedCache::with_size_and_lifespan(
+                capacity,
+                PUBLISH_RESULT_LIFESPAN,
+            )),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, TimedSizedCache<(String, String), PublishState>> {
+        // the cache is only used to skip duplicate work, so a poisoned lock is still good enough
+        self.results.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Look up the publish request with the given key and reserve the key if no such request was
+    /// seen recently. Checking and reserving happens under the same lock, so of two concurrent
+    /// requests with the same key only one publishes its messages.
+    pub fn reserve(&self, queue_name: &str, key: &str) -> Reservation<'_> {
+        let key = (queue_name.to_string(), key.to_string());
+        let mut results = self.lock();
+        match Cached::cache_get(&mut *results, &key) {
+            Some(PublishState::Done(outcomes)) => Reservation::Done(outcomes.clone()),
+            Some(PublishState::InProgress) => Reservation::InProgress,
+            None => {
+                Cached::cache_set(&mut *results, key.clone(), PublishState::InProgress);
+
+                Reservation::Reserved(ReservedKey {
+                    results: self,
+                    key:     Some(key),
+                })
+            },
+        }
+    }
+}
+
+pub static PUBLISH_RESULTS: Lazy<PublishResults> = Lazy::new(PublishResults::new);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn reserve<'a>(results: &'a PublishResults, queue_name: &str, key: &str) -> ReservedKey<'a> {
+        match results.reserve(queue_name, key) {
+            Reservation::Reserved(reserved) => reserved,
+            _ => panic!("Key {} should not be reserved yet", key),
+        }
+    }
+
+    fn outcomes(results: &PublishResults, queue_name: &str, key: &str) -> Option<Vec<PublishOutcome>> {
+        match results.reserve(queue_name, key) {
+            Reservation::Done(outcomes) => Some(outcomes),
+            Reservation::InProgress => panic!("Key {} should not be in progress", key),
+            Reservation::Reserved(_) => None,
+        }
+    }
+
+    #[test]
+    fn publish_results() {
+        let results = PublishResults::new();
+        let created = vec![PublishOutcome {
+            created:    true,
+            message_id: Some("a7d46b8e-32fe-4d35-a3af-74f9a39da2dc".to_string()),
+        }];
+        let duplicate = vec![PublishOutcome {
+            created:    false,
+            message_id: None,
+        }];
+        let first = reserve(&results, "my-queue", "key-1");
+        // a concurrent retry must not publish the messages a second time
+        assert!(matches!(results.reserve("my-queue", "key-1"), Reservation::InProgress));
+        first.finish(created.clone());
+        reserve(&results, "my-queue", "key-2").finish(duplicate.clone());
+        assert_eq!(outcomes(&results, "my-queue", "key-1"), Some(created));
+        assert_eq!(outcomes(&results, "my-queue", "key-2"), Some(duplicate));
+        assert_eq!(outcomes(&results, "other-queue", "key-1"), None);
+    }
+
+    #[test]
+    fn release_failed_reservation() {
+        let results = PublishResults::new();
+        drop(reserve(&results, "my-queue", "key-1"));
+        // the failed request published nothing, so a retry can try again
+        drop(reserve(&results, "my-queue", "key-1"));
+    }
+
+    #[test]
+    fn bounded_publish_results() {
+        let results = PublishResults::with_capacity(2);
+        for key in &["key-1", "key-2", "key-3"] {
+            reserve(&results, "my-queue", key).finish(Vec::new());
+        }
+        assert_eq!(results.lock().cache_size(), 2);
+        assert_eq!(outcomes(&results, "my-queue", "key-1"), None);
+        assert_eq!(outcomes(&results, "my-queue", "key-3"), Some(Vec::new()));
+    }
+}
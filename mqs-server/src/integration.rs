@@ -0,0 +1,380 @@
+use async_trait::async_trait;
+use hyper::{Body, Request, Response};
+use mqs_client::{ClientError, PublishResult, PublishableMessage, ReceiveFilter, Service};
+use mqs_common::{
+    connection::Source,
+    router::{handle, Router},
+    server::{bind, ServerConfig, ServerHandler},
+    test::make_runtime,
+    PublishRejection,
+    QueueConfig,
+    UtcTime,
+};
+use std::{future::pending, net::SocketAddr, sync::Arc, time::Duration};
+
+use crate::{
+    models::test::{TestRepo, TestRepoSource},
+    router::{make, RouterConfig},
+};
+
+struct TestService {
+    source: TestRepoSource,
+    router: Router<(TestRepo, TestRepoSource)>,
+}
+
+#[async_trait]
+impl ServerHandler for TestService {
+    async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        handle(
+            self.source.get(),
+            self.source.clone(),
+            &self.router,
+            RouterConfig::DEFAULT_MAX_MESSAGE_SIZE,
+            req,
+        )
+        .await
+    }
+}
+
+/// Start a server backed by the given source on an ephemeral port of the loopback interface and
+/// return a client connected to it. The server runs until the runtime it was started on shuts down.
+fn start_server(source: TestRepoSource, config: &RouterConfig) -> Service {
+    let service = Arc::new(TestService {
+        source,
+        router: make(config),
+    });
+    let (addr, server) = bind(
+        service,
+        &SocketAddr::from(([127, 0, 0, 1], 0)),
+        None,
+        ServerConfig::DEFAULT_IDLE_TIMEOUT,
+        pending(),
+    )
+    .unwrap();
+    tokio::spawn(server);
+
+    Service::new(&format!("http://{}", addr))
+}
+
+fn message(content: &[u8]) -> PublishableMessage<'static> {
+    PublishableMessage {
+        content_type:     "text/plain",
+        content_encoding: None,
+        trace_id:         None,
+        raw_trace_id:     None,
+        group_id:         None,
+        expires_at:       None,
+        published_at:     None,
+        priority:         None,
+        attributes:       None,
+        message:          content.to_vec(),
+    }
+}
+
+#[test]
+fn queue_lifecycle() {
+    make_runtime().block_on(async {
+        let service = start_server(TestRepoSource::new(), &RouterConfig::default());
+        assert!(service.check_health().await.unwrap());
+        let config = QueueConfig::standard();
+        assert_eq!(
+            service.create_queue("http-queue", None, &config).await.unwrap(),
+            Some(config.clone())
+        );
+        assert_eq!(service.create_queue("http-queue", None, &config).await.unwrap(), None);
+        let page = service.get_queues(None, None, None).await.unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].name, "http-queue");
+        let page = service.get_queues_with_status(None, None, None).await.unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].name, "http-queue");
+        assert_eq!(page.items[0].status.messages, 0);
+        let description = service.describe_queue("http-queue", None).await.unwrap().unwrap();
+        assert_eq!(description.name, "http-queue");
+        assert_eq!(description.status.messages, 0);
+        let output = service.get_queue_config("http-queue", None).await.unwrap().unwrap();
+        assert_eq!(output.name, "http-queue");
+        assert_eq!(output.visibility_timeout, config.visibility_timeout);
+        assert_eq!(output.version, description.version);
+        assert_eq!(service.delete_queue("http-queue", None).await.unwrap(), Some(config));
+        assert!(service.describe_queue("http-queue", None).await.unwrap().is_none());
+        assert!(service.get_queue_config("http-queue", None).await.unwrap().is_none());
+    });
+}
+
+#[test]
+fn rename_queue() {
+    make_runtime().block_on(async {
+        let service = start_server(TestRepoSource::new(), &RouterConfig::default());
+        let config = QueueConfig::standard();
+        for queue_name in &["http-rename-old", "http-rename-taken"] {
+            service.create_queue(queue_name, None, &config).await.unwrap();
+        }
+        service
+            .create_queue("http-rename-source", None, &QueueConfig::with_dlq("http-rename-old", 3))
+            .await
+            .unwrap();
+        service
+            .publish_message("http-rename-old", message(b"renamed"))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            service.rename_queue("http-rename-old", None, "http-rename-taken").await,
+            Err(ClientError::QueueAlreadyExists)
+        ));
+        assert!(matches!(
+            service.rename_queue("http-rename-old", None, "not a queue name").await,
+            Err(ClientError::InvalidQueueName)
+        ));
+        assert_eq!(
+            service
+                .rename_queue("http-rename-missing", None, "http-rename-new")
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            service
+                .rename_queue("http-rename-old", None, "http-rename-new")
+                .await
+                .unwrap(),
+            Some(config)
+        );
+
+        assert!(service
+            .get_queue_config("http-rename-old", None)
+            .await
+            .unwrap()
+            .is_none());
+        let messages = service
+            .get_messages("http-rename-new", None, 10, None, ReceiveFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, b"renamed");
+        let source = service
+            .get_queue_config("http-rename-source", None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            source
+                .redrive_policy
+                .as_ref()
+                .map(|policy| policy.dead_letter_queue.as_str()),
+            Some("http-rename-new")
+        );
+    });
+}
+
+#[test]
+fn modify_queue() {
+    make_runtime().block_on(async {
+        let service = start_server(TestRepoSource::new(), &RouterConfig::default());
+        let config = QueueConfig {
+            allowed_content_types: vec!["text/plain".to_string()],
+            ..QueueConfig::standard()
+        };
+        service.create_queue("http-modify-queue", None, &config).await.unwrap();
+        let before = service
+            .get_queue_config("http-modify-queue", None)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let modified = service
+            .modify_queue("http-modify-queue", None, |config| config.visibility_timeout = 120)
+            .await
+            .unwrap();
+        assert_eq!(
+            modified,
+            Some(QueueConfig {
+                visibility_timeout: 120,
+                ..config
+            })
+        );
+        let after = service
+            .get_queue_config("http-modify-queue", None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(after.visibility_timeout, 120);
+        assert_eq!(after.allowed_content_types, vec!["text/plain".to_string()]);
+        assert_ne!(after.version, before.version);
+
+        // the version fetched before the modification is outdated now
+        assert!(matches!(
+            service
+                .update_queue(
+                    "http-modify-queue",
+                    None,
+                    before.version.as_deref(),
+                    &QueueConfig::standard()
+                )
+                .await,
+            Err(ClientError::VersionMismatch)
+        ));
+        let mut called = false;
+        assert_eq!(
+            service
+                .modify_queue("http-modify-missing", None, |_| called = true)
+                .await
+                .unwrap(),
+            None
+        );
+        assert!(!called);
+    });
+}
+
+#[test]
+fn receive_from_missing_queue() {
+    make_runtime().block_on(async {
+        let mut service = start_server(TestRepoSource::new(), &RouterConfig::default());
+        assert!(matches!(
+            service
+                .get_messages("http-missing", None, 10, None, ReceiveFilter::default())
+                .await,
+            Err(ClientError::ServiceError(404))
+        ));
+        service.set_treat_missing_queue_as_empty(true);
+        assert!(service
+            .get_messages("http-missing", None, 10, None, ReceiveFilter::default())
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(service
+            .receive_and_delete("http-missing", None, 10, None)
+            .await
+            .unwrap()
+            .is_empty());
+        // other requests are not affected
+        assert!(matches!(
+            service.publish_message("http-missing", message(b"lost")).await,
+            Err(ClientError::ServiceError(404))
+        ));
+    });
+}
+
+#[test]
+fn publish_rejections() {
+    make_runtime().block_on(async {
+        let service = start_server(TestRepoSource::new(), &RouterConfig::default());
+        service
+            .create_queue("http-rejections", None, &QueueConfig {
+                allowed_content_types: vec!["text/plain".to_string()],
+                ..QueueConfig::standard()
+            })
+            .await
+            .unwrap();
+        let in_the_future = PublishableMessage {
+            published_at: Some(UtcTime::now().add(Duration::from_secs(3600))),
+            priority: None,
+            ..message(b"future")
+        };
+        match service.publish_message("http-rejections", in_the_future).await {
+            Err(ClientError::PublishRejected { reason, message }) => {
+                assert_eq!(reason, PublishRejection::PublishTimeInFuture);
+                assert_eq!(message, "Message publish time is in the future");
+            },
+            result => panic!("Unexpected result {:?}", result),
+        }
+        let too_large = message(&vec![b'a'; RouterConfig::DEFAULT_MAX_MESSAGE_SIZE + 1]);
+        assert!(matches!(
+            service.publish_messages("http-rejections", &[too_large]).await,
+            Err(ClientError::PublishRejected {
+                reason: PublishRejection::TooLarge,
+                ..
+            })
+        ));
+        let json = PublishableMessage {
+            content_type: "application/json",
+            ..message(b"{}")
+        };
+        assert!(matches!(
+            service.publish_message("http-rejections", json).await,
+            Err(ClientError::UnsupportedContentType)
+        ));
+    });
+}
+
+#[test]
+fn message_lifecycle() {
+    make_runtime().block_on(async {
+        let service = start_server(TestRepoSource::new(), &RouterConfig::default());
+        service
+            .create_queue("http-messages", None, &QueueConfig::standard())
+            .await
+            .unwrap();
+        assert!(service
+            .publish_message("http-messages", message(b"first message"))
+            .await
+            .unwrap());
+        assert!(service
+            .publish_messages("http-messages", &[message(b"second"), message(b"third")])
+            .await
+            .unwrap());
+        let received = service
+            .get_messages("http-messages", None, 10, None, ReceiveFilter::default())
+            .await
+            .unwrap();
+        let mut contents: Vec<&[u8]> = received.iter().map(|msg| msg.content.as_slice()).collect();
+        // the test repository does not keep the order of messages
+        contents.sort_unstable();
+        assert_eq!(contents, vec![&b"first message"[..], b"second", b"third"]);
+        assert!(received.iter().all(|msg| msg.content_type == "text/plain"));
+        assert!(service
+            .get_message("http-messages", None, None)
+            .await
+            .unwrap()
+            .is_none());
+        for msg in &received {
+            let receipt_handle = msg.receipt_handle.as_ref().unwrap();
+            assert!(service.delete_message_by_receipt(None, receipt_handle).await.unwrap());
+        }
+        let receipt_handle = received[0].receipt_handle.as_ref().unwrap();
+        assert!(!service.delete_message_by_receipt(None, receipt_handle).await.unwrap());
+        let outcomes = service
+            .publish_messages_detailed("http-messages", &[message(b"fourth"), message(b"fifth")])
+            .await
+            .unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes.iter().all(|outcome| outcome.created));
+        let published = service.get_message("http-messages", None, None).await.unwrap().unwrap();
+        assert!(outcomes
+            .iter()
+            .any(|outcome| outcome.message_id == Some(published.message_id.to_string())));
+        assert!(service
+            .publish_message("does-not-exist", message(b"lost"))
+            .await
+            .is_err());
+    });
+}
+
+#[test]
+fn publish_deduplicated() {
+    make_runtime().block_on(async {
+        let service = start_server(TestRepoSource::new(), &RouterConfig::default());
+        let mut config = QueueConfig::standard();
+        config.message_deduplication = true;
+        service.create_queue("http-dedup", None, &config).await.unwrap();
+        let message_id = match service
+            .publish_message_detailed("http-dedup", message(b"once"))
+            .await
+            .unwrap()
+        {
+            PublishResult::Created(message_id) => message_id,
+            PublishResult::Deduplicated => panic!("first message was dropped as a duplicate"),
+        };
+        assert_eq!(
+            service
+                .publish_message_detailed("http-dedup", message(b"once"))
+                .await
+                .unwrap(),
+            PublishResult::Deduplicated
+        );
+        assert!(!service.publish_message("http-dedup", message(b"once")).await.unwrap());
+        let received = service.get_message("http-dedup", None, None).await.unwrap().unwrap();
+        assert_eq!(received.message_id, message_id);
+    });
+}
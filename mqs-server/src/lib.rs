@@ -18,11 +18,18 @@ extern crate log;
 #[macro_use]
 extern crate serde_derive;
 
+pub(crate) mod compression;
+pub(crate) mod idempotency;
+#[cfg(test)]
+mod integration;
 pub(crate) mod models;
+pub(crate) mod receipt;
 pub(crate) mod router;
 pub(crate) mod routes;
 pub(crate) mod schema;
 pub(crate) mod wait;
 
+pub use compression::{accepts_gzip, compress_response, MIN_COMPRESSION_SIZE};
 pub use models::PgRepository;
-pub use router::make as make_router;
+pub use receipt::set_receipt_key;
+pub use router::{make as make_router, make_unavailable as make_unavailable_router, RouterConfig};
@@ -1,16 +1,20 @@
 use diesel::{
     backend::Backend,
+    expression::{expression_types::NotSelectable, BoxableExpression},
     pg::Pg,
     prelude::*,
-    query_builder::{AstPass, QueryFragment},
+    query_builder::{AstPass, QueryFragment, QueryId},
     result::{DatabaseErrorKind, Error},
+    sql_types::{Array, Bool, Bytea, Nullable, Timestamp},
 };
-use mqs_common::UtcTime;
+use mqs_common::{logger::audit, UtcTime};
 use sha2::{Digest, Sha256};
+use std::time::Duration;
 use uuid::Uuid;
 
 use crate::{
     models::{queue::Queue, PgRepository},
+    receipt::ReceiptHandle,
     schema::messages,
 };
 
@@ -19,7 +23,13 @@ pub struct MessageInput<'a> {
     pub payload:          &'a [u8],
     pub content_type:     &'a str,
     pub content_encoding: Option<&'a str>,
-    pub trace_id:         Option<Uuid>,
+    pub trace_id:         Option<&'a str>,
+    pub group_id:         Option<&'a str>,
+    pub attributes:       &'a [String],
+    pub json_attributes:  Option<&'a serde_json::Value>,
+    pub expires_at:       Option<UtcTime>,
+    pub published_at:     Option<UtcTime>,
+    pub priority:         i32,
 }
 
 #[derive(Insertable)]
@@ -34,75 +44,278 @@ pub struct NewMessage<'a> {
     pub receives:         i32,
     pub visible_since:    UtcTime,
     pub created_at:       UtcTime,
-    pub trace_id:         Option<Uuid>,
+    pub trace_id:         Option<&'a str>,
+    pub group_id:         Option<&'a str>,
+    pub attributes:       &'a [String],
+    pub expires_at:       Option<UtcTime>,
+    pub json_attributes:  Option<&'a serde_json::Value>,
+    pub priority:         i32,
+    pub published_at:     UtcTime,
 }
 
 #[derive(Queryable, Identifiable, Serialize, Debug, Clone)]
 pub struct Message {
-    pub id:               Uuid,
-    pub payload:          Vec<u8>,
-    pub content_type:     String,
-    pub content_encoding: Option<String>,
-    pub hash:             Option<String>,
-    pub queue:            String,
-    pub receives:         i32,
-    pub visible_since:    UtcTime,
-    pub created_at:       UtcTime,
-    pub trace_id:         Option<Uuid>,
+    pub id:                Uuid,
+    pub payload:           Vec<u8>,
+    pub content_type:      String,
+    pub content_encoding:  Option<String>,
+    pub hash:              Option<String>,
+    pub queue:             String,
+    pub receives:          i32,
+    pub visible_since:     UtcTime,
+    pub created_at:        UtcTime,
+    pub trace_id:          Option<String>,
+    pub first_received_at: Option<UtcTime>,
+    pub last_received_at:  Option<UtcTime>,
+    pub nacks:             i32,
+    pub group_id:          Option<String>,
+    pub attributes:        Vec<String>,
+    pub expires_at:        Option<UtcTime>,
+    pub origin_queue:      Option<String>,
+    pub redrive_reason:    Option<String>,
+    pub json_attributes:   Option<serde_json::Value>,
+    pub redrive_count:     i32,
+    pub priority:          i32,
+    /// Time the message was published at according to the publisher. `created_at` is always the
+    /// time the message reached the server, so replayed messages keep their order in the queue.
+    pub published_at:      UtcTime,
+}
+
+impl Message {
+    /// Check whether the message is past its retention, either of its queue or of the message itself.
+    pub fn is_expired(&self, queue: &Queue, now: UtcTime) -> bool {
+        self.created_at.add_pg_interval(&queue.retention_timeout) < now
+            || matches!(self.expires_at, Some(expires_at) if expires_at < now)
+    }
+
+    /// Check whether the message was already redriven as often as its queue allows. Once such a
+    /// message reaches the maximum number of receives, it is deleted instead of being moved to the
+    /// dead letter queue again, whether or not the queue has a dead letter queue.
+    pub fn exceeds_max_redrives(&self, queue: &Queue) -> bool {
+        matches!(queue.max_redrives, Some(max_redrives) if self.redrive_count >= max_redrives)
+    }
+}
+
+/// A message deleted instead of being moved to a dead letter queue, written to the audit log.
+#[derive(Serialize, Debug)]
+struct DroppedMessageAuditEvent<'a> {
+    action:        &'static str,
+    queue:         &'a str,
+    message_id:    Uuid,
+    receives:      i32,
+    redrive_count: i32,
+}
+
+/// Record in the audit log that a message is deleted because it exceeds the maximum number of
+/// redrives of its queue.
+pub(crate) fn audit_dropped_message(queue: &Queue, message: &Message) {
+    audit(&DroppedMessageAuditEvent {
+        action:        "drop",
+        queue:         &queue.name,
+        message_id:    message.id,
+        receives:      message.receives,
+        redrive_count: message.redrive_count,
+    });
+}
+
+/// Reason for moving a message to another queue. Stored with the message so consumers of the new
+/// queue can tell why the message was moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedriveReason {
+    /// The message was received more often than the queue allows and moved to its dead letter queue.
+    MaxReceives,
+    /// The message was moved by a client.
+    Moved,
+}
+
+impl RedriveReason {
+    /// Value of the redrive reason header of a moved message.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::MaxReceives => "max-receives",
+            Self::Moved => "moved",
+        }
+    }
+}
+
+/// Restricts which messages of a queue can be received.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessageFilter<'a> {
+    /// Only receive messages with exactly this content type.
+    pub content_type: Option<&'a str>,
+    /// Only receive messages created at or after this time. A publish time given by the publisher
+    /// is ignored. Older messages of a group still block newer messages of the same group.
+    pub since:        Option<UtcTime>,
+}
+
+impl MessageFilter<'_> {
+    /// Check whether the given message passes the filter.
+    pub fn matches(&self, message: &Message) -> bool {
+        !matches!(self.content_type, Some(content_type) if message.content_type != content_type)
+            && !matches!(self.since, Some(since) if message.created_at < since)
+    }
+}
+
+diesel::sql_function! {
+    /// Return the first of the two arguments which is not null.
+    fn coalesce(x: Nullable<Timestamp>, y: Timestamp) -> Timestamp;
 }
 
 pub trait MessageRepository: Send {
-    fn insert_message(&mut self, queue: &Queue, input: &MessageInput<'_>) -> QueryResult<bool>;
-    fn get_message_from_queue(&mut self, queue: &Queue, count: i64) -> QueryResult<Vec<Message>>;
-    fn move_message_to_queue(&mut self, ids: Vec<Uuid>, new_queue: &str) -> QueryResult<usize>;
+    /// The current time according to the clock of the repository.
+    fn now(&self) -> UtcTime;
+    /// Insert new messages into a queue in a single transaction, so either all or none of them are
+    /// inserted. Returns the id of each new message or `None` if it was dropped as a duplicate.
+    fn insert_messages(&mut self, queue: &Queue, inputs: &[MessageInput<'_>]) -> QueryResult<Vec<Option<Uuid>>>;
+    fn get_message_from_queue(
+        &mut self,
+        queue: &Queue,
+        count: i64,
+        filter: MessageFilter<'_>,
+        auto_delete: bool,
+    ) -> QueryResult<Vec<Message>>;
+    /// Get the messages which would be received next from a queue without receiving them. The
+    /// payload of the messages is not loaded, the returned messages have an empty payload.
+    fn peek_messages(&mut self, queue: &Queue, count: i64, filter: MessageFilter<'_>) -> QueryResult<Vec<Message>>;
+    /// Find the name of the queue a message is currently in.
+    fn find_message_queue(&mut self, id: Uuid) -> QueryResult<Option<String>>;
+    /// Move messages to another queue. Messages which are already in that queue are left unchanged.
+    /// Returns the number of moved messages.
+    fn move_message_to_queue(&mut self, ids: Vec<Uuid>, new_queue: &str, reason: RedriveReason) -> QueryResult<usize>;
+    fn release_message(&mut self, id: Uuid) -> QueryResult<bool>;
+    /// Release a message in flight unless it was received again since the receipt handle was issued.
+    fn release_message_by_receipt(&mut self, receipt: ReceiptHandle) -> QueryResult<bool>;
+    /// Change when the messages with the given ids or receipt handles become visible again. Messages
+    /// which were received again since their receipt handle was issued are not changed. Returns the
+    /// ids of the changed messages.
+    fn change_messages_visibility(
+        &mut self,
+        ids: Vec<Uuid>,
+        receipts: &[ReceiptHandle],
+        visibility_timeout: Duration,
+    ) -> QueryResult<Vec<Uuid>>;
     fn delete_message_by_id(&mut self, id: Uuid) -> QueryResult<bool>;
+    /// Delete a message unless it was received again since the receipt handle was issued. Returns
+    /// `None` if the message does not exist and `Some(false)` if it was received again.
+    fn delete_message_by_receipt(&mut self, receipt: ReceiptHandle) -> QueryResult<Option<bool>>;
     fn delete_messages_by_ids(&mut self, ids: Vec<Uuid>) -> QueryResult<usize>;
+    fn delete_messages_by_hash(&mut self, queue_name: &str, hash: &str) -> QueryResult<usize>;
+    /// List the ids of at most `limit` received messages of a queue which are still hidden and the
+    /// time they become visible again. Messages which are only delayed are not in flight.
+    fn list_in_flight(&mut self, queue_name: &str, limit: i64) -> QueryResult<Vec<(Uuid, UtcTime)>>;
+    /// List the ids of the oldest visible messages in a queue. Messages which are in flight or still
+    /// delayed are skipped, so they are not taken away from a consumer.
+    fn list_visible_message_ids(&mut self, queue_name: &str, count: i64) -> QueryResult<Vec<Uuid>>;
+}
+
+impl PgRepository {
+    /// Spread the times the given messages received at `now` become visible again over the
+    /// visibility jitter of their queue, so they are not all redelivered at the same instant.
+    fn apply_visibility_jitter(&mut self, queue: &Queue, now: UtcTime, messages: &mut [Message]) -> QueryResult<()> {
+        if messages.is_empty() || !matches!(queue.visibility_jitter, Some(visibility_jitter) if visibility_jitter != 0)
+        {
+            return Ok(());
+        }
+        for message in messages.iter_mut() {
+            message.visible_since = queue.visible_after_receive(now, self.random.next_fraction());
+        }
+        let ids: Vec<Uuid> = messages.iter().map(|message| message.id).collect();
+        let visible_since: Vec<UtcTime> = messages.iter().map(|message| message.visible_since).collect();
+        diesel::sql_query(
+            "UPDATE messages SET visible_since = jitter.visible_since \
+             FROM unnest($1, $2) AS jitter(id, visible_since) WHERE messages.id = jitter.id",
+        )
+        .bind::<Array<diesel::sql_types::Uuid>, _>(ids)
+        .bind::<Array<Timestamp>, _>(visible_since)
+        .execute(&mut self.conn)?;
+
+        Ok(())
+    }
 }
 
 impl MessageRepository for PgRepository {
-    fn insert_message(&mut self, queue: &Queue, input: &MessageInput<'_>) -> QueryResult<bool> {
-        let now = UtcTime::now();
-        let visible_since = now.add_pg_interval(&queue.message_delay);
-        let id = Uuid::new_v4();
-        let hash = if queue.content_based_deduplication {
-            let mut digest = Sha256::default();
-            digest.update(input.payload);
-            let result = digest.finalize();
-            Some(base64::encode(result.as_slice()))
-        } else {
-            None
+    fn now(&self) -> UtcTime {
+        self.clock.now()
+    }
+
+    fn insert_messages(&mut self, queue: &Queue, inputs: &[MessageInput<'_>]) -> QueryResult<Vec<Option<Uuid>>> {
+        let now = self.clock.now();
+        self.conn.transaction(|conn| {
+            inputs
+                .iter()
+                .map(|input| {
+                    // a duplicate aborts the surrounding transaction, so insert every message in its own
+                    // savepoint to only roll back the duplicate
+                    match conn.transaction(|conn| insert_message_row(conn, queue, input, now)) {
+                        Ok(id) => Ok(Some(id)),
+                        Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Ok(None),
+                        Err(err) => Err(err),
+                    }
+                })
+                .collect()
+        })
+    }
+
+    fn get_message_from_queue(
+        &mut self,
+        queue: &Queue,
+        count: i64,
+        filter: MessageFilter<'_>,
+        auto_delete: bool,
+    ) -> QueryResult<Vec<Message>> {
+        let now = self.clock.now();
+        let count = match queue.max_in_flight {
+            None => count,
+            Some(max_in_flight) => {
+                let in_flight: i64 = messages::table
+                    .filter(messages::queue.eq(&queue.name))
+                    .filter(messages::visible_since.gt(now))
+                    .filter(messages::receives.gt(0))
+                    .count()
+                    .get_result(&mut self.conn)?;
+                // concurrent receives can still overshoot the limit by a few messages
+                count.min(max_in_flight - in_flight)
+            },
         };
-        let result = diesel::dsl::insert_into(messages::table)
-            .values(NewMessage {
-                id,
-                payload: input.payload,
-                content_type: input.content_type,
-                content_encoding: input.content_encoding,
-                hash,
-                queue: &queue.name,
-                receives: 0,
-                visible_since,
-                created_at: now,
-                trace_id: input.trace_id,
-            })
-            .execute(&mut self.conn);
-        match result {
-            Ok(_) => Ok(true),
-            Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Ok(false),
-            Err(err) => Err(err),
+        if count < 1 {
+            return Ok(Vec::new());
+        }
+        if auto_delete {
+            // select and delete the messages in a single statement, so they are never visible again.
+            // Expired messages are deleted as well, we just don't return them.
+            let messages: Vec<Message> = diesel::delete(messages::table)
+                .filter(In::new(
+                    messages::id,
+                    MessageIdsForFetch::new(queue, now, count, filter),
+                ))
+                .returning(messages::all_columns)
+                .get_results(&mut self.conn)?;
+
+            return Ok(messages
+                .into_iter()
+                .filter(|message| !message.is_expired(queue, now))
+                .map(|mut message| {
+                    message.receives += 1;
+                    message.first_received_at = message.first_received_at.or(Some(now));
+                    message.last_received_at = Some(now);
+                    message
+                })
+                .collect());
         }
-    }
 
-    fn get_message_from_queue(&mut self, queue: &Queue, count: i64) -> QueryResult<Vec<Message>> {
-        let now = UtcTime::now();
         let visible_since = now.add_pg_interval(&queue.visibility_timeout);
 
         let update_query = diesel::dsl::update(messages::table)
             .set((
                 messages::visible_since.eq(visible_since),
                 messages::receives.eq(messages::receives + 1),
+                messages::first_received_at.eq(coalesce(messages::first_received_at, now).nullable()),
+                messages::last_received_at.eq(now),
+            ))
+            .filter(In::new(
+                messages::id,
+                MessageIdsForFetch::new(queue, now, count, filter),
             ))
-            .filter(In::new(messages::id, MessageIdsForFetch::new(&queue.name, now, count)))
             .returning(messages::all_columns);
 
         let messages: Vec<Message> = update_query.get_results(&mut self.conn)?;
@@ -112,14 +325,21 @@ impl MessageRepository for PgRepository {
         let mut move_to_dead_letter_queue = Vec::new();
         let mut to_delete = Vec::new();
         for message in messages {
-            if message.created_at.add_pg_interval(&queue.retention_timeout) < now {
+            if message.is_expired(queue, now) {
                 to_delete.push(message.id);
                 continue;
             }
             if let Some(max_receives) = queue.max_receives {
                 if message.receives >= max_receives {
-                    // send to dead letter queue
-                    move_to_dead_letter_queue.push(message.id);
+                    if message.exceeds_max_redrives(queue) {
+                        // the message was redriven too often already, delete it instead of sending
+                        // it to the dead letter queue again or redelivering it
+                        audit_dropped_message(queue, &message);
+                        to_delete.push(message.id);
+                    } else if queue.dead_letter_queue.is_some() {
+                        // send to dead letter queue
+                        move_to_dead_letter_queue.push(message.id);
+                    }
                     // do not put a continue statement here, we still want to return this message
                     // to the caller. So we send a message directly to the dead-letter-queue upon receive,
                     // but we still allow the caller to process it. It will appear in the dead-letter-queue
@@ -129,22 +349,127 @@ impl MessageRepository for PgRepository {
             }
             result.push(message);
         }
+        self.apply_visibility_jitter(queue, now, &mut result)?;
         if !to_delete.is_empty() {
             self.delete_messages_by_ids(to_delete)?;
         }
         if let Some(dead_letter_queue) = &queue.dead_letter_queue {
             if !move_to_dead_letter_queue.is_empty() {
-                self.move_message_to_queue(move_to_dead_letter_queue, dead_letter_queue)?;
+                self.move_message_to_queue(move_to_dead_letter_queue, dead_letter_queue, RedriveReason::MaxReceives)?;
             }
         }
         Ok(result)
     }
 
-    fn move_message_to_queue(&mut self, ids: Vec<Uuid>, new_queue: &str) -> QueryResult<usize> {
+    fn peek_messages(&mut self, queue: &Queue, count: i64, filter: MessageFilter<'_>) -> QueryResult<Vec<Message>> {
+        let now = self.clock.now();
+        // select everything but the payload, which might be much larger than the rest of the message
+        let messages: Vec<Message> = messages::table
+            .select((
+                messages::id,
+                diesel::dsl::sql::<Bytea>("''::bytea"),
+                messages::content_type,
+                messages::content_encoding,
+                messages::hash,
+                messages::queue,
+                messages::receives,
+                messages::visible_since,
+                messages::created_at,
+                messages::trace_id,
+                messages::first_received_at,
+                messages::last_received_at,
+                messages::nacks,
+                messages::group_id,
+                messages::attributes,
+                messages::expires_at,
+                messages::origin_queue,
+                messages::redrive_reason,
+                messages::json_attributes,
+                messages::redrive_count,
+                messages::priority,
+                messages::published_at,
+            ))
+            .filter(visible_messages(&queue.name, now, filter))
+            .order(receive_order(queue))
+            .limit(count)
+            .load(&mut self.conn)?;
+
+        Ok(messages
+            .into_iter()
+            .filter(|message| !message.is_expired(queue, now))
+            .collect())
+    }
+
+    fn find_message_queue(&mut self, id: Uuid) -> QueryResult<Option<String>> {
+        messages::table
+            .select(messages::queue)
+            .filter(messages::id.eq(id))
+            .first(&mut self.conn)
+            .optional()
+    }
+
+    fn move_message_to_queue(&mut self, ids: Vec<Uuid>, new_queue: &str, reason: RedriveReason) -> QueryResult<usize> {
+        // the assignments see the row before the update, so the origin is the queue we move from
         diesel::dsl::update(messages::table)
-            .set((messages::queue.eq(new_queue), messages::receives.eq(0)))
+            .set((
+                messages::origin_queue.eq(messages::queue.nullable()),
+                messages::redrive_reason.eq(reason.as_str()),
+                messages::queue.eq(new_queue),
+                messages::receives.eq(0),
+                messages::redrive_count.eq(messages::redrive_count + 1),
+            ))
             .filter(messages::id.eq_any(ids))
+            .filter(messages::queue.ne(new_queue))
+            .execute(&mut self.conn)
+    }
+
+    fn release_message(&mut self, id: Uuid) -> QueryResult<bool> {
+        let now = self.clock.now();
+        diesel::dsl::update(messages::table)
+            .set((messages::visible_since.eq(now), messages::nacks.eq(messages::nacks + 1)))
+            .filter(messages::id.eq(id))
+            .filter(messages::visible_since.gt(now))
+            .filter(messages::receives.gt(0))
+            .execute(&mut self.conn)
+            .map(|count| count > 0)
+    }
+
+    fn release_message_by_receipt(&mut self, receipt: ReceiptHandle) -> QueryResult<bool> {
+        let now = self.clock.now();
+        diesel::dsl::update(messages::table)
+            .set((messages::visible_since.eq(now), messages::nacks.eq(messages::nacks + 1)))
+            .filter(messages::id.eq(receipt.id))
+            .filter(messages::last_received_at.is_not_distinct_from(receipt.received_at))
+            .filter(messages::visible_since.gt(now))
+            .filter(messages::receives.gt(0))
             .execute(&mut self.conn)
+            .map(|count| count > 0)
+    }
+
+    fn change_messages_visibility(
+        &mut self,
+        ids: Vec<Uuid>,
+        receipts: &[ReceiptHandle],
+        visibility_timeout: Duration,
+    ) -> QueryResult<Vec<Uuid>> {
+        let mut condition: Box<dyn BoxableExpression<messages::table, Pg, SqlType = Bool>> =
+            Box::new(messages::id.eq_any(ids));
+        for receipt in receipts {
+            condition = Box::new(
+                condition.or(messages::id
+                    .eq(receipt.id)
+                    .and(messages::last_received_at.is_not_distinct_from(receipt.received_at))),
+            );
+        }
+        let now = self.clock.now();
+        // only messages in flight are changed, a message which is not received yet keeps its delay
+        diesel::dsl::update(messages::table)
+            .set(messages::visible_since.eq(now.add(visibility_timeout)))
+            .filter(condition)
+            .filter(messages::visible_since.gt(now))
+            .filter(messages::receives.gt(0))
+            .returning(messages::id)
+            .get_results(&mut self.conn)
     }
 
     fn delete_message_by_id(&mut self, id: Uuid) -> QueryResult<bool> {
@@ -153,17 +478,157 @@ impl MessageRepository for PgRepository {
             .map(|count| count > 0)
     }
 
+    fn delete_message_by_receipt(&mut self, receipt: ReceiptHandle) -> QueryResult<Option<bool>> {
+        let deleted = diesel::delete(
+            messages::table
+                .filter(messages::id.eq(receipt.id))
+                .filter(messages::last_received_at.is_not_distinct_from(receipt.received_at)),
+        )
+        .execute(&mut self.conn)?;
+        if deleted > 0 {
+            return Ok(Some(true));
+        }
+
+        let exists = diesel::select(diesel::dsl::exists(messages::table.filter(messages::id.eq(receipt.id))))
+            .get_result(&mut self.conn)?;
+        Ok(if exists { Some(false) } else { None })
+    }
+
     fn delete_messages_by_ids(&mut self, ids: Vec<Uuid>) -> QueryResult<usize> {
         diesel::delete(messages::table.filter(messages::id.eq_any(ids))).execute(&mut self.conn)
     }
+
+    fn delete_messages_by_hash(&mut self, queue_name: &str, hash: &str) -> QueryResult<usize> {
+        diesel::delete(
+            messages::table
+                .filter(messages::queue.eq(queue_name))
+                .filter(messages::hash.eq(hash)),
+        )
+        .execute(&mut self.conn)
+    }
+
+    fn list_in_flight(&mut self, queue_name: &str, limit: i64) -> QueryResult<Vec<(Uuid, UtcTime)>> {
+        messages::table
+            .select((messages::id, messages::visible_since))
+            .filter(messages::queue.eq(queue_name))
+            .filter(messages::visible_since.gt(self.clock.now()))
+            .filter(messages::receives.gt(0))
+            .order((messages::visible_since, messages::id))
+            .limit(limit)
+            .load(&mut self.conn)
+    }
+
+    fn list_visible_message_ids(&mut self, queue_name: &str, count: i64) -> QueryResult<Vec<Uuid>> {
+        messages::table
+            .select(messages::id)
+            .filter(messages::queue.eq(queue_name))
+            .filter(messages::visible_since.le(self.clock.now()))
+            .order((messages::created_at, messages::id))
+            .limit(count)
+            .load(&mut self.conn)
+    }
 }
 
+// a message with a group id can only be received once all older messages of the same group in the
+// same queue have been deleted (or moved to another queue).
+const FIRST_IN_GROUP: &str = "(messages.group_id IS NULL OR NOT EXISTS (\
+    SELECT 1 FROM messages AS older \
+    WHERE older.queue = messages.queue \
+    AND older.group_id = messages.group_id \
+    AND (older.created_at < messages.created_at OR (older.created_at = messages.created_at AND older.id < messages.id))\
+))";
+
 struct MessageIdsForFetch {
     sub_query: Box<dyn QueryFragment<Pg>>,
 }
 
+/// Condition for the messages of a queue which can be received at the given time.
+fn insert_message_row(
+    conn: &mut PgConnection,
+    queue: &Queue,
+    input: &MessageInput<'_>,
+    now: UtcTime,
+) -> QueryResult<Uuid> {
+    let visible_since = now.add_pg_interval(&queue.message_delay);
+    let id = Uuid::new_v4();
+    let hash = if queue.content_based_deduplication {
+        let mut digest = Sha256::default();
+        digest.update(input.payload);
+        let result = digest.finalize();
+        Some(base64::encode(result.as_slice()))
+    } else {
+        None
+    };
+    diesel::dsl::insert_into(messages::table)
+        .values(NewMessage {
+            id,
+            payload: input.payload,
+            content_type: input.content_type,
+            content_encoding: input.content_encoding,
+            hash,
+            queue: &queue.name,
+            receives: 0,
+            visible_since,
+            created_at: now,
+            trace_id: input.trace_id,
+            group_id: input.group_id,
+            attributes: input.attributes,
+            expires_at: input.expires_at,
+            json_attributes: input.json_attributes,
+            priority: input.priority,
+            published_at: input.published_at.unwrap_or(now),
+        })
+        .execute(conn)?;
+
+    Ok(id)
+}
+
+fn visible_messages(
+    queue_name: &str,
+    visible_since: UtcTime,
+    filter: MessageFilter<'_>,
+) -> Box<dyn BoxableExpression<messages::table, Pg, SqlType = Bool>> {
+    let condition: Box<dyn BoxableExpression<messages::table, Pg, SqlType = Bool>> = Box::new(
+        messages::queue
+            .eq(queue_name.to_string())
+            .and(messages::visible_since.le(visible_since))
+            .and(diesel::dsl::sql::<Bool>(FIRST_IN_GROUP)),
+    );
+    let condition = if let Some(content_type) = filter.content_type {
+        Box::new(condition.and(messages::content_type.eq(content_type.to_string())))
+    } else {
+        condition
+    };
+    if let Some(since) = filter.since {
+        Box::new(condition.and(messages::created_at.ge(since)))
+    } else {
+        condition
+    }
+}
+
+type OrderExpression = Box<dyn BoxableExpression<messages::table, Pg, SqlType = NotSelectable>>;
+
+/// Order in which the messages of a queue are received. Queues with message priorities deliver
+/// messages with a higher priority first and messages of the same priority in the order they were
+/// published in, other queues deliver the messages which are visible the longest first.
+fn receive_order(queue: &Queue) -> (OrderExpression, OrderExpression) {
+    if queue.message_priorities {
+        (
+            Box::new(messages::priority.desc()),
+            Box::new(messages::created_at.asc()),
+        )
+    } else {
+        (
+            Box::new(messages::visible_since.asc()),
+            Box::new(messages::created_at.asc()),
+        )
+    }
+}
+
 impl MessageIdsForFetch {
-    fn new(queue_name: &str, visible_since: UtcTime, count: i64) -> Self {
+    fn new(queue: &Queue, visible_since: UtcTime, count: i64, filter: MessageFilter<'_>) -> Self {
+        let condition = visible_messages(&queue.name, visible_since, filter);
+
         Self {
             // select all elements which are currently visible, take the first elements visible
             // and limit to the maximum number of elements we want to process.
@@ -171,12 +636,8 @@ impl MessageIdsForFetch {
             sub_query: Box::new(
                 messages::table
                     .select(messages::id)
-                    .filter(
-                        messages::queue
-                            .eq(queue_name.to_string())
-                            .and(messages::visible_since.le(visible_since)),
-                    )
-                    .order(messages::visible_since.asc())
+                    .filter(condition)
+                    .order(receive_order(queue))
                     .for_update()
                     .skip_locked()
                     .limit(count),
@@ -210,7 +671,7 @@ where
     F: Expression<SqlType = T>,
     V: Expression<SqlType = T>,
 {
-    type SqlType = diesel::sql_types::Bool;
+    type SqlType = Bool;
 }
 
 impl<F, V> In<F, V> {
@@ -219,6 +680,13 @@ impl<F, V> In<F, V> {
     }
 }
 
+// the sub query is boxed, so there is no static query id we could cache the query with
+impl<F, V> QueryId for In<F, V> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
 impl<F, V, DB> QueryFragment<DB> for In<F, V>
 where
     DB: Backend,
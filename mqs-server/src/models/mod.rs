@@ -1,4 +1,5 @@
-use mqs_common::connection::DBConn;
+use mqs_common::{connection::DBConn, Clock, Random, SystemClock, SystemRandom};
+use std::sync::Arc;
 
 pub mod health;
 pub mod message;
@@ -7,34 +8,77 @@ pub mod queue;
 /// A `PgRepository` implements the different repository traits to provide a database access layer
 /// for the different request handlers.
 pub struct PgRepository {
-    conn: DBConn,
+    conn:   DBConn,
+    clock:  Arc<dyn Clock>,
+    random: Arc<dyn Random>,
 }
 
 impl PgRepository {
     /// Create a new repository with the given database connection.
     #[must_use]
     pub fn new(conn: DBConn) -> Self {
-        Self { conn }
+        Self::with_clock(conn, Arc::new(SystemClock))
+    }
+
+    /// Create a new repository with the given database connection which reads the current time from
+    /// the given clock.
+    #[must_use]
+    pub fn with_clock(conn: DBConn, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            conn,
+            clock,
+            random: Arc::new(SystemRandom),
+        }
+    }
+
+    /// Use the given source of random numbers instead of the random number generator of the system.
+    #[must_use]
+    pub fn with_random(mut self, random: Arc<dyn Random>) -> Self {
+        self.random = random;
+        self
     }
 }
 
 #[cfg(test)]
 pub(crate) mod test {
-    use crate::models::{
-        health::HealthCheckRepository,
-        message::{Message, MessageInput, MessageRepository},
-        queue::{pg_interval, Queue, QueueDescription, QueueInput, QueueRepository, QueueSource},
+    use crate::{
+        models::{
+            health::HealthCheckRepository,
+            message::{audit_dropped_message, Message, MessageFilter, MessageInput, MessageRepository, RedriveReason},
+            queue::{
+                pg_interval,
+                tags_to_json,
+                Queue,
+                QueueDescription,
+                QueueInput,
+                QueueRename,
+                QueueRepository,
+                QueueSource,
+                QueueUpdate,
+            },
+        },
+        receipt::ReceiptHandle,
     };
     use diesel::QueryResult;
-    use mqs_common::{connection::Source, UtcTime};
+    use mqs_common::{
+        connection::{Source, SourceState},
+        Clock,
+        Page,
+        Random,
+        SystemClock,
+        SystemRandom,
+        UtcTime,
+    };
     use serde::de::StdError;
     use sha2::{Digest, Sha256};
     use std::{
         cell::Cell,
+        cmp::Reverse,
         collections::HashMap,
         fmt::{Display, Formatter},
-        mem::swap,
+        mem::{replace, swap},
         sync::{Arc, Mutex},
+        time::Duration,
     };
     use uuid::Uuid;
 
@@ -51,16 +95,38 @@ pub(crate) mod test {
 
     impl StdError for TestError {}
 
+    #[derive(Clone)]
     pub(crate) struct TestRepoSource {
-        repo: Arc<Mutex<Cell<Option<TestRepoData>>>>,
+        repo:   Arc<Mutex<Cell<Option<TestRepoData>>>>,
+        state:  Option<SourceState>,
+        clock:  Arc<dyn Clock>,
+        random: Arc<dyn Random>,
     }
 
     impl TestRepoSource {
         pub(crate) fn new() -> Self {
             TestRepoSource {
-                repo: Arc::new(Mutex::new(Cell::new(Some(TestRepoData::new())))),
+                repo:   Arc::new(Mutex::new(Cell::new(Some(TestRepoData::new())))),
+                state:  None,
+                clock:  Arc::new(SystemClock),
+                random: Arc::new(SystemRandom),
             }
         }
+
+        pub(crate) fn with_state(mut self, state: SourceState) -> Self {
+            self.state = Some(state);
+            self
+        }
+
+        pub(crate) fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+            self.clock = clock;
+            self
+        }
+
+        pub(crate) fn with_random(mut self, random: Arc<dyn Random>) -> Self {
+            self.random = random;
+            self
+        }
     }
 
     impl Source<TestRepo> for TestRepoSource {
@@ -71,8 +137,14 @@ pub(crate) mod test {
             Some(TestRepo {
                 source_repo: Arc::clone(&self.repo),
                 data,
+                clock: Arc::clone(&self.clock),
+                random: Arc::clone(&self.random),
             })
         }
+
+        fn state(&self) -> Option<SourceState> {
+            self.state
+        }
     }
 
     struct TestRepoData {
@@ -85,6 +157,8 @@ pub(crate) mod test {
     pub(crate) struct TestRepo {
         source_repo: Arc<Mutex<Cell<Option<TestRepoData>>>>,
         data:        TestRepoData,
+        clock:       Arc<dyn Clock>,
+        random:      Arc<dyn Random>,
     }
 
     impl TestRepoData {
@@ -103,6 +177,42 @@ pub(crate) mod test {
             self.data.health = health;
         }
 
+        fn insert_message(&mut self, queue: &Queue, input: &MessageInput<'_>) -> QueryResult<Option<Uuid>> {
+            let (hash, has_duplicate) = self.get_hash_and_duplicate(queue, input);
+            if has_duplicate {
+                return Ok(None);
+            }
+            let now = self.clock.now();
+            let message = Message {
+                id: Uuid::new_v4(),
+                payload: input.payload.to_vec(),
+                content_type: input.content_type.to_string(),
+                content_encoding: input.content_encoding.map(|s| s.to_string()),
+                hash,
+                queue: queue.name.to_string(),
+                receives: 0,
+                visible_since: now.add_pg_interval(&queue.message_delay),
+                created_at: now,
+                trace_id: input.trace_id.map(|s| s.to_string()),
+                first_received_at: None,
+                last_received_at: None,
+                nacks: 0,
+                group_id: input.group_id.map(|s| s.to_string()),
+                attributes: input.attributes.to_vec(),
+                expires_at: input.expires_at,
+                origin_queue: None,
+                redrive_reason: None,
+                json_attributes: input.json_attributes.cloned(),
+                redrive_count: 0,
+                priority: input.priority,
+                published_at: input.published_at.unwrap_or(now),
+            };
+            let id = message.id;
+            self.data.messages.insert(id, message);
+
+            Ok(Some(id))
+        }
+
         fn next_id(&mut self) -> i32 {
             let id = self.data.next_id;
             self.data.next_id = id + 1;
@@ -146,6 +256,16 @@ pub(crate) mod test {
         }
     }
 
+    /// Sort key for the order in which messages are received from the given queue, mirroring the
+    /// order used by the database.
+    fn receive_order(queue: &Queue, message: &Message) -> (Reverse<i32>, UtcTime, UtcTime) {
+        if queue.message_priorities {
+            (Reverse(message.priority), message.created_at, message.created_at)
+        } else {
+            (Reverse(0), message.visible_since, message.created_at)
+        }
+    }
+
     impl HealthCheckRepository for TestRepo {
         fn check_health(&mut self) -> bool {
             self.data.health
@@ -153,66 +273,248 @@ pub(crate) mod test {
     }
 
     impl MessageRepository for TestRepo {
-        fn insert_message(&mut self, queue: &Queue, input: &MessageInput<'_>) -> QueryResult<bool> {
-            let (hash, has_duplicate) = self.get_hash_and_duplicate(queue, input);
-            if has_duplicate {
-                return Ok(false);
-            }
-            let now = UtcTime::now();
-            let message = Message {
-                id: Uuid::new_v4(),
-                payload: input.payload.to_vec(),
-                content_type: input.content_type.to_string(),
-                content_encoding: input.content_encoding.map(|s| s.to_string()),
-                hash,
-                queue: queue.name.to_string(),
-                receives: 0,
-                visible_since: now.add_pg_interval(&queue.message_delay),
-                created_at: now,
-                trace_id: None,
-            };
-            self.data.messages.insert(message.id.clone(), message);
+        fn now(&self) -> UtcTime {
+            self.clock.now()
+        }
 
-            Ok(true)
+        fn insert_messages(&mut self, queue: &Queue, inputs: &[MessageInput<'_>]) -> QueryResult<Vec<Option<Uuid>>> {
+            inputs.iter().map(|input| self.insert_message(queue, input)).collect()
         }
 
-        fn get_message_from_queue(&mut self, queue: &Queue, count: i64) -> QueryResult<Vec<Message>> {
+        fn get_message_from_queue(
+            &mut self,
+            queue: &Queue,
+            count: i64,
+            filter: MessageFilter<'_>,
+            auto_delete: bool,
+        ) -> QueryResult<Vec<Message>> {
+            let now = self.clock.now();
+            let count = match queue.max_in_flight {
+                None => count,
+                Some(max_in_flight) => {
+                    let in_flight = self
+                        .data
+                        .messages
+                        .values()
+                        .filter(|message| {
+                            message.queue == queue.name && message.visible_since > now && message.receives > 0
+                        })
+                        .count() as i64;
+                    count.min(max_in_flight - in_flight)
+                },
+            };
+            if count < 1 {
+                return Ok(Vec::new());
+            }
             let mut result: Vec<Message> = Vec::with_capacity(count as usize);
-            let now = UtcTime::now();
 
-            for message in self.data.messages.values_mut() {
-                if message.visible_since > now || &message.queue != &queue.name {
+            // messages of a group are only visible once all older messages of the group are gone
+            let age = |message: &Message| (message.created_at, message.id);
+            let blocked: Vec<Uuid> = self
+                .data
+                .messages
+                .values()
+                .filter(|message| {
+                    message.group_id.is_some()
+                        && self.data.messages.values().any(|older| {
+                            older.queue == message.queue
+                                && older.group_id == message.group_id
+                                && age(older) < age(message)
+                        })
+                })
+                .map(|message| message.id)
+                .collect();
+
+            let mut candidates: Vec<&Message> = self
+                .data
+                .messages
+                .values()
+                .filter(|message| {
+                    message.visible_since <= now
+                        && message.queue == queue.name
+                        && !blocked.contains(&message.id)
+                        && filter.matches(message)
+                })
+                .collect();
+            candidates.sort_by_key(|message| receive_order(queue, message));
+            let candidates: Vec<Uuid> = candidates.into_iter().map(|message| message.id).collect();
+
+            let mut expired = Vec::new();
+            for id in candidates {
+                if result.len() as i64 >= count {
+                    break;
+                }
+                let message = match self.data.messages.get_mut(&id) {
+                    None => continue,
+                    Some(message) => message,
+                };
+                if message.is_expired(queue, now) {
+                    expired.push(message.id);
                     continue;
                 }
 
                 message.receives += 1;
-                message.visible_since = now.add_pg_interval(&queue.visibility_timeout);
+                message.visible_since = queue.visible_after_receive(now, self.random.next_fraction());
+                message.first_received_at = message.first_received_at.or(Some(now));
+                message.last_received_at = Some(now);
                 result.push(message.clone());
             }
+            for id in expired {
+                self.data.messages.remove(&id);
+            }
+            if auto_delete {
+                for message in &result {
+                    self.data.messages.remove(&message.id);
+                }
+                return Ok(result);
+            }
+            if let Some(max_receives) = queue.max_receives {
+                let (dropped, moved): (Vec<&Message>, Vec<&Message>) = result
+                    .iter()
+                    .filter(|message| message.receives >= max_receives)
+                    .partition(|message| message.exceeds_max_redrives(queue));
+                for message in dropped {
+                    audit_dropped_message(queue, message);
+                    self.data.messages.remove(&message.id);
+                }
+                if let Some(dead_letter_queue) = &queue.dead_letter_queue {
+                    let move_to_dead_letter_queue = moved.into_iter().map(|message| message.id).collect();
+                    self.move_message_to_queue(
+                        move_to_dead_letter_queue,
+                        dead_letter_queue,
+                        RedriveReason::MaxReceives,
+                    )?;
+                }
+            }
 
             Ok(result)
         }
 
-        fn move_message_to_queue(&mut self, ids: Vec<Uuid>, new_queue: &str) -> QueryResult<usize> {
+        fn peek_messages(&mut self, queue: &Queue, count: i64, filter: MessageFilter<'_>) -> QueryResult<Vec<Message>> {
+            let now = self.clock.now();
+            let age = |message: &Message| (message.created_at, message.id);
+            let messages = &self.data.messages;
+            let is_blocked = |message: &Message| {
+                message.group_id.is_some()
+                    && messages.values().any(|older| {
+                        older.queue == message.queue && older.group_id == message.group_id && age(older) < age(message)
+                    })
+            };
+
+            let mut visible: Vec<&Message> = messages
+                .values()
+                .filter(|message| {
+                    message.visible_since <= now
+                        && message.queue == queue.name
+                        && !is_blocked(message)
+                        && filter.matches(message)
+                        && !message.is_expired(queue, now)
+                })
+                .collect();
+            visible.sort_by_key(|message| receive_order(queue, message));
+
+            Ok(visible
+                .into_iter()
+                .take(count as usize)
+                .map(|message| Message {
+                    payload: Vec::new(),
+                    ..message.clone()
+                })
+                .collect())
+        }
+
+        fn find_message_queue(&mut self, id: Uuid) -> QueryResult<Option<String>> {
+            Ok(self.data.messages.get(&id).map(|message| message.queue.clone()))
+        }
+
+        fn move_message_to_queue(
+            &mut self,
+            ids: Vec<Uuid>,
+            new_queue: &str,
+            reason: RedriveReason,
+        ) -> QueryResult<usize> {
             let mut modified = 0;
 
             for id in ids {
                 match self.data.messages.get_mut(&id) {
-                    None => {},
-                    Some(msg) => {
-                        msg.queue = new_queue.to_string();
+                    Some(msg) if msg.queue != new_queue => {
+                        msg.origin_queue = Some(replace(&mut msg.queue, new_queue.to_string()));
+                        msg.redrive_reason = Some(reason.as_str().to_string());
+                        msg.receives = 0;
+                        msg.redrive_count += 1;
                         modified += 1;
                     },
+                    _ => {},
                 }
             }
 
             Ok(modified)
         }
 
+        fn release_message(&mut self, id: Uuid) -> QueryResult<bool> {
+            let now = self.clock.now();
+            match self.data.messages.get_mut(&id) {
+                Some(message) if message.visible_since > now && message.receives > 0 => {
+                    message.visible_since = now;
+                    message.nacks += 1;
+                    Ok(true)
+                },
+                _ => Ok(false),
+            }
+        }
+
+        fn release_message_by_receipt(&mut self, receipt: ReceiptHandle) -> QueryResult<bool> {
+            match self.data.messages.get(&receipt.id) {
+                Some(message) if message.last_received_at == Some(receipt.received_at) => {
+                    self.release_message(receipt.id)
+                },
+                _ => Ok(false),
+            }
+        }
+
+        fn change_messages_visibility(
+            &mut self,
+            ids: Vec<Uuid>,
+            receipts: &[ReceiptHandle],
+            visibility_timeout: Duration,
+        ) -> QueryResult<Vec<Uuid>> {
+            let now = self.clock.now();
+            let visible_since = now.add(visibility_timeout);
+            let mut changed = Vec::with_capacity(ids.len() + receipts.len());
+            let in_flight = |message: &Message| message.visible_since > now && message.receives > 0;
+
+            for id in ids {
+                if let Some(message) = self.data.messages.get_mut(&id) {
+                    if in_flight(message) {
+                        message.visible_since = visible_since;
+                        changed.push(id);
+                    }
+                }
+            }
+            for receipt in receipts {
+                if let Some(message) = self.data.messages.get_mut(&receipt.id) {
+                    if in_flight(message) && message.last_received_at == Some(receipt.received_at) {
+                        message.visible_since = visible_since;
+                        changed.push(receipt.id);
+                    }
+                }
+            }
+
+            Ok(changed)
+        }
+
         fn delete_message_by_id(&mut self, id: Uuid) -> QueryResult<bool> {
             Ok(self.data.messages.remove(&id).is_some())
         }
 
+        fn delete_message_by_receipt(&mut self, receipt: ReceiptHandle) -> QueryResult<Option<bool>> {
+            match self.data.messages.get(&receipt.id) {
+                None => Ok(None),
+                Some(message) if message.last_received_at != Some(receipt.received_at) => Ok(Some(false)),
+                Some(_) => Ok(Some(self.delete_message_by_id(receipt.id)?)),
+            }
+        }
+
         fn delete_messages_by_ids(&mut self, ids: Vec<Uuid>) -> QueryResult<usize> {
             let mut deleted = 0;
 
@@ -224,6 +526,44 @@ pub(crate) mod test {
 
             Ok(deleted)
         }
+
+        fn delete_messages_by_hash(&mut self, queue_name: &str, hash: &str) -> QueryResult<usize> {
+            let count = self.data.messages.len();
+            self.data
+                .messages
+                .retain(|_, message| message.queue != queue_name || message.hash.as_deref() != Some(hash));
+
+            Ok(count - self.data.messages.len())
+        }
+
+        fn list_in_flight(&mut self, queue_name: &str, limit: i64) -> QueryResult<Vec<(Uuid, UtcTime)>> {
+            let now = self.clock.now();
+            let mut in_flight: Vec<(Uuid, UtcTime)> = self
+                .data
+                .messages
+                .values()
+                .filter(|message| message.queue == queue_name && message.visible_since > now && message.receives > 0)
+                .map(|message| (message.id, message.visible_since))
+                .collect();
+            in_flight.sort_by_key(|(id, visible_since)| (*visible_since, *id));
+            in_flight.truncate(limit as usize);
+
+            Ok(in_flight)
+        }
+
+        fn list_visible_message_ids(&mut self, queue_name: &str, count: i64) -> QueryResult<Vec<Uuid>> {
+            let now = self.clock.now();
+            let mut messages: Vec<(UtcTime, Uuid)> = self
+                .data
+                .messages
+                .values()
+                .filter(|message| message.queue == queue_name && message.visible_since <= now)
+                .map(|message| (message.created_at, message.id))
+                .collect();
+            messages.sort();
+
+            Ok(messages.into_iter().take(count as usize).map(|(_, id)| id).collect())
+        }
     }
 
     impl QueueSource for TestRepo {
@@ -237,7 +577,7 @@ pub(crate) mod test {
             if self.find_by_name(queue.name)?.is_some() {
                 return Ok(None);
             }
-            let now = UtcTime::now();
+            let now = self.clock.now();
             let queue = Queue {
                 id:                          self.next_id(),
                 name:                        queue.name.to_string(),
@@ -249,12 +589,33 @@ pub(crate) mod test {
                 content_based_deduplication: queue.content_based_deduplication,
                 created_at:                  now,
                 updated_at:                  now,
+                allowed_content_types:       queue.allowed_content_types.to_vec(),
+                tags:                        tags_to_json(queue.tags),
+                default_content_type:        queue.default_content_type.map(|s| s.to_string()),
+                max_in_flight:               queue.max_in_flight,
+                max_redrives:                queue.max_redrives,
+                visibility_jitter:           queue.visibility_jitter,
+                message_priorities:          queue.message_priorities,
             };
             self.data.queues.insert(queue.name.to_string(), queue.clone());
 
             Ok(Some(queue))
         }
 
+        fn insert_queue_with_dlq(
+            &mut self,
+            queue: &QueueInput<'_>,
+            dead_letter_queue: &QueueInput<'_>,
+        ) -> QueryResult<Option<(Queue, Queue)>> {
+            if self.find_by_name(queue.name)?.is_some() || self.find_by_name(dead_letter_queue.name)?.is_some() {
+                return Ok(None);
+            }
+            let dead_letter_queue = self.insert_queue(dead_letter_queue)?;
+            let queue = self.insert_queue(queue)?;
+
+            Ok(queue.zip(dead_letter_queue))
+        }
+
         fn count_queues(&mut self) -> QueryResult<i64> {
             Ok(self.data.queues.len() as i64)
         }
@@ -265,7 +626,7 @@ pub(crate) mod test {
                 let mut messages_count = 0;
                 let mut visible_messages = 0;
                 let mut oldest_message_age = 0;
-                let now = UtcTime::now();
+                let now = self.clock.now();
 
                 for message in self.data.messages.values() {
                     if &message.queue != &queue.name {
@@ -279,40 +640,91 @@ pub(crate) mod test {
                     }
                 }
 
+                let dead_letter_queue_depth = queue.dead_letter_queue.as_ref().map(|dead_letter_queue| {
+                    self.data
+                        .messages
+                        .values()
+                        .filter(|message| &message.queue == dead_letter_queue)
+                        .count() as i64
+                });
+
                 Ok(Some(QueueDescription {
                     queue,
                     messages: messages_count,
                     visible_messages,
                     oldest_message_age,
+                    dead_letter_queue_depth,
                 }))
             } else {
                 Ok(None)
             }
         }
 
-        fn list_queues(&mut self, offset: Option<i64>, limit: Option<i64>) -> QueryResult<Vec<Queue>> {
-            let mut skip = offset.unwrap_or(0);
-            let max = limit.unwrap_or(self.data.queues.len() as i64) as usize;
-            let mut result = Vec::with_capacity(max);
-
-            for queue in self.data.queues.values() {
-                if skip > 0 {
-                    skip -= 0;
-                    continue;
-                }
-
-                result.push(queue.clone());
-                if result.len() == max {
-                    break;
+        fn describe_queues(&mut self, queues: Vec<Queue>) -> QueryResult<Vec<QueueDescription>> {
+            let mut descriptions = Vec::with_capacity(queues.len());
+            for queue in queues {
+                if let Some(description) = self.describe_queue(&queue.name)? {
+                    descriptions.push(description);
                 }
             }
 
-            Ok(result)
+            Ok(descriptions)
+        }
+
+        fn sample_message_ages(&mut self, queue: &Queue, sample_size: i64) -> QueryResult<Vec<u64>> {
+            let now = self.clock.now();
+            let mut messages: Vec<&Message> = self
+                .data
+                .messages
+                .values()
+                .filter(|message| message.queue == queue.name)
+                .collect();
+            messages.sort_by_key(|message| message.id);
+
+            Ok(messages
+                .into_iter()
+                .take(sample_size as usize)
+                .map(|message| now.since(&message.created_at).map_or(0, |d| d.as_secs()))
+                .collect())
         }
 
-        fn update_queue(&mut self, queue: &QueueInput<'_>) -> QueryResult<Option<Queue>> {
+        fn list_queues(&mut self, offset: Option<i64>, limit: Option<i64>) -> QueryResult<Vec<Queue>> {
+            self.list_queues_with_prefix("", offset, limit)
+        }
+
+        fn count_queues_with_prefix(&mut self, prefix: &str) -> QueryResult<i64> {
+            Ok(self.data.queues.keys().filter(|name| name.starts_with(prefix)).count() as i64)
+        }
+
+        fn list_queues_with_prefix(
+            &mut self,
+            prefix: &str,
+            offset: Option<i64>,
+            limit: Option<i64>,
+        ) -> QueryResult<Vec<Queue>> {
+            // list queues by name like the database does
+            let mut queues: Vec<Queue> = self
+                .data
+                .queues
+                .values()
+                .filter(|queue| queue.name.starts_with(prefix))
+                .cloned()
+                .collect();
+            queues.sort_by(|a, b| a.name.cmp(&b.name));
+
+            Ok(Page::from_all(queues, offset.unwrap_or(0) as usize, limit.map(|limit| limit as usize)).items)
+        }
+
+        fn update_queue(
+            &mut self,
+            queue: &QueueInput<'_>,
+            expected_version: Option<UtcTime>,
+        ) -> QueryResult<QueueUpdate> {
             let old = self.find_by_name(queue.name)?;
             if let Some(old) = old {
+                if matches!(expected_version, Some(version) if version != old.updated_at) {
+                    return Ok(QueueUpdate::VersionMismatch);
+                }
                 let queue = Queue {
                     id:                          old.id,
                     name:                        queue.name.to_string(),
@@ -323,18 +735,53 @@ pub(crate) mod test {
                     message_delay:               pg_interval(queue.message_delay),
                     content_based_deduplication: queue.content_based_deduplication,
                     created_at:                  old.created_at,
-                    updated_at:                  UtcTime::now(),
+                    updated_at:                  self.clock.now(),
+                    allowed_content_types:       queue.allowed_content_types.to_vec(),
+                    tags:                        tags_to_json(queue.tags),
+                    default_content_type:        queue.default_content_type.map(|s| s.to_string()),
+                    max_in_flight:               queue.max_in_flight,
+                    max_redrives:                queue.max_redrives,
+                    visibility_jitter:           queue.visibility_jitter,
+                    message_priorities:          queue.message_priorities,
                 };
                 self.data.queues.insert(queue.name.to_string(), queue.clone());
 
-                Ok(Some(queue))
+                Ok(QueueUpdate::Updated(Box::new(queue)))
             } else {
-                Ok(None)
+                Ok(QueueUpdate::NotFound)
             }
         }
 
         fn delete_queue_by_name(&mut self, name: &str) -> QueryResult<Option<Queue>> {
             Ok(self.data.queues.remove(name))
         }
+
+        fn rename_queue(&mut self, old_name: &str, new_name: &str) -> QueryResult<QueueRename> {
+            if old_name != new_name && self.data.queues.contains_key(new_name) {
+                return Ok(QueueRename::AlreadyExists);
+            }
+            let mut queue = match self.data.queues.remove(old_name) {
+                None => return Ok(QueueRename::NotFound),
+                Some(queue) => queue,
+            };
+            queue.name = new_name.to_string();
+            queue.updated_at = self.clock.now();
+            self.data.queues.insert(queue.name.clone(), queue);
+            for other in self.data.queues.values_mut() {
+                if other.dead_letter_queue.as_deref() == Some(old_name) {
+                    other.dead_letter_queue = Some(new_name.to_string());
+                }
+            }
+            for message in self.data.messages.values_mut() {
+                if message.queue == old_name {
+                    message.queue = new_name.to_string();
+                }
+                if message.origin_queue.as_deref() == Some(old_name) {
+                    message.origin_queue = Some(new_name.to_string());
+                }
+            }
+
+            Ok(QueueRename::Renamed(Box::new(self.data.queues[new_name].clone())))
+        }
     }
 }
@@ -3,9 +3,12 @@ use diesel::{
     pg::data_types::PgInterval,
     prelude::*,
     result::{DatabaseErrorKind, Error},
+    sql_types::{Array, BigInt, Nullable, Timestamp, Varchar},
 };
 use mqs_common::{QueueConfig, QueueConfigOutput, QueueRedrivePolicy, UtcTime};
+use serde_json::Value;
 use std::{
+    collections::BTreeMap,
     convert::TryFrom,
     sync::{
         atomic::{AtomicUsize, Ordering},
@@ -27,6 +30,13 @@ pub struct QueueInput<'a> {
     pub visibility_timeout:          i64,
     pub message_delay:               i64,
     pub content_based_deduplication: bool,
+    pub allowed_content_types:       &'a [String],
+    pub default_content_type:        Option<&'a str>,
+    pub max_in_flight:               Option<i64>,
+    pub max_redrives:                Option<i32>,
+    pub visibility_jitter:           Option<i32>,
+    pub message_priorities:          bool,
+    pub tags:                        &'a BTreeMap<String, String>,
 }
 
 impl<'a> QueueInput<'a> {
@@ -39,6 +49,13 @@ impl<'a> QueueInput<'a> {
             visibility_timeout:          config.visibility_timeout,
             message_delay:               config.message_delay,
             content_based_deduplication: config.message_deduplication,
+            allowed_content_types:       &config.allowed_content_types,
+            default_content_type:        config.default_content_type.as_deref(),
+            max_in_flight:               config.max_in_flight,
+            max_redrives:                config.max_redrives,
+            visibility_jitter:           config.visibility_jitter,
+            message_priorities:          config.message_priorities,
+            tags:                        &config.tags,
         }
     }
 }
@@ -55,6 +72,13 @@ pub struct NewQueue<'a> {
     pub content_based_deduplication: bool,
     pub created_at:                  UtcTime,
     pub updated_at:                  UtcTime,
+    pub allowed_content_types:       &'a [String],
+    pub tags:                        Value,
+    pub default_content_type:        Option<&'a str>,
+    pub max_in_flight:               Option<i64>,
+    pub max_redrives:                Option<i32>,
+    pub visibility_jitter:           Option<i32>,
+    pub message_priorities:          bool,
 }
 
 #[derive(Queryable, Identifiable, Clone, Debug, PartialEq, Eq)]
@@ -69,9 +93,44 @@ pub struct Queue {
     pub content_based_deduplication: bool,
     pub created_at:                  UtcTime,
     pub updated_at:                  UtcTime,
+    pub allowed_content_types:       Vec<String>,
+    pub tags:                        Value,
+    pub default_content_type:        Option<String>,
+    pub max_in_flight:               Option<i64>,
+    pub max_redrives:                Option<i32>,
+    pub visibility_jitter:           Option<i32>,
+    pub message_priorities:          bool,
 }
 
 impl Queue {
+    /// Check whether a message with the given content type may be published to this queue. Parameters
+    /// of the content type (like the charset) are ignored when comparing it against the allowed content
+    /// types.
+    pub(crate) fn accepts_content_type(&self, content_type: &str) -> bool {
+        if self.allowed_content_types.is_empty() {
+            return true;
+        }
+
+        let requested = media_type(content_type);
+        self.allowed_content_types
+            .iter()
+            .any(|allowed| requested.eq_ignore_ascii_case(media_type(allowed)))
+    }
+
+    /// Get the time a message received at `now` becomes visible again. If the queue has a visibility
+    /// jitter, the visibility timeout is extended by up to that percentage, `fraction` (in the range
+    /// `[0, 1)`) selects how much of it is used.
+    pub(crate) fn visible_after_receive(&self, now: UtcTime, fraction: f64) -> UtcTime {
+        let visible_since = now.add_pg_interval(&self.visibility_timeout);
+        match self.visibility_jitter {
+            None | Some(0) => visible_since,
+            Some(visibility_jitter) => {
+                let visibility_timeout = visible_since.since(&now).unwrap_or_default();
+                visible_since.add(visibility_timeout.mul_f64(f64::from(visibility_jitter) / 100.0 * fraction))
+            },
+        }
+    }
+
     #[allow(clippy::missing_const_for_fn)]
     pub(crate) fn into_config_output(self) -> QueueConfigOutput {
         QueueConfigOutput {
@@ -87,10 +146,45 @@ impl Queue {
             visibility_timeout:    pg_interval_seconds(&self.visibility_timeout),
             message_delay:         pg_interval_seconds(&self.message_delay),
             message_deduplication: self.content_based_deduplication,
+            allowed_content_types: self.allowed_content_types,
+            default_content_type:  self.default_content_type,
+            max_in_flight:         self.max_in_flight,
+            max_redrives:          self.max_redrives,
+            visibility_jitter:     self.visibility_jitter,
+            message_priorities:    self.message_priorities,
+            tags:                  tags_from_json(self.tags),
+            version:               Some(self.updated_at.to_rfc3339_nanos()),
         }
     }
 }
 
+/// Encode the tags of a queue as a JSON object to store them in the database.
+pub fn tags_to_json(tags: &BTreeMap<String, String>) -> Value {
+    Value::Object(
+        tags.iter()
+            .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+            .collect(),
+    )
+}
+
+/// Decode the tags of a queue stored in the database. Entries which are not strings are ignored.
+fn tags_from_json(tags: Value) -> BTreeMap<String, String> {
+    match tags {
+        Value::Object(tags) => tags
+            .into_iter()
+            .filter_map(|(key, value)| match value {
+                Value::String(value) => Some((key, value)),
+                _ => None,
+            })
+            .collect(),
+        _ => BTreeMap::new(),
+    }
+}
+
+fn media_type(content_type: &str) -> &str {
+    content_type.split(';').next().unwrap_or_default().trim()
+}
+
 const fn pg_interval_seconds(interval: &PgInterval) -> i64 {
     interval.microseconds / 1_000_000 + interval.days as i64 * (24 * 3600) + interval.months as i64 * (30 * 24 * 3600)
 }
@@ -119,10 +213,47 @@ pub fn pg_interval(mut seconds: i64) -> PgInterval {
 }
 
 pub struct QueueDescription {
-    pub queue:              Queue,
-    pub messages:           i64,
-    pub visible_messages:   i64,
-    pub oldest_message_age: u64,
+    pub queue:                   Queue,
+    pub messages:                i64,
+    pub visible_messages:        i64,
+    pub oldest_message_age:      u64,
+    /// Number of messages in the dead letter queue of the queue, if it has one.
+    pub dead_letter_queue_depth: Option<i64>,
+}
+
+/// Message counts of a single queue as computed by `describe_queues`.
+#[derive(QueryableByName)]
+struct QueueMessageCounts {
+    #[diesel(sql_type = Varchar)]
+    queue:            String,
+    #[diesel(sql_type = BigInt)]
+    messages:         i64,
+    #[diesel(sql_type = BigInt)]
+    visible_messages: i64,
+    #[diesel(sql_type = Nullable<Timestamp>)]
+    oldest_message:   Option<UtcTime>,
+}
+
+/// Result of updating a queue.
+#[derive(Debug)]
+pub enum QueueUpdate {
+    /// The queue was updated, contains the new state of the queue.
+    Updated(Box<Queue>),
+    /// No queue of the given name exists.
+    NotFound,
+    /// The queue exists, but was modified since the expected version.
+    VersionMismatch,
+}
+
+/// Result of renaming a queue.
+#[derive(Debug)]
+pub enum QueueRename {
+    /// The queue was renamed, contains the new state of the queue.
+    Renamed(Box<Queue>),
+    /// No queue of the old name exists.
+    NotFound,
+    /// A queue of the new name does already exist.
+    AlreadyExists,
 }
 
 static CACHE_HITS: AtomicUsize = AtomicUsize::new(0);
@@ -167,13 +298,63 @@ pub trait QueueSource: Send {
     }
 }
 
+/// Drop all queues from the cache of this process. Renaming a queue changes the queues referencing it
+/// as their dead letter queue as well, so single entries can't be updated.
+fn clear_queue_cache() {
+    QUEUE_CACHE.lock().map_or_else(
+        |err| {
+            error!("Failed to get queue cache lock: {}", err);
+        },
+        |mut cache| {
+            Cached::cache_clear(&mut *cache);
+        },
+    );
+}
+
+/// Build a pattern for `LIKE` matching all strings starting with the given prefix. Wildcards in the
+/// prefix are escaped with a backslash, so they only match themselves.
+fn like_prefix(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+
+    pattern
+}
+
 pub trait QueueRepository: QueueSource {
     fn insert_queue(&mut self, queue: &QueueInput<'_>) -> QueryResult<Option<Queue>>;
+    /// Insert a queue and its dead letter queue in a single transaction. Returns `None` without
+    /// inserting anything if one of them does already exist.
+    fn insert_queue_with_dlq(
+        &mut self,
+        queue: &QueueInput<'_>,
+        dead_letter_queue: &QueueInput<'_>,
+    ) -> QueryResult<Option<(Queue, Queue)>>;
     fn count_queues(&mut self) -> QueryResult<i64>;
     fn describe_queue(&mut self, name: &str) -> QueryResult<Option<QueueDescription>>;
+    /// Compute the status of all given queues at once. Returns the descriptions in the same order
+    /// as the queues.
+    fn describe_queues(&mut self, queues: Vec<Queue>) -> QueryResult<Vec<QueueDescription>>;
+    /// Get the ages in seconds of at most `sample_size` messages of the given queue.
+    fn sample_message_ages(&mut self, queue: &Queue, sample_size: i64) -> QueryResult<Vec<u64>>;
     fn list_queues(&mut self, offset: Option<i64>, limit: Option<i64>) -> QueryResult<Vec<Queue>>;
-    fn update_queue(&mut self, queue: &QueueInput<'_>) -> QueryResult<Option<Queue>>;
+    fn count_queues_with_prefix(&mut self, prefix: &str) -> QueryResult<i64>;
+    fn list_queues_with_prefix(
+        &mut self,
+        prefix: &str,
+        offset: Option<i64>,
+        limit: Option<i64>,
+    ) -> QueryResult<Vec<Queue>>;
+    fn update_queue(&mut self, queue: &QueueInput<'_>, expected_version: Option<UtcTime>) -> QueryResult<QueueUpdate>;
     fn delete_queue_by_name(&mut self, name: &str) -> QueryResult<Option<Queue>>;
+    /// Rename a queue. Its messages and the queues using it as their dead letter queue are changed
+    /// to the new name in the same transaction.
+    fn rename_queue(&mut self, old_name: &str, new_name: &str) -> QueryResult<QueueRename>;
 }
 
 impl QueueSource for PgRepository {
@@ -185,26 +366,58 @@ impl QueueSource for PgRepository {
     }
 }
 
+fn insert_queue_row(conn: &mut PgConnection, queue: &QueueInput<'_>, now: UtcTime) -> QueryResult<Option<Queue>> {
+    let result = diesel::dsl::insert_into(queues::table)
+        .values(NewQueue {
+            name:                        queue.name,
+            max_receives:                queue.max_receives,
+            dead_letter_queue:           queue.dead_letter_queue,
+            retention_timeout:           pg_interval(queue.retention_timeout),
+            visibility_timeout:          pg_interval(queue.visibility_timeout),
+            message_delay:               pg_interval(queue.message_delay),
+            content_based_deduplication: queue.content_based_deduplication,
+            created_at:                  now,
+            updated_at:                  now,
+            allowed_content_types:       queue.allowed_content_types,
+            tags:                        tags_to_json(queue.tags),
+            default_content_type:        queue.default_content_type,
+            max_in_flight:               queue.max_in_flight,
+            max_redrives:                queue.max_redrives,
+            visibility_jitter:           queue.visibility_jitter,
+            message_priorities:          queue.message_priorities,
+        })
+        .returning(queues::all_columns)
+        .get_result(conn);
+    match result {
+        Ok(queue) => Ok(Some(queue)),
+        Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
 impl QueueRepository for PgRepository {
     fn insert_queue(&mut self, queue: &QueueInput<'_>) -> QueryResult<Option<Queue>> {
-        let now = UtcTime::now();
-        let result = diesel::dsl::insert_into(queues::table)
-            .values(NewQueue {
-                name:                        queue.name,
-                max_receives:                queue.max_receives,
-                dead_letter_queue:           queue.dead_letter_queue,
-                retention_timeout:           pg_interval(queue.retention_timeout),
-                visibility_timeout:          pg_interval(queue.visibility_timeout),
-                message_delay:               pg_interval(queue.message_delay),
-                content_based_deduplication: queue.content_based_deduplication,
-                created_at:                  now,
-                updated_at:                  now,
-            })
-            .returning(queues::all_columns)
-            .get_result(&mut self.conn);
+        let now = self.clock.now();
+        insert_queue_row(&mut self.conn, queue, now)
+    }
+
+    fn insert_queue_with_dlq(
+        &mut self,
+        queue: &QueueInput<'_>,
+        dead_letter_queue: &QueueInput<'_>,
+    ) -> QueryResult<Option<(Queue, Queue)>> {
+        let now = self.clock.now();
+        let result = self.conn.transaction(|conn| {
+            // roll back the dead letter queue if the queue itself can not be created
+            let dead_letter_queue =
+                insert_queue_row(conn, dead_letter_queue, now)?.ok_or(Error::RollbackTransaction)?;
+            let queue = insert_queue_row(conn, queue, now)?.ok_or(Error::RollbackTransaction)?;
+
+            Ok((queue, dead_letter_queue))
+        });
         match result {
-            Ok(queue) => Ok(Some(queue)),
-            Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Ok(None),
+            Ok(queues) => Ok(Some(queues)),
+            Err(Error::RollbackTransaction) => Ok(None),
             Err(err) => Err(err),
         }
     }
@@ -221,7 +434,7 @@ impl QueueRepository for PgRepository {
                     .filter(messages::queue.eq(&queue.name))
                     .count()
                     .get_result(&mut self.conn)?;
-                let now = UtcTime::now();
+                let now = self.clock.now();
                 let visible_messages = messages::table
                     .filter(messages::queue.eq(&queue.name).and(messages::visible_since.le(now)))
                     .count()
@@ -235,6 +448,15 @@ impl QueueRepository for PgRepository {
                     .skip_locked()
                     .get_result(&mut self.conn)
                     .optional()?;
+                let dead_letter_queue_depth = match &queue.dead_letter_queue {
+                    None => None,
+                    Some(dead_letter_queue) => Some(
+                        messages::table
+                            .filter(messages::queue.eq(dead_letter_queue))
+                            .count()
+                            .get_result(&mut self.conn)?,
+                    ),
+                };
 
                 Ok(Some(QueueDescription {
                     queue,
@@ -242,13 +464,68 @@ impl QueueRepository for PgRepository {
                     visible_messages,
                     oldest_message_age: oldest_message
                         .map_or(0, |created_at| now.since(&created_at).map_or(0, |d| d.as_secs())),
+                    dead_letter_queue_depth,
                 }))
             },
         }
     }
 
+    fn describe_queues(&mut self, queues: Vec<Queue>) -> QueryResult<Vec<QueueDescription>> {
+        let mut names: Vec<&str> = queues.iter().map(|queue| queue.name.as_str()).collect();
+        names.extend(queues.iter().filter_map(|queue| queue.dead_letter_queue.as_deref()));
+        names.sort_unstable();
+        names.dedup();
+        let now = self.clock.now();
+        let counts: Vec<QueueMessageCounts> = diesel::sql_query(
+            "SELECT queue, COUNT(*) AS messages, COUNT(*) FILTER (WHERE visible_since <= $1) AS visible_messages, \
+             MIN(created_at) AS oldest_message FROM messages WHERE queue = ANY($2) GROUP BY queue",
+        )
+        .bind::<Timestamp, _>(now)
+        .bind::<Array<Varchar>, _>(names)
+        .load(&mut self.conn)?;
+        let counts: BTreeMap<String, QueueMessageCounts> =
+            counts.into_iter().map(|count| (count.queue.clone(), count)).collect();
+
+        Ok(queues
+            .into_iter()
+            .map(|queue| {
+                let count = counts.get(&queue.name);
+                let dead_letter_queue_depth = queue
+                    .dead_letter_queue
+                    .as_ref()
+                    .map(|dead_letter_queue| counts.get(dead_letter_queue).map_or(0, |count| count.messages));
+
+                QueueDescription {
+                    messages: count.map_or(0, |count| count.messages),
+                    visible_messages: count.map_or(0, |count| count.visible_messages),
+                    oldest_message_age: count
+                        .and_then(|count| count.oldest_message)
+                        .map_or(0, |created_at| now.since(&created_at).map_or(0, |d| d.as_secs())),
+                    dead_letter_queue_depth,
+                    queue,
+                }
+            })
+            .collect())
+    }
+
+    fn sample_message_ages(&mut self, queue: &Queue, sample_size: i64) -> QueryResult<Vec<u64>> {
+        // message ids are random, so ordering by them selects a sample independent of the age
+        let created_at: Vec<UtcTime> = messages::table
+            .select(messages::created_at)
+            .filter(messages::queue.eq(&queue.name))
+            .order(messages::id.asc())
+            .limit(sample_size)
+            .get_results(&mut self.conn)?;
+        let now = self.clock.now();
+
+        Ok(created_at
+            .iter()
+            .map(|created_at| now.since(created_at).map_or(0, |d| d.as_secs()))
+            .collect())
+    }
+
     fn list_queues(&mut self, offset: Option<i64>, limit: Option<i64>) -> QueryResult<Vec<Queue>> {
-        let query = queues::table.order(queues::id.asc());
+        let query = queues::table.order(queues::name.asc());
 
         match offset {
             None => match limit {
@@ -262,8 +539,42 @@ impl QueueRepository for PgRepository {
         }
     }
 
-    fn update_queue(&mut self, queue: &QueueInput<'_>) -> QueryResult<Option<Queue>> {
-        diesel::dsl::update(queues::table.filter(queues::name.eq(queue.name)))
+    fn count_queues_with_prefix(&mut self, prefix: &str) -> QueryResult<i64> {
+        queues::table
+            .filter(queues::name.like(like_prefix(prefix)).escape('\\'))
+            .count()
+            .get_result(&mut self.conn)
+    }
+
+    fn list_queues_with_prefix(
+        &mut self,
+        prefix: &str,
+        offset: Option<i64>,
+        limit: Option<i64>,
+    ) -> QueryResult<Vec<Queue>> {
+        let mut query = queues::table
+            .filter(queues::name.like(like_prefix(prefix)).escape('\\'))
+            .order(queues::name.asc())
+            .into_boxed();
+        if let Some(offset) = offset {
+            query = query.offset(offset);
+        }
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+
+        query.get_results(&mut self.conn)
+    }
+
+    fn update_queue(&mut self, queue: &QueueInput<'_>, expected_version: Option<UtcTime>) -> QueryResult<QueueUpdate> {
+        let mut query = diesel::dsl::update(queues::table)
+            .filter(queues::name.eq(queue.name))
+            .into_boxed();
+        if let Some(expected_version) = expected_version {
+            query = query.filter(queues::updated_at.eq(expected_version));
+        }
+
+        let updated = query
             .set((
                 queues::max_receives.eq(queue.max_receives),
                 queues::dead_letter_queue.eq(queue.dead_letter_queue),
@@ -271,11 +582,27 @@ impl QueueRepository for PgRepository {
                 queues::visibility_timeout.eq(pg_interval(queue.visibility_timeout)),
                 queues::message_delay.eq(pg_interval(queue.message_delay)),
                 queues::content_based_deduplication.eq(queue.content_based_deduplication),
-                queues::updated_at.eq(UtcTime::now()),
+                queues::allowed_content_types.eq(queue.allowed_content_types),
+                queues::tags.eq(tags_to_json(queue.tags)),
+                queues::default_content_type.eq(queue.default_content_type),
+                queues::max_in_flight.eq(queue.max_in_flight),
+                queues::max_redrives.eq(queue.max_redrives),
+                queues::visibility_jitter.eq(queue.visibility_jitter),
+                queues::message_priorities.eq(queue.message_priorities),
+                queues::updated_at.eq(self.clock.now()),
             ))
             .returning(queues::all_columns)
             .get_result(&mut self.conn)
-            .optional()
+            .optional()?;
+
+        match updated {
+            Some(queue) => Ok(QueueUpdate::Updated(Box::new(queue))),
+            // the update did not match any row, so either the queue does not exist or it has a different version
+            None if expected_version.is_some() && self.find_by_name(queue.name)?.is_some() => {
+                Ok(QueueUpdate::VersionMismatch)
+            },
+            None => Ok(QueueUpdate::NotFound),
+        }
     }
 
     fn delete_queue_by_name(&mut self, name: &str) -> QueryResult<Option<Queue>> {
@@ -284,12 +611,65 @@ impl QueueRepository for PgRepository {
             .get_result(&mut self.conn)
             .optional()
     }
+
+    fn rename_queue(&mut self, old_name: &str, new_name: &str) -> QueryResult<QueueRename> {
+        let now = self.clock.now();
+        let result = self.conn.transaction::<_, Error, _>(|conn| {
+            // the queue of each message and the dead letter queue of other queues reference the
+            // name of the queue with ON UPDATE CASCADE, so they follow the new name
+            let renamed: Option<Queue> = diesel::dsl::update(queues::table.filter(queues::name.eq(old_name)))
+                .set((queues::name.eq(new_name), queues::updated_at.eq(now)))
+                .returning(queues::all_columns)
+                .get_result(conn)
+                .optional()?;
+            if renamed.is_some() {
+                diesel::dsl::update(messages::table.filter(messages::origin_queue.eq(old_name)))
+                    .set(messages::origin_queue.eq(new_name))
+                    .execute(conn)?;
+            }
+
+            Ok(renamed)
+        });
+        match result {
+            Ok(Some(queue)) => {
+                clear_queue_cache();
+                Ok(QueueRename::Renamed(Box::new(queue)))
+            },
+            Ok(None) => Ok(QueueRename::NotFound),
+            Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Ok(QueueRename::AlreadyExists),
+            Err(err) => Err(err),
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn like_prefix_test() {
+        assert_eq!(like_prefix(""), "%");
+        assert_eq!(like_prefix("orders-"), "orders-%");
+        assert_eq!(like_prefix("orders_1"), "orders\\_1%");
+        assert_eq!(like_prefix("100%\\"), "100\\%\\\\%");
+    }
+
+    #[test]
+    fn tags_json_test() {
+        let mut tags = BTreeMap::new();
+        assert_eq!(tags_to_json(&tags), Value::Object(serde_json::Map::new()));
+        tags.insert("team".to_string(), "billing".to_string());
+        tags.insert("cost-center".to_string(), "42".to_string());
+        let json = tags_to_json(&tags);
+        assert_eq!(json.to_string(), "{\"cost-center\":\"42\",\"team\":\"billing\"}");
+        assert_eq!(tags_from_json(json), tags);
+        assert_eq!(
+            tags_from_json(serde_json::json!({"team": "billing", "size": 3})),
+            vec![("team".to_string(), "billing".to_string())].into_iter().collect()
+        );
+        assert!(tags_from_json(Value::Null).is_empty());
+    }
+
     #[test]
     fn cache_test() {
         let initial_hits = CACHE_HITS.load(Ordering::Relaxed);
@@ -321,6 +701,13 @@ mod test {
                 content_based_deduplication: false,
                 created_at:                  UtcTime::now(),
                 updated_at:                  UtcTime::now(),
+                allowed_content_types:       Vec::new(),
+                tags:                        Value::Object(serde_json::Map::new()),
+                default_content_type:        None,
+                max_in_flight:               None,
+                max_redrives:                None,
+                visibility_jitter:           None,
+                message_priorities:          false,
             }))
         }
     }
@@ -0,0 +1,137 @@
+use base64::{
+    alphabet::URL_SAFE,
+    engine::fast_portable::{FastPortable, NO_PAD},
+};
+use cached::once_cell::sync::OnceCell;
+use hmac::{Hmac, Mac};
+use mqs_common::UtcTime;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::models::message::Message;
+
+/// Receipt handles only contain characters which can be used in a path segment without escaping.
+const ENCODING: FastPortable = FastPortable::from(&URL_SAFE, NO_PAD);
+
+/// Length of a SHA-256 digest and thus of the signature of a receipt handle.
+const SIGNATURE_LENGTH: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+static RECEIPT_KEY: OnceCell<Vec<u8>> = OnceCell::new();
+
+/// Set the key receipt handles are signed with. All servers sharing a database need to use the same
+/// key, otherwise a handle issued by one server is rejected by the others. If no key is set, a
+/// random key is generated when the first handle is issued (and a warning is logged) and handles
+/// become invalid once the server restarts.
+///
+/// Returns false if a key was already set or generated.
+pub fn set_receipt_key(key: &[u8]) -> bool {
+    RECEIPT_KEY.set(key.to_vec()).is_ok()
+}
+
+fn receipt_key() -> &'static [u8] {
+    RECEIPT_KEY.get_or_init(|| {
+        warn!("No receipt handle key set, signing receipt handles with a random key which changes on every restart");
+        let mut key = Uuid::new_v4().as_bytes().to_vec();
+        key.extend_from_slice(Uuid::new_v4().as_bytes());
+        key
+    })
+}
+
+fn mac(key: &[u8], data: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac
+}
+
+/// Compute the HMAC-SHA256 of `data`.
+fn sign(key: &[u8], data: &[u8]) -> Vec<u8> {
+    mac(key, data).finalize().into_bytes().to_vec()
+}
+
+/// Identifies a single receive of a message. A handle allows deleting the message or changing its
+/// visibility only until the message is received again, so a consumer whose visibility timeout
+/// expired can't delete a message another consumer is working on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReceiptHandle {
+    pub id:          Uuid,
+    pub received_at: UtcTime,
+}
+
+impl ReceiptHandle {
+    /// Get the handle for the most recent receive of a message. Returns `None` if the message was
+    /// never received.
+    pub fn for_message(message: &Message) -> Option<Self> {
+        message.last_received_at.map(|received_at| Self {
+            id: message.id,
+            received_at,
+        })
+    }
+
+    fn payload(&self) -> String {
+        format!("{}|{}", self.id, self.received_at.to_rfc3339_nanos())
+    }
+
+    /// Encode and sign the handle.
+    pub fn encode(&self) -> String {
+        let mut data = self.payload().into_bytes();
+        let signature = sign(receipt_key(), &data);
+        data.extend_from_slice(&signature);
+        base64::encode_engine(data, &ENCODING)
+    }
+
+    /// Decode a handle returned by `encode`. Returns `None` if the handle is malformed or was not
+    /// signed with the current key.
+    pub fn decode(handle: &str) -> Option<Self> {
+        let data = base64::decode_engine(handle, &ENCODING).ok()?;
+        if data.len() < SIGNATURE_LENGTH {
+            return None;
+        }
+        let (payload, signature) = data.split_at(data.len() - SIGNATURE_LENGTH);
+        // compares the signatures in constant time
+        mac(receipt_key(), payload).verify_slice(signature).ok()?;
+        let payload = std::str::from_utf8(payload).ok()?;
+        let (id, received_at) = payload.split_once('|')?;
+
+        Some(Self {
+            id:          Uuid::parse_str(id).ok()?,
+            received_at: UtcTime::parse_from_rfc3339(received_at).ok()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hmac_sha256() {
+        // test case 2 of RFC 4231
+        let signature = sign(b"Jefe", b"what do ya want for nothing?");
+        let hex: String = signature.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    fn encode_decode() {
+        let handle = ReceiptHandle {
+            id:          Uuid::new_v4(),
+            received_at: UtcTime::now(),
+        };
+        let encoded = handle.encode();
+        assert!(encoded
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(ReceiptHandle::decode(&encoded), Some(handle));
+
+        // changing any part of the handle invalidates its signature
+        let mut data = base64::decode_engine(&encoded, &ENCODING).unwrap();
+        data[0] ^= 1;
+        assert_eq!(ReceiptHandle::decode(&base64::encode_engine(data, &ENCODING)), None);
+        assert_eq!(ReceiptHandle::decode(&encoded[..encoded.len() - 2]), None);
+        assert_eq!(ReceiptHandle::decode(""), None);
+        assert_eq!(ReceiptHandle::decode("not a handle"), None);
+        assert_eq!(ReceiptHandle::decode(&handle.id.to_string()), None);
+    }
+}
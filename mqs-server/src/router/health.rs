@@ -1,18 +1,67 @@
 use async_trait::async_trait;
-use hyper::{Body, Request, Response};
-use mqs_common::router;
+use hyper::{
+    header::{HeaderName, HeaderValue},
+    Body,
+    Request,
+    Response,
+};
+use mqs_common::{
+    connection::{Source, SourceState},
+    router,
+};
 
 use crate::models::health::HealthCheckRepository;
 
 pub struct Handler;
 
 #[async_trait]
-impl<R: HealthCheckRepository, S: Send> router::Handler<(R, S)> for Handler {
-    async fn handle(&self, (mut repo, _): (R, S), _req: Request<Body>, _body: Vec<u8>) -> Response<Body>
+impl<R: HealthCheckRepository, S: Source<R>> router::Handler<(R, S)> for Handler {
+    async fn handle(&self, (mut repo, source): (R, S), _req: Request<Body>, _body: Vec<u8>) -> Response<Body>
     where
         R: 'async_trait,
         S: 'async_trait,
     {
-        Response::new(Body::from(if repo.check_health() { "green" } else { "red" }))
+        // the state includes the connection we are holding right now, so it can't tell whether the
+        // pool is exhausted. If it was, we would not have gotten a connection and the
+        // `UnavailableHandler` would answer instead.
+        let healthy = repo.check_health();
+        health_response(healthy, source.state())
     }
 }
+
+/// Answers health checks while no connection could be taken from the pool. The server is reported
+/// as unhealthy without waiting for a connection.
+pub struct UnavailableHandler;
+
+#[async_trait]
+impl router::Handler<((), Option<SourceState>)> for UnavailableHandler {
+    async fn handle(
+        &self,
+        ((), state): ((), Option<SourceState>),
+        _req: Request<Body>,
+        _body: Vec<u8>,
+    ) -> Response<Body> {
+        if matches!(state, Some(state) if state.is_exhausted()) {
+            warn!("Connection pool is exhausted, reporting unhealthy status");
+        } else {
+            warn!("No database connection available, reporting unhealthy status");
+        }
+        health_response(false, state)
+    }
+}
+
+fn health_response(healthy: bool, state: Option<SourceState>) -> Response<Body> {
+    let mut response = Response::new(Body::from(if healthy { "green" } else { "red" }));
+    if let Some(state) = state {
+        for (name, value) in &[
+            ("x-mqs-pool-connections", state.connections),
+            ("x-mqs-pool-idle-connections", state.idle_connections),
+            ("x-mqs-pool-max-connections", state.max_connections),
+        ] {
+            if let Ok(value) = HeaderValue::from_str(&value.to_string()) {
+                response.headers_mut().insert(HeaderName::from_static(name), value);
+            }
+        }
+    }
+    response
+}
@@ -0,0 +1,29 @@
+use async_trait::async_trait;
+use hyper::{Body, Request, Response};
+use mqs_common::{router, Clock, ServerInfo, UtcTime};
+use std::sync::Arc;
+
+use crate::routes::MqsResponse;
+
+/// Reports the version of the server and since when it is running. It doesn't need a database
+/// connection, so it is served even if the connection pool is exhausted.
+pub struct Handler {
+    pub(crate) clock:      Arc<dyn Clock>,
+    pub(crate) started_at: UtcTime,
+}
+
+#[async_trait]
+impl<A: Send> router::Handler<A> for Handler {
+    async fn handle(&self, _args: A, _req: Request<Body>, _body: Vec<u8>) -> Response<Body>
+    where
+        A: 'async_trait,
+    {
+        let uptime = self.clock.now().since(&self.started_at).unwrap_or_default();
+        MqsResponse::json(&ServerInfo {
+            version:    env!("CARGO_PKG_VERSION").to_string(),
+            started_at: self.started_at.to_rfc3339(),
+            uptime:     uptime.as_secs(),
+        })
+        .into_response()
+    }
+}
@@ -1,27 +1,90 @@
 use async_trait::async_trait;
-use hyper::{header::HeaderName, Body, Request, Response};
-use mqs_common::{connection::Source, get_header, router::Handler};
+use hyper::{
+    header::{HeaderName, HeaderValue, ACCEPT},
+    Body,
+    HeaderMap,
+    Request,
+    Response,
+};
+use mqs_common::{
+    connection::Source,
+    get_header,
+    router::{Handler, HandlerError, TryHandler},
+    ContentHashHeader,
+    MessageIdHeader,
+    TraceIdHeader,
+    UtcTime,
+};
 
 use crate::{
-    models::{message::MessageRepository, queue::QueueRepository},
-    routes::messages::{delete, publish, receive, MaxWaitTime, MessageCount},
+    models::{
+        message::{MessageFilter, MessageRepository},
+        queue::QueueRepository,
+    },
+    routes::messages::{
+        change_visibility,
+        delete,
+        delete_by_hash,
+        list_in_flight,
+        move_message,
+        publish,
+        receive,
+        redrive,
+        release,
+        MaxWaitTime,
+        MessageCount,
+        ReceiveMode,
+    },
 };
 
 pub struct ReceiveMessagesHandler {
-    pub queue_name: String,
+    pub queue_name:        String,
+    pub max_receive_batch: i64,
 }
 
 pub struct PublishMessagesHandler {
-    pub queue_name: String,
+    pub queue_name:       String,
+    pub max_message_size: usize,
 }
 
 pub struct DeleteMessageHandler {
+    pub message_id:        String,
+    pub allow_message_ids: bool,
+}
+
+pub struct ReleaseMessageHandler {
+    pub message_id:        String,
+    pub allow_message_ids: bool,
+}
+
+pub struct MoveMessageHandler {
     pub message_id: String,
 }
 
+pub struct RedriveMessagesHandler {
+    pub queue_name: String,
+}
+
+pub struct ChangeMessagesVisibilityHandler {
+    pub allow_message_ids: bool,
+}
+
+pub struct DeleteMessagesByHashHandler {
+    pub queue_name: String,
+}
+
+pub struct ListInFlightHandler {
+    pub queue_name: String,
+}
+
 #[async_trait]
-impl<R: MessageRepository + QueueRepository, S: Source<R>> Handler<(R, S)> for ReceiveMessagesHandler {
-    async fn handle(&self, (repo, repo_source): (R, S), req: Request<Body>, _body: Vec<u8>) -> Response<Body>
+impl<R: MessageRepository + QueueRepository, S: Source<R>> TryHandler<(R, S)> for ReceiveMessagesHandler {
+    async fn try_handle(
+        &self,
+        (repo, repo_source): (R, S),
+        req: Request<Body>,
+        _body: Vec<u8>,
+    ) -> Result<Response<Body>, HandlerError>
     where
         R: 'async_trait,
         S: 'async_trait,
@@ -29,15 +92,18 @@ impl<R: MessageRepository + QueueRepository, S: Source<R>> Handler<(R, S)> for R
         let message_count = {
             let header_value = get_header(req.headers(), HeaderName::from_static("x-mqs-max-messages"));
             header_value.map_or(Ok(MessageCount(1)), |max_messages| {
-                max_messages.parse().map_or(Err(()), |n| {
-                    if n > 0 && n < 1000 {
-                        Ok(MessageCount(n))
+                max_messages.parse().map_or(Err(()), |n: i64| {
+                    if n > 0 {
+                        // clamp the requested count to the configured maximum, the client can see
+                        // the effective count in the response headers
+                        Ok(MessageCount(n.min(self.max_receive_batch)))
                     } else {
                         Err(())
                     }
                 })
             })
-        };
+        }
+        .map_err(|()| HandlerError::BadRequest("Failed to parse message count".to_string()))?;
         let max_wait_time = {
             let header_value = get_header(req.headers(), HeaderName::from_static("x-mqs-max-wait-time"));
             header_value.map_or(Ok(None), |max_wait_time| {
@@ -49,13 +115,88 @@ impl<R: MessageRepository + QueueRepository, S: Source<R>> Handler<(R, S)> for R
                     }
                 })
             })
+        }
+        .map_err(|()| HandlerError::BadRequest("Failed to parse maximal wait time".to_string()))?;
+        let since = get_header(req.headers(), HeaderName::from_static("x-mqs-since"))
+            .map(UtcTime::parse_from_rfc3339)
+            .transpose()
+            .map_err(|_| HandlerError::BadRequest("Failed to parse since timestamp".to_string()))?;
+        let filter = MessageFilter {
+            content_type: get_header(req.headers(), HeaderName::from_static("x-mqs-filter-content-type")),
+            since,
         };
-        receive(repo, repo_source, &self.queue_name, message_count, max_wait_time)
-            .await
-            .into_response()
+        // with auto delete, messages are deleted as they are returned. They are thus received at most
+        // once instead of at least once
+        let auto_delete = get_header(req.headers(), HeaderName::from_static("x-mqs-auto-delete"))
+            .map_or(Ok(false), |auto_delete| auto_delete.parse().map_err(|_| ()));
+        // with headers only, messages are not received at all and only their metadata is returned
+        let headers_only = get_header(req.headers(), HeaderName::from_static("x-mqs-headers-only"))
+            .map_or(Ok(false), |headers_only| headers_only.parse().map_err(|_| ()));
+        let mode = match (auto_delete, headers_only) {
+            (Err(_), _) => Err("Failed to parse auto delete flag"),
+            (_, Err(_)) => Err("Failed to parse headers only flag"),
+            (Ok(true), Ok(true)) => Err("Auto delete and headers only can not be combined"),
+            (Ok(true), Ok(false)) => Ok(ReceiveMode::AutoDelete),
+            (Ok(false), Ok(true)) => Ok(ReceiveMode::HeadersOnly),
+            (Ok(false), Ok(false)) => Ok(ReceiveMode::Receive),
+        }
+        .map_err(|err| HandlerError::BadRequest(err.to_string()))?;
+        let mut response = receive(
+            repo,
+            repo_source,
+            &self.queue_name,
+            message_count,
+            max_wait_time,
+            filter,
+            mode,
+        )
+        .await;
+        // clients asking for multipart get it even for a single message, so they only have to
+        // parse one kind of response
+        if accepts_multipart(req.headers()) {
+            response = response.force_multipart();
+        }
+        let mut response = response.into_response();
+        if let Ok(value) = HeaderValue::from_str(&message_count.0.to_string()) {
+            response
+                .headers_mut()
+                .insert(HeaderName::from_static("x-mqs-max-messages"), value);
+        }
+        // echo the trace id of the request unless the response is a single received message, its
+        // headers describe the message and a message without a trace id must not get one from us
+        if let Some(trace_id) = req.headers().get(TraceIdHeader::name()) {
+            if !response.headers().contains_key(MessageIdHeader::name()) {
+                response.headers_mut().insert(TraceIdHeader::name(), trace_id.clone());
+            }
+        }
+
+        Ok(response)
     }
 }
 
+/// Check whether the client explicitly accepts a `multipart/mixed` response. Wildcards do not
+/// count, a client accepting anything also accepts a single message.
+fn accepts_multipart(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_range| {
+            let mut parts = media_range.split(';');
+            let name = parts.next().unwrap_or_default().trim();
+            if !name.eq_ignore_ascii_case("multipart/mixed") {
+                return false;
+            }
+
+            // a media range with a quality of zero is not acceptable
+            parts.all(|param| match param.trim().strip_prefix("q=") {
+                Some(q) => !matches!(q.trim().parse::<f32>(), Ok(q) if q <= 0.0),
+                None => true,
+            })
+        })
+}
+
 #[async_trait]
 impl<R: MessageRepository + QueueRepository, S: Send> Handler<(R, S)> for PublishMessagesHandler {
     fn needs_body(&self) -> bool {
@@ -68,9 +209,15 @@ impl<R: MessageRepository + QueueRepository, S: Send> Handler<(R, S)> for Publis
         S: 'async_trait,
     {
         let (parts, _) = req.into_parts();
-        publish(repo, &self.queue_name, body.as_slice(), parts.headers)
-            .await
-            .into_response()
+        publish(
+            repo,
+            &self.queue_name,
+            body.as_slice(),
+            parts.headers,
+            self.max_message_size,
+        )
+        .await
+        .into_response()
     }
 }
 
@@ -81,6 +228,87 @@ impl<R: MessageRepository, S: Send> Handler<(R, S)> for DeleteMessageHandler {
         R: 'async_trait,
         S: 'async_trait,
     {
-        delete(&mut repo, &self.message_id).into_response()
+        delete(&mut repo, &self.message_id, self.allow_message_ids).into_response()
+    }
+}
+
+#[async_trait]
+impl<R: MessageRepository, S: Send> Handler<(R, S)> for ReleaseMessageHandler {
+    async fn handle(&self, (mut repo, _): (R, S), _req: Request<Body>, _body: Vec<u8>) -> Response<Body>
+    where
+        R: 'async_trait,
+        S: 'async_trait,
+    {
+        release(&mut repo, &self.message_id, self.allow_message_ids).into_response()
+    }
+}
+
+#[async_trait]
+impl<R: MessageRepository + QueueRepository, S: Send> Handler<(R, S)> for MoveMessageHandler {
+    fn needs_body(&self) -> bool {
+        true
+    }
+
+    async fn handle(&self, (mut repo, _): (R, S), _req: Request<Body>, body: Vec<u8>) -> Response<Body>
+    where
+        R: 'async_trait,
+        S: 'async_trait,
+    {
+        let params = serde_json::from_slice(body.as_slice());
+        move_message(&mut repo, &self.message_id, params).into_response()
+    }
+}
+
+#[async_trait]
+impl<R: MessageRepository + QueueRepository, S: Send> Handler<(R, S)> for RedriveMessagesHandler {
+    fn needs_body(&self) -> bool {
+        true
+    }
+
+    async fn handle(&self, (mut repo, _): (R, S), _req: Request<Body>, body: Vec<u8>) -> Response<Body>
+    where
+        R: 'async_trait,
+        S: 'async_trait,
+    {
+        let params = serde_json::from_slice(body.as_slice());
+        redrive(&mut repo, &self.queue_name, params).into_response()
+    }
+}
+
+#[async_trait]
+impl<R: MessageRepository, S: Send> Handler<(R, S)> for ChangeMessagesVisibilityHandler {
+    fn needs_body(&self) -> bool {
+        true
+    }
+
+    async fn handle(&self, (mut repo, _): (R, S), _req: Request<Body>, body: Vec<u8>) -> Response<Body>
+    where
+        R: 'async_trait,
+        S: 'async_trait,
+    {
+        let params = serde_json::from_slice(body.as_slice());
+        change_visibility(&mut repo, params, self.allow_message_ids).into_response()
+    }
+}
+
+#[async_trait]
+impl<R: MessageRepository + QueueRepository, S: Send> Handler<(R, S)> for DeleteMessagesByHashHandler {
+    async fn handle(&self, (mut repo, _): (R, S), req: Request<Body>, _body: Vec<u8>) -> Response<Body>
+    where
+        R: 'async_trait,
+        S: 'async_trait,
+    {
+        delete_by_hash(&mut repo, &self.queue_name, ContentHashHeader::get(req.headers())).into_response()
+    }
+}
+
+#[async_trait]
+impl<R: MessageRepository + QueueRepository, S: Send> Handler<(R, S)> for ListInFlightHandler {
+    async fn handle(&self, (mut repo, _): (R, S), _req: Request<Body>, _body: Vec<u8>) -> Response<Body>
+    where
+        R: 'async_trait,
+        S: 'async_trait,
+    {
+        list_in_flight(&mut repo, &self.queue_name).into_response()
     }
 }
@@ -1,68 +1,232 @@
 use hyper::Method;
 use mqs_common::{
-    connection::Source,
-    router::{Router, WildcardRouter},
+    connection::{Source, SourceState},
+    router::{ErrorFormat, MetricsHandler, Router, WildcardRouter},
+    Clock,
+    SystemClock,
+    UtcTime,
 };
+use std::sync::Arc;
 
 use crate::{
     models::{health::HealthCheckRepository, message::MessageRepository, queue::QueueRepository},
     router::{
-        messages::{DeleteMessageHandler, PublishMessagesHandler, ReceiveMessagesHandler},
-        queues::{CreateQueueHandler, DeleteQueueHandler, DescribeQueueHandler, ListQueuesHandler, UpdateQueueHandler},
+        messages::{
+            ChangeMessagesVisibilityHandler,
+            DeleteMessageHandler,
+            DeleteMessagesByHashHandler,
+            ListInFlightHandler,
+            MoveMessageHandler,
+            PublishMessagesHandler,
+            ReceiveMessagesHandler,
+            RedriveMessagesHandler,
+            ReleaseMessageHandler,
+        },
+        queues::{
+            CreateQueueHandler,
+            CreateQueueWithDlqHandler,
+            DeleteQueueHandler,
+            DescribeQueueDeletionHandler,
+            DescribeQueueHandler,
+            ListQueuesHandler,
+            RenameQueueHandler,
+            UpdateQueueHandler,
+        },
     },
 };
 
 pub mod health;
+pub mod info;
 mod messages;
 mod queues;
 
-struct QueuesSubRouter;
+struct QueuesSubRouter {
+    max_queues: Option<i64>,
+}
 
 impl<R: QueueRepository, S: Source<R>> WildcardRouter<(R, S)> for QueuesSubRouter {
     fn with_segment(&self, segment: &str) -> Router<(R, S)> {
         Router::default()
-            .with_handler(Method::GET, DescribeQueueHandler {
+            .with_try_handler(Method::GET, DescribeQueueHandler {
                 queue_name: segment.to_string(),
             })
             .with_handler(Method::PUT, CreateQueueHandler {
                 queue_name: segment.to_string(),
+                max_queues: self.max_queues,
+            })
+            .with_try_handler(Method::POST, UpdateQueueHandler {
+                queue_name: segment.to_string(),
+            })
+            .with_try_handler(Method::DELETE, DeleteQueueHandler {
+                queue_name: segment.to_string(),
+            })
+            .with_route_simple("deletion", Method::GET, DescribeQueueDeletionHandler {
+                queue_name: segment.to_string(),
             })
-            .with_handler(Method::POST, UpdateQueueHandler {
+            .with_route_simple("with-dlq", Method::PUT, CreateQueueWithDlqHandler {
                 queue_name: segment.to_string(),
+                max_queues: self.max_queues,
             })
-            .with_handler(Method::DELETE, DeleteQueueHandler {
+            .with_route_simple("rename", Method::POST, RenameQueueHandler {
                 queue_name: segment.to_string(),
             })
     }
 }
 
-struct MessagesSubRouter;
+struct MessagesSubRouter {
+    max_receive_batch: i64,
+    max_message_size:  usize,
+    allow_message_ids: bool,
+}
 
 impl<R: QueueRepository + MessageRepository, S: Source<R>> WildcardRouter<(R, S)> for MessagesSubRouter {
     fn with_segment(&self, segment: &str) -> Router<(R, S)> {
         Router::default()
-            .with_handler(Method::GET, ReceiveMessagesHandler {
-                queue_name: segment.to_string(),
+            .with_try_handler(Method::GET, ReceiveMessagesHandler {
+                queue_name:        segment.to_string(),
+                max_receive_batch: self.max_receive_batch,
             })
             .with_handler(Method::POST, PublishMessagesHandler {
-                queue_name: segment.to_string(),
+                queue_name:       segment.to_string(),
+                max_message_size: self.max_message_size,
             })
             .with_handler(Method::DELETE, DeleteMessageHandler {
+                message_id:        segment.to_string(),
+                allow_message_ids: self.allow_message_ids,
+            })
+            .with_route_simple("release", Method::POST, ReleaseMessageHandler {
+                message_id:        segment.to_string(),
+                allow_message_ids: self.allow_message_ids,
+            })
+            .with_route_simple("move", Method::POST, MoveMessageHandler {
                 message_id: segment.to_string(),
             })
+            .with_route_simple("hash", Method::DELETE, DeleteMessagesByHashHandler {
+                queue_name: segment.to_string(),
+            })
+            .with_route_simple("in-flight", Method::GET, ListInFlightHandler {
+                queue_name: segment.to_string(),
+            })
+            .with_route_simple("redrive", Method::POST, RedriveMessagesHandler {
+                queue_name: segment.to_string(),
+            })
+    }
+}
+
+/// Configuration of the router and the handlers it creates.
+#[derive(Clone)]
+pub struct RouterConfig {
+    /// Maximum number of messages returned by a single receive request. Clients asking for more
+    /// messages get at most this many messages.
+    pub max_receive_batch: u16,
+    /// Answer OPTIONS requests with the methods supported by the requested resource.
+    pub auto_options:      bool,
+    /// Maximum number of queues which can exist at the same time. Creating more queues is rejected
+    /// with 429 Too Many Requests. `None` allows an unlimited number of queues.
+    pub max_queues:        Option<u32>,
+    /// Maximum size of a message in bytes. Gzip compressed request bodies are rejected with 413
+    /// Payload Too Large if they inflate to more than this many bytes.
+    pub max_message_size:  usize,
+    /// Accept message ids instead of receipt handles when deleting, releasing, or changing the
+    /// visibility of messages. Enabled by default for existing clients. Disable it to only accept
+    /// receipt handles, so a consumer can't act on a message it no longer owns.
+    pub allow_message_ids: bool,
+    /// Format of the bodies of error responses, JSON by default.
+    pub error_format:      ErrorFormat,
+    /// Clock of handlers which don't read the current time from a repository, like `/info`.
+    pub clock:             Arc<dyn Clock>,
+    /// Time the server was started at according to `clock`, reported by `/info`.
+    pub started_at:        UtcTime,
+}
+
+impl RouterConfig {
+    /// Default value for `max_message_size`, one megabyte.
+    pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+    /// Default value for `max_receive_batch`, the largest number of messages a client could request
+    /// before the limit was configurable.
+    pub const DEFAULT_MAX_RECEIVE_BATCH: u16 = 999;
+
+    /// Read the current time from the given clock instead of the clock of the system. The server
+    /// counts as started at the current time of the clock.
+    #[must_use]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.started_at = clock.now();
+        self.clock = clock;
+        self
+    }
+
+    fn info_handler(&self) -> info::Handler {
+        info::Handler {
+            clock:      Arc::clone(&self.clock),
+            started_at: self.started_at,
+        }
+    }
+}
+
+impl Default for RouterConfig {
+    fn default() -> Self {
+        Self {
+            max_receive_batch: Self::DEFAULT_MAX_RECEIVE_BATCH,
+            auto_options:      false,
+            max_queues:        None,
+            max_message_size:  Self::DEFAULT_MAX_MESSAGE_SIZE,
+            allow_message_ids: true,
+            error_format:      ErrorFormat::Json,
+            clock:             Arc::new(SystemClock),
+            started_at:        UtcTime::now(),
+        }
     }
 }
 
 /// Create a new instance of the router.
 #[must_use]
-pub fn make<R: QueueRepository + MessageRepository + HealthCheckRepository, S: Source<R>>() -> Router<(R, S)> {
-    Router::default()
+pub fn make<R: QueueRepository + MessageRepository + HealthCheckRepository, S: Source<R>>(
+    config: &RouterConfig,
+) -> Router<(R, S)> {
+    let router = Router::default()
+        .with_error_format(config.error_format)
         .with_route_simple("health", Method::GET, health::Handler)
+        .with_route_simple("info", Method::GET, config.info_handler())
+        .with_route_simple("metrics", Method::GET, MetricsHandler)
         .with_route(
             "queues",
-            Router::new_simple(Method::GET, ListQueuesHandler).with_wildcard(QueuesSubRouter),
+            Router::default()
+                .with_try_handler(Method::GET, ListQueuesHandler)
+                .with_wildcard(QueuesSubRouter {
+                    max_queues: config.max_queues.map(i64::from),
+                }),
         )
-        .with_route("messages", Router::default().with_wildcard(MessagesSubRouter))
+        .with_route(
+            "messages",
+            Router::new_simple(Method::PATCH, ChangeMessagesVisibilityHandler {
+                allow_message_ids: config.allow_message_ids,
+            })
+            .with_wildcard(MessagesSubRouter {
+                max_receive_batch: i64::from(config.max_receive_batch),
+                max_message_size:  config.max_message_size,
+                allow_message_ids: config.allow_message_ids,
+            }),
+        );
+
+    if config.auto_options {
+        router.with_auto_options()
+    } else {
+        router
+    }
+}
+
+/// Create the router for requests which should not wait for a database connection.
+///
+/// Health checks report the server as unhealthy, metrics are served from the metrics of the process
+/// and the server info is served just like by `make`. The state of the connection pool is passed
+/// instead of a repository.
+#[must_use]
+pub fn make_unavailable(config: &RouterConfig) -> Router<((), Option<SourceState>)> {
+    Router::default()
+        .with_error_format(config.error_format)
+        .with_route_simple("health", Method::GET, health::UnavailableHandler)
+        .with_route_simple("info", Method::GET, config.info_handler())
+        .with_route_simple("metrics", Method::GET, MetricsHandler)
 }
 
 #[cfg(test)]
@@ -71,18 +235,63 @@ mod test {
     use crate::{
         make_router,
         models::{
-            queue::QueueInput,
+            message::MessageFilter,
+            queue::{QueueInput, QueueSource},
             test::{TestRepo, TestRepoSource},
         },
     };
-    use hyper::{Body, Request, Response, StatusCode};
+    use flate2::{write::GzEncoder, Compression};
+    use hyper::{
+        header::{HeaderName, HeaderValue, ACCEPT, ALLOW, CONTENT_ENCODING, CONTENT_TYPE, IF_MATCH},
+        Body,
+        HeaderMap,
+        Request,
+        Response,
+        StatusCode,
+    };
     use mqs_common::{
-        router::Handler,
+        connection::SourceState,
+        multipart,
+        router::{handle, Handler},
         test::{make_runtime, read_body},
+        AgePercentiles,
+        ChangeVisibilityResponse,
+        ContentHashHeader,
+        DeduplicatedHeader,
+        DeleteByHashResponse,
+        ExpiresAtHeader,
+        FixedClock,
+        FixedRandom,
+        GroupIdHeader,
+        IdempotencyKeyHeader,
+        InFlightMessage,
+        InFlightMessagesResponse,
+        MessageAttributesHeader,
+        MessageContentEncodingHeader,
         MessageIdHeader,
+        OriginQueueHeader,
+        PriorityHeader,
+        PublishRejection,
+        PublishRejectionResponse,
+        PublishResponse,
+        PublishedAtHeader,
+        QueueConfig,
+        QueueConfigOutput,
+        QueueDescriptionOutput,
+        QueueStatus,
+        QueueWithDlqOutput,
+        QueuesResponse,
+        QueuesWithStatusResponse,
+        ReceiptHandleHeader,
+        RedriveReasonHeader,
+        RedriveResponse,
+        ServerInfo,
         Status,
+        TraceIdHeader,
+        UtcTime,
     };
-    use std::sync::Arc;
+    use std::{collections::BTreeMap, io::Write, sync::Arc, time::Duration};
+    use uuid::Uuid;
 
     fn run_handler<'a>(
         handler: Arc<dyn Handler<(TestRepo, &'a TestRepoSource)>>,
@@ -95,6 +304,15 @@ mod test {
         handler: Arc<dyn Handler<(TestRepo, &'a TestRepoSource)>>,
         source: &'a TestRepoSource,
         body: Vec<u8>,
+    ) -> Response<Body> {
+        run_handler_with_request(handler, source, Request::new(Body::default()), body)
+    }
+
+    fn run_handler_with_request<'a>(
+        handler: Arc<dyn Handler<(TestRepo, &'a TestRepoSource)>>,
+        source: &'a TestRepoSource,
+        req: Request<Body>,
+        body: Vec<u8>,
     ) -> Response<Body> {
         let rt = make_runtime();
         rt.block_on(async {
@@ -102,16 +320,42 @@ mod test {
                 .get()
                 .expect("The test repo source should always return a repository");
 
-            handler
-                .handle((repo, source), Request::new(Body::default()), body)
-                .await
+            handler.handle((repo, source), req, body).await
         })
     }
 
+    static NO_TAGS: BTreeMap<String, String> = BTreeMap::new();
+
+    /// Configuration of a queue without dead letter queue, limits or other optional features. Tests
+    /// override the fields they need with struct update syntax.
+    fn test_queue(name: &str) -> QueueInput<'_> {
+        QueueInput {
+            name,
+            max_receives: None,
+            dead_letter_queue: None,
+            retention_timeout: 100,
+            visibility_timeout: 10,
+            message_delay: 0,
+            content_based_deduplication: false,
+            allowed_content_types: &[],
+            default_content_type: None,
+            max_in_flight: None,
+            max_redrives: None,
+            visibility_jitter: None,
+            message_priorities: false,
+            tags: &NO_TAGS,
+        }
+    }
+
+    fn queue_version(body: &[u8]) -> String {
+        let output: QueueConfigOutput = serde_json::from_slice(body).unwrap();
+        output.version.clone().expect("queue output should contain a version")
+    }
+
     #[test]
     fn health_router() {
         let source = TestRepoSource::new();
-        let router = make_router::<TestRepo, &TestRepoSource>();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
         let handler = router.route(&Method::GET, vec!["health"].into_iter());
         assert!(handler.is_some());
         let handler = handler.expect("handler should have been found");
@@ -130,10 +374,233 @@ mod test {
         }
     }
 
+    #[test]
+    fn info_router() {
+        let clock = Arc::new(FixedClock::new(UtcTime::from_timestamp(1_590_000_000)));
+        let source = TestRepoSource::new().with_clock(clock.clone());
+        let config = RouterConfig::default().with_clock(clock.clone());
+        let router = make_router::<TestRepo, &TestRepoSource>(&config);
+        let handler = router.route(&Method::GET, vec!["info"].into_iter()).unwrap();
+        clock.advance(Duration::from_secs(90));
+        let mut response = run_handler(handler, &source);
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        let info: ServerInfo = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.started_at, "2020-05-20T18:40:00Z");
+        assert_eq!(info.uptime, 90);
+    }
+
+    #[test]
+    fn health_router_pool_state() {
+        let source = TestRepoSource::new().with_state(SourceState {
+            connections:      3,
+            idle_connections: 1,
+            max_connections:  5,
+        });
+        let full_source = TestRepoSource::new().with_state(SourceState {
+            connections:      5,
+            idle_connections: 0,
+            max_connections:  5,
+        });
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let handler = router.route(&Method::GET, vec!["health"].into_iter()).unwrap();
+        {
+            let mut response = run_handler(Arc::clone(&handler), &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            assert_eq!(
+                response
+                    .headers()
+                    .get(HeaderName::from_static("x-mqs-pool-connections")),
+                Some(&HeaderValue::from_static("3"))
+            );
+            assert_eq!(
+                response
+                    .headers()
+                    .get(HeaderName::from_static("x-mqs-pool-idle-connections")),
+                Some(&HeaderValue::from_static("1"))
+            );
+            assert_eq!(
+                response
+                    .headers()
+                    .get(HeaderName::from_static("x-mqs-pool-max-connections")),
+                Some(&HeaderValue::from_static("5"))
+            );
+            assert_eq!(read_body(response.body_mut()).as_slice(), b"green");
+        }
+        {
+            // the last connection is the one the handler is holding, so this is not an exhausted pool
+            let mut response = run_handler(handler, &full_source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            assert_eq!(read_body(response.body_mut()).as_slice(), b"green");
+        }
+    }
+
+    struct TestConnectionManager;
+
+    impl r2d2::ManageConnection for TestConnectionManager {
+        type Connection = ();
+        type Error = std::io::Error;
+
+        fn connect(&self) -> Result<Self::Connection, Self::Error> {
+            Ok(())
+        }
+
+        fn is_valid(&self, _conn: &mut Self::Connection) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _conn: &mut Self::Connection) -> bool {
+            false
+        }
+    }
+
+    struct PooledRepo {
+        _conn: r2d2::PooledConnection<TestConnectionManager>,
+    }
+
+    impl HealthCheckRepository for PooledRepo {
+        fn check_health(&mut self) -> bool {
+            true
+        }
+    }
+
+    struct PooledSource {
+        pool: r2d2::Pool<TestConnectionManager>,
+    }
+
+    impl Source<PooledRepo> for PooledSource {
+        fn get(&self) -> Option<PooledRepo> {
+            self.pool.try_get().map(|conn| PooledRepo { _conn: conn })
+        }
+
+        fn state(&self) -> Option<SourceState> {
+            Some(SourceState::from(&self.pool))
+        }
+    }
+
+    #[test]
+    fn health_router_single_connection_pool() {
+        let source = PooledSource {
+            pool: r2d2::Pool::builder().max_size(1).build(TestConnectionManager).unwrap(),
+        };
+        let repo = source.get().unwrap();
+        // the only connection is checked out, but by the health check itself
+        assert!(source.get().is_none());
+        let mut response =
+            make_runtime().block_on(health::Handler.handle((repo, &source), Request::new(Body::default()), Vec::new()));
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        assert_eq!(
+            response
+                .headers()
+                .get(HeaderName::from_static("x-mqs-pool-idle-connections")),
+            Some(&HeaderValue::from_static("0"))
+        );
+        assert_eq!(read_body(response.body_mut()).as_slice(), b"green");
+    }
+
+    #[test]
+    fn health_router_without_connection() {
+        let router = make_unavailable(&RouterConfig::default());
+        let state = SourceState {
+            connections:      5,
+            idle_connections: 0,
+            max_connections:  5,
+        };
+        let get = |path: &str| {
+            let req = Request::get(path).body(Body::default()).unwrap();
+            make_runtime().block_on(handle(
+                Some(()),
+                Some(state),
+                &router,
+                RouterConfig::DEFAULT_MAX_MESSAGE_SIZE,
+                req,
+            ))
+        };
+        let mut response = get("/health");
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        assert_eq!(
+            response
+                .headers()
+                .get(HeaderName::from_static("x-mqs-pool-idle-connections")),
+            Some(&HeaderValue::from_static("0"))
+        );
+        assert_eq!(read_body(response.body_mut()).as_slice(), b"red");
+        let mut response = get("/metrics");
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        assert!(!read_body(response.body_mut()).is_empty());
+        let mut response = get("/info");
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        let info: ServerInfo = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(get("/queues").status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn queue_tags_router() {
+        let source = TestRepoSource::new();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let create_handler = router
+            .route(&Method::PUT, vec!["queues", "my-queue"].into_iter())
+            .unwrap();
+        {
+            let mut response = run_handler_with(
+                create_handler,
+                &source,
+                b"{\"retention_timeout\": 600, \"visibility_timeout\": 30, \"message_delay\": 5, \"message_deduplication\": false, \"tags\": {\"team\": \"billing\"}}".to_vec(),
+            );
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+            let body = read_body(response.body_mut());
+            let version = queue_version(&body);
+            assert_eq!(
+                body,
+                format!("{{\"name\":\"my-queue\",\"redrive_policy\":null,\"retention_timeout\":600,\"visibility_timeout\":30,\"message_delay\":5,\"message_deduplication\":false,\"allowed_content_types\":[],\"tags\":{{\"team\":\"billing\"}},\"version\":\"{}\"}}", version).into_bytes(),
+            );
+        }
+        let mut tags = BTreeMap::new();
+        tags.insert("team".to_string(), "billing".to_string());
+        let get_handler = router
+            .route(&Method::GET, vec!["queues", "my-queue"].into_iter())
+            .unwrap();
+        {
+            let mut response = run_handler(get_handler.clone(), &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            let description: QueueDescriptionOutput = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+            assert_eq!(description.tags, tags);
+        }
+        let list_handler = router.route(&Method::GET, vec!["queues"].into_iter()).unwrap();
+        {
+            let mut response = run_handler(list_handler, &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            let response: QueuesResponse = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+            assert_eq!(response.queues[0].tags, tags);
+        }
+        let update_handler = router
+            .route(&Method::POST, vec!["queues", "my-queue"].into_iter())
+            .unwrap();
+        {
+            let mut response = run_handler_with(
+                update_handler,
+                &source,
+                b"{\"retention_timeout\": 600, \"visibility_timeout\": 30, \"message_delay\": 5, \"message_deduplication\": false, \"tags\": {\"team\": \"search\", \"cost-center\": \"42\"}}".to_vec(),
+            );
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            let output: QueueConfigOutput = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+            tags.insert("team".to_string(), "search".to_string());
+            tags.insert("cost-center".to_string(), "42".to_string());
+            assert_eq!(output.tags, tags);
+        }
+        {
+            let mut response = run_handler(get_handler, &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            let description: QueueDescriptionOutput = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+            assert_eq!(description.tags, tags);
+        }
+    }
+
     #[test]
     fn queues_router() {
         let source = TestRepoSource::new();
-        let router = make_router::<TestRepo, &TestRepoSource>();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
         let create_handler = router.route(&Method::PUT, vec!["queues", "my-queue"].into_iter());
         assert!(create_handler.is_some());
         let create_handler = create_handler.unwrap();
@@ -145,9 +612,10 @@ mod test {
             );
             assert_eq!(StatusCode::from(Status::Created), response.status());
             let body = read_body(response.body_mut());
+            let version = queue_version(&body);
             assert_eq!(
                 body,
-                b"{\"name\":\"my-queue\",\"redrive_policy\":null,\"retention_timeout\":600,\"visibility_timeout\":30,\"message_delay\":5,\"message_deduplication\":false}".to_vec(),
+                format!("{{\"name\":\"my-queue\",\"redrive_policy\":null,\"retention_timeout\":600,\"visibility_timeout\":30,\"message_delay\":5,\"message_deduplication\":false,\"allowed_content_types\":[],\"version\":\"{}\"}}", version).into_bytes(),
             );
         }
         {
@@ -167,10 +635,11 @@ mod test {
             let mut response = run_handler(get_handler.clone(), &source);
             assert_eq!(StatusCode::from(Status::Ok), response.status());
             let body = read_body(response.body_mut());
+            let version = queue_version(&body);
             assert_eq!(
                 body,
-                b"{\"name\":\"my-queue\",\"redrive_policy\":null,\"retention_timeout\":600,\"visibility_timeout\":30,\"message_delay\":5,\"message_deduplication\":false,\"status\":{\"messages\":0,\"visible_messages\":0,\"oldest_message_age\":0}}"
-                    .to_vec(),
+                format!("{{\"name\":\"my-queue\",\"redrive_policy\":null,\"retention_timeout\":600,\"visibility_timeout\":30,\"message_delay\":5,\"message_deduplication\":false,\"allowed_content_types\":[],\"version\":\"{}\",\"status\":{{\"messages\":0,\"visible_messages\":0,\"oldest_message_age\":0}}}}", version)
+                    .into_bytes(),
             );
         }
         let list_handler = router.route(&Method::GET, vec!["queues"].into_iter());
@@ -180,10 +649,12 @@ mod test {
             let mut response = run_handler(list_handler, &source);
             assert_eq!(StatusCode::from(Status::Ok), response.status());
             let body = read_body(response.body_mut());
+            let response: QueuesResponse = serde_json::from_slice(&body).unwrap();
+            let version = response.queues[0].version.clone().unwrap();
             assert_eq!(
                 body,
-                b"{\"queues\":[{\"name\":\"my-queue\",\"redrive_policy\":null,\"retention_timeout\":600,\"visibility_timeout\":30,\"message_delay\":5,\"message_deduplication\":false}],\"total\":1}"
-                    .to_vec(),
+                format!("{{\"queues\":[{{\"name\":\"my-queue\",\"redrive_policy\":null,\"retention_timeout\":600,\"visibility_timeout\":30,\"message_delay\":5,\"message_deduplication\":false,\"allowed_content_types\":[],\"version\":\"{}\"}}],\"total\":1}}", version)
+                    .into_bytes(),
             );
         }
         let update_handler = router.route(&Method::POST, vec!["queues", "my-queue"].into_iter());
@@ -197,9 +668,10 @@ mod test {
             );
             assert_eq!(StatusCode::from(Status::Ok), response.status());
             let body = read_body(response.body_mut());
+            let version = queue_version(&body);
             assert_eq!(
                 body,
-                b"{\"name\":\"my-queue\",\"redrive_policy\":null,\"retention_timeout\":30,\"visibility_timeout\":10,\"message_delay\":2,\"message_deduplication\":true}".to_vec(),
+                format!("{{\"name\":\"my-queue\",\"redrive_policy\":null,\"retention_timeout\":30,\"visibility_timeout\":10,\"message_delay\":2,\"message_deduplication\":true,\"allowed_content_types\":[],\"version\":\"{}\"}}", version).into_bytes(),
             );
         }
         let delete_handler = router.route(&Method::DELETE, vec!["queues", "my-queue"].into_iter());
@@ -209,9 +681,10 @@ mod test {
             let mut response = run_handler(delete_handler, &source);
             assert_eq!(StatusCode::from(Status::Ok), response.status());
             let body = read_body(response.body_mut());
+            let version = queue_version(&body);
             assert_eq!(
                 body,
-                b"{\"name\":\"my-queue\",\"redrive_policy\":null,\"retention_timeout\":30,\"visibility_timeout\":10,\"message_delay\":2,\"message_deduplication\":true}".to_vec(),
+                format!("{{\"name\":\"my-queue\",\"redrive_policy\":null,\"retention_timeout\":30,\"visibility_timeout\":10,\"message_delay\":2,\"message_deduplication\":true,\"allowed_content_types\":[],\"version\":\"{}\"}}", version).into_bytes(),
             );
         }
         {
@@ -223,52 +696,2395 @@ mod test {
     }
 
     #[test]
-    fn messages_router() {
+    fn create_queue_invalid_name_router() {
         let source = TestRepoSource::new();
-        source
-            .get()
-            .unwrap()
-            .insert_queue(&QueueInput {
-                name:                        "my-queue",
-                max_receives:                None,
-                dead_letter_queue:           None,
-                retention_timeout:           100,
-                visibility_timeout:          10,
-                message_delay:               0,
-                content_based_deduplication: false,
-            })
-            .unwrap()
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        for queue_name in &["my%20queue", "my.queue", "..", &"q".repeat(81)] {
+            let create_handler = router
+                .route(&Method::PUT, vec!["queues", queue_name].into_iter())
+                .unwrap();
+            let mut response = run_handler_with(
+                create_handler,
+                &source,
+                b"{\"retention_timeout\": 600, \"visibility_timeout\": 30, \"message_delay\": 5, \"message_deduplication\": false}".to_vec(),
+            );
+            assert_eq!(StatusCode::from(Status::BadRequest), response.status());
+            let body = read_body(response.body_mut());
+            assert_eq!(
+                body.as_slice(),
+                &b"{\"error\":\"Queue names must consist of 1 to 80 letters, digits, dashes, or underscores\"}"[..]
+            );
+            assert!(source.get().unwrap().find_by_name(queue_name).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn create_queue_invalid_config_router() {
+        let source = TestRepoSource::new();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let create_handler = router
+            .route(&Method::PUT, vec!["queues", "invalid-config-queue"].into_iter())
             .unwrap();
-        let router = make_router::<TestRepo, &TestRepoSource>();
-        let publish_handler = router.route(&Method::POST, vec!["messages", "my-queue"].into_iter());
-        assert!(publish_handler.is_some());
-        let publish_handler = publish_handler.unwrap();
-        {
-            let mut response = run_handler_with(publish_handler, &source, b"{\"content\": \"my message\"}".to_vec());
+        let mut response = run_handler_with(
+            create_handler,
+            &source,
+            b"{\"redrive_policy\": {\"max_receives\": 0, \"dead_letter_queue\": \"dead\"}, \"retention_timeout\": 600, \"visibility_timeout\": 30, \"message_delay\": 5, \"message_deduplication\": false}".to_vec(),
+        );
+        assert_eq!(StatusCode::from(Status::BadRequest), response.status());
+        assert_eq!(
+            read_body(response.body_mut()).as_slice(),
+            &b"{\"error\":\"The maximum number of receives has to be at least 1\"}"[..]
+        );
+        let update_handler = router
+            .route(&Method::POST, vec!["queues", "invalid-config-queue"].into_iter())
+            .unwrap();
+        let mut response = run_handler_with(
+            update_handler,
+            &source,
+            b"{\"retention_timeout\": 0, \"visibility_timeout\": 30, \"message_delay\": 5, \"message_deduplication\": false}".to_vec(),
+        );
+        assert_eq!(StatusCode::from(Status::BadRequest), response.status());
+        assert_eq!(
+            read_body(response.body_mut()).as_slice(),
+            &b"{\"error\":\"The retention timeout has to be at least 1 second\"}"[..]
+        );
+    }
+
+    #[test]
+    fn list_queues_order_router() {
+        let source = TestRepoSource::new();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let config = b"{\"retention_timeout\": 600, \"visibility_timeout\": 30, \"message_delay\": 0, \"message_deduplication\": false}";
+        for queue_name in &["order-queue-c", "order-queue-a", "order-queue-d", "order-queue-b"] {
+            let create_handler = router
+                .route(&Method::PUT, vec!["queues", queue_name].into_iter())
+                .unwrap();
+            let response = run_handler_with(create_handler, &source, config.to_vec());
             assert_eq!(StatusCode::from(Status::Created), response.status());
-            let body = read_body(response.body_mut());
-            assert_eq!(body.len(), 0);
         }
-        let receive_handler = router.route(&Method::GET, vec!["messages", "my-queue"].into_iter());
-        assert!(receive_handler.is_some());
-        let receive_handler = receive_handler.unwrap();
-        let message_id = {
-            let mut response = run_handler(receive_handler, &source);
+        let list_handler = router.route(&Method::GET, vec!["queues"].into_iter()).unwrap();
+        let list = |uri: &'static str| {
+            let req = Request::get(uri).body(Body::default()).unwrap();
+            let mut response = run_handler_with_request(list_handler.clone(), &source, req, Vec::new());
             assert_eq!(StatusCode::from(Status::Ok), response.status());
-            let body = read_body(response.body_mut());
-            assert_eq!(body.as_slice(), b"{\"content\": \"my message\"}");
-            let response_message_id = MessageIdHeader::get(response.headers());
-            assert!(!response_message_id.is_empty());
-            response_message_id
+            let response: QueuesResponse = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+            assert_eq!(response.total, 4);
+            response
+                .queues
+                .iter()
+                .map(|queue| queue.name.clone())
+                .collect::<Vec<String>>()
+        };
+        for _ in 0..3 {
+            assert_eq!(list("/queues"), vec![
+                "order-queue-a",
+                "order-queue-b",
+                "order-queue-c",
+                "order-queue-d"
+            ]);
+        }
+        assert_eq!(list("/queues?offset=1&limit=2"), vec!["order-queue-b", "order-queue-c"]);
+        assert_eq!(list("/queues?offset=3&limit=2"), vec!["order-queue-d"]);
+        assert!(list("/queues?limit=0").is_empty());
+        assert!(list("/queues?offset=4").is_empty());
+        // invalid parameters are rejected by the handler before the queues are listed
+        let req = Request::get("/queues?limit=many").body(Body::default()).unwrap();
+        let mut response = run_handler_with_request(list_handler, &source, req, Vec::new());
+        assert_eq!(StatusCode::from(Status::BadRequest), response.status());
+        assert_eq!(
+            read_body(response.body_mut()).as_slice(),
+            &b"{\"error\":\"invalid value for number field limit: invalid digit found in string\"}"[..]
+        );
+    }
+
+    #[test]
+    fn list_queues_prefix_router() {
+        let source = TestRepoSource::new();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let config = b"{\"retention_timeout\": 600, \"visibility_timeout\": 30, \"message_delay\": 0, \"message_deduplication\": false}";
+        for queue_name in &["orders-tenant-2", "orders-tenant-1", "orders_x", "invoices-tenant-1"] {
+            let create_handler = router
+                .route(&Method::PUT, vec!["queues", queue_name].into_iter())
+                .unwrap();
+            let response = run_handler_with(create_handler, &source, config.to_vec());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        let list_handler = router.route(&Method::GET, vec!["queues"].into_iter()).unwrap();
+        let list = |uri: &'static str| {
+            let req = Request::get(uri).body(Body::default()).unwrap();
+            let mut response = run_handler_with_request(list_handler.clone(), &source, req, Vec::new());
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            let response: QueuesResponse = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+            let names = response
+                .queues
+                .iter()
+                .map(|queue| queue.name.clone())
+                .collect::<Vec<String>>();
+            (response.total, names)
+        };
+        assert_eq!(
+            list("/queues?prefix=orders-"),
+            (2, vec!["orders-tenant-1".to_string(), "orders-tenant-2".to_string()])
+        );
+        assert_eq!(
+            list("/queues?prefix=orders-&offset=1"),
+            (2, vec!["orders-tenant-2".to_string()])
+        );
+        assert_eq!(list("/queues?prefix=orders").0, 3);
+        assert_eq!(list("/queues?prefix=missing"), (0, Vec::new()));
+    }
+
+    #[test]
+    fn list_queues_with_status_router() {
+        let source = TestRepoSource::new();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let config = b"{\"retention_timeout\": 600, \"visibility_timeout\": 30, \"message_delay\": 0, \"message_deduplication\": false}";
+        for queue_name in &["listed-status-a", "listed-status-b"] {
+            let create_handler = router
+                .route(&Method::PUT, vec!["queues", queue_name].into_iter())
+                .unwrap();
+            let response = run_handler_with(create_handler, &source, config.to_vec());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        for _ in 0..2 {
+            let publish_handler = router
+                .route(&Method::POST, vec!["messages", "listed-status-b"].into_iter())
+                .unwrap();
+            let response = run_handler_with(publish_handler, &source, b"message".to_vec());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        let list_handler = router.route(&Method::GET, vec!["queues"].into_iter()).unwrap();
+        let list = |uri: &'static str| {
+            let req = Request::get(uri).body(Body::default()).unwrap();
+            run_handler_with_request(list_handler.clone(), &source, req, Vec::new())
         };
         {
-            let delete_handler = router.route(&Method::DELETE, vec!["messages", &message_id].into_iter());
-            assert!(delete_handler.is_some());
-            let delete_handler = delete_handler.unwrap();
-            let mut response = run_handler(delete_handler, &source);
+            let mut response = list("/queues?prefix=listed-status-&status=true");
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            let response: QueuesWithStatusResponse = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+            assert_eq!(response.total, 2);
+            let statuses: Vec<(String, i64, i64)> = response
+                .queues
+                .iter()
+                .map(|queue| (queue.name.clone(), queue.status.messages, queue.status.visible_messages))
+                .collect();
+            assert_eq!(statuses, vec![
+                ("listed-status-a".to_string(), 0, 0),
+                ("listed-status-b".to_string(), 2, 2),
+            ]);
+        }
+        {
+            let mut response = list("/queues?prefix=listed-status-&status=false");
             assert_eq!(StatusCode::from(Status::Ok), response.status());
             let body = read_body(response.body_mut());
-            assert_eq!(body.len(), 0);
+            let response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert!(response["queues"][0].get("status").is_none());
+        }
+        {
+            let response = list("/queues?status=maybe");
+            assert_eq!(StatusCode::from(Status::BadRequest), response.status());
+        }
+    }
+
+    #[test]
+    fn create_queue_limit_router() {
+        let source = TestRepoSource::new();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig {
+            max_queues: Some(2),
+            ..RouterConfig::default()
+        });
+        let config = b"{\"retention_timeout\": 600, \"visibility_timeout\": 30, \"message_delay\": 0, \"message_deduplication\": false}";
+        for queue_name in &["limited-queue-1", "limited-queue-2"] {
+            let create_handler = router
+                .route(&Method::PUT, vec!["queues", queue_name].into_iter())
+                .unwrap();
+            let response = run_handler_with(create_handler, &source, config.to_vec());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        let create_handler = router
+            .route(&Method::PUT, vec!["queues", "limited-queue-3"].into_iter())
+            .unwrap();
+        let mut response = run_handler_with(create_handler, &source, config.to_vec());
+        assert_eq!(StatusCode::from(Status::TooManyRequests), response.status());
+        assert_eq!(
+            read_body(response.body_mut()).as_slice(),
+            &b"{\"error\":\"The maximum number of queues has been reached\"}"[..]
+        );
+    }
+
+    #[test]
+    fn create_queue_with_dlq_router() {
+        let source = TestRepoSource::new();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig {
+            max_queues: Some(4),
+            ..RouterConfig::default()
+        });
+        let config = |dead_letter_queue: &str, dlq_redrive: &str| {
+            format!(
+                "{{\"queue\": {{\"redrive_policy\": {}, \"retention_timeout\": 600, \"visibility_timeout\": 30, \"message_delay\": 0, \"message_deduplication\": false}}, \"dead_letter_queue\": {{\"redrive_policy\": {}, \"retention_timeout\": 3600, \"visibility_timeout\": 30, \"message_delay\": 0, \"message_deduplication\": false}}}}",
+                dead_letter_queue, dlq_redrive
+            )
+            .into_bytes()
+        };
+        let create = |queue_name: &str, body: Vec<u8>| {
+            let handler = router
+                .route(&Method::PUT, vec!["queues", queue_name, "with-dlq"].into_iter())
+                .unwrap();
+            run_handler_with(handler, &source, body)
+        };
+        let exists = |queue_name: &str| {
+            let handler = router
+                .route(&Method::GET, vec!["queues", queue_name].into_iter())
+                .unwrap();
+            run_handler(handler, &source).status() == StatusCode::from(Status::Ok)
+        };
+        let redrive_to =
+            |queue_name: &str| format!("{{\"max_receives\": 3, \"dead_letter_queue\": \"{}\"}}", queue_name);
+
+        // the queue needs a redrive policy and the dead letter queue must not point back to it
+        let response = create("dlq-source", config("null", "null"));
+        assert_eq!(StatusCode::from(Status::BadRequest), response.status());
+        let response = create(
+            "dlq-source",
+            config(&redrive_to("dlq-source-dead"), &redrive_to("dlq-source")),
+        );
+        assert_eq!(StatusCode::from(Status::BadRequest), response.status());
+        assert!(!exists("dlq-source"));
+        assert!(!exists("dlq-source-dead"));
+
+        let mut response = create("dlq-source", config(&redrive_to("dlq-source-dead"), "null"));
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let output: QueueWithDlqOutput = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+        assert_eq!(output.queue.name, "dlq-source");
+        assert_eq!(
+            output
+                .queue
+                .redrive_policy
+                .as_ref()
+                .map(|policy| policy.dead_letter_queue.as_str()),
+            Some("dlq-source-dead")
+        );
+        assert_eq!(output.dead_letter_queue.name, "dlq-source-dead");
+        assert_eq!(output.dead_letter_queue.retention_timeout, 3600);
+        assert!(exists("dlq-source"));
+        assert!(exists("dlq-source-dead"));
+
+        // neither queue is created if one of them already exists
+        let response = create("dlq-other", config(&redrive_to("dlq-source-dead"), "null"));
+        assert_eq!(StatusCode::from(Status::Conflict), response.status());
+        assert!(!exists("dlq-other"));
+
+        // both queues count against the limit
+        let create_handler = router
+            .route(&Method::PUT, vec!["queues", "dlq-single"].into_iter())
+            .unwrap();
+        let response = run_handler_with(
+            create_handler,
+            &source,
+            b"{\"retention_timeout\": 600, \"visibility_timeout\": 30, \"message_delay\": 0, \"message_deduplication\": false}".to_vec(),
+        );
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let response = create("dlq-other", config(&redrive_to("dlq-other-dead"), "null"));
+        assert_eq!(StatusCode::from(Status::TooManyRequests), response.status());
+        assert!(!exists("dlq-other"));
+        assert!(!exists("dlq-other-dead"));
+    }
+
+    #[test]
+    fn update_queue_version_router() {
+        let source = TestRepoSource::new();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let create_handler = router
+            .route(&Method::PUT, vec!["queues", "my-queue"].into_iter())
+            .unwrap();
+        let update_handler = router
+            .route(&Method::POST, vec!["queues", "my-queue"].into_iter())
+            .unwrap();
+        let config = b"{\"retention_timeout\": 600, \"visibility_timeout\": 30, \"message_delay\": 5, \"message_deduplication\": false}".to_vec();
+        let update = |version: &str| {
+            let mut req = Request::new(Body::default());
+            req.headers_mut()
+                .insert(IF_MATCH, HeaderValue::from_str(version).unwrap());
+            run_handler_with_request(update_handler.clone(), &source, req, config.clone())
+        };
+        {
+            let response = update("*");
+            assert_eq!(StatusCode::from(Status::NotFound), response.status());
         }
+        let version = {
+            let mut response = run_handler_with(create_handler, &source, config.clone());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+            queue_version(&read_body(response.body_mut()))
+        };
+        {
+            let response = update("\"not a version\"");
+            assert_eq!(StatusCode::from(Status::BadRequest), response.status());
+        }
+        {
+            let response = update("\"2020-01-01T00:00:00.000000000Z\"");
+            assert_eq!(StatusCode::from(Status::Conflict), response.status());
+        }
+        let new_version = {
+            let mut response = update(&format!("\"{}\"", version));
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            queue_version(&read_body(response.body_mut()))
+        };
+        assert_ne!(version, new_version);
+        {
+            let response = update(&version);
+            assert_eq!(StatusCode::from(Status::Conflict), response.status());
+        }
+        {
+            let response = update(&new_version);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+        }
+    }
+
+    #[test]
+    fn describe_queue_deletion_router() {
+        let source = TestRepoSource::new();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let create_handler = router
+            .route(&Method::PUT, vec!["queues", "my-queue"].into_iter())
+            .unwrap();
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "my-queue"].into_iter())
+            .unwrap();
+        let describe_deletion_handler = router
+            .route(&Method::GET, vec!["queues", "my-queue", "deletion"].into_iter())
+            .unwrap();
+        let delete_handler = router
+            .route(&Method::DELETE, vec!["queues", "my-queue"].into_iter())
+            .unwrap();
+        let describe_deletion = || run_handler(describe_deletion_handler.clone(), &source);
+        {
+            let response = describe_deletion();
+            assert_eq!(StatusCode::from(Status::NotFound), response.status());
+        }
+        {
+            let response = run_handler_with(
+                create_handler,
+                &source,
+                b"{\"retention_timeout\": 600, \"visibility_timeout\": 30, \"message_delay\": 0, \"message_deduplication\": false}".to_vec(),
+            );
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        {
+            let response = run_handler_with(publish_handler, &source, b"message".to_vec());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        for _ in 0..2 {
+            let mut response = describe_deletion();
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            let description: QueueDescriptionOutput = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+            assert_eq!(description.name, "my-queue");
+            assert_eq!(description.status.messages, 1);
+        }
+        {
+            let response = run_handler(delete_handler, &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+        }
+        {
+            let response = describe_deletion();
+            assert_eq!(StatusCode::from(Status::NotFound), response.status());
+        }
+    }
+
+    #[test]
+    fn describe_queue_without_status_router() {
+        let source = TestRepoSource::new();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let create_handler = router
+            .route(&Method::PUT, vec!["queues", "status-queue"].into_iter())
+            .unwrap();
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "status-queue"].into_iter())
+            .unwrap();
+        let describe_handler = router
+            .route(&Method::GET, vec!["queues", "status-queue"].into_iter())
+            .unwrap();
+        let describe = |uri: &'static str| {
+            let req = Request::get(uri).body(Body::default()).unwrap();
+            run_handler_with_request(describe_handler.clone(), &source, req, Vec::new())
+        };
+        {
+            let response = describe("/queues/status-queue?status=false");
+            assert_eq!(StatusCode::from(Status::NotFound), response.status());
+        }
+        {
+            let response = run_handler_with(
+                create_handler,
+                &source,
+                b"{\"retention_timeout\": 600, \"visibility_timeout\": 30, \"message_delay\": 0, \"message_deduplication\": false}".to_vec(),
+            );
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+            let response = run_handler_with(publish_handler, &source, b"message".to_vec());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        {
+            let response = describe("/queues/status-queue?status=maybe");
+            assert_eq!(StatusCode::from(Status::BadRequest), response.status());
+        }
+        {
+            let mut response = describe("/queues/status-queue?status=true");
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            let description: QueueDescriptionOutput = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+            assert_eq!(description.status.messages, 1);
+        }
+        {
+            let mut response = describe("/queues/status-queue?status=false");
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            let body = read_body(response.body_mut());
+            let config: QueueConfigOutput = serde_json::from_slice(&body).unwrap();
+            assert_eq!(config.name, "status-queue");
+            let description: QueueDescriptionOutput = serde_json::from_slice(&body).unwrap();
+            assert_eq!(description.status, QueueStatus::default());
+        }
+    }
+
+    #[test]
+    fn describe_queue_age_percentiles_router() {
+        let clock = Arc::new(FixedClock::new(UtcTime::from_timestamp(1_590_000_000)));
+        let source = TestRepoSource::new().with_clock(clock.clone());
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&test_queue("my-queue"))
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "my-queue"].into_iter())
+            .unwrap();
+        let describe_handler = router
+            .route(&Method::GET, vec!["queues", "my-queue"].into_iter())
+            .unwrap();
+        let describe = |uri: &'static str| {
+            let req = Request::get(uri).body(Body::default()).unwrap();
+            let mut response = run_handler_with_request(describe_handler.clone(), &source, req, Vec::new());
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            serde_json::from_slice::<QueueDescriptionOutput>(&read_body(response.body_mut())).unwrap()
+        };
+        assert_eq!(
+            describe("/queues/my-queue?age_percentiles=true").status.age_percentiles,
+            Some(AgePercentiles::default())
+        );
+        // publish messages which are 10, 9, ..., 1 seconds old
+        for i in 0..10 {
+            let response = run_handler_with(publish_handler.clone(), &source, format!("message {}", i).into_bytes());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+            clock.advance(Duration::from_secs(1));
+        }
+        let description = describe("/queues/my-queue?age_percentiles=true");
+        assert_eq!(description.status.oldest_message_age, 10);
+        assert_eq!(
+            description.status.age_percentiles,
+            Some(AgePercentiles {
+                p50: 5,
+                p90: 9,
+                p99: 10,
+            })
+        );
+        assert_eq!(describe("/queues/my-queue").status.age_percentiles, None);
+        assert_eq!(
+            describe("/queues/my-queue?age_percentiles=false")
+                .status
+                .age_percentiles,
+            None
+        );
+    }
+
+    #[test]
+    fn describe_dead_letter_queue_depth_router() {
+        let source = TestRepoSource::new();
+        for (queue_name, max_receives, dead_letter_queue) in
+            &[("depth-source", Some(3), Some("depth-dlq")), ("depth-dlq", None, None)]
+        {
+            source
+                .get()
+                .unwrap()
+                .insert_queue(&QueueInput {
+                    max_receives: *max_receives,
+                    dead_letter_queue: *dead_letter_queue,
+                    ..test_queue(queue_name)
+                })
+                .unwrap()
+                .unwrap();
+        }
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let describe = |queue_name: &str| {
+            let describe_handler = router
+                .route(&Method::GET, vec!["queues", queue_name].into_iter())
+                .unwrap();
+            let mut response = run_handler(describe_handler, &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            serde_json::from_slice::<QueueDescriptionOutput>(&read_body(response.body_mut())).unwrap()
+        };
+        assert_eq!(describe("depth-source").status.dead_letter_queue_depth, Some(0));
+        assert_eq!(describe("depth-dlq").status.dead_letter_queue_depth, None);
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "depth-dlq"].into_iter())
+            .unwrap();
+        for i in 0..2 {
+            let response = run_handler_with(publish_handler.clone(), &source, format!("message {}", i).into_bytes());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        let description = describe("depth-source");
+        assert_eq!(description.status.messages, 0);
+        assert_eq!(description.status.dead_letter_queue_depth, Some(2));
+    }
+
+    #[test]
+    fn messages_router() {
+        let source = TestRepoSource::new();
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&test_queue("my-queue"))
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig {
+            allow_message_ids: true,
+            ..RouterConfig::default()
+        });
+        let publish_handler = router.route(&Method::POST, vec!["messages", "my-queue"].into_iter());
+        assert!(publish_handler.is_some());
+        let publish_handler = publish_handler.unwrap();
+        {
+            let mut response = run_handler_with(publish_handler, &source, b"{\"content\": \"my message\"}".to_vec());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+            let body = read_body(response.body_mut());
+            assert_eq!(body.len(), 0);
+        }
+        let receive_handler = router.route(&Method::GET, vec!["messages", "my-queue"].into_iter());
+        assert!(receive_handler.is_some());
+        let receive_handler = receive_handler.unwrap();
+        let message_id = {
+            let mut response = run_handler(receive_handler, &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            let body = read_body(response.body_mut());
+            assert_eq!(body.as_slice(), b"{\"content\": \"my message\"}");
+            let response_message_id = MessageIdHeader::get(response.headers());
+            assert!(!response_message_id.is_empty());
+            response_message_id
+        };
+        {
+            let delete_handler = router.route(&Method::DELETE, vec!["messages", &message_id].into_iter());
+            assert!(delete_handler.is_some());
+            let delete_handler = delete_handler.unwrap();
+            let mut response = run_handler(delete_handler, &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            let body = read_body(response.body_mut());
+            assert_eq!(body.len(), 0);
+        }
+    }
+
+    #[test]
+    fn idempotency_key_router() {
+        let source = TestRepoSource::new();
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&test_queue("my-queue"))
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "my-queue"].into_iter())
+            .unwrap();
+        // results are remembered by the whole process, so use keys no other test can send
+        let first_key = Uuid::new_v4().to_string();
+        let second_key = Uuid::new_v4().to_string();
+        for (key, messages) in &[(&first_key, 1), (&first_key, 1), (&second_key, 2)] {
+            let mut req = Request::new(Body::default());
+            req.headers_mut()
+                .insert(IdempotencyKeyHeader::name(), HeaderValue::from_str(key).unwrap());
+            let response = run_handler_with_request(publish_handler.clone(), &source, req, b"my message".to_vec());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+            let description = source.get().unwrap().describe_queue("my-queue").unwrap().unwrap();
+            assert_eq!(description.messages, *messages);
+        }
+    }
+
+    #[test]
+    fn allowed_content_types_router() {
+        let source = TestRepoSource::new();
+        let allowed_content_types = vec!["application/json".to_string()];
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                allowed_content_types: &allowed_content_types,
+                ..test_queue("json-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "json-queue"].into_iter())
+            .unwrap();
+        let publish = |content_type: &'static str| {
+            let mut req = Request::new(Body::default());
+            req.headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+            run_handler_with_request(publish_handler.clone(), &source, req, b"{}".to_vec()).status()
+        };
+        assert_eq!(StatusCode::from(Status::UnsupportedMediaType), publish("text/plain"));
+        assert_eq!(StatusCode::from(Status::Created), publish("application/json"));
+        assert_eq!(
+            StatusCode::from(Status::Created),
+            publish("application/json; charset=utf-8")
+        );
+    }
+
+    #[test]
+    fn default_content_type_router() {
+        let source = TestRepoSource::new();
+        let allowed_content_types = vec!["application/json".to_string()];
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                visibility_timeout: 100,
+                allowed_content_types: &allowed_content_types,
+                default_content_type: Some("application/json"),
+                ..test_queue("default-type-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let describe_handler = router
+            .route(&Method::GET, vec!["queues", "default-type-queue"].into_iter())
+            .unwrap();
+        let mut response = run_handler(describe_handler, &source);
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        let description: QueueDescriptionOutput = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+        assert_eq!(description.default_content_type, Some("application/json".to_string()));
+
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "default-type-queue"].into_iter())
+            .unwrap();
+        let response = run_handler_with(publish_handler, &source, b"{}".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "default-type-queue"].into_iter())
+            .unwrap();
+        let response = run_handler(receive_handler, &source);
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), "application/json");
+    }
+
+    #[test]
+    fn receive_content_type_filter_router() {
+        let source = TestRepoSource::new();
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                visibility_timeout: 100,
+                ..test_queue("mixed-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "mixed-queue"].into_iter())
+            .unwrap();
+        for content_type in &["text/plain", "application/json"] {
+            let mut req = Request::new(Body::default());
+            req.headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+            let response = run_handler_with_request(publish_handler.clone(), &source, req, b"{}".to_vec());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "mixed-queue"].into_iter())
+            .unwrap();
+        let receive = |content_type: Option<&'static str>| {
+            let mut req = Request::new(Body::default());
+            if let Some(content_type) = content_type {
+                req.headers_mut().insert(
+                    HeaderName::from_static("x-mqs-filter-content-type"),
+                    HeaderValue::from_static(content_type),
+                );
+            }
+            let response = run_handler_with_request(receive_handler.clone(), &source, req, Vec::new());
+            if response.status() == StatusCode::from(Status::Ok) {
+                Some(
+                    response
+                        .headers()
+                        .get(CONTENT_TYPE)
+                        .unwrap()
+                        .to_str()
+                        .unwrap()
+                        .to_string(),
+                )
+            } else {
+                assert_eq!(StatusCode::from(Status::NoContent), response.status());
+                None
+            }
+        };
+        assert_eq!(receive(Some("application/json")), Some("application/json".to_string()));
+        assert_eq!(receive(Some("application/json")), None);
+        assert_eq!(receive(None), Some("text/plain".to_string()));
+        assert_eq!(receive(None), None);
+    }
+
+    #[test]
+    fn receive_auto_delete_router() {
+        let source = TestRepoSource::new();
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                visibility_timeout: 0,
+                ..test_queue("auto-delete-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "auto-delete-queue"].into_iter())
+            .unwrap();
+        for _ in 0..2 {
+            let mut req = Request::new(Body::default());
+            req.headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+            let response = run_handler_with_request(publish_handler.clone(), &source, req, b"message".to_vec());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "auto-delete-queue"].into_iter())
+            .unwrap();
+        let receive = |auto_delete: Option<&'static str>| {
+            let mut req = Request::new(Body::default());
+            if let Some(auto_delete) = auto_delete {
+                req.headers_mut().insert(
+                    HeaderName::from_static("x-mqs-auto-delete"),
+                    HeaderValue::from_static(auto_delete),
+                );
+            }
+            run_handler_with_request(receive_handler.clone(), &source, req, Vec::new()).status()
+        };
+
+        assert_eq!(receive(Some("yes")), StatusCode::from(Status::BadRequest));
+        // without auto delete the message becomes visible again after the visibility timeout of 0
+        assert_eq!(receive(Some("false")), StatusCode::from(Status::Ok));
+        assert_eq!(receive(Some("true")), StatusCode::from(Status::Ok));
+        assert_eq!(receive(Some("true")), StatusCode::from(Status::Ok));
+        assert_eq!(receive(None), StatusCode::from(Status::NoContent));
+    }
+
+    #[test]
+    fn receive_headers_only_router() {
+        let source = TestRepoSource::new();
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                visibility_timeout: 100,
+                ..test_queue("headers-only-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "headers-only-queue"].into_iter())
+            .unwrap();
+        let mut req = Request::new(Body::default());
+        req.headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        let response = run_handler_with_request(publish_handler, &source, req, b"message".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "headers-only-queue"].into_iter())
+            .unwrap();
+        let receive = |headers: &[(&'static str, &'static str)]| {
+            let mut req = Request::new(Body::default());
+            for (name, value) in headers {
+                req.headers_mut()
+                    .insert(HeaderName::from_static(name), HeaderValue::from_static(value));
+            }
+            let mut response = run_handler_with_request(receive_handler.clone(), &source, req, Vec::new());
+            let content_type = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .map(|value| value.to_str().unwrap().to_string());
+            (response.status(), content_type, read_body(response.body_mut()))
+        };
+
+        assert_eq!(
+            receive(&[("x-mqs-headers-only", "yes")]).0,
+            StatusCode::from(Status::BadRequest)
+        );
+        assert_eq!(
+            receive(&[("x-mqs-headers-only", "true"), ("x-mqs-auto-delete", "true")]).0,
+            StatusCode::from(Status::BadRequest)
+        );
+        // peeking neither receives the message nor returns its content
+        for _ in 0..2 {
+            let (status, content_type, body) = receive(&[("x-mqs-headers-only", "true")]);
+            assert_eq!(status, StatusCode::from(Status::Ok));
+            assert_eq!(content_type, Some("text/plain".to_string()));
+            assert!(body.is_empty());
+        }
+        let (status, _, body) = receive(&[]);
+        assert_eq!(status, StatusCode::from(Status::Ok));
+        assert_eq!(body, b"message".to_vec());
+        // the received message is invisible and thus no longer returned when peeking
+        assert_eq!(
+            receive(&[("x-mqs-headers-only", "true")]).0,
+            StatusCode::from(Status::NoContent)
+        );
+    }
+
+    #[test]
+    fn receive_accept_multipart_router() {
+        let source = TestRepoSource::new();
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                visibility_timeout: 0,
+                ..test_queue("multipart-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "multipart-queue"].into_iter())
+            .unwrap();
+        let mut req = Request::new(Body::default());
+        req.headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        let response = run_handler_with_request(publish_handler, &source, req, b"message".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "multipart-queue"].into_iter())
+            .unwrap();
+        let receive = |accept: Option<&'static str>| {
+            let mut req = Request::new(Body::default());
+            if let Some(accept) = accept {
+                req.headers_mut().insert(ACCEPT, HeaderValue::from_static(accept));
+            }
+            let response = run_handler_with_request(receive_handler.clone(), &source, req, Vec::new());
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            let content_type = response.headers().get(CONTENT_TYPE).unwrap().to_str().unwrap();
+            multipart::is_multipart(content_type).is_some()
+        };
+
+        // the message becomes visible again immediately, so we can receive it multiple times
+        assert!(!receive(None));
+        assert!(!receive(Some("*/*")));
+        assert!(!receive(Some("multipart/mixed;q=0")));
+        assert!(receive(Some("multipart/mixed")));
+        assert!(receive(Some("text/plain, Multipart/Mixed;q=0.5")));
+    }
+
+    #[test]
+    fn receive_trace_id_router() {
+        let source = TestRepoSource::new();
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                visibility_timeout: 100,
+                ..test_queue("traced-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "traced-queue"].into_iter())
+            .unwrap();
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "traced-queue"].into_iter())
+            .unwrap();
+        let receive = |trace_id: Uuid| {
+            let mut req = Request::new(Body::default());
+            req.headers_mut().insert(
+                TraceIdHeader::name(),
+                HeaderValue::from_str(&trace_id.to_string()).unwrap(),
+            );
+            run_handler_with_request(receive_handler.clone(), &source, req, Vec::new())
+        };
+
+        // without a message the trace id of the request is echoed back
+        let request_trace_id = Uuid::new_v4();
+        let response = receive(request_trace_id);
+        assert_eq!(StatusCode::from(Status::NoContent), response.status());
+        assert_eq!(TraceIdHeader::get(response.headers()), Some(request_trace_id));
+
+        // a single message keeps its own trace id
+        let message_trace_id = Uuid::new_v4();
+        let mut req = Request::new(Body::default());
+        req.headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        req.headers_mut().insert(
+            TraceIdHeader::name(),
+            HeaderValue::from_str(&message_trace_id.to_string()).unwrap(),
+        );
+        let response = run_handler_with_request(publish_handler.clone(), &source, req, b"traced".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let response = receive(request_trace_id);
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        assert_eq!(TraceIdHeader::get(response.headers()), Some(message_trace_id));
+
+        // trace ids which are not a uuid are preserved as well
+        let traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+        let mut req = Request::new(Body::default());
+        req.headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        req.headers_mut()
+            .insert(TraceIdHeader::name(), HeaderValue::from_static(traceparent));
+        let response = run_handler_with_request(publish_handler.clone(), &source, req, b"w3c".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let response = receive(request_trace_id);
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        assert_eq!(TraceIdHeader::get_raw(response.headers()), Some(traceparent));
+
+        // a message without a trace id does not get the one of the request
+        let response = run_handler_with(publish_handler, &source, b"untraced".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let response = receive(request_trace_id);
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        assert_eq!(TraceIdHeader::get_raw(response.headers()), None);
+    }
+
+    #[test]
+    fn message_expiry_router() {
+        let source = TestRepoSource::new();
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                visibility_timeout: 100,
+                ..test_queue("expiring-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "expiring-queue"].into_iter())
+            .unwrap();
+        let publish = |expires_at: &str| {
+            let mut req = Request::new(Body::default());
+            req.headers_mut()
+                .insert(ExpiresAtHeader::name(), HeaderValue::from_str(expires_at).unwrap());
+            run_handler_with_request(publish_handler.clone(), &source, req, b"message".to_vec()).status()
+        };
+        let now = UtcTime::now();
+        let expires_at = now.add(Duration::from_secs(50));
+        assert_eq!(StatusCode::from(Status::BadRequest), publish("tomorrow"));
+        assert_eq!(
+            StatusCode::from(Status::BadRequest),
+            publish(&now.add(Duration::from_secs(200)).to_rfc3339())
+        );
+        assert_eq!(StatusCode::from(Status::Created), publish(&expires_at.to_rfc3339()));
+        assert_eq!(
+            StatusCode::from(Status::Created),
+            publish(&now.sub(Duration::from_secs(10)).to_rfc3339())
+        );
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "expiring-queue"].into_iter())
+            .unwrap();
+        let mut req = Request::new(Body::default());
+        req.headers_mut().insert(
+            HeaderName::from_static("x-mqs-max-messages"),
+            HeaderValue::from_static("10"),
+        );
+        let response = run_handler_with_request(receive_handler, &source, req, Vec::new());
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        assert_eq!(
+            ExpiresAtHeader::get(response.headers()).map(|time| time.to_rfc3339()),
+            Some(expires_at.to_rfc3339())
+        );
+    }
+
+    #[test]
+    fn published_at_router() {
+        let now = UtcTime::from_timestamp(1_590_000_000);
+        let clock = Arc::new(FixedClock::new(now));
+        let source = TestRepoSource::new().with_clock(clock.clone());
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                visibility_timeout: 100,
+                ..test_queue("replayed-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "replayed-queue"].into_iter())
+            .unwrap();
+        let publish = |published_at: &str| {
+            let mut req = Request::new(Body::default());
+            req.headers_mut()
+                .insert(PublishedAtHeader::name(), HeaderValue::from_str(published_at).unwrap());
+            run_handler_with_request(publish_handler.clone(), &source, req, b"message".to_vec()).status()
+        };
+        let rejection = |published_at: &str| {
+            let mut req = Request::new(Body::default());
+            req.headers_mut()
+                .insert(PublishedAtHeader::name(), HeaderValue::from_str(published_at).unwrap());
+            let mut response = run_handler_with_request(publish_handler.clone(), &source, req, b"message".to_vec());
+            assert_eq!(StatusCode::from(Status::BadRequest), response.status());
+            let body: PublishRejectionResponse = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+            body.reason.unwrap()
+        };
+        let published_at = now.sub(Duration::from_secs(50));
+        assert_eq!(rejection("yesterday"), PublishRejection::InvalidPublishTime);
+        assert_eq!(
+            rejection(&now.add(Duration::from_secs(60)).to_rfc3339()),
+            PublishRejection::PublishTimeInFuture
+        );
+        assert_eq!(
+            rejection(&now.sub(Duration::from_secs(200)).to_rfc3339()),
+            PublishRejection::PublishTimeExceedsRetention
+        );
+        assert_eq!(StatusCode::from(Status::Created), publish(&now.to_rfc3339()));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(StatusCode::from(Status::Created), publish(&published_at.to_rfc3339()));
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "replayed-queue"].into_iter())
+            .unwrap();
+        // messages are received in the order they arrived, regardless of their publish time
+        for expected in &[now, published_at] {
+            let response = run_handler(receive_handler.clone(), &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            assert_eq!(
+                PublishedAtHeader::get(response.headers()).to_rfc3339(),
+                expected.to_rfc3339()
+            );
+        }
+    }
+
+    #[test]
+    fn receive_since_router() {
+        let now = UtcTime::from_timestamp(1_590_000_000);
+        let clock = Arc::new(FixedClock::new(now));
+        let source = TestRepoSource::new().with_clock(clock.clone());
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                visibility_timeout: 100,
+                ..test_queue("since-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "since-queue"].into_iter())
+            .unwrap();
+        let response = run_handler_with(publish_handler.clone(), &source, b"old".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        clock.advance(Duration::from_secs(60));
+        // a replayed message keeps its original publish time, but is only created now
+        let mut req = Request::new(Body::default());
+        req.headers_mut().insert(
+            PublishedAtHeader::name(),
+            HeaderValue::from_str(&now.to_rfc3339()).unwrap(),
+        );
+        let response = run_handler_with_request(publish_handler, &source, req, b"new".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "since-queue"].into_iter())
+            .unwrap();
+        let receive = |since: Option<&str>| {
+            let mut req = Request::new(Body::default());
+            if let Some(since) = since {
+                req.headers_mut().insert(
+                    HeaderName::from_static("x-mqs-since"),
+                    HeaderValue::from_str(since).unwrap(),
+                );
+            }
+            let mut response = run_handler_with_request(receive_handler.clone(), &source, req, Vec::new());
+            (response.status(), read_body(response.body_mut()))
+        };
+
+        assert_eq!(receive(Some("yesterday")).0, StatusCode::from(Status::BadRequest));
+        let since = now.add(Duration::from_secs(30)).to_rfc3339();
+        assert_eq!(receive(Some(&since)), (StatusCode::from(Status::Ok), b"new".to_vec()));
+        // the older message is left in the queue for consumers without the filter
+        assert_eq!(receive(Some(&since)).0, StatusCode::from(Status::NoContent));
+        assert_eq!(receive(None), (StatusCode::from(Status::Ok), b"old".to_vec()));
+    }
+
+    #[test]
+    fn publish_outcomes_router() {
+        let source = TestRepoSource::new();
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                visibility_timeout: 100,
+                content_based_deduplication: true,
+                ..test_queue("outcome-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "outcome-queue"].into_iter())
+            .unwrap();
+        let publish = |bodies: &[&str]| {
+            let document = multipart::encode(bodies.iter().map(|body| (HeaderMap::new(), body.as_bytes().to_vec())));
+            let mut req = Request::new(Body::default());
+            req.headers_mut().insert(CONTENT_TYPE, document.content_type_header());
+            let mut response = run_handler_with_request(publish_handler.clone(), &source, req, document.into_body());
+            let status = response.status();
+            let body: PublishResponse = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+            (status, body.messages)
+        };
+
+        let (status, first) = publish(&["first", "second"]);
+        assert_eq!(status, StatusCode::from(Status::Created));
+        assert_eq!(first.len(), 2);
+        assert!(first.iter().all(|outcome| outcome.created));
+        assert_ne!(first[0].message_id, first[1].message_id);
+
+        let (status, second) = publish(&["second", "third"]);
+        assert_eq!(status, StatusCode::from(Status::Created));
+        assert_eq!(second.len(), 2);
+        assert!(!second[0].created);
+        assert_eq!(second[0].message_id, None);
+        assert!(second[1].created);
+        let id = second[1].message_id.as_deref().unwrap();
+        assert!(Uuid::parse_str(id).is_ok());
+
+        let (status, third) = publish(&["first", "third"]);
+        assert_eq!(status, StatusCode::from(Status::Ok));
+        assert!(third
+            .iter()
+            .all(|outcome| !outcome.created && outcome.message_id.is_none()));
+
+        // a single message gets its outcome in the headers
+        let response = run_handler_with(publish_handler.clone(), &source, b"fourth".to_vec());
+        assert_eq!(response.status(), StatusCode::from(Status::Created));
+        assert!(Uuid::parse_str(&MessageIdHeader::get(response.headers())).is_ok());
+        assert!(!DeduplicatedHeader::get(response.headers()));
+        let response = run_handler_with(publish_handler, &source, b"fourth".to_vec());
+        assert_eq!(response.status(), StatusCode::from(Status::Ok));
+        assert_eq!(MessageIdHeader::get(response.headers()), "");
+        assert!(DeduplicatedHeader::get(response.headers()));
+    }
+
+    #[test]
+    fn gzip_publish_router() {
+        let source = TestRepoSource::new();
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                visibility_timeout: 100,
+                ..test_queue("gzip-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig {
+            max_message_size: 16,
+            ..RouterConfig::default()
+        });
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "gzip-queue"].into_iter())
+            .unwrap();
+        let publish = |body: Vec<u8>| {
+            let mut req = Request::new(Body::default());
+            req.headers_mut()
+                .insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+            req.headers_mut()
+                .insert(MessageContentEncodingHeader::name(), HeaderValue::from_static("br"));
+            run_handler_with_request(publish_handler.clone(), &source, req, body).status()
+        };
+        let compress = |body: &[u8]| {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body).unwrap();
+            encoder.finish().unwrap()
+        };
+        assert_eq!(StatusCode::from(Status::BadRequest), publish(b"not gzip".to_vec()));
+        assert_eq!(
+            StatusCode::from(Status::PayloadTooLarge),
+            publish(compress(b"a message which is too large"))
+        );
+        assert_eq!(StatusCode::from(Status::Created), publish(compress(b"small message")));
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "gzip-queue"].into_iter())
+            .unwrap();
+        let mut response = run_handler(receive_handler.clone(), &source);
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        assert_eq!(
+            response.headers().get(CONTENT_ENCODING),
+            Some(&HeaderValue::from_static("br"))
+        );
+        assert_eq!(read_body(response.body_mut()), b"small message");
+        let response = run_handler(receive_handler, &source);
+        assert_eq!(StatusCode::from(Status::NoContent), response.status());
+    }
+
+    #[test]
+    fn receive_batch_cap_router() {
+        let source = TestRepoSource::new();
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&test_queue("my-queue"))
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig {
+            max_receive_batch: 2,
+            ..RouterConfig::default()
+        });
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "my-queue"].into_iter())
+            .unwrap();
+        for i in 0..3 {
+            let response = run_handler_with(publish_handler.clone(), &source, format!("message {}", i).into_bytes());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "my-queue"].into_iter())
+            .unwrap();
+        let mut req = Request::new(Body::default());
+        req.headers_mut().insert(
+            HeaderName::from_static("x-mqs-max-messages"),
+            HeaderValue::from_static("10"),
+        );
+        let mut response = run_handler_with_request(receive_handler, &source, req, Vec::new());
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        assert_eq!(
+            response.headers().get(HeaderName::from_static("x-mqs-max-messages")),
+            Some(&HeaderValue::from_static("2"))
+        );
+        let content_type = response.headers().get(CONTENT_TYPE).unwrap().to_str().unwrap();
+        let boundary = multipart::is_multipart(content_type).unwrap();
+        let body = read_body(response.body_mut());
+        let messages = multipart::parse(boundary.as_bytes(), &body).unwrap();
+        assert_eq!(messages.len(), 2);
+    }
+
+    #[test]
+    fn release_message_router() {
+        let source = TestRepoSource::new();
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                visibility_timeout: 100,
+                ..test_queue("my-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "my-queue"].into_iter())
+            .unwrap();
+        let response = run_handler_with(publish_handler, &source, b"my message".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "my-queue"].into_iter())
+            .unwrap();
+        let peek_message_id = {
+            let mut req = Request::new(Body::default());
+            req.headers_mut().insert(
+                HeaderName::from_static("x-mqs-headers-only"),
+                HeaderValue::from_static("true"),
+            );
+            let response = run_handler_with_request(receive_handler.clone(), &source, req, Vec::new());
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            MessageIdHeader::get(response.headers())
+        };
+        {
+            // a message which was never received is not in flight and can't be released
+            let release_handler = router
+                .route(&Method::POST, vec!["messages", &peek_message_id, "release"].into_iter())
+                .unwrap();
+            let response = run_handler(release_handler, &source);
+            assert_eq!(StatusCode::from(Status::NotFound), response.status());
+        }
+        let message_id = {
+            let response = run_handler(receive_handler.clone(), &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            MessageIdHeader::get(response.headers())
+        };
+        assert_eq!(peek_message_id, message_id);
+        {
+            let response = run_handler(receive_handler.clone(), &source);
+            assert_eq!(StatusCode::from(Status::NoContent), response.status());
+        }
+        {
+            let release_handler = router
+                .route(&Method::POST, vec!["messages", &message_id, "release"].into_iter())
+                .unwrap();
+            let response = run_handler(release_handler, &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+        }
+        {
+            // the message is visible again, so releasing it a second time does nothing
+            let release_handler = router
+                .route(&Method::POST, vec!["messages", &message_id, "release"].into_iter())
+                .unwrap();
+            let response = run_handler(release_handler, &source);
+            assert_eq!(StatusCode::from(Status::NotFound), response.status());
+        }
+        {
+            let response = run_handler(receive_handler, &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            assert_eq!(MessageIdHeader::get(response.headers()), message_id);
+        }
+        {
+            let release_handler = router
+                .route(
+                    &Method::POST,
+                    vec!["messages", "a7d46b8e-32fe-4d35-a3af-74f9a39da2dc", "release"].into_iter(),
+                )
+                .unwrap();
+            let response = run_handler(release_handler, &source);
+            assert_eq!(StatusCode::from(Status::NotFound), response.status());
+        }
+    }
+
+    #[test]
+    fn move_message_router() {
+        let source = TestRepoSource::new();
+        for queue_name in &["move-source", "move-target"] {
+            source
+                .get()
+                .unwrap()
+                .insert_queue(&QueueInput {
+                    visibility_timeout: 0,
+                    ..test_queue(queue_name)
+                })
+                .unwrap()
+                .unwrap();
+        }
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "move-source"].into_iter())
+            .unwrap();
+        let response = run_handler_with(publish_handler, &source, b"my message".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let receive = |queue_name: &str| {
+            let receive_handler = router
+                .route(&Method::GET, vec!["messages", queue_name].into_iter())
+                .unwrap();
+            let response = run_handler(receive_handler, &source);
+            if response.status() == StatusCode::from(Status::Ok) {
+                Some(MessageIdHeader::get(response.headers()))
+            } else {
+                None
+            }
+        };
+        let message_id = receive("move-source").unwrap();
+        let move_message = |message_id: &str, body: &[u8]| {
+            let move_handler = router
+                .route(&Method::POST, vec!["messages", message_id, "move"].into_iter())
+                .unwrap();
+            run_handler_with(move_handler, &source, body.to_vec()).status()
+        };
+        assert_eq!(
+            StatusCode::from(Status::BadRequest),
+            move_message("not a uuid", b"{\"queue\":\"move-target\"}")
+        );
+        assert_eq!(StatusCode::from(Status::BadRequest), move_message(&message_id, b"{}"));
+        assert_eq!(
+            StatusCode::from(Status::NotFound),
+            move_message(&message_id, b"{\"queue\":\"does-not-exist\"}")
+        );
+        assert_eq!(
+            StatusCode::from(Status::NotFound),
+            move_message("a7d46b8e-32fe-4d35-a3af-74f9a39da2dc", b"{\"queue\":\"move-target\"}")
+        );
+        assert_eq!(
+            StatusCode::from(Status::BadRequest),
+            move_message(&message_id, b"{\"queue\":\"move-source\"}")
+        );
+        assert_eq!(
+            StatusCode::from(Status::Ok),
+            move_message(&message_id, b"{\"queue\":\"move-target\"}")
+        );
+        assert_eq!(
+            StatusCode::from(Status::BadRequest),
+            move_message(&message_id, b"{\"queue\":\"move-target\"}")
+        );
+        assert_eq!(receive("move-source"), None);
+        assert_eq!(receive("move-target"), Some(message_id.clone()));
+
+        // the rejected move neither counted as a redrive nor changed the origin of the message
+        let mut repo = source.get().unwrap();
+        let queue = repo.find_by_name("move-target").unwrap().unwrap();
+        let message = repo
+            .peek_messages(&queue, 1, MessageFilter::default())
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(message.redrive_count, 1);
+        assert_eq!(message.origin_queue.as_deref(), Some("move-source"));
+    }
+
+    #[test]
+    fn redrive_router() {
+        let source = TestRepoSource::new();
+        for queue_name in &["redrive-all-dlq", "redrive-all-target"] {
+            source
+                .get()
+                .unwrap()
+                .insert_queue(&test_queue(queue_name))
+                .unwrap()
+                .unwrap();
+        }
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "redrive-all-dlq"].into_iter())
+            .unwrap();
+        // more messages than fit into a single batch
+        for i in 0..150 {
+            let response = run_handler_with(publish_handler.clone(), &source, format!("message {}", i).into_bytes());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        let redrive_handler = router
+            .route(
+                &Method::POST,
+                vec!["messages", "redrive-all-dlq", "redrive"].into_iter(),
+            )
+            .unwrap();
+        let redrive = |body: &[u8]| {
+            let mut response = run_handler_with(redrive_handler.clone(), &source, body.to_vec());
+            if response.status() == StatusCode::from(Status::Ok) {
+                let output: RedriveResponse = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+                Ok(output.moved)
+            } else {
+                Err(response.status())
+            }
+        };
+
+        assert_eq!(Err(StatusCode::from(Status::BadRequest)), redrive(b"{}"));
+        assert_eq!(
+            Err(StatusCode::from(Status::BadRequest)),
+            redrive(b"{\"queue\":\"redrive-all-dlq\",\"max\":10}")
+        );
+        assert_eq!(
+            Err(StatusCode::from(Status::NotFound)),
+            redrive(b"{\"queue\":\"does-not-exist\",\"max\":10}")
+        );
+        assert_eq!(Ok(0), redrive(b"{\"queue\":\"redrive-all-target\",\"max\":0}"));
+        assert_eq!(Ok(120), redrive(b"{\"queue\":\"redrive-all-target\",\"max\":120}"));
+        assert_eq!(Ok(30), redrive(b"{\"queue\":\"redrive-all-target\",\"max\":100}"));
+        assert_eq!(Ok(0), redrive(b"{\"queue\":\"redrive-all-target\",\"max\":100}"));
+
+        let mut repo = source.get().unwrap();
+        assert_eq!(repo.list_visible_message_ids("redrive-all-dlq", 200).unwrap().len(), 0);
+        assert_eq!(
+            repo.list_visible_message_ids("redrive-all-target", 200).unwrap().len(),
+            150
+        );
+        drop(repo);
+
+        // a message in flight stays with the consumer which received it
+        let response = run_handler_with(publish_handler, &source, b"in flight".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "redrive-all-dlq"].into_iter())
+            .unwrap();
+        let response = run_handler(receive_handler, &source);
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        assert_eq!(Ok(0), redrive(b"{\"queue\":\"redrive-all-target\",\"max\":100}"));
+    }
+
+    #[test]
+    fn list_in_flight_router() {
+        let clock = Arc::new(FixedClock::new(UtcTime::from_timestamp(1_590_000_000)));
+        let source = TestRepoSource::new().with_clock(clock.clone());
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                retention_timeout: 1000,
+                visibility_timeout: 30,
+                ..test_queue("in-flight-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "in-flight-queue"].into_iter())
+            .unwrap();
+        for payload in &[&b"first"[..], &b"second"[..]] {
+            let response = run_handler_with(publish_handler.clone(), &source, payload.to_vec());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        let list_in_flight = |queue_name: &str| {
+            let handler = router
+                .route(&Method::GET, vec!["messages", queue_name, "in-flight"].into_iter())
+                .unwrap();
+            let mut response = run_handler(handler, &source);
+            if response.status() == StatusCode::from(Status::Ok) {
+                let result: InFlightMessagesResponse = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+                Ok(result.messages)
+            } else {
+                Err(response.status())
+            }
+        };
+        assert_eq!(list_in_flight("in-flight-queue"), Ok(Vec::new()));
+        assert_eq!(
+            list_in_flight("does-not-exist"),
+            Err(StatusCode::from(Status::NotFound))
+        );
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "in-flight-queue"].into_iter())
+            .unwrap();
+        let response = run_handler(receive_handler, &source);
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        let message_id = MessageIdHeader::get(response.headers());
+        assert_eq!(
+            list_in_flight("in-flight-queue"),
+            Ok(vec![InFlightMessage {
+                message_id,
+                visible_at: "2020-05-20T18:40:30Z".to_string(),
+            }])
+        );
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(list_in_flight("in-flight-queue"), Ok(Vec::new()));
+        // a delayed message is hidden, but was never received, so it is not in flight
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                message_delay: 60,
+                ..test_queue("delayed-in-flight-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "delayed-in-flight-queue"].into_iter())
+            .unwrap();
+        let response = run_handler_with(publish_handler, &source, b"delayed".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        assert_eq!(list_in_flight("delayed-in-flight-queue"), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn delete_messages_by_hash_router() {
+        let source = TestRepoSource::new();
+        for (queue_name, content_based_deduplication) in &[("hash-queue", true), ("no-hash-queue", false)] {
+            source
+                .get()
+                .unwrap()
+                .insert_queue(&QueueInput {
+                    visibility_timeout: 0,
+                    content_based_deduplication: *content_based_deduplication,
+                    ..test_queue(queue_name)
+                })
+                .unwrap()
+                .unwrap();
+        }
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "hash-queue"].into_iter())
+            .unwrap();
+        let publish = |payload: &[u8]| {
+            let response = run_handler_with(publish_handler.clone(), &source, payload.to_vec());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        };
+        publish(b"poison");
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "hash-queue"].into_iter())
+            .unwrap();
+        let receive = || {
+            let mut response = run_handler(receive_handler.clone(), &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            let hash = ContentHashHeader::get(response.headers()).unwrap().to_string();
+            (read_body(response.body_mut()), hash)
+        };
+        let (body, hash) = receive();
+        assert_eq!(body, b"poison");
+        publish(b"fine");
+        let delete_by_hash = |queue_name: &str, hash: Option<&str>| {
+            let delete_handler = router
+                .route(&Method::DELETE, vec!["messages", queue_name, "hash"].into_iter())
+                .unwrap();
+            let mut req = Request::new(Body::default());
+            if let Some(hash) = hash {
+                req.headers_mut()
+                    .insert(ContentHashHeader::name(), HeaderValue::from_str(hash).unwrap());
+            }
+            let mut response = run_handler_with_request(delete_handler, &source, req, Vec::new());
+            if response.status() == StatusCode::from(Status::Ok) {
+                let result: DeleteByHashResponse = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+                Ok(result.deleted)
+            } else {
+                Err(response.status())
+            }
+        };
+        assert_eq!(
+            delete_by_hash("hash-queue", None),
+            Err(StatusCode::from(Status::BadRequest))
+        );
+        assert_eq!(
+            delete_by_hash("no-hash-queue", Some(&hash)),
+            Err(StatusCode::from(Status::BadRequest))
+        );
+        assert_eq!(
+            delete_by_hash("does-not-exist", Some(&hash)),
+            Err(StatusCode::from(Status::NotFound))
+        );
+        assert_eq!(delete_by_hash("hash-queue", Some(&hash)), Ok(1));
+        assert_eq!(delete_by_hash("hash-queue", Some(&hash)), Ok(0));
+        assert_eq!(receive().0, b"fine");
+    }
+
+    #[test]
+    fn redrive_headers_router() {
+        let source = TestRepoSource::new();
+        for (queue_name, max_receives, dead_letter_queue) in &[
+            ("redrive-source", Some(1), Some("redrive-dlq")),
+            ("redrive-dlq", None, None),
+        ] {
+            source
+                .get()
+                .unwrap()
+                .insert_queue(&QueueInput {
+                    max_receives: *max_receives,
+                    dead_letter_queue: *dead_letter_queue,
+                    visibility_timeout: 0,
+                    ..test_queue(queue_name)
+                })
+                .unwrap()
+                .unwrap();
+        }
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "redrive-source"].into_iter())
+            .unwrap();
+        let response = run_handler_with(publish_handler, &source, b"my message".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let receive = |queue_name: &str| {
+            let receive_handler = router
+                .route(&Method::GET, vec!["messages", queue_name].into_iter())
+                .unwrap();
+            let response = run_handler(receive_handler, &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            (
+                MessageIdHeader::get(response.headers()),
+                OriginQueueHeader::get(response.headers()).map(ToString::to_string),
+                RedriveReasonHeader::get(response.headers()).map(ToString::to_string),
+            )
+        };
+
+        // the first receive exhausts the receives of the message, so it is moved to the dead letter queue
+        let (message_id, origin_queue, redrive_reason) = receive("redrive-source");
+        assert_eq!(origin_queue, None);
+        assert_eq!(redrive_reason, None);
+        let (dlq_message_id, origin_queue, redrive_reason) = receive("redrive-dlq");
+        assert_eq!(dlq_message_id, message_id);
+        assert_eq!(origin_queue.as_deref(), Some("redrive-source"));
+        assert_eq!(redrive_reason.as_deref(), Some("max-receives"));
+
+        // moving the message back records the dead letter queue as its origin
+        let move_handler = router
+            .route(&Method::POST, vec!["messages", &message_id, "move"].into_iter())
+            .unwrap();
+        let response = run_handler_with(move_handler, &source, b"{\"queue\":\"redrive-source\"}".to_vec());
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        let (_, origin_queue, redrive_reason) = receive("redrive-source");
+        assert_eq!(origin_queue.as_deref(), Some("redrive-dlq"));
+        assert_eq!(redrive_reason.as_deref(), Some("moved"));
+    }
+
+    #[test]
+    fn rename_queue_router() {
+        let source = TestRepoSource::new();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let create_handler = router
+            .route(&Method::PUT, vec!["queues", "rename-router"].into_iter())
+            .unwrap();
+        let response = run_handler_with(
+            create_handler,
+            &source,
+            serde_json::to_vec(&QueueConfig::standard()).unwrap(),
+        );
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let rename = |queue_name: &str, body: &[u8]| {
+            let rename_handler = router
+                .route(&Method::POST, vec!["queues", queue_name, "rename"].into_iter())
+                .unwrap();
+            run_handler_with(rename_handler, &source, body.to_vec()).status()
+        };
+
+        assert_eq!(
+            rename("rename-router", b"{\"queue\":\"rename-router-new\"}"),
+            StatusCode::from(Status::BadRequest)
+        );
+        assert_eq!(
+            rename("rename-router", b"{\"name\":\"rename router\"}"),
+            StatusCode::from(Status::BadRequest)
+        );
+        assert_eq!(
+            rename("rename-router-missing", b"{\"name\":\"rename-router-new\"}"),
+            StatusCode::from(Status::NotFound)
+        );
+        assert_eq!(
+            rename("rename-router", b"{\"name\":\"rename-router-new\"}"),
+            StatusCode::from(Status::Ok)
+        );
+        assert_eq!(
+            rename("rename-router-new", b"{\"name\":\"rename-router-new\"}"),
+            StatusCode::from(Status::Ok)
+        );
+        assert!(source.get().unwrap().find_by_name("rename-router").unwrap().is_none());
+        assert!(source
+            .get()
+            .unwrap()
+            .find_by_name("rename-router-new")
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn max_redrives_router() {
+        let source = TestRepoSource::new();
+        // both queues use each other as dead letter queue, so a failing message would move forever
+        for (queue_name, dead_letter_queue, max_redrives) in &[
+            ("redrive-loop-a", "redrive-loop-b", Some(1)),
+            ("redrive-loop-b", "redrive-loop-a", None),
+        ] {
+            source
+                .get()
+                .unwrap()
+                .insert_queue(&QueueInput {
+                    max_receives: Some(1),
+                    dead_letter_queue: Some(dead_letter_queue),
+                    visibility_timeout: 0,
+                    max_redrives: *max_redrives,
+                    ..test_queue(queue_name)
+                })
+                .unwrap()
+                .unwrap();
+        }
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig {
+            allow_message_ids: true,
+            ..RouterConfig::default()
+        });
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "redrive-loop-a"].into_iter())
+            .unwrap();
+        let response = run_handler_with(publish_handler, &source, b"poison".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let receive = |queue_name: &str| {
+            let receive_handler = router
+                .route(&Method::GET, vec!["messages", queue_name].into_iter())
+                .unwrap();
+            let response = run_handler(receive_handler, &source);
+            if response.status() == StatusCode::from(Status::NoContent) {
+                return None;
+            }
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            Some(MessageIdHeader::get(response.headers()))
+        };
+
+        // the message is still delivered and moved while it was redriven less often than allowed
+        let message_id = receive("redrive-loop-a").unwrap();
+        assert_eq!(receive("redrive-loop-b"), Some(message_id.clone()));
+
+        // after the second redrive, the message is still delivered when it reaches the maximum
+        // receives, but it is deleted instead of being moved again
+        assert_eq!(receive("redrive-loop-a"), Some(message_id.clone()));
+        assert_eq!(receive("redrive-loop-b"), None);
+        assert_eq!(receive("redrive-loop-a"), None);
+
+        let delete_handler = router
+            .route(&Method::DELETE, vec!["messages", &message_id].into_iter())
+            .unwrap();
+        let response = run_handler(delete_handler, &source);
+        assert_eq!(StatusCode::from(Status::NotFound), response.status());
+    }
+
+    #[test]
+    fn max_redrives_without_dead_letter_queue_router() {
+        let source = TestRepoSource::new();
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                max_receives: Some(2),
+                visibility_timeout: 0,
+                max_redrives: Some(0),
+                ..test_queue("redrive-terminal")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "redrive-terminal"].into_iter())
+            .unwrap();
+        let response = run_handler_with(publish_handler, &source, b"poison".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "redrive-terminal"].into_iter())
+            .unwrap();
+        let receive = || run_handler(Arc::clone(&receive_handler), &source).status();
+
+        // without a dead letter queue, the message is deleted once it reaches the maximum receives
+        assert_eq!(receive(), StatusCode::from(Status::Ok));
+        assert_eq!(receive(), StatusCode::from(Status::Ok));
+        assert_eq!(receive(), StatusCode::from(Status::NoContent));
+    }
+
+    #[test]
+    fn options_router() {
+        let source = TestRepoSource::new();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        assert!(router
+            .route(&Method::OPTIONS, vec!["queues", "foo"].into_iter())
+            .is_none());
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig {
+            auto_options: true,
+            ..RouterConfig::default()
+        });
+        let options_handler = router
+            .route(&Method::OPTIONS, vec!["queues", "foo"].into_iter())
+            .unwrap();
+        let response = run_handler(options_handler, &source);
+        assert_eq!(StatusCode::from(Status::NoContent), response.status());
+        assert_eq!(
+            response.headers().get(ALLOW),
+            Some(&HeaderValue::from_static("DELETE, GET, OPTIONS, POST, PUT"))
+        );
+    }
+
+    #[test]
+    fn visibility_timeout_router() {
+        let clock = Arc::new(FixedClock::new(UtcTime::from_timestamp(1_590_000_000)));
+        let source = TestRepoSource::new().with_clock(clock.clone());
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                message_delay: 5,
+                ..test_queue("delayed-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "delayed-queue"].into_iter())
+            .unwrap();
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "delayed-queue"].into_iter())
+            .unwrap();
+        let receive = || run_handler(receive_handler.clone(), &source).status();
+        {
+            let response = run_handler_with(publish_handler, &source, b"message".to_vec());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        // the message is delayed for 5 seconds
+        assert_eq!(StatusCode::from(Status::NoContent), receive());
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(StatusCode::from(Status::Ok), receive());
+        // after a receive, the message is hidden for 10 seconds
+        assert_eq!(StatusCode::from(Status::NoContent), receive());
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(StatusCode::from(Status::NoContent), receive());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(StatusCode::from(Status::Ok), receive());
+    }
+
+    #[test]
+    fn visibility_jitter_router() {
+        let clock = Arc::new(FixedClock::new(UtcTime::from_timestamp(1_590_000_000)));
+        let random = Arc::new(FixedRandom::new(0.5));
+        let source = TestRepoSource::new()
+            .with_clock(clock.clone())
+            .with_random(random.clone());
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                visibility_jitter: Some(40),
+                ..test_queue("jitter-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "jitter-queue"].into_iter())
+            .unwrap();
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "jitter-queue"].into_iter())
+            .unwrap();
+        let receive = || run_handler(receive_handler.clone(), &source).status();
+        {
+            let response = run_handler_with(publish_handler, &source, b"message".to_vec());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        // half of the jitter band of 4 seconds is added to the visibility timeout
+        assert_eq!(StatusCode::from(Status::Ok), receive());
+        clock.advance(Duration::from_secs(11));
+        assert_eq!(StatusCode::from(Status::NoContent), receive());
+        clock.advance(Duration::from_secs(1));
+        // without a random offset, only the visibility timeout is used
+        random.set(0.0);
+        assert_eq!(StatusCode::from(Status::Ok), receive());
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(StatusCode::from(Status::NoContent), receive());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(StatusCode::from(Status::Ok), receive());
+    }
+
+    #[test]
+    fn message_priorities_router() {
+        let clock = Arc::new(FixedClock::new(UtcTime::from_timestamp(1_590_000_000)));
+        let source = TestRepoSource::new().with_clock(clock.clone());
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                message_priorities: true,
+                ..test_queue("priority-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "priority-queue"].into_iter())
+            .unwrap();
+        let publish = |priority: Option<&'static str>, message: &str| {
+            let mut req = Request::new(Body::default());
+            if let Some(priority) = priority {
+                req.headers_mut()
+                    .insert(PriorityHeader::name(), HeaderValue::from_static(priority));
+            }
+            let response = run_handler_with_request(publish_handler.clone(), &source, req, message.as_bytes().to_vec());
+            clock.advance(Duration::from_secs(1));
+            response.status()
+        };
+        assert_eq!(StatusCode::from(Status::Created), publish(None, "default"));
+        assert_eq!(StatusCode::from(Status::Created), publish(Some("5"), "first urgent"));
+        assert_eq!(StatusCode::from(Status::Created), publish(Some("-1"), "background"));
+        assert_eq!(StatusCode::from(Status::Created), publish(Some("5"), "second urgent"));
+        assert_eq!(StatusCode::from(Status::Created), publish(Some("1"), "important"));
+        assert_eq!(StatusCode::from(Status::BadRequest), publish(Some("urgent"), "invalid"));
+
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "priority-queue"].into_iter())
+            .unwrap();
+        // higher priorities are received first, equal priorities in the order they were published in
+        for (message, priority) in &[
+            ("first urgent", Some(Ok(5))),
+            ("second urgent", Some(Ok(5))),
+            ("important", Some(Ok(1))),
+            ("default", None),
+            ("background", Some(Ok(-1))),
+        ] {
+            let mut response = run_handler(receive_handler.clone(), &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            assert_eq!(&PriorityHeader::get(response.headers()), priority);
+            assert_eq!(read_body(response.body_mut()), message.as_bytes());
+        }
+        assert_eq!(
+            StatusCode::from(Status::NoContent),
+            run_handler(receive_handler, &source).status()
+        );
+    }
+
+    #[test]
+    fn max_in_flight_router() {
+        let clock = Arc::new(FixedClock::new(UtcTime::from_timestamp(1_590_000_000)));
+        let source = TestRepoSource::new().with_clock(clock.clone());
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                max_in_flight: Some(2),
+                ..test_queue("limited-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "limited-queue"].into_iter())
+            .unwrap();
+        for i in 0..3 {
+            let response = run_handler_with(publish_handler.clone(), &source, format!("message {}", i).into_bytes());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "limited-queue"].into_iter())
+            .unwrap();
+        let receive = || {
+            let mut req = Request::new(Body::default());
+            req.headers_mut().insert(
+                HeaderName::from_static("x-mqs-max-messages"),
+                HeaderValue::from_static("10"),
+            );
+            let mut response = run_handler_with_request(receive_handler.clone(), &source, req, Vec::new());
+            if response.status() == StatusCode::from(Status::NoContent) {
+                return 0;
+            }
+            let content_type = response.headers().get(CONTENT_TYPE).unwrap().to_str().unwrap();
+            let boundary = multipart::is_multipart(content_type).unwrap();
+            let body = read_body(response.body_mut());
+            multipart::parse(boundary.as_bytes(), &body).unwrap().len()
+        };
+
+        assert_eq!(receive(), 2);
+        // the third message is visible, but receiving it would exceed the limit
+        assert_eq!(receive(), 0);
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(receive(), 2);
+
+        let describe_handler = router
+            .route(&Method::GET, vec!["queues", "limited-queue"].into_iter())
+            .unwrap();
+        let mut response = run_handler(describe_handler, &source);
+        let description: QueueDescriptionOutput = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+        assert_eq!(description.max_in_flight, Some(2));
+    }
+
+    #[test]
+    fn receipt_handle_router() {
+        let clock = Arc::new(FixedClock::new(UtcTime::from_timestamp(1_590_000_000)));
+        let source = TestRepoSource::new().with_clock(clock.clone());
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                retention_timeout: 1000,
+                ..test_queue("receipt-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig {
+            allow_message_ids: false,
+            ..RouterConfig::default()
+        });
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "receipt-queue"].into_iter())
+            .unwrap();
+        let response = run_handler_with(publish_handler, &source, b"message".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "receipt-queue"].into_iter())
+            .unwrap();
+        {
+            // peeking at a message does not allow deleting it
+            let mut req = Request::new(Body::default());
+            req.headers_mut().insert(
+                HeaderName::from_static("x-mqs-headers-only"),
+                HeaderValue::from_static("true"),
+            );
+            let response = run_handler_with_request(receive_handler.clone(), &source, req, Vec::new());
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            assert_eq!(ReceiptHandleHeader::get(response.headers()), None);
+        }
+        let receive = || {
+            let response = run_handler(receive_handler.clone(), &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            ReceiptHandleHeader::get(response.headers()).unwrap().to_string()
+        };
+        let visibility_handler = router.route(&Method::PATCH, vec!["messages"].into_iter()).unwrap();
+        let change_visibility = |receipt: &str| {
+            let body = format!("{{\"message_ids\":[\"{}\"],\"visibility_timeout\":0}}", receipt);
+            let mut response = run_handler_with(visibility_handler.clone(), &source, body.into_bytes());
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            serde_json::from_slice::<ChangeVisibilityResponse>(&read_body(response.body_mut()))
+                .unwrap()
+                .changed
+        };
+        let delete = |receipt: &str| {
+            let handler = router
+                .route(&Method::DELETE, vec!["messages", receipt].into_iter())
+                .unwrap();
+            run_handler(handler, &source).status()
+        };
+        let release = |receipt: &str| {
+            let handler = router
+                .route(&Method::POST, vec!["messages", receipt, "release"].into_iter())
+                .unwrap();
+            run_handler(handler, &source).status()
+        };
+
+        let first_receipt = receive();
+        assert_eq!(change_visibility(&first_receipt), vec![true]);
+        // the message is received again, so the first handle becomes stale
+        clock.advance(Duration::from_secs(1));
+        let second_receipt = receive();
+        assert_ne!(first_receipt, second_receipt);
+        assert_eq!(change_visibility(&first_receipt), vec![false]);
+        assert_eq!(delete(&first_receipt), StatusCode::from(Status::Conflict));
+        assert_eq!(delete(&second_receipt), StatusCode::from(Status::Ok));
+        assert_eq!(delete(&second_receipt), StatusCode::from(Status::NotFound));
+        assert_eq!(delete("not-a-receipt-handle"), StatusCode::from(Status::BadRequest));
+        // message ids are only accepted if allowed in the config
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "receipt-queue"].into_iter())
+            .unwrap();
+        let response = run_handler_with(publish_handler, &source, b"second message".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let response = run_handler(receive_handler.clone(), &source);
+        let message_id = MessageIdHeader::get(response.headers());
+        let third_receipt = ReceiptHandleHeader::get(response.headers()).unwrap().to_string();
+        assert_eq!(delete(&message_id), StatusCode::from(Status::BadRequest));
+        assert_eq!(release(&message_id), StatusCode::from(Status::BadRequest));
+        assert_eq!(change_visibility(&message_id), vec![false]);
+        assert_eq!(release(&third_receipt), StatusCode::from(Status::Ok));
+        assert_eq!(release(&third_receipt), StatusCode::from(Status::NotFound));
+        clock.advance(Duration::from_secs(1));
+        let fourth_receipt = receive();
+        assert_eq!(release(&third_receipt), StatusCode::from(Status::NotFound));
+        assert_eq!(release(&fourth_receipt), StatusCode::from(Status::Ok));
+    }
+
+    #[test]
+    fn change_messages_visibility_router() {
+        let clock = Arc::new(FixedClock::new(UtcTime::from_timestamp(1_590_000_000)));
+        let source = TestRepoSource::new().with_clock(clock.clone());
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                retention_timeout: 1000,
+                ..test_queue("batch-visibility-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig {
+            allow_message_ids: true,
+            ..RouterConfig::default()
+        });
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "batch-visibility-queue"].into_iter())
+            .unwrap();
+        let response = run_handler_with(publish_handler.clone(), &source, b"message".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "batch-visibility-queue"].into_iter())
+            .unwrap();
+        let receive = || run_handler(receive_handler.clone(), &source);
+        let message_id = {
+            let response = receive();
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            MessageIdHeader::get(response.headers())
+        };
+        let visibility_handler = router.route(&Method::PATCH, vec!["messages"].into_iter()).unwrap();
+        let change_visibility = |body: String| run_handler_with(visibility_handler.clone(), &source, body.into_bytes());
+        {
+            let mut response = change_visibility(format!(
+                "{{\"message_ids\":[\"{}\",\"a7d46b8e-32fe-4d35-a3af-74f9a39da2dc\",\"not a uuid\"],\"visibility_timeout\":60}}",
+                message_id
+            ));
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            let result: ChangeVisibilityResponse = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+            assert_eq!(result.changed, vec![true, false, false]);
+        }
+        // the message stays hidden for 60 seconds instead of 10
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(StatusCode::from(Status::NoContent), receive().status());
+        {
+            let response = change_visibility(format!(
+                "{{\"message_ids\":[\"{}\"],\"visibility_timeout\":43201}}",
+                message_id
+            ));
+            assert_eq!(StatusCode::from(Status::BadRequest), response.status());
+        }
+        {
+            let response = change_visibility("{}".to_string());
+            assert_eq!(StatusCode::from(Status::BadRequest), response.status());
+        }
+        {
+            let message_ids = vec![format!("\"{}\"", message_id); 1001].join(",");
+            let response = change_visibility(format!(
+                "{{\"message_ids\":[{}],\"visibility_timeout\":0}}",
+                message_ids
+            ));
+            assert_eq!(StatusCode::from(Status::BadRequest), response.status());
+        }
+        {
+            let response = change_visibility(format!(
+                "{{\"message_ids\":[\"{}\"],\"visibility_timeout\":0}}",
+                message_id
+            ));
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+        }
+        assert_eq!(StatusCode::from(Status::Ok), receive().status());
+        // a message which was never received is not in flight, so its delay is not changed
+        let response = run_handler_with(publish_handler, &source, b"new message".to_vec());
+        assert_eq!(StatusCode::from(Status::Created), response.status());
+        let new_message_id = MessageIdHeader::get(response.headers());
+        {
+            let mut response = change_visibility(format!(
+                "{{\"message_ids\":[\"{}\"],\"visibility_timeout\":60}}",
+                new_message_id
+            ));
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            let result: ChangeVisibilityResponse = serde_json::from_slice(&read_body(response.body_mut())).unwrap();
+            assert_eq!(result.changed, vec![false]);
+        }
+    }
+
+    #[test]
+    fn message_attributes_router() {
+        let source = TestRepoSource::new();
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                visibility_timeout: 100,
+                ..test_queue("my-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "my-queue"].into_iter())
+            .unwrap();
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "my-queue"].into_iter())
+            .unwrap();
+        {
+            let mut req = Request::new(Body::default());
+            req.headers_mut().insert(
+                HeaderName::from_static("x-mqs-attr-tenant"),
+                HeaderValue::from_static("acme"),
+            );
+            req.headers_mut()
+                .insert(HeaderName::from_static("x-custom"), HeaderValue::from_static("dropped"));
+            let response = run_handler_with_request(publish_handler, &source, req, b"message".to_vec());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        {
+            let response = run_handler(receive_handler, &source);
+            assert_eq!(StatusCode::from(Status::Ok), response.status());
+            assert_eq!(
+                response.headers().get("x-mqs-attr-tenant"),
+                Some(&HeaderValue::from_static("acme"))
+            );
+            assert_eq!(response.headers().get("x-custom"), None);
+        }
+    }
+
+    #[test]
+    fn message_json_attributes_router() {
+        let source = TestRepoSource::new();
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                visibility_timeout: 100,
+                ..test_queue("json-attributes-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig::default());
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "json-attributes-queue"].into_iter())
+            .unwrap();
+        let publish = |attributes: &'static str| {
+            let mut req = Request::new(Body::default());
+            req.headers_mut()
+                .insert(MessageAttributesHeader::name(), HeaderValue::from_static(attributes));
+            run_handler_with_request(publish_handler.clone(), &source, req, b"message".to_vec()).status()
+        };
+
+        assert_eq!(publish("{"), StatusCode::from(Status::BadRequest));
+        assert_eq!(publish("[1, 2]"), StatusCode::from(Status::BadRequest));
+        assert_eq!(
+            publish("{\"tenant\": \"acme\", \"city\": \"Z\\u00fcrich\", \"priority\": 2}"),
+            StatusCode::from(Status::Created)
+        );
+
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "json-attributes-queue"].into_iter())
+            .unwrap();
+        let response = run_handler(receive_handler, &source);
+        assert_eq!(StatusCode::from(Status::Ok), response.status());
+        assert_eq!(
+            MessageAttributesHeader::get(response.headers()).unwrap().unwrap(),
+            serde_json::json!({"tenant": "acme", "city": "Zürich", "priority": 2})
+        );
+    }
+
+    #[test]
+    fn message_group_router() {
+        let source = TestRepoSource::new();
+        source
+            .get()
+            .unwrap()
+            .insert_queue(&QueueInput {
+                visibility_timeout: 100,
+                ..test_queue("my-queue")
+            })
+            .unwrap()
+            .unwrap();
+        let router = make_router::<TestRepo, &TestRepoSource>(&RouterConfig {
+            allow_message_ids: true,
+            ..RouterConfig::default()
+        });
+        let publish_handler = router
+            .route(&Method::POST, vec!["messages", "my-queue"].into_iter())
+            .unwrap();
+        for (group_id, message) in &[(Some("group"), "first"), (Some("group"), "second"), (None, "other")] {
+            let mut req = Request::new(Body::default());
+            if let Some(group_id) = group_id {
+                req.headers_mut()
+                    .insert(GroupIdHeader::name(), HeaderValue::from_static(group_id));
+            }
+            let response = run_handler_with_request(publish_handler.clone(), &source, req, message.as_bytes().to_vec());
+            assert_eq!(StatusCode::from(Status::Created), response.status());
+        }
+        let receive_handler = router
+            .route(&Method::GET, vec!["messages", "my-queue"].into_iter())
+            .unwrap();
+        let receive = || {
+            let mut req = Request::new(Body::default());
+            req.headers_mut().insert(
+                HeaderName::from_static("x-mqs-max-messages"),
+                HeaderValue::from_static("10"),
+            );
+            let mut response = run_handler_with_request(receive_handler.clone(), &source, req, Vec::new());
+            if response.status() == StatusCode::from(Status::NoContent) {
+                return Vec::new();
+            }
+            if let Some(boundary) = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|content_type| multipart::is_multipart(content_type.to_str().unwrap()))
+            {
+                let body = read_body(response.body_mut());
+                multipart::parse(boundary.as_bytes(), &body)
+                    .unwrap()
+                    .into_iter()
+                    .map(|(headers, _)| (MessageIdHeader::get(&headers), GroupIdHeader::get(&headers).is_some()))
+                    .collect()
+            } else {
+                vec![(
+                    MessageIdHeader::get(response.headers()),
+                    GroupIdHeader::get(response.headers()).is_some(),
+                )]
+            }
+        };
+        // only one message of the group is in flight at any time
+        let received = receive();
+        assert_eq!(received.len(), 2);
+        let grouped: Vec<&String> = received
+            .iter()
+            .filter(|(_, grouped)| *grouped)
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(grouped.len(), 1);
+        assert!(receive().is_empty());
+        let delete_handler = router
+            .route(&Method::DELETE, vec!["messages", grouped[0]].into_iter())
+            .unwrap();
+        assert_eq!(
+            StatusCode::from(Status::Ok),
+            run_handler(delete_handler, &source).status()
+        );
+        let received = receive();
+        assert_eq!(received.len(), 1);
+        assert!(received[0].1);
+        assert_ne!(&received[0].0, grouped[0]);
     }
 }
@@ -1,6 +1,6 @@
 use async_trait::async_trait;
 use hyper::{Body, Request, Response};
-use mqs_common::router::Handler;
+use mqs_common::router::{Handler, HandlerError, TryHandler};
 use std::convert::TryInto;
 
 use crate::{models::queue::QueueRepository, routes::queues};
@@ -11,6 +11,12 @@ pub struct DescribeQueueHandler {
 
 pub struct CreateQueueHandler {
     pub queue_name: String,
+    pub max_queues: Option<i64>,
+}
+
+pub struct CreateQueueWithDlqHandler {
+    pub queue_name: String,
+    pub max_queues: Option<i64>,
 }
 
 pub struct UpdateQueueHandler {
@@ -21,16 +27,36 @@ pub struct DeleteQueueHandler {
     pub queue_name: String,
 }
 
+pub struct DescribeQueueDeletionHandler {
+    pub queue_name: String,
+}
+
+pub struct RenameQueueHandler {
+    pub queue_name: String,
+}
+
 pub struct ListQueuesHandler;
 
 #[async_trait]
-impl<R: QueueRepository, S: Send> Handler<(R, S)> for DescribeQueueHandler {
-    async fn handle(&self, (mut repo, _): (R, S), _req: Request<Body>, _body: Vec<u8>) -> Response<Body>
+impl<R: QueueRepository, S: Send> TryHandler<(R, S)> for DescribeQueueHandler {
+    async fn try_handle(
+        &self,
+        (mut repo, _): (R, S),
+        req: Request<Body>,
+        _body: Vec<u8>,
+    ) -> Result<Response<Body>, HandlerError>
     where
         R: 'async_trait,
         S: 'async_trait,
     {
-        queues::describe(&mut repo, &self.queue_name).into_response()
+        let response = if queues::include_status(&req).map_err(HandlerError::BadRequest)? {
+            let age_percentiles = queues::include_age_percentiles(&req).map_err(HandlerError::BadRequest)?;
+            queues::describe(&mut repo, &self.queue_name, age_percentiles)
+        } else {
+            queues::describe_config(&mut repo, &self.queue_name)
+        };
+
+        Ok(response.into_response())
     }
 }
 
@@ -46,12 +72,12 @@ impl<R: QueueRepository, S: Send> Handler<(R, S)> for CreateQueueHandler {
         S: 'async_trait,
     {
         let params = serde_json::from_slice(body.as_slice());
-        queues::new(&mut repo, &self.queue_name, params).into_response()
+        queues::new(&mut repo, &self.queue_name, self.max_queues, params).into_response()
     }
 }
 
 #[async_trait]
-impl<R: QueueRepository, S: Send> Handler<(R, S)> for UpdateQueueHandler {
+impl<R: QueueRepository, S: Send> Handler<(R, S)> for CreateQueueWithDlqHandler {
     fn needs_body(&self) -> bool {
         true
     }
@@ -62,28 +88,94 @@ impl<R: QueueRepository, S: Send> Handler<(R, S)> for UpdateQueueHandler {
         S: 'async_trait,
     {
         let params = serde_json::from_slice(body.as_slice());
-        queues::update(&mut repo, &self.queue_name, params).into_response()
+        queues::new_with_dlq(&mut repo, &self.queue_name, self.max_queues, params).into_response()
+    }
+}
+
+#[async_trait]
+impl<R: QueueRepository, S: Send> TryHandler<(R, S)> for UpdateQueueHandler {
+    fn needs_body(&self) -> bool {
+        true
+    }
+
+    async fn try_handle(
+        &self,
+        (mut repo, _): (R, S),
+        req: Request<Body>,
+        body: Vec<u8>,
+    ) -> Result<Response<Body>, HandlerError>
+    where
+        R: 'async_trait,
+        S: 'async_trait,
+    {
+        let expected_version = queues::expected_version(&req).map_err(|err| {
+            error!("Failed to parse expected queue version: {}", err);
+            HandlerError::BadRequest(err)
+        })?;
+        let params = serde_json::from_slice(body.as_slice());
+
+        Ok(queues::update(&mut repo, &self.queue_name, expected_version, params).into_response())
+    }
+}
+
+#[async_trait]
+impl<R: QueueRepository, S: Send> TryHandler<(R, S)> for DeleteQueueHandler {
+    async fn try_handle(
+        &self,
+        (mut repo, _): (R, S),
+        _req: Request<Body>,
+        _body: Vec<u8>,
+    ) -> Result<Response<Body>, HandlerError>
+    where
+        R: 'async_trait,
+        S: 'async_trait,
+    {
+        Ok(queues::delete(&mut repo, &self.queue_name).into_response())
     }
 }
 
 #[async_trait]
-impl<R: QueueRepository, S: Send> Handler<(R, S)> for DeleteQueueHandler {
+impl<R: QueueRepository, S: Send> Handler<(R, S)> for DescribeQueueDeletionHandler {
     async fn handle(&self, (mut repo, _): (R, S), _req: Request<Body>, _body: Vec<u8>) -> Response<Body>
     where
         R: 'async_trait,
         S: 'async_trait,
     {
-        queues::delete(&mut repo, &self.queue_name).into_response()
+        queues::describe_deletion(&mut repo, &self.queue_name).into_response()
+    }
+}
+
+#[async_trait]
+impl<R: QueueRepository, S: Send> Handler<(R, S)> for RenameQueueHandler {
+    fn needs_body(&self) -> bool {
+        true
+    }
+
+    async fn handle(&self, (mut repo, _): (R, S), _req: Request<Body>, body: Vec<u8>) -> Response<Body>
+    where
+        R: 'async_trait,
+        S: 'async_trait,
+    {
+        let params = serde_json::from_slice(body.as_slice());
+        queues::rename(&mut repo, &self.queue_name, params).into_response()
     }
 }
 
 #[async_trait]
-impl<R: QueueRepository, S: Send> Handler<(R, S)> for ListQueuesHandler {
-    async fn handle(&self, (mut repo, _): (R, S), req: Request<Body>, _body: Vec<u8>) -> Response<Body>
+impl<R: QueueRepository, S: Send> TryHandler<(R, S)> for ListQueuesHandler {
+    async fn try_handle(
+        &self,
+        (mut repo, _): (R, S),
+        req: Request<Body>,
+        _body: Vec<u8>,
+    ) -> Result<Response<Body>, HandlerError>
     where
         R: 'async_trait,
         S: 'async_trait,
     {
-        queues::list(&mut repo, (&req).try_into()).into_response()
+        let range = (&req).try_into().map_err(HandlerError::BadRequest)?;
+        let with_status = queues::with_status(&req).map_err(HandlerError::BadRequest)?;
+
+        Ok(queues::list(&mut repo, range, queues::prefix(&req).as_deref(), with_status).into_response())
     }
 }
@@ -1,15 +1,47 @@
+use diesel::{data_types::PgInterval, QueryResult};
 use hyper::{
     header::{HeaderValue, CONTENT_ENCODING, CONTENT_TYPE},
     HeaderMap,
 };
-use mqs_common::{connection::Source, get_header, multipart, Status, TraceIdHeader, DEFAULT_CONTENT_TYPE};
+use mqs_common::{
+    connection::Source,
+    get_header,
+    multipart,
+    ChangeVisibilityRequest,
+    ChangeVisibilityResponse,
+    DeleteByHashResponse,
+    ExpiresAtHeader,
+    GroupIdHeader,
+    IdempotencyKeyHeader,
+    InFlightMessage,
+    InFlightMessagesResponse,
+    MessageAttributeHeaders,
+    MessageAttributesHeader,
+    MessageContentEncodingHeader,
+    MoveMessageRequest,
+    PriorityHeader,
+    PublishOutcome,
+    PublishRejection,
+    PublishResponse,
+    PublishedAtHeader,
+    RedriveRequest,
+    RedriveResponse,
+    Status,
+    TraceIdHeader,
+    UtcTime,
+    DEFAULT_CONTENT_TYPE,
+};
+use std::{collections::HashSet, time::Duration};
 use uuid::Uuid;
 
 use crate::{
+    compression::{decompress_gzip, is_gzip_encoded},
+    idempotency::{Reservation, PUBLISH_RESULTS},
     models::{
-        message::{MessageInput, MessageRepository},
-        queue::QueueRepository,
+        message::{Message, MessageFilter, MessageInput, MessageRepository, RedriveReason},
+        queue::{Queue, QueueRepository},
     },
+    receipt::ReceiptHandle,
     routes::MqsResponse,
     wait::MESSAGE_WAIT_QUEUE,
 };
@@ -20,12 +52,82 @@ fn boundary_from_headers(headers: &HeaderMap<HeaderValue>) -> Option<String> {
     multipart::is_multipart(content_type)
 }
 
+// messages without a content type get the default content type of their queue, if it has one
+fn message_content_type<'a>(headers: &'a HeaderMap<HeaderValue>, queue: &'a Queue) -> &'a str {
+    let default_content_type = queue.default_content_type.as_deref().unwrap_or(DEFAULT_CONTENT_TYPE);
+    headers
+        .get(CONTENT_TYPE)
+        .map_or_else(|| default_content_type, |v| v.to_str().unwrap_or(default_content_type))
+}
+
+// attributes are stored as `name:value`, header names can never contain a colon
+fn encode_attributes(headers: &HeaderMap<HeaderValue>) -> Vec<String> {
+    MessageAttributeHeaders::get(headers)
+        .into_iter()
+        .map(|(name, value)| format!("{}:{}", name, value))
+        .collect()
+}
+
+#[allow(clippy::too_many_lines)]
 pub async fn publish<R: QueueRepository + MessageRepository>(
     mut repo: R,
     queue_name: &str,
     message_content: &[u8],
-    headers: HeaderMap<HeaderValue>,
+    mut headers: HeaderMap<HeaderValue>,
+    max_message_size: usize,
 ) -> MqsResponse {
+    let idempotency_key = IdempotencyKeyHeader::get(&headers).map(ToString::to_string);
+    // multipart requests get the outcome of each message, a single message only needs the status
+    let detailed = boundary_from_headers(&headers).is_some();
+    // the key stays reserved until the request finished, a failed request releases it again
+    let reserved_key = match &idempotency_key {
+        None => None,
+        Some(key) => match PUBLISH_RESULTS.reserve(queue_name, key) {
+            Reservation::Done(outcomes) => {
+                info!(
+                    "Returning result of earlier publish request {} to queue {}",
+                    key, queue_name
+                );
+                return publish_response(&outcomes, detailed);
+            },
+            Reservation::InProgress => {
+                info!("Publish request {} to queue {} is still in progress", key, queue_name);
+                return MqsResponse::retry_later("A request with the same idempotency key is still in progress");
+            },
+            Reservation::Reserved(reserved_key) => Some(reserved_key),
+        },
+    };
+    // a gzip encoded request body is only compressed for transport, the message itself is stored
+    // with the encoding declared by the X-MQS-CONTENT-ENCODING header
+    let decompressed;
+    let message_content = if is_gzip_encoded(&headers) {
+        match decompress_gzip(message_content, max_message_size) {
+            Err(err) => {
+                info!("Failed to decompress request body: {}", err);
+                return MqsResponse::publish_rejected(
+                    PublishRejection::InvalidBody,
+                    "Failed to decompress request body",
+                );
+            },
+            Ok(None) => {
+                info!(
+                    "Rejecting request body exceeding {} bytes after decompression",
+                    max_message_size
+                );
+                return MqsResponse::publish_rejected(
+                    PublishRejection::TooLarge,
+                    "Request body exceeds the maximum message size after decompression",
+                );
+            },
+            Ok(Some(body)) => {
+                headers.remove(CONTENT_ENCODING);
+                decompressed = body;
+                decompressed.as_slice()
+            },
+        }
+    } else {
+        message_content
+    };
     let messages = boundary_from_headers(&headers).map_or_else(
         || Ok(vec![(headers, message_content)]),
         |boundary| multipart::parse(boundary.as_bytes(), message_content),
@@ -33,7 +135,10 @@ pub async fn publish<R: QueueRepository + MessageRepository>(
     let messages = match messages {
         Err(err) => {
             error!("Failed to understand request body: {}", err);
-            return MqsResponse::status(Status::BadRequest);
+            return MqsResponse::publish_rejected(
+                PublishRejection::InvalidBody,
+                "Failed to parse multipart request body",
+            );
         },
         Ok(messages) => messages,
     };
@@ -49,38 +154,199 @@ pub async fn publish<R: QueueRepository + MessageRepository>(
         Ok(Some(queue)) => queue,
     };
 
-    let mut created_some = false;
-
-    for (message_headers, message_payload) in messages {
-        info!("Inserting new message into queue {}", &queue_name);
-        match repo.insert_message(&queue, &MessageInput {
-            payload:          message_payload,
-            content_type:     message_headers
-                .get(CONTENT_TYPE)
-                .map_or_else(|| DEFAULT_CONTENT_TYPE, |v| v.to_str().unwrap_or(DEFAULT_CONTENT_TYPE)),
-            content_encoding: get_header(&message_headers, CONTENT_ENCODING),
-            trace_id:         TraceIdHeader::get(&message_headers),
-        }) {
-            Err(err) => {
-                error!("Failed to insert new message into queue {}: {}", &queue_name, err);
-                return MqsResponse::status(Status::InternalServerError);
+    // a message may expire earlier than the retention timeout of its queue, but never later
+    let now = repo.now();
+    let max_expires_at = now.add_pg_interval(&queue.retention_timeout);
+    // a message published earlier than the retention timeout allows would be deleted right away
+    let retention = &queue.retention_timeout;
+    let min_published_at = now.add_pg_interval(&PgInterval::new(
+        -retention.microseconds,
+        -retention.days,
+        -retention.months,
+    ));
+    let mut parsed_headers = Vec::with_capacity(messages.len());
+    for (message_headers, _) in &messages {
+        let content_type = message_content_type(message_headers, &queue);
+        if !queue.accepts_content_type(content_type) {
+            info!(
+                "Rejecting message with content type {} for queue {}",
+                content_type, &queue_name
+            );
+            return MqsResponse::publish_rejected(
+                PublishRejection::UnsupportedContentType,
+                "The queue does not accept the content type of the message",
+            );
+        }
+        let expires_at = match get_header(message_headers, ExpiresAtHeader::name())
+            .map(UtcTime::parse_from_rfc3339)
+            .transpose()
+        {
+            Err(_) => {
+                return MqsResponse::publish_rejected(
+                    PublishRejection::InvalidExpiryTime,
+                    "Failed to parse message expiry time",
+                )
             },
-            Ok(true) => {
-                debug!("Published new message into queue {}", &queue_name);
-                created_some = true;
+            Ok(expires_at) => expires_at,
+        };
+        if matches!(expires_at, Some(expires_at) if expires_at > max_expires_at) {
+            info!(
+                "Rejecting message expiring after the retention timeout of queue {}",
+                queue_name
+            );
+            return MqsResponse::publish_rejected(
+                PublishRejection::ExpiryExceedsRetention,
+                "Message expiry time exceeds the retention timeout of the queue",
+            );
+        }
+        let published_at = match get_header(message_headers, PublishedAtHeader::name())
+            .map(UtcTime::parse_from_rfc3339)
+            .transpose()
+        {
+            Err(_) => {
+                return MqsResponse::publish_rejected(
+                    PublishRejection::InvalidPublishTime,
+                    "Failed to parse message publish time",
+                )
             },
-            Ok(false) => {
-                debug!("New message already exists in queue {}", &queue_name);
+            Ok(published_at) => published_at,
+        };
+        if matches!(published_at, Some(published_at) if published_at > now) {
+            info!("Rejecting message published in the future for queue {}", queue_name);
+            return MqsResponse::publish_rejected(
+                PublishRejection::PublishTimeInFuture,
+                "Message publish time is in the future",
+            );
+        }
+        if matches!(published_at, Some(published_at) if published_at < min_published_at) {
+            info!(
+                "Rejecting message published before the retention timeout of queue {}",
+                queue_name
+            );
+            return MqsResponse::publish_rejected(
+                PublishRejection::PublishTimeExceedsRetention,
+                "Message publish time exceeds the retention timeout of the queue",
+            );
+        }
+        let json_attributes = match MessageAttributesHeader::get(message_headers).transpose() {
+            Err(_) => {
+                return MqsResponse::publish_rejected(
+                    PublishRejection::InvalidAttributes,
+                    "Failed to parse message attributes",
+                )
             },
+            Ok(json_attributes) => json_attributes,
+        };
+        if matches!(&json_attributes, Some(attributes) if !attributes.is_object()) {
+            return MqsResponse::publish_rejected(
+                PublishRejection::InvalidAttributes,
+                "Message attributes need to be a JSON object",
+            );
         }
+        let priority = match PriorityHeader::get(message_headers).transpose() {
+            Err(_) => {
+                return MqsResponse::publish_rejected(
+                    PublishRejection::InvalidPriority,
+                    "Failed to parse message priority",
+                )
+            },
+            Ok(priority) => priority,
+        };
+        parsed_headers.push((expires_at, published_at, json_attributes, priority));
     }
 
-    if created_some {
+    let attributes: Vec<Vec<String>> = messages
+        .iter()
+        .map(|(message_headers, _)| encode_attributes(message_headers))
+        .collect();
+    let inputs: Vec<MessageInput<'_>> = messages
+        .iter()
+        .zip(&attributes)
+        .zip(&parsed_headers)
+        .map(
+            |(
+                ((message_headers, message_payload), attributes),
+                (expires_at, published_at, json_attributes, priority),
+            )| {
+                MessageInput {
+                    payload: message_payload,
+                    content_type: message_content_type(message_headers, &queue),
+                    content_encoding: MessageContentEncodingHeader::get(message_headers)
+                        .or_else(|| get_header(message_headers, CONTENT_ENCODING)),
+                    trace_id: TraceIdHeader::get_raw(message_headers),
+                    group_id: GroupIdHeader::get(message_headers),
+                    attributes,
+                    json_attributes: json_attributes.as_ref(),
+                    expires_at: *expires_at,
+                    published_at: *published_at,
+                    priority: priority.unwrap_or(0),
+                }
+            },
+        )
+        .collect();
+
+    // insert all messages at once, so a failed request publishes nothing and can be retried with the
+    // same idempotency key
+    info!("Inserting {} new messages into queue {}", inputs.len(), &queue_name);
+    let ids = match repo.insert_messages(&queue, &inputs) {
+        Err(err) => {
+            error!("Failed to insert new messages into queue {}: {}", &queue_name, err);
+            return MqsResponse::status(Status::InternalServerError);
+        },
+        Ok(ids) => ids,
+    };
+    let outcomes: Vec<PublishOutcome> = ids
+        .into_iter()
+        .map(|id| {
+            if let Some(id) = id {
+                debug!("Published new message {} into queue {}", id, &queue_name);
+            } else {
+                debug!("New message already exists in queue {}", &queue_name);
+            }
+            PublishOutcome {
+                created:    id.is_some(),
+                message_id: id.map(|id| id.to_string()),
+            }
+        })
+        .collect();
+
+    let response = publish_response(&outcomes, detailed);
+    let created = outcomes.iter().any(|outcome| outcome.created);
+    // record the outcomes before waking up consumers, so a retry arriving meanwhile is answered with
+    // them instead of waiting for the key
+    if let Some(reserved_key) = reserved_key {
+        reserved_key.finish(outcomes);
+    }
+    if created {
         MESSAGE_WAIT_QUEUE.signal(&queue).await;
-        MqsResponse::status(Status::Created)
-    } else {
-        MqsResponse::status(Status::Ok)
     }
+
+    response
+}
+
+/// Maximum number of seconds the visibility of a message can be changed to.
+const MAX_VISIBILITY_TIMEOUT: u64 = 43200;
+
+/// Maximum number of messages whose visibility can be changed with a single request.
+const MAX_VISIBILITY_CHANGES: usize = 1000;
+
+/// Maximum number of messages returned when listing the messages in flight.
+const MAX_LISTED_IN_FLIGHT: i64 = 1000;
+
+fn publish_response(outcomes: &[PublishOutcome], detailed: bool) -> MqsResponse {
+    if !detailed {
+        // a request without multipart body always publishes a single message
+        return MqsResponse::Published(outcomes.first().and_then(|outcome| outcome.message_id.clone()));
+    }
+    let status = if outcomes.iter().any(|outcome| outcome.created) {
+        Status::Created
+    } else {
+        Status::Ok
+    };
+
+    MqsResponse::status_json(status, &PublishResponse {
+        messages: outcomes.to_vec(),
+    })
 }
 
 #[derive(Clone, Copy)]
@@ -88,25 +354,41 @@ pub struct MessageCount(pub i64);
 #[derive(Clone, Copy)]
 pub struct MaxWaitTime(pub u64);
 
+/// How messages are read from a queue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReceiveMode {
+    /// Receive the messages, they become visible again after the visibility timeout.
+    Receive,
+    /// Receive and delete the messages, they are received at most once.
+    AutoDelete,
+    /// Only return the metadata of the messages without receiving them. The body of the returned
+    /// messages is empty.
+    HeadersOnly,
+}
+
+fn fetch_messages<R: MessageRepository>(
+    repo: &mut R,
+    queue: &Queue,
+    count: MessageCount,
+    filter: MessageFilter<'_>,
+    mode: ReceiveMode,
+) -> QueryResult<Vec<Message>> {
+    match mode {
+        ReceiveMode::Receive => repo.get_message_from_queue(queue, count.0, filter, false),
+        ReceiveMode::AutoDelete => repo.get_message_from_queue(queue, count.0, filter, true),
+        ReceiveMode::HeadersOnly => repo.peek_messages(queue, count.0, filter),
+    }
+}
+
 pub async fn receive<R: QueueRepository + MessageRepository, S: Source<R>>(
     mut repo: R,
     repo_source: S,
     queue_name: &str,
-    message_count: Result<MessageCount, ()>,
-    max_wait_time: Result<Option<MaxWaitTime>, ()>,
+    count: MessageCount,
+    wait_time: Option<MaxWaitTime>,
+    filter: MessageFilter<'_>,
+    mode: ReceiveMode,
 ) -> MqsResponse {
-    let count = match message_count {
-        Err(_) => {
-            return MqsResponse::error_static("Failed to parse message count");
-        },
-        Ok(count) => count,
-    };
-    let wait_time = match max_wait_time {
-        Err(_) => {
-            return MqsResponse::error_static("Failed to parse maximal wait time");
-        },
-        Ok(wait_time) => wait_time,
-    };
     let queue = match repo.find_by_name_cached(queue_name) {
         Err(err) => {
             error!("Failed to find queue {} for message receive: {}", queue_name, err);
@@ -119,7 +401,7 @@ pub async fn receive<R: QueueRepository + MessageRepository, S: Source<R>>(
         Ok(Some(queue)) => queue,
     };
     debug!("Reading {} message(s) from queue {}", count.0, queue_name);
-    let mut messages = match repo.get_message_from_queue(&queue, count.0) {
+    let mut messages = match fetch_messages(&mut repo, &queue, count, filter, mode) {
         Ok(messages) => messages,
         Err(err) => {
             error!("Failed reading message from queue {}: {}", queue_name, err);
@@ -133,7 +415,7 @@ pub async fn receive<R: QueueRepository + MessageRepository, S: Source<R>>(
                 None => {
                     warn!("Failed to get second database connection");
                 },
-                Some(mut repo) => match repo.get_message_from_queue(&queue, count.0) {
+                Some(mut repo) => match fetch_messages(&mut repo, &queue, count, filter, mode) {
                     Ok(new_messages) => {
                         messages = new_messages;
                     },
@@ -147,14 +429,248 @@ pub async fn receive<R: QueueRepository + MessageRepository, S: Source<R>>(
     }
     if messages.is_empty() {
         MqsResponse::status(Status::NoContent)
+    } else if mode == ReceiveMode::Receive {
+        MqsResponse::received_messages(messages)
     } else {
         MqsResponse::messages(messages)
     }
 }
 
-pub fn delete<R: MessageRepository>(repo: &mut R, message_id: &str) -> MqsResponse {
+pub fn release<R: MessageRepository>(repo: &mut R, message_id: &str, allow_message_ids: bool) -> MqsResponse {
+    if let Some(receipt) = ReceiptHandle::decode(message_id) {
+        info!("Releasing message {} by receipt handle", receipt.id);
+        return released_response(receipt.id, repo.release_message_by_receipt(receipt));
+    }
+    if !allow_message_ids {
+        return MqsResponse::error_static("Message ID needs to be a receipt handle");
+    }
+
+    Uuid::parse_str(message_id).map_or_else(
+        |_| MqsResponse::error_static("Message ID needs to be a UUID or a receipt handle"),
+        |id| {
+            info!("Releasing message {}", id);
+            released_response(id, repo.release_message(id))
+        },
+    )
+}
+
+fn released_response(id: Uuid, released: QueryResult<bool>) -> MqsResponse {
+    match released {
+        Ok(true) => {
+            info!("Released message {}", id);
+            MqsResponse::status(Status::Ok)
+        },
+        Ok(false) => {
+            info!("Message {} was not found or is not in flight", id);
+            MqsResponse::status(Status::NotFound)
+        },
+        Err(err) => {
+            error!("Failed to release message {}: {}", id, err);
+            MqsResponse::status(Status::InternalServerError)
+        },
+    }
+}
+
+pub fn move_message<R: QueueRepository + MessageRepository>(
+    repo: &mut R,
+    message_id: &str,
+    params: Result<MoveMessageRequest, serde_json::Error>,
+) -> MqsResponse {
+    let id = match Uuid::parse_str(message_id) {
+        Err(_) => return MqsResponse::error_static("Message ID needs to be a UUID"),
+        Ok(id) => id,
+    };
+    let params = match params {
+        Err(err) => {
+            let err_message = format!("{:?}", err);
+            error!("Failed to parse move params: {}", err_message);
+            return MqsResponse::error_owned(&err_message);
+        },
+        Ok(params) => params,
+    };
+    match repo.find_by_name(&params.queue) {
+        Err(err) => {
+            error!("Failed to find queue {} to move message to: {}", params.queue, err);
+            return MqsResponse::status(Status::InternalServerError);
+        },
+        Ok(None) => {
+            info!("No queue with name {} found to move message {} to", params.queue, id);
+            return MqsResponse::status(Status::NotFound);
+        },
+        Ok(Some(_)) => {},
+    }
+    match repo.find_message_queue(id) {
+        Err(err) => {
+            error!("Failed to find message {} to move: {}", id, err);
+            return MqsResponse::status(Status::InternalServerError);
+        },
+        Ok(None) => {
+            info!("Message {} was not found", id);
+            return MqsResponse::status(Status::NotFound);
+        },
+        Ok(Some(queue)) if queue == params.queue => {
+            info!("Message {} is already in queue {}", id, queue);
+            return MqsResponse::error_static("Can not move a message to the queue it is in");
+        },
+        Ok(Some(_)) => {},
+    }
+
+    info!("Moving message {} to queue {}", id, params.queue);
+    match repo.move_message_to_queue(vec![id], &params.queue, RedriveReason::Moved) {
+        Ok(0) => {
+            info!("Message {} was not found", id);
+            MqsResponse::status(Status::NotFound)
+        },
+        Ok(_) => {
+            info!("Moved message {} to queue {}", id, params.queue);
+            MqsResponse::status(Status::Ok)
+        },
+        Err(err) => {
+            error!("Failed to move message {} to queue {}: {}", id, params.queue, err);
+            MqsResponse::status(Status::InternalServerError)
+        },
+    }
+}
+
+/// Number of messages moved per update when redriving a dead letter queue.
+const REDRIVE_BATCH_SIZE: u64 = 100;
+
+pub fn redrive<R: QueueRepository + MessageRepository>(
+    repo: &mut R,
+    queue_name: &str,
+    params: Result<RedriveRequest, serde_json::Error>,
+) -> MqsResponse {
+    let params = match params {
+        Err(err) => {
+            let err_message = format!("{:?}", err);
+            error!("Failed to parse redrive params: {}", err_message);
+            return MqsResponse::error_owned(&err_message);
+        },
+        Ok(params) => params,
+    };
+    if params.queue == queue_name {
+        return MqsResponse::error_static("Can not redrive messages to the queue they are in");
+    }
+    for name in [queue_name, params.queue.as_str()] {
+        match repo.find_by_name(name) {
+            Err(err) => {
+                error!("Failed to find queue {} for redrive: {}", name, err);
+                return MqsResponse::status(Status::InternalServerError);
+            },
+            Ok(None) => {
+                info!("No queue with name {} found for redrive", name);
+                return MqsResponse::status(Status::NotFound);
+            },
+            Ok(Some(_)) => {},
+        }
+    }
+
+    info!(
+        "Moving up to {} messages from queue {} to queue {}",
+        params.max, queue_name, params.queue
+    );
+    let mut moved = 0;
+    while moved < params.max {
+        let batch_size = (params.max - moved).min(REDRIVE_BATCH_SIZE);
+        let ids = match repo.list_visible_message_ids(queue_name, batch_size as i64) {
+            Ok(ids) => ids,
+            Err(err) => {
+                error!("Failed to list messages of queue {}: {}", queue_name, err);
+                return MqsResponse::status(Status::InternalServerError);
+            },
+        };
+        if ids.is_empty() {
+            break;
+        }
+        match repo.move_message_to_queue(ids, &params.queue, RedriveReason::Moved) {
+            Ok(count) => moved += count as u64,
+            Err(err) => {
+                error!(
+                    "Failed to move messages from queue {} to queue {}: {}",
+                    queue_name, params.queue, err
+                );
+                return MqsResponse::status(Status::InternalServerError);
+            },
+        }
+    }
+    info!(
+        "Moved {} messages from queue {} to queue {}",
+        moved, queue_name, params.queue
+    );
+
+    MqsResponse::json(&RedriveResponse { moved })
+}
+
+pub fn change_visibility<R: MessageRepository>(
+    repo: &mut R,
+    params: Result<ChangeVisibilityRequest, serde_json::Error>,
+    allow_message_ids: bool,
+) -> MqsResponse {
+    let params = match params {
+        Err(err) => {
+            let err_message = format!("{:?}", err);
+            error!("Failed to parse visibility params: {}", err_message);
+            return MqsResponse::error_owned(&err_message);
+        },
+        Ok(params) => params,
+    };
+    if params.visibility_timeout > MAX_VISIBILITY_TIMEOUT {
+        return MqsResponse::error_static("Visibility timeout must not exceed 43200 seconds");
+    }
+    if params.message_ids.len() > MAX_VISIBILITY_CHANGES {
+        return MqsResponse::error_static("The visibility of at most 1000 messages can be changed at once");
+    }
+
+    // each entry is a receipt handle or, if allowed, a message id
+    let mut valid_ids = Vec::with_capacity(params.message_ids.len());
+    let mut receipts = Vec::new();
+    let ids: Vec<Option<Uuid>> = params
+        .message_ids
+        .iter()
+        .map(|id| {
+            if let Some(id) = Uuid::parse_str(id).ok().filter(|_| allow_message_ids) {
+                valid_ids.push(id);
+                Some(id)
+            } else if let Some(receipt) = ReceiptHandle::decode(id) {
+                receipts.push(receipt);
+                Some(receipt.id)
+            } else {
+                None
+            }
+        })
+        .collect();
+    info!(
+        "Changing visibility of {} messages to {} seconds",
+        params.message_ids.len(),
+        params.visibility_timeout
+    );
+    match repo.change_messages_visibility(valid_ids, &receipts, Duration::from_secs(params.visibility_timeout)) {
+        Ok(changed_ids) => {
+            info!("Changed visibility of {} messages", changed_ids.len());
+            let changed_ids: HashSet<Uuid> = changed_ids.into_iter().collect();
+            let changed = ids
+                .iter()
+                .map(|id| matches!(id, Some(id) if changed_ids.contains(id)))
+                .collect();
+            MqsResponse::json(&ChangeVisibilityResponse { changed })
+        },
+        Err(err) => {
+            error!("Failed to change visibility of messages: {}", err);
+            MqsResponse::status(Status::InternalServerError)
+        },
+    }
+}
+
+pub fn delete<R: MessageRepository>(repo: &mut R, message_id: &str, allow_message_ids: bool) -> MqsResponse {
+    if let Some(receipt) = ReceiptHandle::decode(message_id) {
+        return delete_by_receipt(repo, receipt);
+    }
+    if !allow_message_ids {
+        return MqsResponse::error_static("Message ID needs to be a receipt handle");
+    }
+
     Uuid::parse_str(message_id).map_or_else(
-        |_| MqsResponse::error_static("Message ID needs to be a UUID"),
+        |_| MqsResponse::error_static("Message ID needs to be a UUID or a receipt handle"),
         |id| {
             info!("Deleting message {}", id);
             let deleted = repo.delete_message_by_id(id);
@@ -175,3 +691,107 @@ pub fn delete<R: MessageRepository>(repo: &mut R, message_id: &str) -> MqsRespon
         },
     )
 }
+
+fn delete_by_receipt<R: MessageRepository>(repo: &mut R, receipt: ReceiptHandle) -> MqsResponse {
+    info!("Deleting message {} by receipt handle", receipt.id);
+    match repo.delete_message_by_receipt(receipt) {
+        Ok(Some(true)) => {
+            info!("Deleted message {}", receipt.id);
+            MqsResponse::status(Status::Ok)
+        },
+        Ok(Some(false)) => {
+            info!(
+                "Message {} was received again since the receipt handle was issued",
+                receipt.id
+            );
+            MqsResponse::status(Status::Conflict)
+        },
+        Ok(None) => {
+            info!("Message {} was not found", receipt.id);
+            MqsResponse::status(Status::NotFound)
+        },
+        Err(err) => {
+            error!("Failed to delete message {}: {}", receipt.id, err);
+            MqsResponse::status(Status::InternalServerError)
+        },
+    }
+}
+
+pub fn delete_by_hash<R: QueueRepository + MessageRepository>(
+    repo: &mut R,
+    queue_name: &str,
+    hash: Option<&str>,
+) -> MqsResponse {
+    let hash = match hash {
+        None => return MqsResponse::error_static("Content hash is missing"),
+        Some(hash) => hash,
+    };
+    match repo.find_by_name(queue_name) {
+        Err(err) => {
+            error!("Failed to find queue {}: {}", queue_name, err);
+            return MqsResponse::status(Status::InternalServerError);
+        },
+        Ok(None) => {
+            info!("Queue {} was not found", queue_name);
+            return MqsResponse::status(Status::NotFound);
+        },
+        // messages only have a hash if their queue deduplicates them by content
+        Ok(Some(queue)) if !queue.content_based_deduplication => {
+            return MqsResponse::error_static("Queue does not use content based deduplication");
+        },
+        Ok(Some(_)) => {},
+    }
+
+    info!("Deleting messages with hash {} from queue {}", hash, queue_name);
+    match repo.delete_messages_by_hash(queue_name, hash) {
+        Ok(deleted) => {
+            info!(
+                "Deleted {} messages with hash {} from queue {}",
+                deleted, hash, queue_name
+            );
+            MqsResponse::json(&DeleteByHashResponse {
+                deleted: deleted as u64,
+            })
+        },
+        Err(err) => {
+            error!(
+                "Failed to delete messages with hash {} from queue {}: {}",
+                hash, queue_name, err
+            );
+            MqsResponse::status(Status::InternalServerError)
+        },
+    }
+}
+
+pub fn list_in_flight<R: QueueRepository + MessageRepository>(repo: &mut R, queue_name: &str) -> MqsResponse {
+    match repo.find_by_name(queue_name) {
+        Err(err) => {
+            error!("Failed to find queue {}: {}", queue_name, err);
+            return MqsResponse::status(Status::InternalServerError);
+        },
+        Ok(None) => {
+            info!("Queue {} was not found", queue_name);
+            return MqsResponse::status(Status::NotFound);
+        },
+        Ok(Some(_)) => {},
+    }
+
+    match repo.list_in_flight(queue_name, MAX_LISTED_IN_FLIGHT) {
+        Ok(in_flight) => {
+            info!("Found {} messages in flight in queue {}", in_flight.len(), queue_name);
+            MqsResponse::json(&InFlightMessagesResponse {
+                messages: in_flight
+                    .into_iter()
+                    .map(|(id, visible_since)| InFlightMessage {
+                        message_id: id.to_string(),
+                        visible_at: visible_since.to_rfc3339(),
+                    })
+                    .collect(),
+            })
+        },
+        Err(err) => {
+            error!("Failed to list messages in flight in queue {}: {}", queue_name, err);
+            MqsResponse::status(Status::InternalServerError)
+        },
+    }
+}
@@ -1,20 +1,35 @@
 use hyper::{
-    header::{HeaderValue, CONTENT_ENCODING, CONTENT_TYPE},
+    header::{HeaderName, HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER},
     Body,
     HeaderMap,
 };
 use mqs_common::{
     multipart,
+    router::ErrorMessage,
+    ContentHashHeader,
+    DeduplicatedHeader,
+    ExpiresAtHeader,
+    FirstReceivedAtHeader,
+    GroupIdHeader,
+    LastReceivedAtHeader,
+    MessageAttributeHeaders,
+    MessageAttributesHeader,
     MessageIdHeader,
     MessageReceivesHeader,
+    OriginQueueHeader,
+    PriorityHeader,
+    PublishRejection,
+    PublishRejectionResponse,
     PublishedAtHeader,
+    ReceiptHandleHeader,
+    RedriveReasonHeader,
     Status,
     TraceIdHeader,
     VisibleAtHeader,
 };
 use serde::Serialize;
 
-use crate::models::message::Message;
+use crate::{models::message::Message, receipt::ReceiptHandle};
 
 pub mod messages;
 pub mod queues;
@@ -28,7 +43,17 @@ pub struct ErrorResponse<'a> {
 pub enum MqsResponse {
     Status(Status),
     Json(Status, String),
-    Message(Status, Vec<Message>),
+    /// An error response and its message, which is sent instead of the body if errors are
+    /// formatted as plain text.
+    Error(Box<MqsResponse>, String),
+    /// Messages and whether a receipt handle is returned with each of them.
+    Message(Status, Vec<Message>, bool),
+    Multipart(Status, Vec<Message>, bool),
+    /// Outcome of publishing a single message: the id of the new message or `None` if it was
+    /// dropped as a duplicate.
+    Published(Option<String>),
+    /// A response asking the client to retry the request after one second.
+    RetryLater(Box<MqsResponse>),
 }
 
 impl MqsResponse {
@@ -37,11 +62,47 @@ impl MqsResponse {
     }
 
     pub(crate) fn error_static(error: &'static str) -> Self {
-        Self::status_json(Status::BadRequest, &ErrorResponse { error })
+        Self::status_error(Status::BadRequest, error)
     }
 
     pub(crate) fn error_owned(error: &str) -> Self {
-        Self::status_json(Status::BadRequest, &ErrorResponse { error })
+        Self::status_error(Status::BadRequest, error)
+    }
+
+    pub(crate) fn status_error(status: Status, error: &str) -> Self {
+        Self::Error(
+            Box::new(Self::status_json(status, &ErrorResponse { error })),
+            error.to_string(),
+        )
+    }
+
+    /// Answer with 503 Service Unavailable and ask the client to retry the request shortly.
+    pub(crate) fn retry_later(error: &str) -> Self {
+        Self::RetryLater(Box::new(Self::status_error(Status::ServiceUnavailable, error)))
+    }
+
+    /// Reject a publish request. JSON error responses include the reason, so clients can tell the
+    /// different problems apart.
+    pub(crate) fn publish_rejected(reason: PublishRejection, error: &str) -> Self {
+        let status = match reason {
+            PublishRejection::TooLarge => Status::PayloadTooLarge,
+            PublishRejection::UnsupportedContentType => Status::UnsupportedMediaType,
+            PublishRejection::InvalidBody
+            | PublishRejection::InvalidExpiryTime
+            | PublishRejection::ExpiryExceedsRetention
+            | PublishRejection::InvalidPublishTime
+            | PublishRejection::PublishTimeInFuture
+            | PublishRejection::PublishTimeExceedsRetention
+            | PublishRejection::InvalidAttributes
+            | PublishRejection::InvalidPriority => Status::BadRequest,
+        };
+        Self::Error(
+            Box::new(Self::status_json(status, &PublishRejectionResponse {
+                error:  error.to_string(),
+                reason: Some(reason),
+            })),
+            error.to_string(),
+        )
     }
 
     pub(crate) fn json<T: Serialize>(body: &T) -> Self {
@@ -60,7 +121,21 @@ impl MqsResponse {
     }
 
     pub(crate) fn messages(messages: Vec<Message>) -> Self {
-        Self::Message(Status::Ok, messages)
+        Self::Message(Status::Ok, messages, false)
+    }
+
+    /// Return messages which were just received together with a receipt handle for each of them.
+    pub(crate) fn received_messages(messages: Vec<Message>) -> Self {
+        Self::Message(Status::Ok, messages, true)
+    }
+
+    /// Encode messages as a multipart document even if there is only a single message. Responses
+    /// without messages are returned unchanged.
+    pub(crate) fn force_multipart(self) -> Self {
+        match self {
+            Self::Message(status, messages, receipts) => Self::Multipart(status, messages, receipts),
+            response => response,
+        }
     }
 
     pub(crate) fn into_response(self) -> hyper::Response<Body> {
@@ -70,43 +145,81 @@ impl MqsResponse {
                 *res.status_mut() = status.into();
                 res
             },
+            Self::RetryLater(response) => {
+                let mut res = response.into_response();
+                res.headers_mut().insert(RETRY_AFTER, HeaderValue::from_static("1"));
+                res
+            },
             Self::Json(status, body) => {
+                let content_length = HeaderValue::from(body.len());
                 let mut res = hyper::Response::new(Body::from(body));
                 *res.status_mut() = status.into();
                 res.headers_mut()
                     .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+                res.headers_mut().insert(CONTENT_LENGTH, content_length);
                 res
             },
-            Self::Message(status, mut messages) => {
+            Self::Error(response, message) => {
+                let mut res = response.into_response();
+                res.extensions_mut().insert(ErrorMessage(message));
+                res
+            },
+            Self::Message(status, mut messages, receipts) => {
                 if messages.len() == 1 {
                     let message = messages.pop().unwrap();
 
                     let mut res = hyper::Response::new(Body::default());
                     *res.status_mut() = status.into();
-                    Self::add_message_headers(res.headers_mut(), &message);
+                    Self::add_message_headers(res.headers_mut(), &message, receipts);
+                    res.headers_mut()
+                        .insert(CONTENT_LENGTH, HeaderValue::from(message.payload.len()));
                     *res.body_mut() = Body::from(message.payload);
                     return res;
                 }
 
-                let message_parts = messages.into_iter().map(|message| {
-                    let mut headers = HeaderMap::new();
-                    Self::add_message_headers(&mut headers, &message);
-                    (headers, message.payload)
-                });
-                let (boundary, body) = multipart::encode(message_parts);
-
-                let mut res = hyper::Response::new(Body::from(body));
-                *res.status_mut() = status.into();
-                res.headers_mut().insert(
-                    CONTENT_TYPE,
-                    HeaderValue::from_str(&format!("multipart/mixed; boundary={}", &boundary)).unwrap(),
-                );
+                Self::multipart_response(status, messages, receipts)
+            },
+            Self::Multipart(status, messages, receipts) => Self::multipart_response(status, messages, receipts),
+            Self::Published(message_id) => {
+                let mut res = hyper::Response::new(Body::default());
+                if let Some(message_id) = message_id {
+                    *res.status_mut() = Status::Created.into();
+                    if let Ok(value) = HeaderValue::from_str(&message_id) {
+                        res.headers_mut().insert(MessageIdHeader::name(), value);
+                    }
+                } else {
+                    *res.status_mut() = Status::Ok.into();
+                    res.headers_mut()
+                        .insert(DeduplicatedHeader::name(), HeaderValue::from_static("true"));
+                }
                 res
             },
         }
     }
 
-    fn add_message_headers(headers: &mut HeaderMap, message: &Message) {
+    fn multipart_response(status: Status, messages: Vec<Message>, receipts: bool) -> hyper::Response<Body> {
+        let message_parts: Vec<_> = messages
+            .into_iter()
+            .map(|message| {
+                let mut headers = HeaderMap::new();
+                Self::add_message_headers(&mut headers, &message, receipts);
+                (headers, message.payload)
+            })
+            .collect();
+        // encode the parts while sending the response instead of building a second copy of
+        // all messages in memory
+        let document = multipart::encode_streaming(message_parts);
+        let content_type = document.content_type_header();
+        let content_length = HeaderValue::from(document.encoded_len());
+
+        let mut res = hyper::Response::new(document.into_body());
+        *res.status_mut() = status.into();
+        res.headers_mut().insert(CONTENT_TYPE, content_type);
+        res.headers_mut().insert(CONTENT_LENGTH, content_length);
+        res
+    }
+
+    fn add_message_headers(headers: &mut HeaderMap, message: &Message, receipts: bool) {
         if let Ok(value) = HeaderValue::from_str(&message.content_type) {
             headers.insert(CONTENT_TYPE, value);
         }
@@ -115,30 +228,89 @@ impl MqsResponse {
                 headers.insert(CONTENT_ENCODING, value);
             }
         }
-        if let Some(trace_id) = message.trace_id {
-            if let Ok(value) = HeaderValue::from_str(&trace_id.to_string()) {
+        if let Some(trace_id) = &message.trace_id {
+            if let Ok(value) = HeaderValue::from_str(trace_id) {
                 headers.insert(TraceIdHeader::name(), value);
             }
         }
+        if let Some(group_id) = &message.group_id {
+            if let Ok(value) = HeaderValue::from_str(group_id) {
+                headers.insert(GroupIdHeader::name(), value);
+            }
+        }
+        if let Some(origin_queue) = &message.origin_queue {
+            if let Ok(value) = HeaderValue::from_str(origin_queue) {
+                headers.insert(OriginQueueHeader::name(), value);
+            }
+        }
+        if let Some(redrive_reason) = &message.redrive_reason {
+            if let Ok(value) = HeaderValue::from_str(redrive_reason) {
+                headers.insert(RedriveReasonHeader::name(), value);
+            }
+        }
         if let Ok(value) = HeaderValue::from_str(&message.id.to_string()) {
             headers.insert(MessageIdHeader::name(), value);
         }
+        if receipts {
+            if let Some(receipt) = ReceiptHandle::for_message(message) {
+                if let Ok(value) = HeaderValue::from_str(&receipt.encode()) {
+                    headers.insert(ReceiptHandleHeader::name(), value);
+                }
+            }
+        }
         if let Ok(value) = HeaderValue::from_str(&format!("{}", message.receives)) {
             headers.insert(MessageReceivesHeader::name(), value);
         }
-        if let Ok(value) = HeaderValue::from_str(&message.created_at.to_rfc3339()) {
+        if message.priority != 0 {
+            headers.insert(PriorityHeader::name(), HeaderValue::from(message.priority));
+        }
+        if let Ok(value) = HeaderValue::from_str(&message.published_at.to_rfc3339()) {
             headers.insert(PublishedAtHeader::name(), value);
         }
         if let Ok(value) = HeaderValue::from_str(&message.visible_since.to_rfc3339()) {
             headers.insert(VisibleAtHeader::name(), value);
         }
+        if let Some(first_received_at) = &message.first_received_at {
+            if let Ok(value) = HeaderValue::from_str(&first_received_at.to_rfc3339()) {
+                headers.insert(FirstReceivedAtHeader::name(), value);
+            }
+        }
+        if let Some(last_received_at) = &message.last_received_at {
+            if let Ok(value) = HeaderValue::from_str(&last_received_at.to_rfc3339()) {
+                headers.insert(LastReceivedAtHeader::name(), value);
+            }
+        }
+        if let Some(expires_at) = &message.expires_at {
+            if let Ok(value) = HeaderValue::from_str(&expires_at.to_rfc3339()) {
+                headers.insert(ExpiresAtHeader::name(), value);
+            }
+        }
+        if let Some(hash) = &message.hash {
+            if let Ok(value) = HeaderValue::from_str(hash) {
+                headers.insert(ContentHashHeader::name(), value);
+            }
+        }
+        if let Some(json_attributes) = &message.json_attributes {
+            if let Ok(value) = HeaderValue::from_str(&MessageAttributesHeader::encode(json_attributes)) {
+                headers.insert(MessageAttributesHeader::name(), value);
+            }
+        }
+        for attribute in &message.attributes {
+            if let Some((name, value)) = attribute.split_once(':') {
+                if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+                    if MessageAttributeHeaders::is_attribute(&name) {
+                        headers.append(name, value);
+                    }
+                }
+            }
+        }
     }
 }
 
 #[cfg(test)]
 pub(crate) mod test {
     use super::*;
-    use mqs_common::{test::read_body, UtcTime};
+    use mqs_common::{router::ErrorFormat, test::read_body, UtcTime};
 
     #[test]
     fn status_response() {
@@ -161,23 +333,42 @@ pub(crate) mod test {
         ];
         for (expected_status, response) in responses.iter_mut() {
             assert_eq!(response.status().as_u16(), *expected_status as u16);
-            assert_eq!(response.headers().len(), 1);
+            assert_eq!(response.headers().len(), 2);
             assert_eq!(
                 response.headers().get(CONTENT_TYPE).unwrap(),
                 HeaderValue::from_static("application/json")
             );
+            assert_eq!(response.headers().get(CONTENT_LENGTH).unwrap(), "16");
             assert_eq!(read_body(response.body_mut()).as_slice(), json.as_bytes());
         }
     }
 
+    #[test]
+    fn text_error_response() {
+        let mut response = ErrorFormat::Text
+            .format_response(MqsResponse::status_error(Status::TooManyRequests, "too many queues").into_response());
+        assert_eq!(response.status().as_u16(), Status::TooManyRequests as u16);
+        assert_eq!(response.headers().len(), 2);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            HeaderValue::from_static("text/plain; charset=utf-8")
+        );
+        assert_eq!(response.headers().get(CONTENT_LENGTH).unwrap(), "15");
+        assert_eq!(read_body(response.body_mut()).as_slice(), b"too many queues");
+    }
+
     #[test]
     fn message_response_empty() {
         let mut response = MqsResponse::messages(Vec::new()).into_response();
         assert_eq!(response.status().as_u16(), Status::Ok as u16);
-        assert_eq!(response.headers().len(), 1);
+        assert_eq!(response.headers().len(), 2);
         let ct = response.headers().get(CONTENT_TYPE).unwrap().to_str().unwrap();
         assert!(ct.starts_with("multipart/mixed; boundary="));
         let boundary = ct["multipart/mixed; boundary=".len()..].to_string();
+        assert_eq!(
+            response.headers().get(CONTENT_LENGTH).unwrap(),
+            &format!("{}", boundary.len() + 4)
+        );
         assert_eq!(
             read_body(response.body_mut()).as_slice(),
             format!("--{}--", boundary).as_bytes()
@@ -187,7 +378,7 @@ pub(crate) mod test {
     fn mk_message(index: u8, encoding: Option<String>) -> Message {
         let now = UtcTime::now();
         Message {
-            id:               uuid::Uuid::from_bytes([
+            id:                uuid::Uuid::from_bytes([
                 10 + index,
                 20,
                 30,
@@ -205,22 +396,35 @@ pub(crate) mod test {
                 34,
                 44,
             ]),
-            payload:          vec![65, 66, 67],
-            content_type:     "text/plain".to_string(),
-            content_encoding: encoding,
-            hash:             None,
-            queue:            String::new(),
-            receives:         index as i32 + 1,
-            visible_since:    now,
-            created_at:       now,
-            trace_id:         None,
+            payload:           vec![65, 66, 67],
+            content_type:      "text/plain".to_string(),
+            content_encoding:  encoding,
+            hash:              None,
+            queue:             String::new(),
+            receives:          index as i32 + 1,
+            visible_since:     now,
+            created_at:        now,
+            trace_id:          None,
+            first_received_at: None,
+            last_received_at:  None,
+            nacks:             0,
+            group_id:          None,
+            attributes:        Vec::new(),
+            expires_at:        None,
+            origin_queue:      None,
+            redrive_reason:    None,
+            json_attributes:   None,
+            redrive_count:     0,
+            priority:          0,
+            published_at:      now,
         }
     }
 
     fn message_response_single_with_encoding(encoding: Option<String>) {
         let mut response = MqsResponse::messages(vec![mk_message(0, encoding.clone())]).into_response();
         assert_eq!(response.status().as_u16(), Status::Ok as u16);
-        assert_eq!(response.headers().len(), if encoding.is_some() { 6 } else { 5 });
+        assert_eq!(response.headers().len(), if encoding.is_some() { 7 } else { 6 });
+        assert_eq!(response.headers().get(CONTENT_LENGTH).unwrap(), "3");
         let ct = response.headers().get(CONTENT_TYPE).unwrap().to_str().unwrap();
         let message_id = MessageIdHeader::get(response.headers());
         assert_eq!(ct, "text/plain");
@@ -242,6 +446,71 @@ pub(crate) mod test {
         message_response_single_with_encoding(Some("gzip".to_string()));
     }
 
+    #[test]
+    fn message_response_single_received() {
+        let mut message = mk_message(0, None);
+        let first_received_at = UtcTime::from_timestamp(449_884_800);
+        let last_received_at = UtcTime::from_timestamp(449_884_830);
+        message.first_received_at = Some(first_received_at);
+        message.last_received_at = Some(last_received_at);
+        let response = MqsResponse::messages(vec![message]).into_response();
+        assert_eq!(response.headers().len(), 8);
+        assert_eq!(FirstReceivedAtHeader::get(response.headers()), Some(first_received_at));
+        assert_eq!(LastReceivedAtHeader::get(response.headers()), Some(last_received_at));
+    }
+
+    #[test]
+    fn message_response_single_expires_at() {
+        let mut message = mk_message(0, None);
+        let expires_at = UtcTime::from_timestamp(449_884_800);
+        message.expires_at = Some(expires_at);
+        let response = MqsResponse::messages(vec![message]).into_response();
+        assert_eq!(response.headers().len(), 7);
+        assert_eq!(ExpiresAtHeader::get(response.headers()), Some(expires_at));
+    }
+
+    #[test]
+    fn message_response_single_content_hash() {
+        let mut message = mk_message(0, None);
+        message.hash = Some("n4bQgYhMfWWaL+qgxVrQFaO/TxsrC4Is0V1sFbDwCgg=".to_string());
+        let response = MqsResponse::messages(vec![message]).into_response();
+        assert_eq!(response.headers().len(), 7);
+        assert_eq!(
+            ContentHashHeader::get(response.headers()),
+            Some("n4bQgYhMfWWaL+qgxVrQFaO/TxsrC4Is0V1sFbDwCgg=")
+        );
+    }
+
+    #[test]
+    fn message_response_single_attributes() {
+        let mut message = mk_message(0, None);
+        message.attributes = vec![
+            "x-mqs-attr-tenant:acme".to_string(),
+            "x-mqs-attr-tag:first".to_string(),
+            "x-mqs-attr-tag:second".to_string(),
+            "content-type:application/json".to_string(),
+            "invalid".to_string(),
+        ];
+        let response = MqsResponse::messages(vec![message]).into_response();
+        assert_eq!(response.headers().len(), 9);
+        assert_eq!(
+            response.headers().get("x-mqs-attr-tenant"),
+            Some(&HeaderValue::from_static("acme"))
+        );
+        assert_eq!(
+            response
+                .headers()
+                .get_all("x-mqs-attr-tag")
+                .iter()
+                .collect::<Vec<&HeaderValue>>(),
+            vec!["first", "second"]
+        );
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE),
+            Some(&HeaderValue::from_static("text/plain"))
+        );
+    }
+
     fn message_response_multiple_with_encoding(encoding: Option<String>) {
         let messages = vec![
             mk_message(0, encoding.clone()),
@@ -250,7 +519,8 @@ pub(crate) mod test {
         ];
         let mut response = MqsResponse::messages(messages.clone()).into_response();
         assert_eq!(response.status().as_u16(), Status::Ok as u16);
-        assert_eq!(response.headers().len(), 1);
+        assert_eq!(response.headers().len(), 2);
+        let content_length = response.headers().get(CONTENT_LENGTH).unwrap().clone();
         let ct = response.headers().get(CONTENT_TYPE).unwrap().to_str().unwrap();
         if let Some(boundary) = multipart::is_multipart(ct) {
             let encoding_header = if let Some(encoding) = encoding {
@@ -258,8 +528,10 @@ pub(crate) mod test {
             } else {
                 String::new()
             };
+            let body = read_body(response.body_mut());
+            assert_eq!(content_length, &format!("{}", body.len()));
             assert_eq!(
-                read_body(response.body_mut()).as_slice(),
+                body.as_slice(),
                 format!(
                     "{}\r\ncontent-type: text/plain\r\n{}x-mqs-message-id: {}\r\nx-mqs-message-receives: 1\r\nx-mqs-message-published-at: {}\r\nx-mqs-message-visible-at: {}\r\n\r\nABC\r\n\
                     {}\r\ncontent-type: text/plain\r\n{}x-mqs-message-id: {}\r\nx-mqs-message-receives: 2\r\nx-mqs-message-published-at: {}\r\nx-mqs-message-visible-at: {}\r\n\r\nABC\r\n\
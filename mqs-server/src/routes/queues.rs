@@ -1,18 +1,96 @@
 use diesel::QueryResult;
-use hyper::{Body, Request};
-use mqs_common::{QueueConfig, QueuesResponse, Status};
+use hyper::{header::IF_MATCH, Body, Request};
+use mqs_common::{
+    is_valid_queue_name,
+    logger::audit,
+    AgePercentiles,
+    Page,
+    QueueConfig,
+    QueueConfigOutput,
+    QueueDescriptionOutput,
+    QueueWithDlqConfig,
+    QueueWithDlqOutput,
+    QueuesResponse,
+    QueuesWithStatusResponse,
+    RenameQueueRequest,
+    Status,
+    UtcTime,
+    MAX_QUEUE_NAME_LENGTH,
+};
 use std::convert::TryFrom;
 
 use crate::{
-    models::queue::{Queue, QueueInput, QueueRepository},
+    models::queue::{Queue, QueueDescription, QueueInput, QueueRename, QueueRepository, QueueUpdate},
     routes::MqsResponse,
 };
 
+/// Maximum number of messages sampled to compute the percentiles of the message ages of a queue.
+const AGE_SAMPLE_SIZE: i64 = 1000;
+
+/// An administrative operation on a queue, written to the audit log after it succeeded.
+#[derive(Serialize, Debug)]
+struct QueueAuditEvent<'a> {
+    action:       &'static str,
+    queue:        &'a str,
+    /// Name of the queue before it was renamed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    renamed_from: Option<&'a str>,
+    config:       &'a QueueConfigOutput,
+}
+
+fn audit_queue(status: Status, action: &'static str, config: &QueueConfigOutput) -> MqsResponse {
+    audit(&QueueAuditEvent {
+        action,
+        queue: &config.name,
+        renamed_from: None,
+        config,
+    });
+
+    MqsResponse::status_json(status, config)
+}
+
+/// Check whether `new_queues` more queues can be created without exceeding `max_queues`. Returns the
+/// response to send instead of creating the queues if not.
+fn check_max_queues<R: QueueRepository>(
+    repo: &mut R,
+    queue_name: &str,
+    max_queues: Option<i64>,
+    new_queues: i64,
+) -> Option<MqsResponse> {
+    let max_queues = max_queues?;
+    match repo.count_queues() {
+        Err(err) => {
+            error!("Failed to count queues before creating queue {}: {}", queue_name, err);
+            Some(MqsResponse::status(Status::InternalServerError))
+        },
+        Ok(count) if count + new_queues > max_queues => {
+            info!(
+                "Refusing to create queue {}, the limit of {} queues is reached",
+                queue_name, max_queues
+            );
+            Some(MqsResponse::status_error(
+                Status::TooManyRequests,
+                "The maximum number of queues has been reached",
+            ))
+        },
+        Ok(_) => None,
+    }
+}
+
 pub fn new<R: QueueRepository>(
     repo: &mut R,
     queue_name: &str,
+    max_queues: Option<i64>,
     params: Result<QueueConfig, serde_json::Error>,
 ) -> MqsResponse {
+    if !is_valid_queue_name(queue_name) {
+        info!("Refusing to create queue with invalid name {}", queue_name);
+        return MqsResponse::error_owned(&format!(
+            "Queue names must consist of 1 to {} letters, digits, dashes, or underscores",
+            MAX_QUEUE_NAME_LENGTH
+        ));
+    }
+
     match params {
         Err(err) => {
             let err_message = format!("{:?}", err);
@@ -20,13 +98,22 @@ pub fn new<R: QueueRepository>(
             MqsResponse::error_owned(&err_message)
         },
         Ok(config) => {
+            if let Err(err) = config.validate() {
+                info!("Refusing to create queue {} with invalid config: {}", queue_name, err);
+                return MqsResponse::error_owned(&err.to_string());
+            }
+
+            if let Some(response) = check_max_queues(repo, queue_name, max_queues, 1) {
+                return response;
+            }
+
             info!("Creating new queue {}", queue_name);
             let created = repo.insert_queue(&QueueInput::new(&config, queue_name));
 
             match created {
                 Ok(Some(queue)) => {
                     info!("Created new queue {}", queue_name);
-                    MqsResponse::status_json(Status::Created, &queue.into_config_output())
+                    audit_queue(Status::Created, "create", &queue.into_config_output())
                 },
                 Ok(None) => {
                     info!("Queue {} did already exist", queue_name);
@@ -41,9 +128,92 @@ pub fn new<R: QueueRepository>(
     }
 }
 
+pub fn new_with_dlq<R: QueueRepository>(
+    repo: &mut R,
+    queue_name: &str,
+    max_queues: Option<i64>,
+    params: Result<QueueWithDlqConfig, serde_json::Error>,
+) -> MqsResponse {
+    if !is_valid_queue_name(queue_name) {
+        info!("Refusing to create queue with invalid name {}", queue_name);
+        return MqsResponse::error_owned(&format!(
+            "Queue names must consist of 1 to {} letters, digits, dashes, or underscores",
+            MAX_QUEUE_NAME_LENGTH
+        ));
+    }
+
+    let config = match params {
+        Err(err) => {
+            let err_message = format!("{:?}", err);
+            error!("Failed to parse queue params: {}", &err_message);
+            return MqsResponse::error_owned(&err_message);
+        },
+        Ok(config) => config,
+    };
+    if let Err(err) = config.validate(queue_name) {
+        info!(
+            "Refusing to create queue {} with dead letter queue with invalid config: {}",
+            queue_name, err
+        );
+        return MqsResponse::error_owned(&err.to_string());
+    }
+    let dead_letter_queue_name = config.dead_letter_queue_name().unwrap_or_default();
+
+    if let Some(response) = check_max_queues(repo, queue_name, max_queues, 2) {
+        return response;
+    }
+
+    info!(
+        "Creating new queue {} with dead letter queue {}",
+        queue_name, dead_letter_queue_name
+    );
+    let created = repo.insert_queue_with_dlq(
+        &QueueInput::new(&config.queue, queue_name),
+        &QueueInput::new(&config.dead_letter_queue, dead_letter_queue_name),
+    );
+
+    match created {
+        Ok(Some((queue, dead_letter_queue))) => {
+            info!(
+                "Created new queue {} with dead letter queue {}",
+                queue_name, dead_letter_queue_name
+            );
+            let output = QueueWithDlqOutput {
+                queue:             queue.into_config_output(),
+                dead_letter_queue: dead_letter_queue.into_config_output(),
+            };
+            for config in [&output.dead_letter_queue, &output.queue] {
+                audit(&QueueAuditEvent {
+                    action: "create",
+                    queue: &config.name,
+                    renamed_from: None,
+                    config,
+                });
+            }
+
+            MqsResponse::status_json(Status::Created, &output)
+        },
+        Ok(None) => {
+            info!(
+                "Queue {} or dead letter queue {} did already exist",
+                queue_name, dead_letter_queue_name
+            );
+            MqsResponse::status(Status::Conflict)
+        },
+        Err(err) => {
+            error!(
+                "Failed to create new queue {} with dead letter queue {}, {:?}: {}",
+                queue_name, dead_letter_queue_name, config, err
+            );
+            MqsResponse::status(Status::InternalServerError)
+        },
+    }
+}
+
 pub fn update<R: QueueRepository>(
     repo: &mut R,
     queue_name: &str,
+    expected_version: Option<UtcTime>,
     params: Result<QueueConfig, serde_json::Error>,
 ) -> MqsResponse {
     match params {
@@ -53,18 +223,27 @@ pub fn update<R: QueueRepository>(
             MqsResponse::error_owned(&err_message)
         },
         Ok(config) => {
+            if let Err(err) = config.validate() {
+                info!("Refusing to update queue {} with invalid config: {}", queue_name, err);
+                return MqsResponse::error_owned(&err.to_string());
+            }
+
             info!("Updating queue {}", queue_name);
-            let result = repo.update_queue(&QueueInput::new(&config, queue_name));
+            let result = repo.update_queue(&QueueInput::new(&config, queue_name), expected_version);
 
             match result {
-                Ok(Some(queue)) => {
+                Ok(QueueUpdate::Updated(queue)) => {
                     info!("Updated queue {}", queue_name);
-                    MqsResponse::json(&queue.into_config_output())
+                    audit_queue(Status::Ok, "update", &queue.into_config_output())
                 },
-                Ok(None) => {
+                Ok(QueueUpdate::NotFound) => {
                     info!("Queue {} did not exist", queue_name);
                     MqsResponse::status(Status::NotFound)
                 },
+                Ok(QueueUpdate::VersionMismatch) => {
+                    info!("Queue {} was modified concurrently", queue_name);
+                    MqsResponse::status(Status::Conflict)
+                },
                 Err(err) => {
                     error!("Failed to update queue {}, {:?}: {}", queue_name, config, err);
                     MqsResponse::status(Status::InternalServerError)
@@ -80,7 +259,7 @@ pub fn delete<R: QueueRepository>(repo: &mut R, queue_name: &str) -> MqsResponse
     match deleted {
         Ok(Some(queue)) => {
             info!("Deleted queue {}", queue_name);
-            MqsResponse::json(&queue.into_config_output())
+            audit_queue(Status::Ok, "delete", &queue.into_config_output())
         },
         Ok(None) => {
             info!("Queue {} wasp not found", queue_name);
@@ -93,6 +272,113 @@ pub fn delete<R: QueueRepository>(repo: &mut R, queue_name: &str) -> MqsResponse
     }
 }
 
+pub fn rename<R: QueueRepository>(
+    repo: &mut R,
+    queue_name: &str,
+    params: Result<RenameQueueRequest, serde_json::Error>,
+) -> MqsResponse {
+    let params = match params {
+        Err(err) => {
+            let err_message = format!("{:?}", err);
+            error!("Failed to parse rename params: {}", &err_message);
+            return MqsResponse::error_owned(&err_message);
+        },
+        Ok(params) => params,
+    };
+    if !is_valid_queue_name(&params.name) {
+        info!(
+            "Refusing to rename queue {} to invalid name {}",
+            queue_name, params.name
+        );
+        return MqsResponse::error_owned(&format!(
+            "Queue names must consist of 1 to {} letters, digits, dashes, or underscores",
+            MAX_QUEUE_NAME_LENGTH
+        ));
+    }
+
+    info!("Renaming queue {} to {}", queue_name, params.name);
+    match repo.rename_queue(queue_name, &params.name) {
+        Ok(QueueRename::Renamed(queue)) => {
+            info!("Renamed queue {} to {}", queue_name, params.name);
+            let config = queue.into_config_output();
+            audit(&QueueAuditEvent {
+                action:       "rename",
+                queue:        &config.name,
+                renamed_from: Some(queue_name),
+                config:       &config,
+            });
+
+            MqsResponse::json(&config)
+        },
+        Ok(QueueRename::NotFound) => {
+            info!("Queue {} was not found", queue_name);
+            MqsResponse::status(Status::NotFound)
+        },
+        Ok(QueueRename::AlreadyExists) => {
+            info!(
+                "Queue {} can't be renamed, queue {} does already exist",
+                queue_name, params.name
+            );
+            MqsResponse::status_error(Status::Conflict, "A queue with the new name does already exist")
+        },
+        Err(err) => {
+            error!("Failed to rename queue {} to {}: {}", queue_name, params.name, err);
+            MqsResponse::status(Status::InternalServerError)
+        },
+    }
+}
+
+pub fn describe_deletion<R: QueueRepository>(repo: &mut R, queue_name: &str) -> MqsResponse {
+    info!("Describing deletion of queue {}", queue_name);
+    describe(repo, queue_name, false)
+}
+
+/// Parse the queue version expected by the client from the `If-Match` header of a request. Returns
+/// `None` if no header is given or the header matches any version.
+pub fn expected_version(req: &Request<Body>) -> Result<Option<UtcTime>, String> {
+    match req.headers().get(IF_MATCH) {
+        None => Ok(None),
+        Some(value) => {
+            let value = value
+                .to_str()
+                .map_err(|err| format!("invalid value for header If-Match: {}", err))?
+                .trim();
+            if value == "*" {
+                return Ok(None);
+            }
+
+            let version = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            UtcTime::parse_from_rfc3339(version)
+                .map(Some)
+                .map_err(|err| format!("invalid value for header If-Match: {:?}", err))
+        },
+    }
+}
+
+/// Check whether a request asks for the status of a queue in addition to its configuration.
+pub fn include_status(req: &Request<Body>) -> Result<bool, String> {
+    bool_query_parameter(req, "status", true)
+}
+
+/// Check whether a request asks for the percentiles of the message ages in the status of a queue.
+pub fn include_age_percentiles(req: &Request<Body>) -> Result<bool, String> {
+    bool_query_parameter(req, "age_percentiles", false)
+}
+
+fn bool_query_parameter(req: &Request<Body>, name: &str, default: bool) -> Result<bool, String> {
+    let query = req.uri().query().unwrap_or("");
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key.as_ref() == name)
+        .map_or(Ok(default), |(_, value)| {
+            value
+                .parse()
+                .map_err(|err| format!("invalid value for boolean field {}: {}", name, err))
+        })
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Range {
     offset: Option<i64>,
@@ -122,43 +408,131 @@ impl TryFrom<&Request<Body>> for Range {
     }
 }
 
-fn list_queues_and_count<R: QueueRepository>(repo: &mut R, range: &Range) -> QueryResult<QueuesResponse> {
-    let queues = repo.list_queues(range.offset, range.limit)?;
-    let total = repo.count_queues()?;
-    Ok(QueuesResponse {
-        queues: queues.into_iter().map(Queue::into_config_output).collect(),
+/// Get the prefix the names of listed queues have to start with, if the request restricts them.
+pub fn prefix(req: &Request<Body>) -> Option<String> {
+    let query = req.uri().query().unwrap_or("");
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key.as_ref() == "prefix")
+        .map(|(_, value)| value.into_owned())
+}
+
+/// Check whether the status of every queue should be included when listing queues.
+pub fn with_status(req: &Request<Body>) -> Result<bool, String> {
+    bool_query_parameter(req, "status", false)
+}
+
+fn list_queues_page<R: QueueRepository>(repo: &mut R, range: &Range, prefix: Option<&str>) -> QueryResult<Page<Queue>> {
+    let (queues, total) = match prefix {
+        None => (repo.list_queues(range.offset, range.limit)?, repo.count_queues()?),
+        Some(prefix) => (
+            repo.list_queues_with_prefix(prefix, range.offset, range.limit)?,
+            repo.count_queues_with_prefix(prefix)?,
+        ),
+    };
+
+    Ok(Page {
+        items: queues,
         total,
+        offset: range.offset.map_or(0, |offset| usize::try_from(offset).unwrap_or(0)),
+        limit: range.limit.map(|limit| usize::try_from(limit).unwrap_or(0)),
     })
 }
 
-pub fn list<R: QueueRepository>(repo: &mut R, range: Result<Range, String>) -> MqsResponse {
-    match range {
-        Err(err) => MqsResponse::error_owned(&err),
-        Ok(range) => match list_queues_and_count(repo, &range) {
-            Ok(response) => MqsResponse::json(&response),
-            Err(err) => {
-                error!(
-                    "Failed to read range of queues {:?}-{:?}: {}",
-                    range.offset, range.limit, err
-                );
+fn list_queues_and_count<R: QueueRepository>(
+    repo: &mut R,
+    range: &Range,
+    prefix: Option<&str>,
+) -> QueryResult<QueuesResponse> {
+    Ok(list_queues_page(repo, range, prefix)?
+        .map(Queue::into_config_output)
+        .into())
+}
 
-                MqsResponse::status(Status::InternalServerError)
-            },
+/// List queues together with their status. The message counts of all queues on the page are
+/// computed with a single query instead of describing each queue on its own.
+fn list_queues_with_status<R: QueueRepository>(
+    repo: &mut R,
+    range: &Range,
+    prefix: Option<&str>,
+) -> QueryResult<QueuesWithStatusResponse> {
+    let page = list_queues_page(repo, range, prefix)?;
+    let descriptions = repo.describe_queues(page.items)?;
+    let page = Page {
+        items:  descriptions.into_iter().map(description_output).collect(),
+        total:  page.total,
+        offset: page.offset,
+        limit:  page.limit,
+    };
+
+    Ok(page.into())
+}
+
+fn description_output(description: QueueDescription) -> QueueDescriptionOutput {
+    let mut output = description.queue.into_config_output().into_description(
+        description.messages,
+        description.visible_messages,
+        description.oldest_message_age,
+    );
+    output.status.dead_letter_queue_depth = description.dead_letter_queue_depth;
+
+    output
+}
+
+pub fn list<R: QueueRepository>(repo: &mut R, range: Range, prefix: Option<&str>, with_status: bool) -> MqsResponse {
+    let result = if with_status {
+        list_queues_with_status(repo, &range, prefix).map(|response| MqsResponse::json(&response))
+    } else {
+        list_queues_and_count(repo, &range, prefix).map(|response| MqsResponse::json(&response))
+    };
+
+    match result {
+        Ok(response) => response,
+        Err(err) => {
+            error!(
+                "Failed to read range of queues {:?}-{:?}: {}",
+                range.offset, range.limit, err
+            );
+
+            MqsResponse::status(Status::InternalServerError)
         },
     }
 }
 
-pub fn describe<R: QueueRepository>(repo: &mut R, queue_name: &str) -> MqsResponse {
-    match repo.describe_queue(queue_name) {
+/// Return only the configuration of a queue without computing its status, which is cheaper than
+/// `describe` for queues containing many messages.
+pub fn describe_config<R: QueueRepository>(repo: &mut R, queue_name: &str) -> MqsResponse {
+    match repo.find_by_name(queue_name) {
         Err(err) => {
             error!("Failed to describe queue {}: {}", queue_name, err);
             MqsResponse::status(Status::InternalServerError)
         },
         Ok(None) => MqsResponse::status(Status::NotFound),
-        Ok(Some(description)) => MqsResponse::json(&description.queue.into_config_output().into_description(
-            description.messages,
-            description.visible_messages,
-            description.oldest_message_age,
-        )),
+        Ok(Some(queue)) => MqsResponse::json(&queue.into_config_output()),
     }
 }
+
+pub fn describe<R: QueueRepository>(repo: &mut R, queue_name: &str, age_percentiles: bool) -> MqsResponse {
+    let description = match repo.describe_queue(queue_name) {
+        Err(err) => {
+            error!("Failed to describe queue {}: {}", queue_name, err);
+            return MqsResponse::status(Status::InternalServerError);
+        },
+        Ok(None) => return MqsResponse::status(Status::NotFound),
+        Ok(Some(description)) => description,
+    };
+    let percentiles = if age_percentiles {
+        match repo.sample_message_ages(&description.queue, AGE_SAMPLE_SIZE) {
+            Err(err) => {
+                error!("Failed to sample message ages of queue {}: {}", queue_name, err);
+                return MqsResponse::status(Status::InternalServerError);
+            },
+            Ok(ages) => Some(AgePercentiles::from_ages(ages)),
+        }
+    } else {
+        None
+    };
+    let mut output = description_output(description);
+    output.status.age_percentiles = percentiles;
+
+    MqsResponse::json(&output)
+}
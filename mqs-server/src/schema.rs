@@ -11,7 +11,19 @@ diesel::table! {
         receives -> Int4,
         visible_since -> Timestamp,
         created_at -> Timestamp,
-        trace_id -> Nullable<Uuid>,
+        trace_id -> Nullable<Varchar>,
+        first_received_at -> Nullable<Timestamp>,
+        last_received_at -> Nullable<Timestamp>,
+        nacks -> Int4,
+        group_id -> Nullable<Varchar>,
+        attributes -> Array<Varchar>,
+        expires_at -> Nullable<Timestamp>,
+        origin_queue -> Nullable<Varchar>,
+        redrive_reason -> Nullable<Varchar>,
+        json_attributes -> Nullable<Jsonb>,
+        redrive_count -> Int4,
+        priority -> Int4,
+        published_at -> Timestamp,
     }
 }
 
@@ -27,6 +39,13 @@ diesel::table! {
         content_based_deduplication -> Bool,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        allowed_content_types -> Array<Varchar>,
+        tags -> Jsonb,
+        default_content_type -> Nullable<Varchar>,
+        max_in_flight -> Nullable<Int8>,
+        max_redrives -> Nullable<Int4>,
+        visibility_jitter -> Nullable<Int4>,
+        message_priorities -> Bool,
     }
 }
 
@@ -139,6 +139,13 @@ pub mod test {
             content_based_deduplication: false,
             created_at:                  UtcTime::now(),
             updated_at:                  UtcTime::now(),
+            allowed_content_types:       Vec::new(),
+            default_content_type:        None,
+            max_in_flight:               None,
+            max_redrives:                None,
+            visibility_jitter:           None,
+            message_priorities:          false,
+            tags:                        serde_json::Value::Object(serde_json::Map::new()),
         }
     }
 